@@ -0,0 +1,334 @@
+//! Opt-in transfer acceptance: an account can set a threshold above which incoming transfers
+//! are held until it accepts or rejects them, so it never holds tokens it didn't agree to.
+//!
+//! A held transfer is escrowed on the contract with its transfer fee. Accepting pays the amount
+//! to the receiver and charges the fee; rejecting returns both to the sender. With a compliance
+//! registry set, transfers are held once the registry allowed them. Only plain transfers are
+//! held: `ft_transfer_call` needs the receiver to get the tokens in the same call, so it is
+//! rejected above the threshold. Transfers only look up the receiver's threshold while some
+//! account has one.
+//!
+//! The sender of a held `ft_transfer` pays the storage of the hold with the attached deposit.
+//! An account has at most `MAX_HELD_TRANSFERS` transfers held at once; further transfers that
+//! would be held are rejected until it decides on some.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+
+/// Transfers that can be held for an account at once.
+const MAX_HELD_TRANSFERS: usize = 50;
+
+pub type HeldTransferId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct HeldTransfer {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: Balance,
+    /// Transfer fee escrowed with the amount.
+    pub fee: Balance,
+    pub memo: Option<String>,
+    pub held_at: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HeldTransferView {
+    pub transfer_id: U64,
+    pub sender_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    pub held_at: U64,
+}
+
+impl Contract {
+    /// Whether a transfer of `amount` to `receiver_id` has to be accepted first.
+    pub(crate) fn internal_requires_acceptance(
+        &self,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> bool {
+        self.acceptance_accounts > 0
+            && self
+                .acceptance_thresholds
+                .get(receiver_id)
+                .is_some_and(|threshold| amount > threshold)
+    }
+
+    /// Whether another transfer can be held for `receiver_id`.
+    pub(crate) fn internal_can_hold_transfer(&self, receiver_id: &AccountId) -> bool {
+        self.held_by_receiver
+            .get(receiver_id)
+            .is_none_or(|transfer_ids| transfer_ids.len() < MAX_HELD_TRANSFERS)
+    }
+
+    /// Holds a transfer whose amount and fee are already escrowed, until the receiver decides.
+    pub(crate) fn internal_hold_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        fee: Balance,
+        memo: Option<String>,
+    ) {
+        let transfer_id = self.next_held_transfer_id;
+        self.next_held_transfer_id += 1;
+        let mut transfer_ids = self.held_by_receiver.get(&receiver_id).unwrap_or_default();
+        assert!(
+            transfer_ids.len() < MAX_HELD_TRANSFERS,
+            "ERR_TOO_MANY_HELD_TRANSFERS"
+        );
+        transfer_ids.push(transfer_id);
+        self.held_by_receiver.insert(&receiver_id, &transfer_ids);
+        events::emit_event(
+            "transfer_held",
+            json!({
+                "transfer_id": U64(transfer_id),
+                "sender_id": sender_id,
+                "receiver_id": receiver_id,
+                "amount": U128(amount),
+            }),
+        );
+        self.held_transfers.insert(
+            &transfer_id,
+            &HeldTransfer {
+                sender_id,
+                receiver_id,
+                amount,
+                fee,
+                memo,
+                held_at: env::block_timestamp(),
+            },
+        );
+    }
+
+    /// Pays out a transfer whose amount and fee are escrowed, charging the fee.
+    pub(crate) fn internal_complete_escrowed_transfer(&mut self, transfer: HeldTransfer) {
+        self.internal_escrow_release(&transfer.receiver_id, transfer.amount);
+        self.internal_on_transfer_completed(
+            &transfer.sender_id,
+            &transfer.receiver_id,
+            transfer.amount,
+        );
+        self.internal_charge_escrowed_fee(&transfer.sender_id, transfer.fee);
+        if let Some(memo) = transfer.memo {
+            log!("Memo: {}", memo);
+        }
+    }
+
+    /// Charges a transfer fee escrowed from `sender_id`. The fee is refunded if it was removed
+    /// while the transfer was escrowed.
+    pub(crate) fn internal_charge_escrowed_fee(&mut self, sender_id: &AccountId, fee: Balance) {
+        if self.config.transfer_fee.is_some() {
+            self.internal_charge_transfer_fee(&env::current_account_id(), fee);
+        } else if fee > 0 {
+            self.internal_escrow_release(sender_id, fee);
+        }
+    }
+
+    /// Removes a held transfer addressed to the predecessor.
+    fn internal_take_held_transfer(&mut self, transfer_id: HeldTransferId) -> HeldTransfer {
+        let transfer = self
+            .held_transfers
+            .get(&transfer_id)
+            .expect("ERR_NO_HELD_TRANSFER");
+        assert_eq!(
+            env::predecessor_account_id(),
+            transfer.receiver_id,
+            "ERR_NOT_ALLOWED"
+        );
+        self.held_transfers.remove(&transfer_id);
+        let mut transfer_ids = self
+            .held_by_receiver
+            .get(&transfer.receiver_id)
+            .unwrap_or_default();
+        transfer_ids.retain(|id| *id != transfer_id);
+        if transfer_ids.is_empty() {
+            self.held_by_receiver.remove(&transfer.receiver_id);
+        } else {
+            self.held_by_receiver
+                .insert(&transfer.receiver_id, &transfer_ids);
+        }
+        transfer
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Holds incoming transfers of more than `threshold` to the predecessor until it accepts
+    /// them, or stops holding them with `None`. The attached deposit covers the storage of the
+    /// setting; the rest is refunded.
+    #[payable]
+    pub fn set_acceptance_threshold(&mut self, threshold: Option<U128>) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let account_id = env::predecessor_account_id();
+        let previous = match threshold {
+            Some(threshold) => self
+                .acceptance_thresholds
+                .insert(&account_id, &threshold.into()),
+            None => self.acceptance_thresholds.remove(&account_id),
+        };
+        match (previous.is_some(), threshold.is_some()) {
+            (false, true) => self.acceptance_accounts += 1,
+            (true, false) => self.acceptance_accounts -= 1,
+            _ => {}
+        }
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn get_acceptance_threshold(&self, account_id: ValidAccountId) -> Option<U128> {
+        self.acceptance_thresholds
+            .get(account_id.as_ref())
+            .map(|threshold| threshold.into())
+    }
+
+    /// Accepts a transfer held for the predecessor, paying it out.
+    #[payable]
+    pub fn accept_transfer(&mut self, transfer_id: U64) {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        let transfer = self.internal_take_held_transfer(transfer_id.0);
+        self.assert_not_blacklisted(&transfer.sender_id);
+        self.assert_not_blacklisted(&transfer.receiver_id);
+        self.internal_complete_escrowed_transfer(transfer);
+        events::emit_event("transfer_accepted", json!({ "transfer_id": transfer_id }));
+    }
+
+    /// Rejects a transfer held for the predecessor, returning it to the sender.
+    #[payable]
+    pub fn reject_transfer(&mut self, transfer_id: U64) {
+        assert_one_yocto();
+        let transfer = self.internal_take_held_transfer(transfer_id.0);
+        self.internal_escrow_release(&transfer.sender_id, transfer.amount + transfer.fee);
+        events::emit_event("transfer_rejected", json!({ "transfer_id": transfer_id }));
+    }
+
+    /// Transfers held for `account_id` to accept or reject.
+    pub fn get_held_transfers(&self, account_id: ValidAccountId) -> Vec<HeldTransferView> {
+        self.held_by_receiver
+            .get(account_id.as_ref())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|transfer_id| {
+                self.held_transfers
+                    .get(&transfer_id)
+                    .map(|transfer| HeldTransferView {
+                        transfer_id: transfer_id.into(),
+                        sender_id: transfer.sender_id,
+                        amount: transfer.amount.into(),
+                        memo: transfer.memo,
+                        held_at: transfer.held_at.into(),
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.set_acceptance_threshold(Some(100.into()));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_accept_held_transfer() {
+        let (mut context, mut contract) = setup();
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        contract.ft_transfer(accounts(2), 250.into(), Some("case 7".to_string()));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 150);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        let held = contract.get_held_transfers(accounts(2));
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].amount.0, 250);
+        assert_eq!(held[0].memo.as_deref(), Some("case 7"));
+        contract.accept_transfer(held[0].transfer_id);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 350);
+        assert!(contract.get_held_transfers(accounts(2)).is_empty());
+    }
+
+    #[test]
+    fn test_reject_held_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        contract.ft_transfer(accounts(2), 250.into(), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        let held = contract.get_held_transfers(accounts(2));
+        contract.reject_transfer(held[0].transfer_id);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 500);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ACCEPTANCE_REQUIRED")]
+    fn test_transfer_call_above_threshold() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer_call(accounts(2), 250.into(), None, String::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_held_transfer_pays_storage() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(2), 250.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOO_MANY_HELD_TRANSFERS")]
+    fn test_too_many_held_transfers() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.set_acceptance_threshold(Some(0.into()));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(22))
+            .build());
+        for _ in 0..MAX_HELD_TRANSFERS {
+            contract.ft_transfer(accounts(2), 1.into(), None);
+        }
+        assert_eq!(
+            contract.get_held_transfers(accounts(2)).len(),
+            MAX_HELD_TRANSFERS
+        );
+        contract.ft_transfer(accounts(2), 1.into(), None);
+    }
+}
@@ -0,0 +1,179 @@
+//! Owner-assigned account tiers with per-tier transfer limits.
+//!
+//! Every account is `retail` unless the owner assigns another tier. Each tier can cap the size of
+//! a single transfer and the volume an account sends within 24 hours, both unlimited until set.
+//! The volume ceiling is enforced together with the global cap in `volume_limit`.
+
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json::json;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum AccountTier {
+    Retail,
+    Institutional,
+    Internal,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TierLimits {
+    /// Largest single transfer, unlimited if `None`.
+    pub max_transfer: Option<U128>,
+    /// Most an account may send within 24 hours, unlimited if `None`.
+    pub daily_volume: Option<U128>,
+}
+
+impl TierLimits {
+    pub(crate) fn check_max_transfer(&self, amount: Balance) -> Result<(), &'static str> {
+        match self.max_transfer {
+            Some(max_transfer) if amount > max_transfer.0 => Err("ERR_TIER_MAX_TRANSFER"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Contract {
+    fn internal_account_tier(&self, account_id: &AccountId) -> AccountTier {
+        self.account_tiers
+            .get(account_id)
+            .unwrap_or(AccountTier::Retail)
+    }
+
+    pub(crate) fn internal_tier_limits(&self, account_id: &AccountId) -> TierLimits {
+        self.tier_limits
+            .get(&self.internal_account_tier(account_id))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn internal_set_tier_limits(&mut self, tier: AccountTier, limits: TierLimits) {
+        events::emit_config_changed("tier_limits", json!({ "tier": tier, "limits": limits }));
+        self.tier_limits.insert(&tier, &limits);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Assigns `tier` to `account_id`; `None` makes it retail again. The owner pays for the
+    /// storage out of the attached deposit.
+    #[payable]
+    pub fn set_account_tier(&mut self, account_id: ValidAccountId, tier: Option<AccountTier>) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        match tier {
+            Some(tier) if tier != AccountTier::Retail => {
+                self.account_tiers.insert(account_id.as_ref(), &tier);
+            }
+            _ => {
+                self.account_tiers.remove(account_id.as_ref());
+            }
+        }
+        events::emit_event(
+            "account_tier_changed",
+            json!({
+                "account_id": account_id,
+                "tier": tier.unwrap_or(AccountTier::Retail),
+            }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn get_account_tier(&self, account_id: ValidAccountId) -> AccountTier {
+        self.internal_account_tier(account_id.as_ref())
+    }
+
+    #[payable]
+    pub fn set_tier_limits(&mut self, tier: AccountTier, limits: TierLimits) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        self.internal_set_tier_limits(tier, limits);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn get_tier_limits(&self, tier: AccountTier) -> TierLimits {
+        self.tier_limits.get(&tier).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+    use crate::volume_limit::DAY;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(10_000);
+        mint_to(&mut contract, accounts(1), 5_000);
+        mint_to(&mut contract, accounts(2), 0);
+        mint_to(&mut contract, accounts(3), 2_000);
+        contract.set_tier_limits(
+            AccountTier::Retail,
+            TierLimits {
+                max_transfer: Some(100.into()),
+                daily_volume: Some(150.into()),
+            },
+        );
+        contract.set_tier_limits(
+            AccountTier::Institutional,
+            TierLimits {
+                max_transfer: Some(1_000.into()),
+                daily_volume: None,
+            },
+        );
+        contract.set_account_tier(accounts(3), Some(AccountTier::Institutional));
+        context.attached_deposit(1);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_daily_volume() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        contract.ft_transfer(accounts(2), 50.into(), None);
+        assert_eq!(contract.get_outgoing_volume(accounts(1)).0, 150);
+
+        testing_env!(context.block_timestamp(DAY).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TIER_DAILY_VOLUME")]
+    fn test_daily_volume_exceeded() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        contract.ft_transfer(accounts(2), 100.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TIER_MAX_TRANSFER")]
+    fn test_institutional_max_transfer() {
+        let (mut context, mut contract) = setup();
+        assert_eq!(
+            contract.get_account_tier(accounts(3)),
+            AccountTier::Institutional
+        );
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.ft_transfer(accounts(2), 1_000.into(), None);
+        contract.ft_transfer(accounts(2), 1_001.into(), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Atomic batches of admin operations.
+//!
+//! `admin_batch` applies several configuration, role and limit changes in one call, e.g. from a
+//! single DAO proposal. A failing action panics, which reverts the whole call, so either all
+//! actions take effect or none does.
+
+use crate::account_tiers::{AccountTier, TierLimits};
+use crate::config::ConfigUpdate;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde::Deserialize;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Treasury,
+    RebaseOracle,
+    IndexManager,
+    MerkleKeeper,
+    KycIssuer,
+    BlacklistManager,
+    ComplianceOfficer,
+    ComplianceBurner,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum AdminAction {
+    UpdateConfig(Box<ConfigUpdate>),
+    SetRole {
+        role: Role,
+        account_id: Option<ValidAccountId>,
+    },
+    AddGuardian(ValidAccountId),
+    RemoveGuardian(ValidAccountId),
+    SetTierLimits {
+        tier: AccountTier,
+        limits: TierLimits,
+    },
+    SetVolumeCapExempt {
+        account_id: ValidAccountId,
+        exempt: bool,
+    },
+}
+
+impl Role {
+    fn name(self) -> &'static str {
+        match self {
+            Role::Treasury => "treasury",
+            Role::RebaseOracle => "rebase_oracle",
+            Role::IndexManager => "index_manager",
+            Role::MerkleKeeper => "merkle_keeper",
+            Role::KycIssuer => "kyc_issuer",
+            Role::BlacklistManager => "blacklist_manager",
+            Role::ComplianceOfficer => "compliance_officer",
+            Role::ComplianceBurner => "compliance_burner",
+        }
+    }
+}
+
+impl Contract {
+    /// Sets a role like its own setter would. The treasury can only be replaced, like with
+    /// `set_treasury`, since payouts may be routed to it.
+    fn internal_set_role(&mut self, role: Role, account_id: Option<AccountId>) {
+        assert!(
+            !(matches!(role, Role::Treasury) && account_id.is_none()),
+            "ERR_NO_TREASURY"
+        );
+        let slot = match role {
+            Role::Treasury => &mut self.treasury_id,
+            Role::RebaseOracle => &mut self.rebase_oracle,
+            Role::IndexManager => &mut self.index_manager,
+            Role::MerkleKeeper => &mut self.merkle_keeper,
+            Role::KycIssuer => &mut self.kyc_issuer,
+            Role::BlacklistManager => &mut self.blacklist_manager,
+            Role::ComplianceOfficer => &mut self.compliance_officer,
+            Role::ComplianceBurner => &mut self.compliance_burner,
+        };
+        *slot = account_id;
+        events::emit_role_changed(role.name(), slot.as_ref());
+    }
+
+    fn internal_apply_admin_action(&mut self, action: AdminAction) {
+        match action {
+            AdminAction::UpdateConfig(update) => self.internal_update_config(*update),
+            AdminAction::SetRole { role, account_id } => {
+                self.internal_set_role(role, account_id.map(|a| a.into()))
+            }
+            AdminAction::AddGuardian(account_id) => self.internal_add_guardian(account_id.into()),
+            AdminAction::RemoveGuardian(account_id) => {
+                self.internal_remove_guardian(account_id.into())
+            }
+            AdminAction::SetTierLimits { tier, limits } => {
+                self.internal_set_tier_limits(tier, limits)
+            }
+            AdminAction::SetVolumeCapExempt { account_id, exempt } => {
+                self.internal_set_volume_cap_exempt(account_id.as_ref(), exempt)
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Applies `actions` in order, all or none. The attached deposit covers the storage they
+    /// use; the rest is refunded.
+    #[payable]
+    pub fn admin_batch(&mut self, actions: Vec<AdminAction>) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        assert!(!actions.is_empty(), "ERR_EMPTY_BATCH");
+        for action in actions {
+            self.internal_apply_admin_action(action);
+        }
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_admin_batch() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        let actions: Vec<AdminAction> = near_sdk::serde_json::from_str(
+            r#"[
+                {"update_config": {"min_transfer_amount": "5"}},
+                {"set_role": {"role": "kyc_issuer", "account_id": "charlie"}},
+                {"add_guardian": "danny"},
+                {"set_tier_limits": {"tier": "retail", "limits": {"max_transfer": "100"}}}
+            ]"#,
+        )
+        .unwrap();
+        contract.admin_batch(actions);
+        assert_eq!(contract.get_min_transfer_amount().0, 5);
+        assert_eq!(contract.get_kyc_issuer(), Some(accounts(2).into()));
+        assert_eq!(contract.get_guardians(), vec![accounts(3).to_string()]);
+        assert_eq!(
+            contract
+                .get_tier_limits(AccountTier::Retail)
+                .max_transfer
+                .unwrap()
+                .0,
+            100
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_GUARDIAN")]
+    fn test_admin_batch_fails_as_a_whole() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.admin_batch(vec![
+            AdminAction::SetRole {
+                role: Role::Treasury,
+                account_id: Some(accounts(1)),
+            },
+            AdminAction::RemoveGuardian(accounts(3)),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_TREASURY")]
+    fn test_admin_batch_cannot_unset_treasury() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.admin_batch(vec![AdminAction::SetRole {
+            role: Role::Treasury,
+            account_id: None,
+        }]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ALLOWED")]
+    fn test_admin_batch_not_owner() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.admin_batch(vec![AdminAction::AddGuardian(accounts(1))]);
+    }
+}
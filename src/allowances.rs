@@ -0,0 +1,324 @@
+//! Allowances: a holder approves a spender to transfer up to an amount on its behalf with
+//! `ft_transfer_from`, optionally until an expiry.
+//!
+//! Transfers by a spender go through the same restrictions as the holder's own transfers and
+//! count against the holder's limits. Expiring allowances are indexed by expiry, so
+//! `get_expiring_allowances` can list those about to lapse, e.g. for reminders or cleanup. The
+//! holder pays for the storage of its allowances.
+//!
+//! `increase_allowance` and `decrease_allowance` adjust an allowance relative to its current
+//! amount. Unlike replacing it with `ft_approve`, this can't be raced by a spender spending the
+//! old allowance right before the new one takes effect and then spending the new one too.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde_json::json;
+
+const MAX_EXPIRING_LIMIT: u64 = 100;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Allowance {
+    pub amount: Balance,
+    /// Block timestamp from which the allowance can't be used anymore, never if `None`.
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllowanceView {
+    pub owner_id: AccountId,
+    pub spender_id: AccountId,
+    pub amount: U128,
+    pub expires_at: Option<U64>,
+}
+
+impl Contract {
+    /// Replaces the allowance of `spender_id` on `owner_id`'s tokens, removing it if `amount` is
+    /// 0, and keeps the expiry index in sync.
+    pub(crate) fn internal_set_allowance(
+        &mut self,
+        owner_id: &AccountId,
+        spender_id: &AccountId,
+        amount: Balance,
+        expires_at: Option<u64>,
+    ) {
+        let key = (owner_id.clone(), spender_id.clone());
+        if let Some(expires_at) = self.allowances.get(&key).and_then(|a| a.expires_at) {
+            self.allowance_expiries
+                .remove(&(expires_at, owner_id.clone(), spender_id.clone()));
+        }
+        if amount == 0 {
+            self.allowances.remove(&key);
+        } else {
+            self.allowances
+                .insert(&key, &Allowance { amount, expires_at });
+            if let Some(expires_at) = expires_at {
+                self.allowance_expiries
+                    .insert(&(expires_at, owner_id.clone(), spender_id.clone()), &());
+            }
+        }
+        events::emit_event(
+            "allowance_changed",
+            json!({
+                "owner_id": owner_id,
+                "spender_id": spender_id,
+                "amount": U128(amount),
+                "expires_at": expires_at.map(U64),
+            }),
+        );
+    }
+
+    pub(crate) fn internal_approve(
+        &mut self,
+        owner_id: &AccountId,
+        spender_id: &AccountId,
+        amount: Balance,
+        expires_at: Option<U64>,
+    ) {
+        assert_ne!(owner_id, spender_id, "ERR_SELF_APPROVAL");
+        if let Some(expires_at) = expires_at {
+            assert!(expires_at.0 > env::block_timestamp(), "ERR_EXPIRY_IN_PAST");
+        }
+        self.internal_set_allowance(
+            owner_id,
+            spender_id,
+            amount,
+            expires_at.map(|expires_at| expires_at.into()),
+        );
+    }
+
+    /// Allowance of `spender_id` on `owner_id`'s tokens, 0 if there is none. Panics if it
+    /// expired.
+    pub(crate) fn internal_active_allowance(
+        &self,
+        owner_id: &AccountId,
+        spender_id: &AccountId,
+    ) -> Allowance {
+        let allowance = self
+            .allowances
+            .get(&(owner_id.clone(), spender_id.clone()))
+            .unwrap_or(Allowance {
+                amount: 0,
+                expires_at: None,
+            });
+        assert!(
+            allowance
+                .expires_at
+                .is_none_or(|expires_at| env::block_timestamp() < expires_at),
+            "ERR_ALLOWANCE_EXPIRED"
+        );
+        allowance
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Allows `spender_id` to transfer up to `amount` of the predecessor's tokens, replacing an
+    /// earlier allowance, until `expires_at` if set. An amount of 0 revokes the allowance.
+    #[payable]
+    pub fn ft_approve(
+        &mut self,
+        spender_id: ValidAccountId,
+        amount: U128,
+        expires_at: Option<U64>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let owner_id = env::predecessor_account_id();
+        self.internal_approve(&owner_id, spender_id.as_ref(), amount.into(), expires_at);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Raises the allowance of `spender_id` by `amount` and returns the new allowance. An
+    /// existing allowance keeps its expiry; an expired one has to be replaced with `ft_approve`.
+    #[payable]
+    pub fn increase_allowance(&mut self, spender_id: ValidAccountId, amount: U128) -> U128 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let owner_id = env::predecessor_account_id();
+        assert_ne!(&owner_id, spender_id.as_ref(), "ERR_SELF_APPROVAL");
+        let allowance = self.internal_active_allowance(&owner_id, spender_id.as_ref());
+        let new_amount = allowance
+            .amount
+            .checked_add(amount.0)
+            .expect("ERR_OVERFLOW");
+        self.internal_set_allowance(
+            &owner_id,
+            spender_id.as_ref(),
+            new_amount,
+            allowance.expires_at,
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        new_amount.into()
+    }
+
+    /// Lowers the allowance of `spender_id` by `amount` and returns the new allowance. Fails if
+    /// the allowance is smaller than `amount`, e.g. because the spender used it meanwhile.
+    #[payable]
+    pub fn decrease_allowance(&mut self, spender_id: ValidAccountId, amount: U128) -> U128 {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let allowance = self.internal_active_allowance(&owner_id, spender_id.as_ref());
+        let new_amount = allowance
+            .amount
+            .checked_sub(amount.0)
+            .expect("ERR_ALLOWANCE_BELOW_ZERO");
+        self.internal_set_allowance(
+            &owner_id,
+            spender_id.as_ref(),
+            new_amount,
+            allowance.expires_at,
+        );
+        new_amount.into()
+    }
+
+    /// Transfers `amount` of `owner_id`'s tokens to `receiver_id` out of the predecessor's
+    /// allowance. Expired allowances are rejected.
+    #[payable]
+    pub fn ft_transfer_from(
+        &mut self,
+        owner_id: ValidAccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        let spender_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&spender_id);
+        let allowance = self.internal_active_allowance(owner_id.as_ref(), &spender_id);
+        assert!(amount.0 <= allowance.amount, "ERR_INSUFFICIENT_ALLOWANCE");
+        self.internal_set_allowance(
+            owner_id.as_ref(),
+            &spender_id,
+            allowance.amount - amount.0,
+            allowance.expires_at,
+        );
+        self.internal_holder_transfer(owner_id.into(), receiver_id.into(), amount, memo, None);
+    }
+
+    /// Amount `spender_id` can still transfer, 0 if the allowance expired.
+    pub fn ft_allowance(&self, owner_id: ValidAccountId, spender_id: ValidAccountId) -> U128 {
+        self.allowances
+            .get(&(owner_id.into(), spender_id.into()))
+            .filter(|allowance| {
+                allowance
+                    .expires_at
+                    .is_none_or(|expires_at| env::block_timestamp() < expires_at)
+            })
+            .map_or(0, |allowance| allowance.amount)
+            .into()
+    }
+
+    /// Lists up to `limit` allowances expiring before `before`, including those expired already,
+    /// soonest first.
+    pub fn get_expiring_allowances(&self, before: U64, limit: U64) -> Vec<AllowanceView> {
+        let limit = std::cmp::min(limit.0, MAX_EXPIRING_LIMIT) as usize;
+        self.allowance_expiries
+            .iter()
+            .take_while(|((expires_at, _, _), _)| *expires_at < before.0)
+            .take(limit)
+            .filter_map(|((_, owner_id, spender_id), _)| {
+                let allowance = self
+                    .allowances
+                    .get(&(owner_id.clone(), spender_id.clone()))?;
+                Some(AllowanceView {
+                    owner_id,
+                    spender_id,
+                    amount: allowance.amount.into(),
+                    expires_at: allowance.expires_at.map(|expires_at| expires_at.into()),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+        mint_to(&mut contract, accounts(3), 0);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.ft_approve(accounts(2), 100.into(), Some(10.into()));
+        context.attached_deposit(1);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_transfer_from() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer_from(accounts(1), accounts(3), 60.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 60);
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ALLOWANCE_EXPIRED")]
+    fn test_transfer_from_expired() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(10)
+            .build());
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 0);
+        contract.ft_transfer_from(accounts(1), accounts(3), 60.into(), None);
+    }
+
+    #[test]
+    fn test_adjust_allowance() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        assert_eq!(contract.increase_allowance(accounts(2), 50.into()).0, 150);
+        testing_env!(context.attached_deposit(1).build());
+        assert_eq!(contract.decrease_allowance(accounts(2), 120.into()).0, 30);
+        let expiring = contract.get_expiring_allowances(11.into(), 10.into());
+        assert_eq!(expiring[0].expires_at.unwrap().0, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ALLOWANCE_BELOW_ZERO")]
+    fn test_decrease_allowance_below_zero() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer_from(accounts(1), accounts(3), 60.into(), None);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.decrease_allowance(accounts(2), 100.into());
+    }
+
+    #[test]
+    fn test_expiring_allowances() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        contract.ft_approve(accounts(3), 50.into(), Some(20.into()));
+        contract.ft_approve(accounts(4), 50.into(), None);
+        let expiring = contract.get_expiring_allowances(15.into(), 10.into());
+        assert_eq!(expiring.len(), 1);
+        assert_eq!(expiring[0].spender_id, accounts(2).to_string());
+
+        contract.ft_approve(accounts(2), 0.into(), None);
+        let expiring = contract.get_expiring_allowances(100.into(), 10.into());
+        assert_eq!(expiring.len(), 1);
+        assert_eq!(expiring[0].spender_id, accounts(3).to_string());
+    }
+}
@@ -0,0 +1,248 @@
+//! Pull billing for subscriptions: a merchant registers a plan charging an amount once per
+//! period, customers subscribe to it, and the merchant collects each period with `charge`.
+//!
+//! Charges are paid out of the customer's allowance to the merchant, so the customer keeps
+//! control of the total with `ft_approve`, `decrease_allowance` and expiries, while the
+//! subscription limits the merchant to the plan's amount once per period. A charge moves the
+//! next allowed charge a full period after it, so missed periods can't be charged afterwards.
+//! Charges are transfers by the customer and go through the same restrictions.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+
+pub type PlanId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct BillingPlan {
+    pub merchant_id: AccountId,
+    pub amount: Balance,
+    /// Nanoseconds between two charges.
+    pub period: u64,
+    /// Whether customers can still subscribe.
+    pub active: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Subscription {
+    /// Block timestamp from which the merchant can charge again.
+    pub next_charge_at: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BillingPlanView {
+    pub plan_id: U64,
+    pub merchant_id: AccountId,
+    pub amount: U128,
+    pub period: U64,
+    pub active: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers a plan of the predecessor charging `amount` every `period` nanoseconds. The
+    /// attached deposit covers the storage of the plan.
+    #[payable]
+    pub fn create_billing_plan(&mut self, amount: U128, period: U64) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        assert!(period.0 > 0, "ERR_ZERO_PERIOD");
+        let plan_id = self.next_billing_plan_id;
+        self.next_billing_plan_id += 1;
+        self.billing_plans.insert(
+            &plan_id,
+            &BillingPlan {
+                merchant_id: env::predecessor_account_id(),
+                amount: amount.into(),
+                period: period.into(),
+                active: true,
+            },
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        plan_id.into()
+    }
+
+    /// Stops new subscriptions to a plan of the predecessor. Existing subscriptions can still be
+    /// charged until customers cancel them.
+    #[payable]
+    pub fn close_billing_plan(&mut self, plan_id: U64) {
+        assert_one_yocto();
+        let mut plan = self.billing_plans.get(&plan_id.0).expect("ERR_NO_PLAN");
+        assert_eq!(
+            env::predecessor_account_id(),
+            plan.merchant_id,
+            "ERR_NOT_ALLOWED"
+        );
+        plan.active = false;
+        self.billing_plans.insert(&plan_id.0, &plan);
+    }
+
+    /// Subscribes the predecessor to a plan. The merchant can charge right away. The attached
+    /// deposit covers the storage of the subscription.
+    #[payable]
+    pub fn subscribe(&mut self, plan_id: U64) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let plan = self.billing_plans.get(&plan_id.0).expect("ERR_NO_PLAN");
+        assert!(plan.active, "ERR_PLAN_CLOSED");
+        let key = (env::predecessor_account_id(), plan_id.0);
+        assert!(!self.subscriptions.contains_key(&key), "ERR_SUBSCRIBED");
+        self.subscriptions.insert(
+            &key,
+            &Subscription {
+                next_charge_at: env::block_timestamp(),
+            },
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Cancels the predecessor's subscription to a plan.
+    #[payable]
+    pub fn unsubscribe(&mut self, plan_id: U64) {
+        assert_one_yocto();
+        self.subscriptions
+            .remove(&(env::predecessor_account_id(), plan_id.0))
+            .expect("ERR_NOT_SUBSCRIBED");
+    }
+
+    /// Charges `customer_id` the amount of a plan of the predecessor, out of the customer's
+    /// allowance to the predecessor.
+    #[payable]
+    pub fn charge(&mut self, customer_id: ValidAccountId, plan_id: U64) {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        let merchant_id = env::predecessor_account_id();
+        let customer_id: AccountId = customer_id.into();
+        let plan = self.billing_plans.get(&plan_id.0).expect("ERR_NO_PLAN");
+        assert_eq!(merchant_id, plan.merchant_id, "ERR_NOT_ALLOWED");
+        let key = (customer_id.clone(), plan_id.0);
+        let mut subscription = self.subscriptions.get(&key).expect("ERR_NOT_SUBSCRIBED");
+        let now = env::block_timestamp();
+        assert!(now >= subscription.next_charge_at, "ERR_ALREADY_CHARGED");
+        let allowance = self.internal_active_allowance(&customer_id, &merchant_id);
+        assert!(
+            plan.amount <= allowance.amount,
+            "ERR_INSUFFICIENT_ALLOWANCE"
+        );
+        self.internal_set_allowance(
+            &customer_id,
+            &merchant_id,
+            allowance.amount - plan.amount,
+            allowance.expires_at,
+        );
+        subscription.next_charge_at = now + plan.period;
+        self.subscriptions.insert(&key, &subscription);
+        events::emit_event(
+            "subscription_charged",
+            json!({
+                "plan_id": plan_id,
+                "customer_id": customer_id,
+                "amount": U128(plan.amount),
+            }),
+        );
+        self.internal_holder_transfer(
+            customer_id,
+            merchant_id,
+            plan.amount.into(),
+            Some(format!("plan {}", plan_id.0)),
+            None,
+        );
+    }
+
+    pub fn get_billing_plan(&self, plan_id: U64) -> Option<BillingPlanView> {
+        self.billing_plans
+            .get(&plan_id.0)
+            .map(|plan| BillingPlanView {
+                plan_id,
+                merchant_id: plan.merchant_id,
+                amount: plan.amount.into(),
+                period: plan.period.into(),
+                active: plan.active,
+            })
+    }
+
+    /// Block timestamp from which `customer_id` can be charged again for a plan, `None` if it
+    /// isn't subscribed.
+    pub fn get_next_charge_at(&self, customer_id: ValidAccountId, plan_id: U64) -> Option<U64> {
+        self.subscriptions
+            .get(&(customer_id.into(), plan_id.0))
+            .map(|subscription| subscription.next_charge_at.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    /// Subscribes accounts(1) to a plan of accounts(2) charging 100 per 10 ns, with an
+    /// allowance of 250.
+    fn setup() -> (VMContextBuilder, Contract, U64) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let plan_id = contract.create_billing_plan(100.into(), 10.into());
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.subscribe(plan_id);
+        contract.ft_approve(accounts(2), 250.into(), None);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        (context, contract, plan_id)
+    }
+
+    #[test]
+    fn test_charge_once_per_period() {
+        let (mut context, mut contract, plan_id) = setup();
+        contract.charge(accounts(1), plan_id);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        assert_eq!(
+            contract.get_next_charge_at(accounts(1), plan_id),
+            Some(10.into())
+        );
+
+        testing_env!(context.block_timestamp(10).build());
+        contract.charge(accounts(1), plan_id);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 300);
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ALREADY_CHARGED")]
+    fn test_charge_twice_in_period() {
+        let (mut context, mut contract, plan_id) = setup();
+        contract.charge(accounts(1), plan_id);
+        testing_env!(context.block_timestamp(9).build());
+        contract.charge(accounts(1), plan_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_SUBSCRIBED")]
+    fn test_charge_after_unsubscribe() {
+        let (mut context, mut contract, plan_id) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.unsubscribe(plan_id);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.charge(accounts(1), plan_id);
+    }
+}
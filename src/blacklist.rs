@@ -0,0 +1,149 @@
+//! Sanctions list mirrored from an off-chain compliance service.
+//!
+//! `update_blacklist_batch` applies additions and removals in one call and bumps the list
+//! version, so the service can tell which version the contract holds and resync from there.
+//! Blacklisted accounts can neither send nor receive transfers, nor be minted to.
+
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde_json::json;
+
+/// Most accounts added and removed in a single batch.
+const MAX_BLACKLIST_BATCH: usize = 100;
+
+impl Contract {
+    pub(crate) fn assert_not_blacklisted(&self, account_id: &AccountId) {
+        assert!(!self.blacklist.contains(account_id), "ERR_BLACKLISTED");
+    }
+
+    fn assert_blacklist_manager(&self) {
+        let predecessor_id = env::predecessor_account_id();
+        assert!(
+            predecessor_id == self.owner_id
+                || self.blacklist_manager.as_ref() == Some(&predecessor_id),
+            "ERR_NOT_ALLOWED"
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account that may update the blacklist in addition to the owner.
+    #[payable]
+    pub fn set_blacklist_manager(&mut self, manager_id: Option<ValidAccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.blacklist_manager = manager_id.map(|a| a.into());
+        events::emit_role_changed("blacklist_manager", self.blacklist_manager.as_ref());
+    }
+
+    pub fn get_blacklist_manager(&self) -> Option<AccountId> {
+        self.blacklist_manager.clone()
+    }
+
+    /// Adds `add` to and removes `remove` from the blacklist, and returns the new list version.
+    /// Removals are applied after additions. The caller pays for the storage of new entries out
+    /// of the attached deposit.
+    #[payable]
+    pub fn update_blacklist_batch(
+        &mut self,
+        add: Vec<ValidAccountId>,
+        remove: Vec<ValidAccountId>,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_blacklist_manager();
+        assert!(
+            add.len() + remove.len() <= MAX_BLACKLIST_BATCH,
+            "ERR_BATCH_TOO_LARGE"
+        );
+        for account_id in &add {
+            self.blacklist.insert(account_id.as_ref());
+        }
+        for account_id in &remove {
+            self.blacklist.remove(account_id.as_ref());
+        }
+        self.blacklist_version += 1;
+        events::emit_event(
+            "blacklist_updated",
+            json!({
+                "version": U64(self.blacklist_version),
+                "added": add,
+                "removed": remove,
+            }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        self.blacklist_version.into()
+    }
+
+    pub fn get_blacklist_version(&self) -> U64 {
+        self.blacklist_version.into()
+    }
+
+    pub fn is_blacklisted(&self, account_id: ValidAccountId) -> bool {
+        self.blacklist.contains(account_id.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_update_blacklist_batch() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_blacklist_manager(Some(accounts(3)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let version = contract.update_blacklist_batch(vec![accounts(1), accounts(2)], vec![]);
+        assert_eq!(version.0, 1);
+        assert!(get_logs()
+            .iter()
+            .any(|log| log.contains("blacklist_updated")));
+        let version = contract.update_blacklist_batch(vec![], vec![accounts(1)]);
+        assert_eq!(version.0, 2);
+        assert_eq!(contract.get_blacklist_version().0, 2);
+        assert!(!contract.is_blacklisted(accounts(1)));
+        assert!(contract.is_blacklisted(accounts(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BLACKLISTED")]
+    fn test_blacklisted_sender() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.update_blacklist_batch(vec![accounts(1)], vec![]);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ALLOWED")]
+    fn test_update_blacklist_not_manager() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.update_blacklist_batch(vec![accounts(2)], vec![]);
+    }
+}
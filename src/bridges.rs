@@ -0,0 +1,185 @@
+//! Bridge connectors: accounts other than the owner allowed to `mint`, each up to its own cap.
+//!
+//! A bridge's cap bounds the supply it has minted, on top of the global `max_supply`, so an
+//! exploited bridge can only inflate the supply by its own cap. Lowering a cap below the outstanding amount only stops further mints. Bridges
+//! mint with their own nonces and call rate, like any minter.
+//!
+//! Holders leave through `burn_to_bridge`, which burns their tokens and emits a `bridge_burn`
+//! event with the destination chain and address for the relayer to release the tokens there.
+//! Each burn gets a sequential id so the relayer can process every burn exactly once.
+
+use crate::events::BurnReason;
+use crate::pause::PauseSurface;
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+
+const MAX_DEST_CHAIN_LEN: usize = 64;
+const MAX_DEST_ADDRESS_LEN: usize = 128;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Bridge {
+    pub mint_cap: Balance,
+    /// Minted through the bridge.
+    pub outstanding: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BridgeView {
+    pub mint_cap: U128,
+    pub outstanding: U128,
+}
+
+impl Contract {
+    /// Checks the predecessor may mint `amount`: the owner always can, a bridge within its cap.
+    pub(crate) fn internal_assert_minter(&mut self, amount: Balance) {
+        let minter_id = env::predecessor_account_id();
+        if minter_id == self.owner_id {
+            return;
+        }
+        let mut bridge = self.bridges.get(&minter_id).expect("ERR_NOT_ALLOWED");
+        bridge.outstanding = bridge
+            .outstanding
+            .checked_add(amount)
+            .filter(|outstanding| *outstanding <= bridge.mint_cap)
+            .expect("ERR_BRIDGE_CAP_EXCEEDED");
+        self.bridges.insert(&minter_id, &bridge);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Allows `bridge_id` to mint up to `mint_cap` outstanding, or changes its cap.
+    #[payable]
+    pub fn set_bridge(&mut self, bridge_id: ValidAccountId, mint_cap: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        let bridge_id: AccountId = bridge_id.into();
+        let outstanding = self
+            .bridges
+            .get(&bridge_id)
+            .map_or(0, |bridge| bridge.outstanding);
+        self.bridges.insert(
+            &bridge_id,
+            &Bridge {
+                mint_cap: mint_cap.into(),
+                outstanding,
+            },
+        );
+        events::emit_event(
+            "bridge_updated",
+            json!({ "bridge_id": bridge_id, "mint_cap": mint_cap }),
+        );
+    }
+
+    /// Stops `bridge_id` from minting.
+    #[payable]
+    pub fn remove_bridge(&mut self, bridge_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.bridges
+            .remove(bridge_id.as_ref())
+            .expect("ERR_NO_BRIDGE");
+        events::emit_event("bridge_removed", json!({ "bridge_id": bridge_id }));
+    }
+
+    /// Burns `amount` of the predecessor's tokens to release them to `dest_address` on
+    /// `dest_chain`. Returns the id of the burn in the `bridge_burn` event.
+    #[payable]
+    pub fn burn_to_bridge(
+        &mut self,
+        amount: U128,
+        dest_chain: String,
+        dest_address: String,
+    ) -> U64 {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        assert!(
+            !dest_chain.is_empty() && dest_chain.len() <= MAX_DEST_CHAIN_LEN,
+            "ERR_INVALID_DEST_CHAIN"
+        );
+        assert!(
+            !dest_address.is_empty() && dest_address.len() <= MAX_DEST_ADDRESS_LEN,
+            "ERR_INVALID_DEST_ADDRESS"
+        );
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let burn_id = self.next_bridge_burn_id;
+        self.next_bridge_burn_id += 1;
+        self.internal_burn(&account_id, amount.into(), None, Some(BurnReason::Bridge));
+        events::emit_event(
+            "bridge_burn",
+            json!({
+                "burn_id": U64(burn_id),
+                "account_id": account_id,
+                "amount": amount,
+                "dest_chain": dest_chain,
+                "dest_address": dest_address,
+            }),
+        );
+        burn_id.into()
+    }
+
+    pub fn get_bridge(&self, bridge_id: ValidAccountId) -> Option<BridgeView> {
+        self.bridges
+            .get(bridge_id.as_ref())
+            .map(|bridge| BridgeView {
+                mint_cap: bridge.mint_cap.into(),
+                outstanding: bridge.outstanding.into(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_bridge_mints_within_cap() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 0)]);
+        contract.set_bridge(accounts(3), 300.into());
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.mint(accounts(1), 200.into(), None, 1.into());
+        contract.mint(accounts(1), 100.into(), None, 2.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 300);
+        assert_eq!(contract.get_bridge(accounts(3)).unwrap().outstanding.0, 300);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BRIDGE_CAP_EXCEEDED")]
+    fn test_bridge_mint_over_cap() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 0)]);
+        contract.set_bridge(accounts(3), 300.into());
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.mint(accounts(1), 200.into(), None, 1.into());
+        contract.mint(accounts(1), 101.into(), None, 2.into());
+    }
+
+    #[test]
+    fn test_burn_to_bridge() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 500)]);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.burn_to_bridge(100.into(), "ethereum".into(), "0xabc".into());
+        let burn_id = contract.burn_to_bridge(50.into(), "ethereum".into(), "0xabc".into());
+        assert_eq!(burn_id.0, 1);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 350);
+        assert_eq!(contract.ft_total_supply().0, 350);
+        assert!(get_logs().last().unwrap().contains(
+            r#""event":"bridge_burn","data":[{"burn_id":"1","account_id":"bob","amount":"50","dest_chain":"ethereum","dest_address":"0xabc"}]"#
+        ));
+    }
+}
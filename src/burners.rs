@@ -0,0 +1,96 @@
+//! Burn leaderboard for community burn campaigns: how much each account burned of its own
+//! tokens with `self_burn`, and the top `MAX_TOP_BURNERS` accounts by that total.
+//!
+//! Only voluntary burns count, not transfer fees, bridge withdrawals or burns by the owner. The
+//! leaderboard is kept sorted as burns happen, in its own storage slot so that other calls don't
+//! load it. Ties keep the account that reached the total first ahead.
+
+use crate::*;
+
+const MAX_TOP_BURNERS: usize = 25;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BurnerView {
+    pub account_id: AccountId,
+    pub burned: U128,
+}
+
+impl Contract {
+    /// Adds a voluntary burn of `amount` by `account_id` to its total and the leaderboard.
+    pub(crate) fn internal_record_self_burn(&mut self, account_id: &AccountId, amount: Balance) {
+        let burned = self.burned_by_account.get(account_id).unwrap_or(0) + amount;
+        self.burned_by_account.insert(account_id, &burned);
+        let mut top = self.top_burners.get().unwrap_or_default();
+        top.retain(|entry| entry.account_id != *account_id);
+        let index = top
+            .iter()
+            .position(|entry| entry.burned.0 < burned)
+            .unwrap_or(top.len());
+        if index < MAX_TOP_BURNERS {
+            top.insert(
+                index,
+                BurnerView {
+                    account_id: account_id.clone(),
+                    burned: burned.into(),
+                },
+            );
+            top.truncate(MAX_TOP_BURNERS);
+            self.top_burners.set(&top);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Total `account_id` burned with `self_burn`.
+    pub fn get_burned_by(&self, account_id: ValidAccountId) -> U128 {
+        self.burned_by_account
+            .get(account_id.as_ref())
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// The accounts that burned the most, largest first, at most `MAX_TOP_BURNERS`.
+    pub fn get_top_burners(&self, limit: u32) -> Vec<BurnerView> {
+        let mut top = self.top_burners.get().unwrap_or_default();
+        top.truncate(limit as usize);
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_top_burners() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(
+            10_000,
+            &[
+                (accounts(1), 1_000),
+                (accounts(2), 1_000),
+                (accounts(3), 1_000),
+            ],
+        );
+        contract.burn(accounts(3), 500.into(), None, None);
+        for (account, amount) in [(1, 100), (2, 150), (1, 100)] {
+            testing_env!(context.predecessor_account_id(accounts(account)).build());
+            contract.self_burn(amount.into(), None, None);
+        }
+
+        assert_eq!(contract.get_burned_by(accounts(1)).0, 200);
+        assert_eq!(contract.get_burned_by(accounts(3)).0, 0);
+        let top = contract.get_top_burners(10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].account_id, accounts(1).to_string());
+        assert_eq!(top[0].burned.0, 200);
+        assert_eq!(top[1].account_id, accounts(2).to_string());
+        assert_eq!(contract.get_top_burners(1).len(), 1);
+    }
+}
@@ -0,0 +1,326 @@
+//! Per-block checkpoints of shares and of the share index, so balances at past block heights
+//! can be queried without an archival node.
+//!
+//! Checkpoints are opt-in because every balance change then writes to state that the contract
+//! pays for. Each checkpoint holds the value at the end of its block and at most one is kept
+//! per block. The historical share index only reflects persisted index changes: interest that
+//! accrued lazily between two persisted accruals is not interpolated. The tokens in escrow on
+//! the contract account are an amount rather than shares and get checkpoints of their own.
+//!
+//! Locks are checkpointed too, for voting power at past heights. Since the lock multiplier
+//! decays with time, every checkpointed block also records its timestamp. A height without a
+//! checkpoint uses the timestamp of the last checkpointed block before it, which slightly
+//! overstates the remaining lock time; snapshots record their own block, so they are exact.
+
+use crate::lockup::Lock;
+use crate::*;
+use near_sdk::json_types::U64;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub enum CheckpointKey {
+    ShareIndex,
+    Account(AccountId),
+    Escrow,
+    LockAmount(AccountId),
+    LockUnlockAt(AccountId),
+    Timestamp,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Checkpoint {
+    pub block_height: u64,
+    pub value: u128,
+}
+
+impl Contract {
+    fn internal_push_checkpoint(&mut self, key: CheckpointKey, value: u128) {
+        self.internal_write_checkpoint(key, value);
+        self.internal_checkpoint_clock();
+    }
+
+    /// Records the timestamp of the current block.
+    pub(crate) fn internal_checkpoint_clock(&mut self) {
+        self.internal_write_checkpoint(CheckpointKey::Timestamp, env::block_timestamp() as u128);
+    }
+
+    fn internal_write_checkpoint(&mut self, key: CheckpointKey, value: u128) {
+        let block_height = env::block_index();
+        let count = self.checkpoint_counts.get(&key).unwrap_or(0);
+        let same_block = count > 0
+            && self
+                .checkpoints
+                .get(&(key.clone(), count - 1))
+                .unwrap()
+                .block_height
+                == block_height;
+        let position = if same_block { count - 1 } else { count };
+        self.checkpoints.insert(
+            &(key.clone(), position),
+            &Checkpoint {
+                block_height,
+                value,
+            },
+        );
+        self.checkpoint_counts.insert(&key, &(position + 1));
+    }
+
+    /// Records `value` after a change. `previous_value` is the value before the change, needed
+    /// to seed the history of keys that had no checkpoint yet.
+    fn internal_checkpoint_change(
+        &mut self,
+        key: CheckpointKey,
+        previous_value: u128,
+        value: u128,
+    ) {
+        let enabled_at = match self.checkpoints_enabled_at {
+            Some(enabled_at) => enabled_at,
+            None => return,
+        };
+        if self.checkpoint_counts.get(&key).is_none() && enabled_at < env::block_index() {
+            self.checkpoints.insert(
+                &(key.clone(), 0),
+                &Checkpoint {
+                    block_height: enabled_at,
+                    value: previous_value,
+                },
+            );
+            self.checkpoint_counts.insert(&key, &1);
+        }
+        self.internal_push_checkpoint(key, value);
+    }
+
+    /// Records the `shares` of the account after a change.
+    pub(crate) fn internal_checkpoint_account(
+        &mut self,
+        account_id: &AccountId,
+        previous_shares: Balance,
+        shares: Balance,
+    ) {
+        self.internal_checkpoint_change(
+            CheckpointKey::Account(account_id.clone()),
+            previous_shares,
+            shares,
+        );
+    }
+
+    /// Records the tokens in escrow after a change.
+    pub(crate) fn internal_checkpoint_escrow(&mut self, previous_amount: Balance, amount: Balance) {
+        self.internal_checkpoint_change(CheckpointKey::Escrow, previous_amount, amount);
+    }
+
+    /// Records the lock of the account after a change, `None` once it's removed.
+    pub(crate) fn internal_checkpoint_lock(
+        &mut self,
+        account_id: &AccountId,
+        previous_lock: Option<&Lock>,
+        lock: Option<&Lock>,
+    ) {
+        let amount = |lock: Option<&Lock>| lock.map_or(0, |lock| lock.amount.0);
+        let unlock_at = |lock: Option<&Lock>| lock.map_or(0, |lock| lock.unlock_at.0 as u128);
+        self.internal_checkpoint_change(
+            CheckpointKey::LockAmount(account_id.clone()),
+            amount(previous_lock),
+            amount(lock),
+        );
+        self.internal_checkpoint_change(
+            CheckpointKey::LockUnlockAt(account_id.clone()),
+            unlock_at(previous_lock),
+            unlock_at(lock),
+        );
+    }
+
+    /// Persists a new share index. All index changes go through here to keep checkpoints.
+    pub(crate) fn internal_set_share_index(&mut self, share_index: u128) {
+        self.share_index = share_index;
+        if self.checkpoints_enabled_at.is_some() {
+            self.internal_push_checkpoint(CheckpointKey::ShareIndex, share_index);
+        }
+    }
+
+    /// Value of the checkpointed key at the end of `block_height`, `current` if it didn't
+    /// change since checkpoints were enabled.
+    fn internal_checkpoint_value_at(
+        &self,
+        key: CheckpointKey,
+        block_height: u64,
+        current: u128,
+    ) -> u128 {
+        let enabled_at = self
+            .checkpoints_enabled_at
+            .expect("ERR_CHECKPOINTS_DISABLED");
+        assert!(block_height >= enabled_at, "ERR_BEFORE_CHECKPOINTS");
+        assert!(block_height <= env::block_index(), "ERR_FUTURE_BLOCK");
+        let count = self.checkpoint_counts.get(&key).unwrap_or(0);
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = (low + high) / 2;
+            let checkpoint = self.checkpoints.get(&(key.clone(), mid)).unwrap();
+            if checkpoint.block_height <= block_height {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        if low == 0 {
+            // Only possible when there are no checkpoints, since the first one is at or before
+            // `enabled_at`.
+            current
+        } else {
+            self.checkpoints.get(&(key, low - 1)).unwrap().value
+        }
+    }
+
+    pub(crate) fn internal_balance_of_at(
+        &self,
+        account_id: &AccountId,
+        block_height: u64,
+    ) -> Balance {
+        let current = self.token.accounts.get(account_id).unwrap_or(0);
+        let shares = self.internal_checkpoint_value_at(
+            CheckpointKey::Account(account_id.clone()),
+            block_height,
+            current,
+        );
+        let share_index = self.internal_checkpoint_value_at(
+            CheckpointKey::ShareIndex,
+            block_height,
+            self.share_index,
+        );
+        let balance = crate::utils::mul_div(shares, share_index, SHARE_INDEX_DENOMINATOR, false);
+        if *account_id == env::current_account_id() {
+            balance
+                + self.internal_checkpoint_value_at(
+                    CheckpointKey::Escrow,
+                    block_height,
+                    self.escrow_balance,
+                )
+        } else {
+            balance
+        }
+    }
+
+    /// Lock of the account at the end of `block_height`, if it had one.
+    pub(crate) fn internal_lock_at(
+        &self,
+        account_id: &AccountId,
+        block_height: u64,
+    ) -> Option<Lock> {
+        let current = self.locks.get(account_id);
+        let amount = self.internal_checkpoint_value_at(
+            CheckpointKey::LockAmount(account_id.clone()),
+            block_height,
+            current.as_ref().map_or(0, |lock| lock.amount.0),
+        );
+        let unlock_at = self.internal_checkpoint_value_at(
+            CheckpointKey::LockUnlockAt(account_id.clone()),
+            block_height,
+            current.as_ref().map_or(0, |lock| lock.unlock_at.0 as u128),
+        );
+        if amount == 0 {
+            None
+        } else {
+            Some(Lock {
+                amount: amount.into(),
+                unlock_at: (unlock_at as u64).into(),
+            })
+        }
+    }
+
+    /// Timestamp of the last checkpointed block at or before `block_height`.
+    pub(crate) fn internal_timestamp_at(&self, block_height: u64) -> u64 {
+        self.internal_checkpoint_value_at(
+            CheckpointKey::Timestamp,
+            block_height,
+            env::block_timestamp() as u128,
+        ) as u64
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Starts recording checkpoints. Can't be undone, as a gap would make history unreliable.
+    #[payable]
+    pub fn enable_checkpoints(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(self.checkpoints_enabled_at.is_none(), "ERR_ALREADY_ENABLED");
+        self.internal_accrue_interest();
+        self.checkpoints_enabled_at = Some(env::block_index());
+        self.internal_set_share_index(self.share_index);
+        events::emit_config_changed("checkpoints_enabled", true);
+    }
+
+    pub fn get_checkpoints_enabled_at(&self) -> Option<U64> {
+        self.checkpoints_enabled_at
+            .map(|block_height| block_height.into())
+    }
+
+    pub fn shares_of_at(&self, account_id: ValidAccountId, block_height: U64) -> U128 {
+        let account_id: AccountId = account_id.into();
+        let current = self.token.accounts.get(&account_id).unwrap_or(0);
+        self.internal_checkpoint_value_at(
+            CheckpointKey::Account(account_id),
+            block_height.0,
+            current,
+        )
+        .into()
+    }
+
+    /// Balance of the account at the end of `block_height`.
+    pub fn balance_of_at(&self, account_id: ValidAccountId, block_height: U64) -> U128 {
+        self.internal_balance_of_at(account_id.as_ref(), block_height.0)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::I128;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_balance_of_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_index(10).build());
+        let mut contract = new_contract(10_000);
+        mint_to(&mut contract, accounts(1), 100);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.enable_checkpoints();
+        contract.enable_rebase();
+
+        testing_env!(context.block_index(20).build());
+        mint_to(&mut contract, accounts(1), 50);
+        testing_env!(context.block_index(30).build());
+        contract.rebase(I128(150));
+        testing_env!(context
+            .block_index(40)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+
+        assert_eq!(contract.balance_of_at(accounts(1), 10.into()).0, 100);
+        assert_eq!(contract.balance_of_at(accounts(1), 25.into()).0, 150);
+        assert_eq!(contract.balance_of_at(accounts(1), 30.into()).0, 300);
+        assert_eq!(contract.balance_of_at(accounts(1), 40.into()).0, 200);
+        assert_eq!(contract.balance_of_at(accounts(2), 35.into()).0, 0);
+        assert_eq!(contract.balance_of_at(accounts(2), 40.into()).0, 100);
+    }
+
+    #[test]
+    fn test_escrow_balance_of_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_index(10).build());
+        let mut contract = new_contract_with_balances(10_000, &[(accounts(1), 100)]);
+        contract.internal_escrow_deposit(&accounts(1).into(), 30);
+        contract.enable_checkpoints();
+
+        testing_env!(context.block_index(20).build());
+        contract.internal_escrow_deposit(&accounts(1).into(), 20);
+        assert_eq!(contract.balance_of_at(contract_account(), 10.into()).0, 30);
+        assert_eq!(contract.balance_of_at(contract_account(), 20.into()).0, 50);
+    }
+}
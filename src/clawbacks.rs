@@ -0,0 +1,195 @@
+//! Clawbacks: the owner, e.g. a DAO, can take tokens back from an account into a recovery
+//! account after a public notice period.
+//!
+//! Initiating a clawback announces it in a `clawback_initiated` event and escrows the amount on
+//! the contract, so the account can't move it away during the notice. The owner can cancel it
+//! during `Config::clawback_notice_period`, which returns the amount, and execute it afterwards,
+//! which releases the amount to the recovery account. The notice period can only be raised.
+
+use crate::config::ConfigUpdate;
+use crate::utils::refund_deposit;
+use crate::volume_limit::DAY;
+use crate::*;
+use near_sdk::serde_json::json;
+
+pub const DEFAULT_CLAWBACK_NOTICE_PERIOD: u64 = 7 * DAY;
+const MAX_CLAWBACK_REASON_LEN: usize = 256;
+
+pub type ClawbackId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Clawback {
+    pub account_id: AccountId,
+    pub amount: U128,
+    pub recovery_id: AccountId,
+    pub reason: String,
+    pub initiated_at: U64,
+    /// Block timestamp from which the clawback can be executed.
+    pub executable_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Raises the notice period between initiating and executing a clawback.
+    #[payable]
+    pub fn set_clawback_notice_period(&mut self, period: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            clawback_notice_period: Some(period),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_clawback_notice_period(&self) -> U64 {
+        self.config.clawback_notice_period.into()
+    }
+
+    /// Starts clawing back `amount` from `account_id` to `recovery_id` for `reason`, and returns
+    /// the ID of the clawback. The attached deposit covers the storage; the rest is refunded.
+    #[payable]
+    pub fn initiate_clawback(
+        &mut self,
+        account_id: ValidAccountId,
+        amount: U128,
+        recovery_id: ValidAccountId,
+        reason: String,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        assert_ne!(
+            account_id.as_ref(),
+            recovery_id.as_ref(),
+            "ERR_SAME_ACCOUNT"
+        );
+        assert!(
+            self.token.accounts.contains_key(recovery_id.as_ref()),
+            "The account is not registered"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            !reason.trim().is_empty() && reason.len() <= MAX_CLAWBACK_REASON_LEN,
+            "ERR_INVALID_REASON"
+        );
+        self.internal_escrow_deposit(account_id.as_ref(), amount.into());
+        let now = env::block_timestamp();
+        let clawback = Clawback {
+            account_id: account_id.into(),
+            amount,
+            recovery_id: recovery_id.into(),
+            reason,
+            initiated_at: now.into(),
+            executable_at: (now + self.config.clawback_notice_period).into(),
+        };
+        let clawback_id = self.next_clawback_id;
+        self.next_clawback_id += 1;
+        events::emit_event(
+            "clawback_initiated",
+            json!({
+                "clawback_id": U64(clawback_id),
+                "account_id": clawback.account_id,
+                "amount": clawback.amount,
+                "recovery_id": clawback.recovery_id,
+                "reason": clawback.reason,
+                "executable_at": clawback.executable_at,
+            }),
+        );
+        self.clawbacks.insert(&clawback_id, &clawback);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        clawback_id.into()
+    }
+
+    /// Cancels a clawback during its notice period and returns the amount to the account.
+    #[payable]
+    pub fn cancel_clawback(&mut self, clawback_id: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        let clawback = self.clawbacks.get(&clawback_id.0).expect("ERR_NO_CLAWBACK");
+        assert!(
+            env::block_timestamp() < clawback.executable_at.0,
+            "ERR_NOTICE_PERIOD_OVER"
+        );
+        self.clawbacks.remove(&clawback_id.0);
+        self.internal_escrow_release(&clawback.account_id, clawback.amount.into());
+        events::emit_event("clawback_cancelled", json!({ "clawback_id": clawback_id }));
+    }
+
+    /// Executes a clawback after its notice period, releasing the amount to the recovery
+    /// account.
+    #[payable]
+    pub fn execute_clawback(&mut self, clawback_id: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        let clawback = self.clawbacks.get(&clawback_id.0).expect("ERR_NO_CLAWBACK");
+        assert!(
+            env::block_timestamp() >= clawback.executable_at.0,
+            "ERR_NOTICE_PERIOD_ACTIVE"
+        );
+        self.clawbacks.remove(&clawback_id.0);
+        self.internal_escrow_release(&clawback.recovery_id, clawback.amount.into());
+        events::emit_event(
+            "clawback_executed",
+            json!({
+                "clawback_id": clawback_id,
+                "account_id": clawback.account_id,
+                "amount": clawback.amount,
+                "recovery_id": clawback.recovery_id,
+            }),
+        );
+    }
+
+    pub fn get_clawback(&self, clawback_id: U64) -> Option<Clawback> {
+        self.clawbacks.get(&clawback_id.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract =
+            new_contract_with_balances(10_000, &[(accounts(1), 1_000), (accounts(2), 0)]);
+        contract.initiate_clawback(accounts(1), 400.into(), accounts(2), "Case 7".to_string());
+        testing_env!(context.attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_clawback_after_notice() {
+        let (mut context, mut contract) = setup();
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 600);
+        testing_env!(context
+            .block_timestamp(DEFAULT_CLAWBACK_NOTICE_PERIOD)
+            .build());
+        contract.execute_clawback(0.into());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 400);
+        assert!(contract.get_clawback(0.into()).is_none());
+    }
+
+    #[test]
+    fn test_cancel_clawback() {
+        let (_, mut contract) = setup();
+        contract.cancel_clawback(0.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOTICE_PERIOD_ACTIVE")]
+    fn test_clawback_during_notice() {
+        let (_, mut contract) = setup();
+        contract.execute_clawback(0.into());
+    }
+}
@@ -0,0 +1,249 @@
+//! Optional compliance check of holder-initiated transfers against an external registry.
+//!
+//! When a registry is configured, `ft_transfer` and `ft_transfer_call` move the amount into
+//! escrow on the contract and ask the registry `is_allowed(from, to, amount)`. The callback
+//! completes the transfer if the registry approves it, and returns the tokens to the sender if it
+//! refuses or the call fails. Balances only show the transfer once the callback has run, and
+//! `ft_transfer_call` calls the receiver after the check.
+
+use crate::config::ConfigUpdate;
+use crate::ft_core::{internal_call_receiver, GAS_FOR_FT_TRANSFER_CALL};
+use crate::*;
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json::json;
+use near_sdk::{ext_contract, Gas, Promise, PromiseOrValue, PromiseResult};
+
+const GAS_FOR_IS_ALLOWED: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_COMPLIANCE_CHECKED: Gas = 10_000_000_000_000;
+/// Gas kept for the transfer call itself on top of the registry call and the callback.
+const GAS_FOR_CHECKED_TRANSFER: Gas = 10_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+
+#[ext_contract(ext_compliance_registry)]
+trait ComplianceRegistry {
+    fn is_allowed(&self, from: AccountId, to: AccountId, amount: U128) -> bool;
+}
+
+/// Transfer held in escrow while the registry is asked about it.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingTransfer {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    /// Transfer fee escrowed with the amount.
+    pub fee: U128,
+    pub memo: Option<String>,
+    /// Set for `ft_transfer_call`.
+    pub msg: Option<String>,
+}
+
+#[ext_contract(ext_self)]
+trait ComplianceCallbacks {
+    fn on_compliance_checked(&mut self, transfer: PendingTransfer) -> PromiseOrValue<U128>;
+}
+
+impl Contract {
+    /// Escrows `amount` and its transfer fee from the sender and asks `registry_id` whether the transfer is allowed.
+    /// With `msg` set, the receiver is called as in `ft_transfer_call` once the check passes.
+    pub(crate) fn internal_checked_transfer(
+        &mut self,
+        registry_id: &AccountId,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: Option<String>,
+    ) -> Promise {
+        assert_ne!(
+            sender_id, receiver_id,
+            "Sender and receiver should be different"
+        );
+        let callback_gas = if msg.is_some() {
+            let callback_gas = env::prepaid_gas()
+                .checked_sub(GAS_FOR_IS_ALLOWED + GAS_FOR_CHECKED_TRANSFER)
+                .expect("ERR_NOT_ENOUGH_GAS");
+            assert!(
+                callback_gas > GAS_FOR_FT_TRANSFER_CALL + GAS_FOR_ON_COMPLIANCE_CHECKED,
+                "ERR_NOT_ENOUGH_GAS"
+            );
+            callback_gas
+        } else {
+            GAS_FOR_ON_COMPLIANCE_CHECKED
+        };
+        let fee = self.internal_transfer_fee(amount.into());
+        self.internal_escrow_deposit(&sender_id, amount.0 + fee);
+        ext_compliance_registry::is_allowed(
+            sender_id.clone(),
+            receiver_id.clone(),
+            amount,
+            registry_id,
+            NO_DEPOSIT,
+            GAS_FOR_IS_ALLOWED,
+        )
+        .then(ext_self::on_compliance_checked(
+            PendingTransfer {
+                sender_id,
+                receiver_id,
+                amount,
+                fee: fee.into(),
+                memo,
+                msg,
+            },
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            callback_gas,
+        ))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the registry checked before every `ft_transfer` and `ft_transfer_call`, or turns the
+    /// checks off with `None`.
+    #[payable]
+    pub fn set_compliance_registry(&mut self, registry_id: Option<ValidAccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            compliance_registry: Some(registry_id),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_compliance_registry(&self) -> Option<AccountId> {
+        self.config.compliance_registry.clone()
+    }
+
+    /// Completes an escrowed transfer if the registry allowed it. Blocked transfers, and those
+    /// to receivers that unregistered in the meantime, go back to the sender. Returns the amount
+    /// transferred, like `ft_resolve_transfer`.
+    #[private]
+    pub fn on_compliance_checked(&mut self, transfer: PendingTransfer) -> PromiseOrValue<U128> {
+        let PendingTransfer {
+            sender_id,
+            receiver_id,
+            amount,
+            fee,
+            memo,
+            msg,
+        } = transfer;
+        let allowed = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<bool>(&value).unwrap_or(false)
+            }
+            _ => false,
+        };
+        if !allowed || !self.token.accounts.contains_key(&receiver_id) {
+            self.internal_escrow_release(&sender_id, amount.0 + fee.0);
+            events::emit_event(
+                "transfer_blocked",
+                json!({ "sender_id": sender_id, "receiver_id": receiver_id, "amount": amount }),
+            );
+            return PromiseOrValue::Value(0.into());
+        }
+        if msg.is_none() && self.internal_requires_cosignature(&sender_id, amount.into()) {
+            self.internal_pend_cosigned_transfer(
+                sender_id,
+                receiver_id,
+                amount.into(),
+                fee.into(),
+                memo,
+            );
+            return PromiseOrValue::Value(amount);
+        }
+        if msg.is_none() && self.internal_requires_acceptance(&receiver_id, amount.into()) {
+            if !self.internal_can_hold_transfer(&receiver_id) {
+                log!(
+                    "Refund {} to {}: ERR_TOO_MANY_HELD_TRANSFERS",
+                    amount.0,
+                    sender_id
+                );
+                self.internal_escrow_release(&sender_id, amount.0 + fee.0);
+                return PromiseOrValue::Value(0.into());
+            }
+            self.internal_hold_transfer(sender_id, receiver_id, amount.into(), fee.into(), memo);
+            return PromiseOrValue::Value(amount);
+        }
+        self.internal_transfer(
+            &env::current_account_id(),
+            &receiver_id,
+            amount.into(),
+            memo,
+        );
+        self.internal_on_transfer_completed(&sender_id, &receiver_id, amount.into());
+        // The fee is refunded if it was removed while the check was pending.
+        if self.config.transfer_fee.is_some() {
+            self.internal_charge_transfer_fee(&env::current_account_id(), fee.into());
+        } else if fee.0 > 0 {
+            self.internal_escrow_release(&sender_id, fee.into());
+        }
+        match msg {
+            Some(msg) => internal_call_receiver(sender_id, receiver_id, amount, msg).into(),
+            None => {
+                self.internal_record_incoming(&sender_id, &receiver_id, amount.into());
+                PromiseOrValue::Value(amount)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup(allowed: bool) -> Contract {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.set_compliance_registry(Some(accounts(3)));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+
+        testing_env!(
+            context.predecessor_account_id(contract_account()).build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                near_sdk::serde_json::to_vec(&allowed).unwrap()
+            )]
+        );
+        contract.on_compliance_checked(PendingTransfer {
+            sender_id: accounts(1).into(),
+            receiver_id: accounts(2).into(),
+            amount: 100.into(),
+            fee: 0.into(),
+            memo: None,
+            msg: None,
+        });
+        contract
+    }
+
+    #[test]
+    fn test_allowed_transfer() {
+        let contract = setup(true);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        assert_eq!(contract.ft_balance_of(contract_account()).0, 0);
+    }
+
+    #[test]
+    fn test_blocked_transfer() {
+        let contract = setup(false);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 500);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+        assert!(get_logs()
+            .iter()
+            .any(|log| log.contains("transfer_blocked")));
+    }
+}
@@ -0,0 +1,233 @@
+//! Compliance burns: the compliance burner can burn tokens from blacklisted accounts, e.g. funds
+//! confirmed stolen, but only through a timelocked, publicly logged procedure.
+//!
+//! A burn is first proposed with a mandatory reason, e.g. a case reference, and can only be
+//! executed `Config::compliance_burn_delay` later, while the account is still blacklisted. The
+//! delay can only be raised. Until execution the burner or the owner can cancel it. Every step
+//! emits an event, and the executed burn is reported as an `ft_burn` with the `compliance`
+//! reason and the reason as memo.
+
+use crate::config::ConfigUpdate;
+use crate::utils::refund_deposit;
+use crate::volume_limit::DAY;
+use crate::*;
+use near_sdk::serde_json::json;
+
+pub const DEFAULT_COMPLIANCE_BURN_DELAY: u64 = DAY;
+const MAX_COMPLIANCE_BURN_REASON_LEN: usize = 256;
+
+pub type ComplianceBurnId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ComplianceBurn {
+    pub account_id: AccountId,
+    pub amount: U128,
+    pub reason: String,
+    pub proposed_at: U64,
+    /// Block timestamp from which the burn can be executed.
+    pub executable_at: U64,
+}
+
+impl Contract {
+    fn assert_compliance_burner(&self) {
+        assert_eq!(
+            Some(&env::predecessor_account_id()),
+            self.compliance_burner.as_ref(),
+            "ERR_NOT_ALLOWED"
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account that may burn from blacklisted accounts, or removes the role with `None`.
+    #[payable]
+    pub fn set_compliance_burner(&mut self, burner_id: Option<ValidAccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.compliance_burner = burner_id.map(|a| a.into());
+        events::emit_role_changed("compliance_burner", self.compliance_burner.as_ref());
+    }
+
+    pub fn get_compliance_burner(&self) -> Option<AccountId> {
+        self.compliance_burner.clone()
+    }
+
+    /// Raises the delay between proposing and executing a compliance burn.
+    #[payable]
+    pub fn set_compliance_burn_delay(&mut self, delay: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            compliance_burn_delay: Some(delay),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_compliance_burn_delay(&self) -> U64 {
+        self.config.compliance_burn_delay.into()
+    }
+
+    /// Proposes burning `amount` from the blacklisted `account_id` for `reason`, and returns the
+    /// ID of the proposal. The attached deposit covers the storage; the rest is refunded.
+    #[payable]
+    pub fn propose_compliance_burn(
+        &mut self,
+        account_id: ValidAccountId,
+        amount: U128,
+        reason: String,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_compliance_burner();
+        assert!(
+            self.blacklist.contains(account_id.as_ref()),
+            "ERR_NOT_BLACKLISTED"
+        );
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        assert!(
+            !reason.trim().is_empty() && reason.len() <= MAX_COMPLIANCE_BURN_REASON_LEN,
+            "ERR_INVALID_REASON"
+        );
+        let now = env::block_timestamp();
+        let burn = ComplianceBurn {
+            account_id: account_id.into(),
+            amount,
+            reason,
+            proposed_at: now.into(),
+            executable_at: (now + self.config.compliance_burn_delay).into(),
+        };
+        let burn_id = self.next_compliance_burn_id;
+        self.next_compliance_burn_id += 1;
+        events::emit_event(
+            "compliance_burn_proposed",
+            json!({
+                "burn_id": U64(burn_id),
+                "account_id": burn.account_id,
+                "amount": burn.amount,
+                "reason": burn.reason,
+                "executable_at": burn.executable_at,
+            }),
+        );
+        self.compliance_burns.insert(&burn_id, &burn);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        burn_id.into()
+    }
+
+    /// Executes a proposed burn once its timelock has passed. The account must still be
+    /// blacklisted.
+    #[payable]
+    pub fn execute_compliance_burn(&mut self, burn_id: U64) {
+        assert_one_yocto();
+        self.assert_compliance_burner();
+        let burn = self
+            .compliance_burns
+            .get(&burn_id.0)
+            .expect("ERR_NO_COMPLIANCE_BURN");
+        assert!(
+            env::block_timestamp() >= burn.executable_at.0,
+            "ERR_TIMELOCK_ACTIVE"
+        );
+        assert!(
+            self.blacklist.contains(&burn.account_id),
+            "ERR_NOT_BLACKLISTED"
+        );
+        self.compliance_burns.remove(&burn_id.0);
+        events::emit_event(
+            "compliance_burn_executed",
+            json!({
+                "burn_id": burn_id,
+                "account_id": burn.account_id,
+                "amount": burn.amount,
+            }),
+        );
+        self.internal_burn(
+            &burn.account_id,
+            burn.amount.into(),
+            Some(burn.reason),
+            Some(BurnReason::Compliance),
+        );
+    }
+
+    /// Cancels a proposed burn. Callable by the compliance burner or the owner.
+    #[payable]
+    pub fn cancel_compliance_burn(&mut self, burn_id: U64) {
+        assert_one_yocto();
+        if env::predecessor_account_id() != self.owner_id {
+            self.assert_compliance_burner();
+        }
+        self.compliance_burns
+            .remove(&burn_id.0)
+            .expect("ERR_NO_COMPLIANCE_BURN");
+        events::emit_event("compliance_burn_cancelled", json!({ "burn_id": burn_id }));
+    }
+
+    pub fn get_compliance_burn(&self, burn_id: U64) -> Option<ComplianceBurn> {
+        self.compliance_burns.get(&burn_id.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract_with_balances(10_000, &[(accounts(1), 1_000)]);
+        contract.update_blacklist_batch(vec![accounts(1)], vec![]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_compliance_burner(Some(accounts(3)));
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.propose_compliance_burn(accounts(1), 400.into(), "Case 42".to_string());
+        testing_env!(context.attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_compliance_burn_after_timelock() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .block_timestamp(DEFAULT_COMPLIANCE_BURN_DELAY)
+            .build());
+        contract.execute_compliance_burn(0.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 600);
+        assert!(contract.get_compliance_burn(0.into()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TIMELOCK_ACTIVE")]
+    fn test_compliance_burn_before_timelock() {
+        let (_, mut contract) = setup();
+        contract.execute_compliance_burn(0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_BLACKLISTED")]
+    fn test_compliance_burn_after_unblacklisting() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.update_blacklist_batch(vec![], vec![accounts(1)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .block_timestamp(DEFAULT_COMPLIANCE_BURN_DELAY)
+            .build());
+        contract.execute_compliance_burn(0.into());
+    }
+}
@@ -0,0 +1,176 @@
+//! Composite user actions: `compose` runs several of the caller's actions in one call, e.g.
+//! approve, then stake, then transfer-call, so a flow doesn't need a transaction per step.
+//!
+//! All state changes of the batch are atomic: if any action fails, none takes effect. Receivers
+//! of `transfer_call` actions are called once the batch completes and refunds are resolved
+//! afterwards as usual, since cross-contract calls can't be part of the atomic batch. The
+//! receiver call gets all the remaining gas, so a batch has at most one `transfer_call`, as its
+//! last action.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde::Deserialize;
+
+/// Most actions in a single `compose` call.
+const MAX_COMPOSE_ACTIONS: usize = 10;
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum UserAction {
+    Approve {
+        spender_id: ValidAccountId,
+        amount: U128,
+        expires_at: Option<U64>,
+    },
+    Transfer {
+        receiver_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+    },
+    TransferCall {
+        receiver_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    },
+    #[cfg(feature = "staking")]
+    Stake {
+        amount: U128,
+        lock_tier: Option<u32>,
+    },
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Runs `actions` in order for the predecessor, all or none. The attached deposit covers the
+    /// storage they use; the rest is refunded.
+    #[payable]
+    pub fn compose(&mut self, actions: Vec<UserAction>) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        assert!(
+            !actions.is_empty() && actions.len() <= MAX_COMPOSE_ACTIONS,
+            "ERR_INVALID_ACTION_COUNT"
+        );
+        let account_id = env::predecessor_account_id();
+        let last_index = actions.len() - 1;
+        for (index, action) in actions.into_iter().enumerate() {
+            match action {
+                UserAction::Approve {
+                    spender_id,
+                    amount,
+                    expires_at,
+                } => self.internal_approve(&account_id, spender_id.as_ref(), amount.0, expires_at),
+                UserAction::Transfer {
+                    receiver_id,
+                    amount,
+                    memo,
+                } => {
+                    self.assert_not_paused(PauseSurface::Transfers);
+                    self.internal_holder_transfer(
+                        account_id.clone(),
+                        receiver_id.into(),
+                        amount,
+                        memo,
+                        None,
+                    );
+                }
+                UserAction::TransferCall {
+                    receiver_id,
+                    amount,
+                    memo,
+                    msg,
+                } => {
+                    assert_eq!(index, last_index, "ERR_TRANSFER_CALL_NOT_LAST");
+                    self.assert_not_paused(PauseSurface::Transfers);
+                    self.assert_not_paused(PauseSurface::FtTransferCall);
+                    self.internal_holder_transfer(
+                        account_id.clone(),
+                        receiver_id.into(),
+                        amount,
+                        memo,
+                        Some(msg),
+                    );
+                }
+                #[cfg(feature = "staking")]
+                UserAction::Stake { amount, lock_tier } => {
+                    self.internal_stake(&account_id, amount.into(), lock_tier)
+                }
+            }
+        }
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+}
+
+#[cfg(all(test, feature = "staking"))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_compose() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+        mint_to(&mut contract, accounts(2), 0);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let actions: Vec<UserAction> = near_sdk::serde_json::from_str(
+            r#"[
+                {"approve": {"spender_id": "danny", "amount": "50"}},
+                {"stake": {"amount": "200"}},
+                {"transfer_call": {"receiver_id": "charlie", "amount": "100", "msg": ""}}
+            ]"#,
+        )
+        .unwrap();
+        contract.compose(actions);
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(3)).0, 50);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        assert_eq!(contract.get_stake(accounts(1)).amount.0, 200);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 200);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TRANSFER_CALL_NOT_LAST")]
+    fn test_compose_transfer_call_not_last() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+        mint_to(&mut contract, accounts(2), 0);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let actions: Vec<UserAction> = near_sdk::serde_json::from_str(
+            r#"[
+                {"transfer_call": {"receiver_id": "charlie", "amount": "100", "msg": ""}},
+                {"transfer_call": {"receiver_id": "charlie", "amount": "100", "msg": ""}}
+            ]"#,
+        )
+        .unwrap();
+        contract.compose(actions);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_ACTION_COUNT")]
+    fn test_compose_empty() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.compose(vec![]);
+    }
+}
@@ -0,0 +1,341 @@
+//! Configuration knobs kept together in one versioned `Config`.
+//!
+//! `update_config` applies a partial update in one call, so a DAO can change several parameters
+//! with a single proposal. The per-knob setters are shorthands for single-field updates and go
+//! through the same validation. Every changed field emits its own `config_changed` event.
+
+use crate::clawbacks::DEFAULT_CLAWBACK_NOTICE_PERIOD;
+use crate::compliance_burns::DEFAULT_COMPLIANCE_BURN_DELAY;
+use crate::faucet::FAUCET_COOLDOWN;
+use crate::fees::TransferFee;
+use crate::interest::MAX_INTEREST_RATE_BPS;
+use crate::pause::SurfacePause;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Deserializer};
+
+/// Bumped whenever fields are added to `Config`.
+pub const CONFIG_VERSION: u32 = 12;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Config {
+    /// Smallest amount accepted by holder-initiated transfers.
+    pub min_transfer_amount: Balance,
+    /// Balances below this amount can be swept into the treasury.
+    pub dust_threshold: Balance,
+    /// Annual interest rate in basis points, applied to `share_index`.
+    pub interest_rate_bps: u32,
+    /// Amount handed out per faucet claim, `None` while the faucet is disabled.
+    pub faucet_amount: Option<Balance>,
+    /// Time between two faucet claims of an account, in nanoseconds.
+    pub faucet_cooldown: u64,
+    /// Time between requesting and executing a withdrawal of sale proceeds, in nanoseconds.
+    pub sale_withdrawal_delay: u64,
+    /// Set with `pause`, not through config updates. May include expired pauses.
+    pub pauses: Vec<SurfacePause>,
+    /// Set with `set_emergency_mode`, not through config updates.
+    pub emergency_mode: bool,
+    /// Longest a pause lasts before it expires on its own, unlimited if `None`. Once set, it can
+    /// only be lowered. Changes apply to later pauses.
+    pub max_pause_duration: Option<u64>,
+    /// Contract asked whether each transfer is allowed before it completes, none if `None`.
+    pub compliance_registry: Option<AccountId>,
+    /// Balance above which receivers need a valid KYC attestation, no requirement if `None`.
+    pub kyc_threshold: Option<Balance>,
+    /// Most an account may send within 24 hours unless exempt, unlimited if `None`.
+    pub daily_volume_cap: Option<Balance>,
+    /// How many times a minter may call `mint` per hour, unlimited if `None`.
+    pub max_mints_per_hour: Option<u32>,
+    /// Fee charged on holder transfers and where it goes, none if `None`.
+    pub transfer_fee: Option<TransferFee>,
+    /// Time between proposing and executing a compliance burn, in nanoseconds.
+    pub compliance_burn_delay: u64,
+    /// Time between initiating and executing a clawback, in nanoseconds.
+    pub clawback_notice_period: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            min_transfer_amount: 0,
+            dust_threshold: 0,
+            interest_rate_bps: 0,
+            faucet_amount: None,
+            faucet_cooldown: FAUCET_COOLDOWN,
+            sale_withdrawal_delay: 0,
+            pauses: vec![],
+            emergency_mode: false,
+            max_pause_duration: None,
+            compliance_registry: None,
+            kyc_threshold: None,
+            daily_volume_cap: None,
+            max_mints_per_hour: None,
+            transfer_fee: None,
+            compliance_burn_delay: DEFAULT_COMPLIANCE_BURN_DELAY,
+            clawback_notice_period: DEFAULT_CLAWBACK_NOTICE_PERIOD,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConfigView {
+    pub version: u32,
+    pub min_transfer_amount: U128,
+    pub dust_threshold: U128,
+    pub interest_rate_bps: u32,
+    pub faucet_amount: Option<U128>,
+    pub faucet_cooldown: U64,
+    pub sale_withdrawal_delay: U64,
+    /// Pauses in effect.
+    pub pauses: Vec<SurfacePause>,
+    pub emergency_mode: bool,
+    pub max_pause_duration: Option<U64>,
+    pub compliance_registry: Option<AccountId>,
+    pub kyc_threshold: Option<U128>,
+    pub daily_volume_cap: Option<U128>,
+    pub max_mints_per_hour: Option<u32>,
+    pub transfer_fee: Option<TransferFee>,
+    pub compliance_burn_delay: U64,
+    pub clawback_notice_period: U64,
+}
+
+/// Partial update of the config. Omitted fields are left unchanged; `faucet_amount: null`
+/// disables the faucet, `max_pause_duration: null` lets pauses last until lifted,
+/// `compliance_registry: null` turns off compliance checks, `kyc_threshold: null` turns off the
+/// KYC requirement, `daily_volume_cap: null` and `max_mints_per_hour: null` lift the
+/// respective limits, and `transfer_fee: null` removes the transfer fee.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(deny_unknown_fields)]
+pub struct ConfigUpdate {
+    #[serde(default)]
+    pub min_transfer_amount: Option<U128>,
+    #[serde(default)]
+    pub dust_threshold: Option<U128>,
+    #[serde(default)]
+    pub interest_rate_bps: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub faucet_amount: Option<Option<U128>>,
+    #[serde(default)]
+    pub faucet_cooldown: Option<U64>,
+    /// Can only be raised.
+    #[serde(default)]
+    pub sale_withdrawal_delay: Option<U64>,
+    /// Can only be lowered once set.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_pause_duration: Option<Option<U64>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub compliance_registry: Option<Option<ValidAccountId>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub kyc_threshold: Option<Option<U128>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub daily_volume_cap: Option<Option<U128>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_mints_per_hour: Option<Option<u32>>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub transfer_fee: Option<Option<TransferFee>>,
+    /// Can only be raised.
+    #[serde(default)]
+    pub compliance_burn_delay: Option<U64>,
+    /// Can only be raised.
+    #[serde(default)]
+    pub clawback_notice_period: Option<U64>,
+}
+
+/// Tells an explicit `null` apart from an omitted field.
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl Contract {
+    pub(crate) fn internal_update_config(&mut self, update: ConfigUpdate) {
+        if let Some(amount) = update.min_transfer_amount {
+            self.config.min_transfer_amount = amount.into();
+            events::emit_config_changed("min_transfer_amount", amount);
+        }
+        if let Some(threshold) = update.dust_threshold {
+            self.config.dust_threshold = threshold.into();
+            events::emit_config_changed("dust_threshold", threshold);
+        }
+        if let Some(rate_bps) = update.interest_rate_bps {
+            assert!(rate_bps <= MAX_INTEREST_RATE_BPS, "ERR_RATE_TOO_HIGH");
+            // Interest accrued under the previous rate is settled first.
+            self.internal_accrue_interest();
+            self.config.interest_rate_bps = rate_bps;
+            events::emit_config_changed("interest_rate_bps", rate_bps);
+        }
+        if let Some(amount) = update.faucet_amount {
+            self.config.faucet_amount = amount.map(|amount| amount.into());
+            events::emit_config_changed("faucet_amount", amount);
+        }
+        if let Some(cooldown) = update.faucet_cooldown {
+            self.config.faucet_cooldown = cooldown.into();
+            events::emit_config_changed("faucet_cooldown", cooldown);
+        }
+        if let Some(delay) = update.sale_withdrawal_delay {
+            assert!(
+                delay.0 >= self.config.sale_withdrawal_delay,
+                "ERR_DELAY_DECREASE"
+            );
+            self.config.sale_withdrawal_delay = delay.into();
+            events::emit_config_changed("sale_withdrawal_delay", delay);
+        }
+        if let Some(duration) = update.max_pause_duration {
+            if let Some(current) = self.config.max_pause_duration {
+                assert!(
+                    duration.is_some_and(|duration| duration.0 <= current),
+                    "ERR_PAUSE_DURATION_INCREASE"
+                );
+            }
+            self.config.max_pause_duration = duration.map(|duration| duration.into());
+            events::emit_config_changed("max_pause_duration", duration);
+        }
+        if let Some(registry_id) = update.compliance_registry {
+            self.config.compliance_registry = registry_id.map(|registry_id| registry_id.into());
+            events::emit_config_changed("compliance_registry", &self.config.compliance_registry);
+        }
+        if let Some(threshold) = update.kyc_threshold {
+            self.config.kyc_threshold = threshold.map(|threshold| threshold.into());
+            events::emit_config_changed("kyc_threshold", threshold);
+        }
+        if let Some(cap) = update.daily_volume_cap {
+            self.config.daily_volume_cap = cap.map(|cap| cap.into());
+            events::emit_config_changed("daily_volume_cap", cap);
+        }
+        if let Some(max_calls) = update.max_mints_per_hour {
+            self.config.max_mints_per_hour = max_calls;
+            events::emit_config_changed("max_mints_per_hour", max_calls);
+        }
+        if let Some(transfer_fee) = update.transfer_fee {
+            if let Some(transfer_fee) = transfer_fee.as_ref() {
+                self.internal_prepare_transfer_fee(transfer_fee);
+            }
+            events::emit_config_changed("transfer_fee", &transfer_fee);
+            self.config.transfer_fee = transfer_fee;
+        }
+        if let Some(delay) = update.compliance_burn_delay {
+            assert!(
+                delay.0 >= self.config.compliance_burn_delay,
+                "ERR_DELAY_DECREASE"
+            );
+            self.config.compliance_burn_delay = delay.into();
+            events::emit_config_changed("compliance_burn_delay", delay);
+        }
+        if let Some(period) = update.clawback_notice_period {
+            assert!(
+                period.0 >= self.config.clawback_notice_period,
+                "ERR_DELAY_DECREASE"
+            );
+            self.config.clawback_notice_period = period.into();
+            events::emit_config_changed("clawback_notice_period", period);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_config(&self) -> ConfigView {
+        ConfigView {
+            version: CONFIG_VERSION,
+            min_transfer_amount: self.config.min_transfer_amount.into(),
+            dust_threshold: self.config.dust_threshold.into(),
+            interest_rate_bps: self.config.interest_rate_bps,
+            faucet_amount: self.config.faucet_amount.map(|amount| amount.into()),
+            faucet_cooldown: self.config.faucet_cooldown.into(),
+            sale_withdrawal_delay: self.config.sale_withdrawal_delay.into(),
+            pauses: self.internal_active_pauses(),
+            emergency_mode: self.config.emergency_mode,
+            max_pause_duration: self
+                .config
+                .max_pause_duration
+                .map(|duration| duration.into()),
+            compliance_registry: self.config.compliance_registry.clone(),
+            kyc_threshold: self.config.kyc_threshold.map(|threshold| threshold.into()),
+            daily_volume_cap: self.config.daily_volume_cap.map(|cap| cap.into()),
+            max_mints_per_hour: self.config.max_mints_per_hour,
+            transfer_fee: self.config.transfer_fee.clone(),
+            compliance_burn_delay: self.config.compliance_burn_delay.into(),
+            clawback_notice_period: self.config.clawback_notice_period.into(),
+        }
+    }
+
+    /// Applies all fields set in `update` at once, or none if any of them is invalid.
+    #[payable]
+    pub fn update_config(&mut self, update: ConfigUpdate) -> ConfigView {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(update);
+        self.get_config()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_update_config() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_faucet_amount(Some(10.into()));
+        let update: ConfigUpdate = near_sdk::serde_json::from_str(
+            r#"{"min_transfer_amount": "5", "faucet_amount": null, "sale_withdrawal_delay": "100"}"#,
+        )
+        .unwrap();
+        let config = contract.update_config(update);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.min_transfer_amount.0, 5);
+        assert!(config.faucet_amount.is_none());
+        assert_eq!(config.sale_withdrawal_delay.0, 100);
+        assert_eq!(config.faucet_cooldown.0, FAUCET_COOLDOWN);
+        assert_eq!(contract.get_min_transfer_amount().0, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ALLOWED")]
+    fn test_update_config_not_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.update_config(ConfigUpdate::default());
+    }
+}
@@ -0,0 +1,295 @@
+//! Co-signed transfers: the owner can put a custodial account under a policy naming a second
+//! signer and a threshold. Holder transfers of more than the threshold from that account are
+//! escrowed as pending until the co-signer approves them. The co-signer or the custodial account
+//! can cancel a pending transfer instead, returning it.
+//!
+//! A pending transfer is escrowed with its transfer fee, like transfers held for acceptance, and
+//! once approved it is held for the receiver if the receiver requires acceptance. With a
+//! compliance registry set, transfers wait for the co-signer once the registry allowed them.
+//! `ft_transfer_call` is rejected above the threshold. Transfers only look up the sender's policy
+//! while some account has one.
+
+use crate::acceptance::HeldTransfer;
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+
+pub type CosignedTransferId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CustodyPolicy {
+    pub cosigner_id: AccountId,
+    /// Transfers above this amount need the co-signer's approval.
+    pub threshold: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingCosignView {
+    pub transfer_id: U64,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    pub created_at: U64,
+}
+
+impl Contract {
+    /// Whether a transfer of `amount` from `sender_id` needs the co-signer's approval.
+    pub(crate) fn internal_requires_cosignature(
+        &self,
+        sender_id: &AccountId,
+        amount: Balance,
+    ) -> bool {
+        self.custodial_accounts > 0
+            && self
+                .custody_policies
+                .get(sender_id)
+                .is_some_and(|policy| amount > policy.threshold.0)
+    }
+
+    /// Keeps a transfer whose amount and fee are already escrowed until it is co-signed.
+    pub(crate) fn internal_pend_cosigned_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        fee: Balance,
+        memo: Option<String>,
+    ) {
+        let transfer_id = self.next_cosigned_transfer_id;
+        self.next_cosigned_transfer_id += 1;
+        let mut transfer_ids = self.cosign_by_sender.get(&sender_id).unwrap_or_default();
+        transfer_ids.push(transfer_id);
+        self.cosign_by_sender.insert(&sender_id, &transfer_ids);
+        events::emit_event(
+            "cosignature_required",
+            json!({
+                "transfer_id": U64(transfer_id),
+                "sender_id": sender_id,
+                "receiver_id": receiver_id,
+                "amount": U128(amount),
+            }),
+        );
+        self.cosigned_transfers.insert(
+            &transfer_id,
+            &HeldTransfer {
+                sender_id,
+                receiver_id,
+                amount,
+                fee,
+                memo,
+                held_at: env::block_timestamp(),
+            },
+        );
+    }
+
+    /// Removes a pending transfer, checking that the predecessor is its co-signer, or its sender
+    /// if `allow_sender`.
+    fn internal_take_cosigned_transfer(
+        &mut self,
+        transfer_id: CosignedTransferId,
+        allow_sender: bool,
+    ) -> HeldTransfer {
+        let transfer = self
+            .cosigned_transfers
+            .get(&transfer_id)
+            .expect("ERR_NO_PENDING_TRANSFER");
+        let predecessor_id = env::predecessor_account_id();
+        let is_cosigner = self
+            .custody_policies
+            .get(&transfer.sender_id)
+            .is_some_and(|policy| policy.cosigner_id == predecessor_id);
+        assert!(
+            is_cosigner || (allow_sender && predecessor_id == transfer.sender_id),
+            "ERR_NOT_ALLOWED"
+        );
+        self.cosigned_transfers.remove(&transfer_id);
+        let mut transfer_ids = self
+            .cosign_by_sender
+            .get(&transfer.sender_id)
+            .unwrap_or_default();
+        transfer_ids.retain(|id| *id != transfer_id);
+        if transfer_ids.is_empty() {
+            self.cosign_by_sender.remove(&transfer.sender_id);
+        } else {
+            self.cosign_by_sender
+                .insert(&transfer.sender_id, &transfer_ids);
+        }
+        transfer
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Puts `account_id` under a co-signature policy, or lifts it with `None`. Transfers pending
+    /// when the policy is lifted or changed can still be approved or cancelled by the co-signer
+    /// of the current policy, or cancelled by the account. The attached deposit covers the
+    /// storage of the policy; the rest is refunded.
+    #[payable]
+    pub fn set_custody_policy(
+        &mut self,
+        account_id: ValidAccountId,
+        policy: Option<CustodyPolicy>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        if let Some(policy) = &policy {
+            assert!(
+                env::is_valid_account_id(policy.cosigner_id.as_bytes()),
+                "ERR_INVALID_ACCOUNT"
+            );
+            assert_ne!(
+                &policy.cosigner_id,
+                account_id.as_ref(),
+                "ERR_COSIGNER_IS_ACCOUNT"
+            );
+        }
+        let previous = match &policy {
+            Some(policy) => self.custody_policies.insert(account_id.as_ref(), policy),
+            None => self.custody_policies.remove(account_id.as_ref()),
+        };
+        match (previous.is_some(), policy.is_some()) {
+            (false, true) => self.custodial_accounts += 1,
+            (true, false) => self.custodial_accounts -= 1,
+            _ => {}
+        }
+        events::emit_event(
+            "custody_policy_changed",
+            json!({ "account_id": account_id, "policy": policy }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn get_custody_policy(&self, account_id: ValidAccountId) -> Option<CustodyPolicy> {
+        self.custody_policies.get(account_id.as_ref())
+    }
+
+    /// Approves a pending transfer as its co-signer, paying it out or holding it for the
+    /// receiver's acceptance.
+    #[payable]
+    pub fn cosign_transfer(&mut self, transfer_id: U64) {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        let transfer = self.internal_take_cosigned_transfer(transfer_id.0, false);
+        self.assert_not_blacklisted(&transfer.sender_id);
+        self.assert_not_blacklisted(&transfer.receiver_id);
+        events::emit_event("transfer_cosigned", json!({ "transfer_id": transfer_id }));
+        if self.internal_requires_acceptance(&transfer.receiver_id, transfer.amount) {
+            self.internal_hold_transfer(
+                transfer.sender_id,
+                transfer.receiver_id,
+                transfer.amount,
+                transfer.fee,
+                transfer.memo,
+            );
+        } else {
+            self.internal_complete_escrowed_transfer(transfer);
+        }
+    }
+
+    /// Cancels a pending transfer as its co-signer or sender, returning it to the sender.
+    #[payable]
+    pub fn cancel_cosigned_transfer(&mut self, transfer_id: U64) {
+        assert_one_yocto();
+        let transfer = self.internal_take_cosigned_transfer(transfer_id.0, true);
+        self.internal_escrow_release(&transfer.sender_id, transfer.amount + transfer.fee);
+        events::emit_event(
+            "cosigned_transfer_cancelled",
+            json!({ "transfer_id": transfer_id }),
+        );
+    }
+
+    /// Transfers from `account_id` waiting for the co-signer.
+    pub fn get_pending_cosigned_transfers(
+        &self,
+        account_id: ValidAccountId,
+    ) -> Vec<PendingCosignView> {
+        self.cosign_by_sender
+            .get(account_id.as_ref())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|transfer_id| {
+                self.cosigned_transfers
+                    .get(&transfer_id)
+                    .map(|transfer| PendingCosignView {
+                        transfer_id: transfer_id.into(),
+                        receiver_id: transfer.receiver_id,
+                        amount: transfer.amount.into(),
+                        memo: transfer.memo,
+                        created_at: transfer.held_at.into(),
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+        contract.set_custody_policy(
+            accounts(1),
+            Some(CustodyPolicy {
+                cosigner_id: accounts(3).into(),
+                threshold: 100.into(),
+            }),
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        contract.ft_transfer(accounts(2), 300.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_cosign_transfer() {
+        let (mut context, mut contract) = setup();
+        let pending = contract.get_pending_cosigned_transfers(accounts(1));
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].amount.0, 300);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.cosign_transfer(pending[0].transfer_id);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 400);
+        assert!(contract
+            .get_pending_cosigned_transfers(accounts(1))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_cancel_cosigned_transfer() {
+        let (_, mut contract) = setup();
+        let pending = contract.get_pending_cosigned_transfers(accounts(1));
+        contract.cancel_cosigned_transfer(pending[0].transfer_id);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ALLOWED")]
+    fn test_sender_cannot_cosign() {
+        let (_, mut contract) = setup();
+        let pending = contract.get_pending_cosigned_transfers(accounts(1));
+        contract.cosign_transfer(pending[0].transfer_id);
+    }
+}
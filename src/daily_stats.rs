@@ -0,0 +1,140 @@
+//! Daily transfer analytics: volume and unique senders of holder transfers per UTC day, kept
+//! for the last `DAILY_STATS_DAYS` days.
+//!
+//! Tracking is off by default and turned on by the owner, since counting unique senders reads
+//! and writes the sender's last active day on every transfer. Days older than the window are
+//! deleted as new days start. Transfers are counted like in `get_stats`.
+
+use crate::volume_limit::DAY;
+use crate::*;
+use near_sdk::serde::Serialize;
+
+pub const DAILY_STATS_DAYS: u64 = 90;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct DailyVolume {
+    pub volume: Balance,
+    pub unique_senders: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DailyVolumeView {
+    /// Block timestamp at which the day started.
+    pub day_start: U64,
+    pub volume: U128,
+    pub unique_senders: U64,
+}
+
+impl Contract {
+    pub(crate) fn internal_record_daily_volume(&mut self, sender_id: &AccountId, amount: Balance) {
+        if !self.daily_stats_enabled {
+            return;
+        }
+        let today = env::block_timestamp() / DAY;
+        if today > self.daily_stats_last_day {
+            // Recorded days lie within the window ending at the last recorded day.
+            let expired_from = (self.daily_stats_last_day + 1).saturating_sub(DAILY_STATS_DAYS);
+            let expired_to = std::cmp::min(
+                (today + 1).saturating_sub(DAILY_STATS_DAYS),
+                self.daily_stats_last_day + 1,
+            );
+            for day in expired_from..expired_to {
+                self.daily_volumes.remove(&day);
+            }
+            self.daily_stats_last_day = today;
+        }
+        let mut daily = self.daily_volumes.get(&today).unwrap_or_default();
+        daily.volume = daily.volume.saturating_add(amount);
+        if self.sender_last_day.insert(sender_id, &today) != Some(today) {
+            daily.unique_senders += 1;
+        }
+        self.daily_volumes.insert(&today, &daily);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Turns the daily transfer analytics on or off.
+    #[payable]
+    pub fn set_daily_stats_enabled(&mut self, enabled: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.daily_stats_enabled = enabled;
+        events::emit_config_changed("daily_stats_enabled", enabled);
+    }
+
+    pub fn get_daily_stats_enabled(&self) -> bool {
+        self.daily_stats_enabled
+    }
+
+    /// Volume and unique senders of the last `days` days, today first, at most
+    /// `DAILY_STATS_DAYS`. Days without transfers are included with zeros.
+    pub fn get_daily_volumes(&self, days: u32) -> Vec<DailyVolumeView> {
+        let today = env::block_timestamp() / DAY;
+        let days = std::cmp::min(days as u64, DAILY_STATS_DAYS);
+        (0..std::cmp::min(days, today + 1))
+            .map(|offset| {
+                let day = today - offset;
+                let daily = self.daily_volumes.get(&day).unwrap_or_default();
+                DailyVolumeView {
+                    day_start: (day * DAY).into(),
+                    volume: daily.volume.into(),
+                    unique_senders: daily.unique_senders.into(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_daily_volumes() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(
+            1_000,
+            &[(accounts(1), 500), (accounts(2), 300), (accounts(3), 0)],
+        );
+        contract.set_daily_stats_enabled(true);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(3), 100.into(), None);
+        testing_env!(context.block_timestamp(DAY).build());
+        contract.ft_transfer(accounts(3), 50.into(), None);
+        contract.ft_transfer(accounts(3), 25.into(), None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer(accounts(3), 10.into(), None);
+
+        let days = contract.get_daily_volumes(7);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].day_start.0, DAY);
+        assert_eq!(days[0].volume.0, 85);
+        assert_eq!(days[0].unique_senders.0, 2);
+        assert_eq!(days[1].volume.0, 100);
+        assert_eq!(days[1].unique_senders.0, 1);
+    }
+
+    #[test]
+    fn test_daily_volumes_expire() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+        contract.set_daily_stats_enabled(true);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        testing_env!(context.block_timestamp(DAILY_STATS_DAYS * DAY).build());
+        contract.ft_transfer(accounts(2), 50.into(), None);
+        assert!(contract.daily_volumes.get(&0).is_none());
+    }
+}
@@ -0,0 +1,180 @@
+//! Optional demurrage: balances lose a configurable annual rate of their value to a community
+//! pool, rewarding circulation over hoarding.
+//!
+//! The decay is settled lazily per account: whenever an account's balance changes, the shares
+//! that decayed since its last settlement move to the pool first, and `settle_demurrage` lets
+//! anyone settle a dormant account. Balance views already show the decayed balance, but the
+//! pool's balance only grows as accounts settle. The decay of an account is computed at the
+//! current rate for the whole time since its last settlement, or since demurrage was turned on.
+//! The pool and the contract's own escrow don't decay.
+
+use crate::interest::{BPS_DENOMINATOR, NANOSECONDS_PER_YEAR};
+use crate::utils::mul_div;
+use crate::*;
+use near_sdk::serde_json::json;
+
+pub(crate) const MAX_DEMURRAGE_RATE_BPS: u32 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DemurrageConfig {
+    /// Annual decay rate in basis points.
+    pub rate_bps: u32,
+    pub pool_id: AccountId,
+}
+
+impl Contract {
+    /// Shares of `account_id`, holding `shares`, that decayed since its last settlement.
+    pub(crate) fn demurrage_due(&self, account_id: &AccountId, shares: Balance) -> Balance {
+        let demurrage = match &self.demurrage {
+            Some(demurrage) => demurrage,
+            None => return 0,
+        };
+        if shares == 0
+            || *account_id == demurrage.pool_id
+            || *account_id == env::current_account_id()
+        {
+            return 0;
+        }
+        // Settlements from before demurrage was last turned on don't count.
+        let settled_at = self
+            .demurrage_settled_at
+            .get(account_id)
+            .map_or(self.demurrage_since, |settled_at| {
+                std::cmp::max(settled_at, self.demurrage_since)
+            });
+        let elapsed = env::block_timestamp().saturating_sub(settled_at);
+        let due = mul_div(
+            shares,
+            demurrage.rate_bps as u128 * elapsed as u128,
+            BPS_DENOMINATOR * NANOSECONDS_PER_YEAR,
+            false,
+        );
+        std::cmp::min(due, shares)
+    }
+
+    /// Moves the decayed shares of `account_id` to the pool. Must run before anything changes
+    /// the account's shares, so that new tokens don't decay retroactively.
+    pub(crate) fn internal_settle_demurrage(&mut self, account_id: &AccountId) {
+        let pool_id = match &self.demurrage {
+            Some(demurrage) => demurrage.pool_id.clone(),
+            None => return,
+        };
+        let shares = match self.token.accounts.get(account_id) {
+            Some(shares) => shares,
+            None => return,
+        };
+        if *account_id == pool_id || *account_id == env::current_account_id() {
+            return;
+        }
+        let due = self.demurrage_due(account_id, shares);
+        // The clock keeps running while the decay rounds down to nothing, so frequent small
+        // interactions don't avoid it.
+        if due == 0 && shares > 0 {
+            return;
+        }
+        self.demurrage_settled_at
+            .insert(account_id, &env::block_timestamp());
+        if due > 0 {
+            self.internal_withdraw_shares(account_id, due);
+            self.internal_deposit_shares(&pool_id, due);
+            events::emit_event(
+                "demurrage_settled",
+                json!({
+                    "account_id": account_id,
+                    "amount": U128(self.shares_to_amount(due)),
+                }),
+            );
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Turns demurrage on, changes its rate or pool, or turns it off with `None`. Changes apply
+    /// to the decay not settled yet. The pool must be registered.
+    #[payable]
+    pub fn set_demurrage(&mut self, demurrage: Option<DemurrageConfig>) {
+        assert_one_yocto();
+        self.assert_owner();
+        if let Some(demurrage) = &demurrage {
+            assert!(
+                demurrage.rate_bps <= MAX_DEMURRAGE_RATE_BPS,
+                "ERR_RATE_TOO_HIGH"
+            );
+            assert!(
+                self.token.accounts.contains_key(&demurrage.pool_id),
+                "ERR_POOL_NOT_REGISTERED"
+            );
+        }
+        if self.demurrage.is_none() {
+            self.demurrage_since = env::block_timestamp();
+        }
+        self.demurrage = demurrage;
+        events::emit_config_changed("demurrage", &self.demurrage);
+    }
+
+    pub fn get_demurrage(&self) -> Option<DemurrageConfig> {
+        self.demurrage.clone()
+    }
+
+    /// Moves the decay of `account_id` to the pool.
+    pub fn settle_demurrage(&mut self, account_id: ValidAccountId) {
+        self.internal_settle_demurrage(account_id.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_demurrage_settles_on_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(
+            10_000,
+            &[(accounts(1), 1_000), (accounts(2), 0), (accounts(3), 0)],
+        );
+        contract.set_demurrage(Some(DemurrageConfig {
+            rate_bps: 1_000,
+            pool_id: accounts(3).into(),
+        }));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(NANOSECONDS_PER_YEAR as u64 / 2)
+            .build());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 950);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 0);
+        contract.ft_transfer(accounts(2), 450.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 500);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 450);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 50);
+    }
+
+    #[test]
+    fn test_settle_dormant_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(10_000, &[(accounts(1), 1_000), (accounts(3), 0)]);
+        contract.set_demurrage(Some(DemurrageConfig {
+            rate_bps: 10_000,
+            pool_id: accounts(3).into(),
+        }));
+
+        testing_env!(context
+            .block_timestamp(NANOSECONDS_PER_YEAR as u64 * 2)
+            .build());
+        contract.settle_demurrage(accounts(1));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, 1_000);
+    }
+}
@@ -0,0 +1,124 @@
+//! Sweeping dust balances into the treasury, to keep the holder set meaningful.
+//!
+//! Only accounts that opted in with `set_dust_sweep_consent` can be swept. Dust left behind by
+//! a forced `storage_unregister` goes to the treasury as well instead of being burned.
+
+use crate::config::ConfigUpdate;
+use crate::*;
+
+impl Contract {
+    /// Credits the balance of a force-unregistered account to the treasury if it's dust.
+    /// `balance` is in shares.
+    pub(crate) fn internal_sweep_closed_dust(&mut self, balance: Balance) {
+        let amount = self.shares_to_amount(balance);
+        if let Some(treasury_id) = self.treasury_id.clone() {
+            if amount > 0
+                && amount < self.config.dust_threshold
+                && self.token.accounts.contains_key(&treasury_id)
+            {
+                self.internal_deposit_shares(&treasury_id, balance);
+                log!("Swept {} of dust to {}", amount, treasury_id);
+            }
+        }
+    }
+
+    /// Moves the balance of `account_id` to the treasury if it's dust and the account
+    /// consented. Returns the swept amount.
+    pub(crate) fn internal_sweep_dust(
+        &mut self,
+        account_id: &AccountId,
+        treasury_id: &AccountId,
+    ) -> Balance {
+        if account_id == treasury_id || !self.dust_sweep_consents.contains(account_id) {
+            return 0;
+        }
+        let balance = self.internal_balance_of(account_id);
+        if balance == 0 || balance >= self.config.dust_threshold {
+            return 0;
+        }
+        let shares = self.token.accounts.get(account_id).unwrap();
+        self.internal_withdraw_shares(account_id, shares);
+        self.internal_deposit_shares(treasury_id, shares);
+        balance
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Balances strictly below the threshold count as dust. 0 disables sweeping.
+    #[payable]
+    pub fn set_dust_threshold(&mut self, threshold: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            dust_threshold: Some(threshold),
+            ..Default::default()
+        });
+    }
+
+    /// Allows or forbids the owner to sweep the predecessor's dust balance.
+    pub fn set_dust_sweep_consent(&mut self, consent: bool) {
+        let account_id = env::predecessor_account_id();
+        if consent {
+            self.dust_sweep_consents.insert(&account_id);
+        } else {
+            self.dust_sweep_consents.remove(&account_id);
+        }
+    }
+
+    /// Moves the dust balances of the given consenting accounts to the treasury. Accounts
+    /// without consent or above the threshold are skipped. Returns the swept total.
+    #[payable]
+    pub fn sweep_dust(&mut self, account_ids: Vec<ValidAccountId>) -> U128 {
+        assert_one_yocto();
+        self.assert_owner();
+        let treasury_id = self.treasury_id.clone().expect("ERR_NO_TREASURY");
+        let mut swept: Balance = 0;
+        for account_id in account_ids {
+            swept += self.internal_sweep_dust(account_id.as_ref(), &treasury_id);
+        }
+        log!("Swept {} of dust to {}", swept, treasury_id);
+        swept.into()
+    }
+
+    pub fn get_dust_threshold(&self) -> U128 {
+        self.config.dust_threshold.into()
+    }
+
+    pub fn has_dust_sweep_consent(&self, account_id: ValidAccountId) -> bool {
+        self.dust_sweep_consents.contains(account_id.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_sweep_dust() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 5);
+        mint_to(&mut contract, accounts(2), 5);
+        mint_to(&mut contract, accounts(3), 50);
+        mint_to(&mut contract, accounts(4), 0);
+        contract.set_treasury(accounts(4));
+        contract.set_dust_threshold(10.into());
+
+        for account_id in [accounts(1), accounts(3)] {
+            testing_env!(context.predecessor_account_id(account_id).build());
+            contract.set_dust_sweep_consent(true);
+        }
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let swept = contract.sweep_dust(vec![accounts(1), accounts(2), accounts(3)]);
+        assert_eq!(swept.0, 5);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 5);
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 5);
+    }
+}
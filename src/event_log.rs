@@ -0,0 +1,154 @@
+//! Replay buffer of the latest `EVENT_LOG_SIZE` `near_ft` events, so light integrations can catch
+//! up on events they missed without running an indexer. The NEP-141 `ft_mint` and `ft_burn`
+//! events aren't buffered: every token indexer tracks them already, and checking the buffer on
+//! each mint and burn would cost them a storage read.
+//!
+//! The owner turns the buffer on, as every buffered event is a storage write paid by the
+//! contract. Every event gets the next sequence number, starting at 0, and overwrites the slot of
+//! the event `EVENT_LOG_SIZE` before it, so the buffer never takes more than `EVENT_LOG_SIZE`
+//! slots of storage. The events are written from `events::emit`, which has no access to the
+//! contract, so the buffer keeps its state under its own storage keys instead of the contract
+//! state.
+
+use crate::*;
+use near_sdk::serde_json::Value;
+
+pub const EVENT_LOG_SIZE: u64 = 100;
+const MAX_EVENTS_PER_PAGE: u64 = 50;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct EventLogHeader {
+    pub enabled: bool,
+    pub next_seq: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LoggedEvent {
+    pub seq: u64,
+    pub block_height: u64,
+    pub timestamp: u64,
+    /// The NEP-297 event object as logged.
+    pub json: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LoggedEventView {
+    pub seq: U64,
+    pub block_height: U64,
+    pub timestamp: U64,
+    pub event: Value,
+}
+
+fn header() -> LazyOption<EventLogHeader> {
+    LazyOption::new(StorageKey::EventLogHeader, None)
+}
+
+fn slots() -> LookupMap<u64, LoggedEvent> {
+    LookupMap::new(StorageKey::EventLog)
+}
+
+/// Appends an event, given as its logged JSON, to the buffer if it's enabled.
+pub(crate) fn record(json: String) {
+    let mut header = header();
+    let mut state = match header.get() {
+        Some(state) if state.enabled => state,
+        _ => return,
+    };
+    let seq = state.next_seq;
+    state.next_seq += 1;
+    header.set(&state);
+    slots().insert(
+        &(seq % EVENT_LOG_SIZE),
+        &LoggedEvent {
+            seq,
+            block_height: env::block_index(),
+            timestamp: env::block_timestamp(),
+            json,
+        },
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Starts or stops buffering events. Sequence numbers continue where they stopped.
+    #[payable]
+    pub fn set_event_log_enabled(&mut self, enabled: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        let mut header = header();
+        let mut state = header.get().unwrap_or_default();
+        state.enabled = enabled;
+        header.set(&state);
+        events::emit_config_changed("event_log_enabled", enabled);
+    }
+
+    pub fn get_event_log_enabled(&self) -> bool {
+        header().get().is_some_and(|state| state.enabled)
+    }
+
+    /// Buffered events from `from_seq` on, oldest first. Starts at the oldest buffered event if
+    /// `from_seq` was already overwritten, so a first `seq` above `from_seq` means events were
+    /// missed.
+    pub fn get_events(&self, from_seq: U64, limit: u64) -> Vec<LoggedEventView> {
+        let next_seq = header().get().unwrap_or_default().next_seq;
+        let from_seq = std::cmp::max(from_seq.0, next_seq.saturating_sub(EVENT_LOG_SIZE));
+        let limit = std::cmp::min(limit, MAX_EVENTS_PER_PAGE);
+        let slots = slots();
+        (from_seq..std::cmp::min(next_seq, from_seq.saturating_add(limit)))
+            .filter_map(|seq| slots.get(&(seq % EVENT_LOG_SIZE)))
+            .map(|event| LoggedEventView {
+                seq: event.seq.into(),
+                block_height: event.block_height.into(),
+                timestamp: event.timestamp.into(),
+                event: near_sdk::serde_json::from_str(&event.json).unwrap_or(Value::Null),
+            })
+            .collect()
+    }
+
+    /// Sequence number the next event will get.
+    pub fn get_next_event_seq(&self) -> U64 {
+        header().get().unwrap_or_default().next_seq.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_get_events() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract(1_000);
+        contract.set_event_log_enabled(true);
+        let first_seq = contract.get_next_event_seq().0;
+        contract.change_max_supply(2_000);
+        contract.change_max_supply(3_000);
+        let events = contract.get_events(first_seq.into(), 10);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].seq.0, first_seq);
+        assert_eq!(events[1].event["event"], "max_supply_changed");
+        assert_eq!(events[1].event["data"][0]["new_max_supply"], "3000");
+    }
+
+    #[test]
+    fn test_events_overwritten() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_event_log_enabled(true);
+        for max_supply in 0..EVENT_LOG_SIZE + 5 {
+            // A fresh context per call keeps the log count under the limit.
+            testing_env!(context.build());
+            contract.change_max_supply(1_000 + max_supply as u128);
+        }
+        let next_seq = contract.get_next_event_seq().0;
+        let events = contract.get_events(0.into(), 10);
+        assert_eq!(events[0].seq.0, next_seq - EVENT_LOG_SIZE);
+        assert_eq!(events.len(), 10);
+    }
+}
@@ -0,0 +1,163 @@
+//! Standardized JSON events in the NEP-297 format, e.g.
+//! `EVENT_JSON:{"standard":"near_ft","version":"1.0.0","event":"max_supply_changed","data":[...]}`,
+//! so monitoring systems can alert on admin actions. The latest `near_ft` events are also kept in
+//! the replay buffer of `event_log`.
+
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::{json, Value};
+use near_sdk::{log, AccountId, Balance};
+
+pub const EVENT_STANDARD: &str = "near_ft";
+pub const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+/// NEP-141 events, as defined by the fungible token events standard.
+pub const NEP141_STANDARD: &str = "nep141";
+pub const NEP141_STANDARD_VERSION: &str = "1.0.0";
+
+/// Why tokens were burned, included in `ft_burn` events for supply analytics.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum BurnReason {
+    AllocationExpired,
+    Bridge,
+    Compliance,
+    Redemption,
+    TransferFee,
+}
+
+fn emit(standard: &str, version: &str, event: &str, data: Value) -> String {
+    let json = json!({
+        "standard": standard,
+        "version": version,
+        "event": event,
+        "data": [data],
+    })
+    .to_string();
+    log!("EVENT_JSON:{}", json);
+    json
+}
+
+pub(crate) fn emit_event<T: Serialize>(event: &str, data: T) {
+    let json = emit(EVENT_STANDARD, EVENT_STANDARD_VERSION, event, json!(data));
+    crate::event_log::record(json);
+}
+
+pub(crate) fn emit_ft_mint(owner_id: &AccountId, amount: Balance, memo: Option<&str>) {
+    emit(
+        NEP141_STANDARD,
+        NEP141_STANDARD_VERSION,
+        "ft_mint",
+        json!({ "owner_id": owner_id, "amount": U128(amount), "memo": memo }),
+    );
+}
+
+pub(crate) fn emit_ft_burn(
+    owner_id: &AccountId,
+    amount: Balance,
+    memo: Option<&str>,
+    reason: Option<BurnReason>,
+) {
+    emit(
+        NEP141_STANDARD,
+        NEP141_STANDARD_VERSION,
+        "ft_burn",
+        json!({
+            "owner_id": owner_id,
+            "amount": U128(amount),
+            "memo": memo,
+            "reason": reason,
+        }),
+    );
+}
+
+pub(crate) fn emit_ownership_transferred(old_owner_id: &AccountId, new_owner_id: &AccountId) {
+    emit_event(
+        "ownership_transferred",
+        json!({ "old_owner_id": old_owner_id, "new_owner_id": new_owner_id }),
+    );
+}
+
+pub(crate) fn emit_max_supply_changed(old_max_supply: Balance, new_max_supply: Balance) {
+    emit_event(
+        "max_supply_changed",
+        json!({
+            "old_max_supply": U128(old_max_supply),
+            "new_max_supply": U128(new_max_supply),
+        }),
+    );
+}
+
+/// A privileged account was assigned to `role`, or the role was cleared.
+pub(crate) fn emit_role_changed(role: &str, account_id: Option<&AccountId>) {
+    emit_event(
+        "role_changed",
+        json!({ "role": role, "account_id": account_id }),
+    );
+}
+
+/// A configuration knob was set to `value`.
+pub(crate) fn emit_config_changed<T: Serialize>(key: &str, value: T) {
+    emit_event("config_changed", json!({ "key": key, "value": value }));
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+    use crate::*;
+
+    #[test]
+    fn test_admin_events() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract(1_000);
+        contract.change_max_supply(2_000);
+        contract.set_owner(accounts(1).into());
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"near_ft","version":"1.0.0","event":"max_supply_changed","data":[{"old_max_supply":"1000","new_max_supply":"2000"}]}"#,
+                r#"EVENT_JSON:{"standard":"near_ft","version":"1.0.0","event":"ownership_transferred","data":[{"old_owner_id":"alice","new_owner_id":"bob"}]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mint_event() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract(1_000);
+        contract.internal_ensure_registered(&accounts(1).into());
+        contract.mint(
+            accounts(1),
+            100.into(),
+            Some("bridge-tx-1".to_string()),
+            1.into(),
+        );
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_mint","data":[{"owner_id":"bob","amount":"100","memo":"bridge-tx-1"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_burn_event() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 100);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.self_burn(
+            40.into(),
+            Some("ticket-7".to_string()),
+            Some(BurnReason::Redemption),
+        );
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 60);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_burn","data":[{"owner_id":"bob","amount":"40","memo":"ticket-7","reason":"redemption"}]}"#
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Expiring mints: promotional tokens minted with use-it-or-lose-it terms. The recipient must
+//! activate them within a number of days, either by moving any tokens out or by calling
+//! `activate_allocation`; after that the owner can take them back with `reclaim_allocation`,
+//! returning them to the owner or burning them.
+//!
+//! An account has at most one pending allocation: minting more adds to it and moves its deadline
+//! to the later one. Reclaiming takes at most the account's balance. The owner pays for the
+//! storage of allocations. Transfers only look up the sender's allocation while some are
+//! pending.
+
+use crate::events::BurnReason;
+use crate::utils::refund_deposit;
+use crate::volume_limit::DAY;
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Allocation {
+    pub amount: Balance,
+    /// Block timestamp after which the owner can reclaim the allocation.
+    pub expires_at: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllocationView {
+    pub amount: U128,
+    pub expires_at: U64,
+    pub expired: bool,
+}
+
+impl Contract {
+    /// Activates the pending allocation of `account_id`, if any.
+    pub(crate) fn internal_activate_allocation(&mut self, account_id: &AccountId) {
+        if self.pending_allocations == 0 {
+            return;
+        }
+        if let Some(allocation) = self.allocations.remove(account_id) {
+            self.pending_allocations -= 1;
+            events::emit_event(
+                "allocation_activated",
+                json!({ "account_id": account_id, "amount": U128(allocation.amount) }),
+            );
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Mints `amount` to `account_id`, registering it if needed, to be activated within
+    /// `activate_within_days`. The attached deposit covers the storage; the rest is refunded.
+    #[payable]
+    pub fn mint_expiring(
+        &mut self,
+        account_id: ValidAccountId,
+        amount: U128,
+        activate_within_days: u32,
+        memo: Option<String>,
+        nonce: U64,
+    ) -> MintReceipt {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_owner();
+        assert!(activate_within_days > 0, "ERR_INVALID_PERIOD");
+        self.internal_ensure_registered(account_id.as_ref());
+        let receipt = self.mint(account_id.clone(), amount, memo, nonce);
+        let expires_at = env::block_timestamp() + activate_within_days as u64 * DAY;
+        let allocation = match self.allocations.get(account_id.as_ref()) {
+            Some(allocation) => Allocation {
+                amount: allocation.amount + amount.0,
+                expires_at: std::cmp::max(allocation.expires_at, expires_at),
+            },
+            None => {
+                self.pending_allocations += 1;
+                Allocation {
+                    amount: amount.into(),
+                    expires_at,
+                }
+            }
+        };
+        self.allocations.insert(account_id.as_ref(), &allocation);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        receipt
+    }
+
+    /// Activates the predecessor's pending allocation, keeping the tokens for good.
+    #[payable]
+    pub fn activate_allocation(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.allocations.contains_key(&account_id),
+            "ERR_NO_ALLOCATION"
+        );
+        self.internal_activate_allocation(&account_id);
+    }
+
+    /// Takes back the expired allocation of `account_id`, burning it or returning it to the
+    /// owner. Returns the reclaimed amount.
+    #[payable]
+    pub fn reclaim_allocation(&mut self, account_id: ValidAccountId, burn: bool) -> U128 {
+        assert_one_yocto();
+        self.assert_owner();
+        let account_id: AccountId = account_id.into();
+        let allocation = self
+            .allocations
+            .get(&account_id)
+            .expect("ERR_NO_ALLOCATION");
+        assert!(
+            env::block_timestamp() >= allocation.expires_at,
+            "ERR_ALLOCATION_NOT_EXPIRED"
+        );
+        self.allocations.remove(&account_id);
+        self.pending_allocations -= 1;
+        let amount = std::cmp::min(allocation.amount, self.internal_balance_of(&account_id));
+        if amount > 0 {
+            if burn {
+                self.internal_burn(
+                    &account_id,
+                    amount,
+                    None,
+                    Some(BurnReason::AllocationExpired),
+                );
+            } else {
+                let owner_id = self.owner_id.clone();
+                self.internal_transfer(&account_id, &owner_id, amount, None);
+            }
+        }
+        events::emit_event(
+            "allocation_reclaimed",
+            json!({ "account_id": account_id, "amount": U128(amount), "burned": burn }),
+        );
+        amount.into()
+    }
+
+    pub fn get_allocation(&self, account_id: ValidAccountId) -> Option<AllocationView> {
+        self.allocations
+            .get(account_id.as_ref())
+            .map(|allocation| AllocationView {
+                amount: allocation.amount.into(),
+                expires_at: allocation.expires_at.into(),
+                expired: env::block_timestamp() >= allocation.expires_at,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (near_sdk::test_utils::VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(2), 0)]);
+        let nonce = next_mint_nonce(&contract);
+        contract.mint_expiring(accounts(1), 300.into(), 7, None, nonce);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_reclaim_expired_allocation() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).block_timestamp(7 * DAY).build());
+        assert!(contract.get_allocation(accounts(1)).unwrap().expired);
+        assert_eq!(contract.reclaim_allocation(accounts(1), true).0, 300);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_total_supply().0, 0);
+        assert!(contract.get_allocation(accounts(1)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_ALLOCATION")]
+    fn test_transfer_activates_allocation() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        assert!(contract.get_allocation(accounts(1)).is_none());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .block_timestamp(7 * DAY)
+            .build());
+        contract.reclaim_allocation(accounts(1), false);
+    }
+}
@@ -0,0 +1,179 @@
+//! Paginated state export for off-chain reconciliation.
+//!
+//! Pages walk `account_index` in account ID order and the cursor is the last exported account
+//! ID, so accounts registered or removed between calls don't shift the pages.
+//!
+//! `get_accounts` pages by position instead, in the same order, for auditors reconciling the
+//! supply against a snapshot. Positions shift when accounts register or leave between calls,
+//! and reaching a position walks all accounts before it, so `export_state` suits large sets.
+
+use crate::*;
+use near_contract_standards::storage_management::{StorageBalance, StorageManagement};
+use near_sdk::json_types::U64;
+use near_sdk::serde::Serialize;
+use std::convert::TryFrom;
+
+const MAX_EXPORT_LIMIT: u64 = 500;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountExport {
+    pub account_id: AccountId,
+    pub balance: U128,
+    pub shares: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountAudit {
+    pub account_id: AccountId,
+    pub balance: U128,
+    pub shares: U128,
+    /// NEP-145 storage balance of the account.
+    pub storage_balance: Option<StorageBalance>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StateExport {
+    pub block_height: U64,
+    pub owner_id: AccountId,
+    pub treasury_id: Option<AccountId>,
+    pub max_supply: U128,
+    pub total_supply: U128,
+    pub total_shares: U128,
+    pub share_index: U128,
+    pub accounts: Vec<AccountExport>,
+    /// Pass as `from_key` to get the next page. `None` on the last page.
+    pub next_key: Option<AccountId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Exports up to `limit` accounts with an ID strictly greater than `from_key`, along with
+    /// the token configuration.
+    pub fn export_state(&self, from_key: Option<AccountId>, limit: U64) -> StateExport {
+        self.assert_enumerable();
+        let limit = std::cmp::min(limit.0, MAX_EXPORT_LIMIT) as usize;
+        let entries: Box<dyn Iterator<Item = (AccountId, ())>> = match from_key {
+            Some(from_key) => Box::new(self.account_index.iter_from(from_key)),
+            None => Box::new(self.account_index.iter()),
+        };
+        let account_ids: Vec<AccountId> = entries
+            .take(limit)
+            .map(|(account_id, _)| account_id)
+            .collect();
+        let next_key = account_ids
+            .last()
+            .filter(|account_id| self.account_index.higher(account_id).is_some())
+            .cloned();
+        StateExport {
+            block_height: env::block_index().into(),
+            owner_id: self.owner_id.clone(),
+            treasury_id: self.treasury_id.clone(),
+            max_supply: self.max_supply.into(),
+            total_supply: self.internal_total_supply().into(),
+            total_shares: self.token.total_supply.into(),
+            share_index: self.effective_share_index().into(),
+            accounts: account_ids
+                .into_iter()
+                .map(|account_id| {
+                    let shares = self.token.accounts.get(&account_id).unwrap_or(0);
+                    AccountExport {
+                        balance: self.shares_to_amount(shares).into(),
+                        shares: shares.into(),
+                        account_id,
+                    }
+                })
+                .collect(),
+            next_key,
+        }
+    }
+
+    /// Returns up to `limit` registered accounts in account ID order, starting with the
+    /// `from_index`th.
+    pub fn get_accounts(&self, from_index: U64, limit: U64) -> Vec<AccountAudit> {
+        self.assert_enumerable();
+        let limit = std::cmp::min(limit.0, MAX_EXPORT_LIMIT) as usize;
+        self.account_index
+            .iter()
+            .skip(from_index.0 as usize)
+            .take(limit)
+            .map(|(account_id, _)| {
+                let shares = self.token.accounts.get(&account_id).unwrap_or(0);
+                AccountAudit {
+                    balance: self.shares_to_amount(shares).into(),
+                    shares: shares.into(),
+                    storage_balance: self
+                        .storage_balance_of(ValidAccountId::try_from(account_id.clone()).unwrap()),
+                    account_id,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+    use crate::InitOptions;
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::storage_management::StorageManagement;
+
+    #[test]
+    fn test_export_pages() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract(1_000);
+        for i in 1..5 {
+            mint_to(&mut contract, accounts(i), i as u128 * 10);
+        }
+        let page = contract.export_state(None, 3.into());
+        assert_eq!(page.accounts.len(), 3);
+        assert_eq!(page.accounts[0].account_id, accounts(1).to_string());
+        let page = contract.export_state(page.next_key, 3.into());
+        assert_eq!(page.accounts.len(), 1);
+        assert_eq!(page.accounts[0].balance.0, 40);
+        assert!(page.next_key.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENUMERABLE")]
+    fn test_export_not_enumerable() {
+        testing_env!(get_context(accounts(0)).build());
+        let enumerable = new_contract(1_000).storage_balance_bounds().min.0;
+        let mut contract = new_contract_with_options(
+            1_000,
+            InitOptions {
+                enumerable_accounts: false,
+                ..Default::default()
+            },
+        );
+        assert!(contract.storage_balance_bounds().min.0 < enumerable);
+        mint_to(&mut contract, accounts(1), 10);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10);
+        contract.export_state(None, 10.into());
+    }
+
+    #[test]
+    fn test_get_accounts() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract(1_000);
+        for i in 1..5 {
+            mint_to(&mut contract, accounts(i), i as u128 * 10);
+        }
+        let page = contract.get_accounts(1.into(), 2.into());
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].account_id, accounts(2).to_string());
+        assert_eq!(page[1].balance.0, 30);
+        assert!(page[0].storage_balance.is_some());
+        let total: u128 = contract
+            .get_accounts(0.into(), 10.into())
+            .iter()
+            .map(|account| account.balance.0)
+            .sum();
+        assert_eq!(total, contract.ft_total_supply().0);
+    }
+}
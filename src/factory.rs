@@ -0,0 +1,308 @@
+//! Token factory: deploys this contract's wasm as a new token on a subaccount of the factory.
+//!
+//! The owner uploads the wasm once with `set_token_code`, since a contract can't read its own
+//! code. `create_token` then creates `<prefix>.<factory>`, deploys the code and initializes it
+//! with the caller as owner. The caller pays for the new account's storage out of the attached
+//! deposit; if the deployment fails, the callback refunds it.
+//!
+//! Tokens created successfully are added to a registry with their creator, a summary of their
+//! metadata and the creation time, so wallets can discover them. The deposit also covers the
+//! registry entry.
+
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::{Base64VecU8, U64};
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json::json;
+use near_sdk::{ext_contract, Gas, Promise, PromiseResult};
+
+/// Storage reserved for the state of a new token on top of its code.
+const TOKEN_STATE_STORAGE: u64 = 20_000;
+const GAS_FOR_TOKEN_INIT: Gas = 50_000_000_000_000;
+const GAS_FOR_ON_TOKEN_CREATED: Gas = 10_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+/// Storage reserved for a registry entry on the factory.
+const REGISTRY_ENTRY_STORAGE: u64 = 500;
+const MAX_TOKENS_LIMIT: u64 = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenSummary {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RegisteredToken {
+    pub token_id: AccountId,
+    pub creator_id: AccountId,
+    pub summary: TokenSummary,
+    pub created_at: U64,
+}
+
+/// Arguments of `new` on the created token.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct TokenInitArgs<'a> {
+    owner_id: &'a AccountId,
+    metadata: &'a FungibleTokenMetadata,
+    max_supply: Balance,
+}
+
+#[ext_contract(ext_self)]
+trait FactoryCallbacks {
+    fn on_token_created(
+        &mut self,
+        creator_id: AccountId,
+        token_id: AccountId,
+        summary: TokenSummary,
+        deposit: U128,
+    ) -> bool;
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Stores the wasm deployed by `create_token`. The attached deposit covers its storage.
+    #[payable]
+    pub fn set_token_code(&mut self, code: Base64VecU8) {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_owner();
+        let code: Vec<u8> = code.into();
+        assert!(!code.is_empty(), "ERR_EMPTY_CODE");
+        self.token_code.set(&code);
+        events::emit_config_changed("token_code_hash", hex_hash(&env::sha256(&code)));
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Deploys a token on `<prefix>.<factory>` owned by the predecessor. The attached deposit
+    /// must cover the storage of the code, the token's state and its registry entry; the rest
+    /// is refunded.
+    #[payable]
+    pub fn create_token(
+        &mut self,
+        prefix: String,
+        metadata: FungibleTokenMetadata,
+        max_supply: U128,
+    ) -> Promise {
+        assert!(
+            !prefix.is_empty() && !prefix.contains('.'),
+            "ERR_INVALID_PREFIX"
+        );
+        let token_id = format!("{}.{}", prefix, env::current_account_id());
+        assert!(
+            env::is_valid_account_id(token_id.as_bytes()),
+            "ERR_INVALID_PREFIX"
+        );
+        metadata.assert_valid();
+        let code = self.token_code.get().expect("ERR_NO_TOKEN_CODE");
+        let storage = code.len() as u64 + TOKEN_STATE_STORAGE;
+        let deposit = env::storage_byte_cost() * Balance::from(storage);
+        refund_deposit(storage + REGISTRY_ENTRY_STORAGE);
+        let summary = TokenSummary {
+            name: metadata.name.clone(),
+            symbol: metadata.symbol.clone(),
+            decimals: metadata.decimals,
+        };
+        let creator_id = env::predecessor_account_id();
+        events::emit_event(
+            "token_creation_started",
+            json!({ "creator_id": creator_id, "token_id": token_id }),
+        );
+        Promise::new(token_id.clone())
+            .create_account()
+            .transfer(deposit)
+            .deploy_contract(code)
+            .function_call(
+                b"new".to_vec(),
+                near_sdk::serde_json::to_vec(&TokenInitArgs {
+                    owner_id: &creator_id,
+                    metadata: &metadata,
+                    max_supply: max_supply.into(),
+                })
+                .unwrap(),
+                NO_DEPOSIT,
+                GAS_FOR_TOKEN_INIT,
+            )
+            .then(ext_self::on_token_created(
+                creator_id,
+                token_id,
+                summary,
+                deposit.into(),
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_ON_TOKEN_CREATED,
+            ))
+    }
+
+    /// Registers the created token, or refunds the creator if the deployment failed, in which
+    /// case the deposit came back to the factory. Returns whether the token was created.
+    #[private]
+    pub fn on_token_created(
+        &mut self,
+        creator_id: AccountId,
+        token_id: AccountId,
+        summary: TokenSummary,
+        deposit: U128,
+    ) -> bool {
+        let created = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        if created {
+            events::emit_event(
+                "token_created",
+                json!({ "creator_id": creator_id, "token_id": token_id, "summary": summary }),
+            );
+            let index = self.registered_tokens.len();
+            self.registered_tokens.push(&RegisteredToken {
+                token_id,
+                creator_id: creator_id.clone(),
+                summary,
+                created_at: env::block_timestamp().into(),
+            });
+            let mut indices = self
+                .registered_tokens_by_creator
+                .get(&creator_id)
+                .unwrap_or_default();
+            indices.push(index);
+            self.registered_tokens_by_creator
+                .insert(&creator_id, &indices);
+        } else {
+            log!("Creation of {} failed, refunding {}", token_id, deposit.0);
+            Promise::new(creator_id).transfer(deposit.0);
+        }
+        created
+    }
+
+    pub fn get_registered_token_count(&self) -> U64 {
+        self.registered_tokens.len().into()
+    }
+
+    /// Returns up to `limit` tokens created through the factory in creation order, starting
+    /// with the `from`th.
+    pub fn get_registered_tokens(&self, from: U64, limit: U64) -> Vec<RegisteredToken> {
+        let limit = std::cmp::min(limit.0, MAX_TOKENS_LIMIT);
+        let end = std::cmp::min(from.0.saturating_add(limit), self.registered_tokens.len());
+        (from.0..end)
+            .filter_map(|index| self.registered_tokens.get(index))
+            .collect()
+    }
+
+    pub fn get_registered_tokens_by_creator(
+        &self,
+        creator_id: ValidAccountId,
+    ) -> Vec<RegisteredToken> {
+        self.registered_tokens_by_creator
+            .get(creator_id.as_ref())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|index| self.registered_tokens.get(index))
+            .collect()
+    }
+}
+
+fn hex_hash(hash: &[u8]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn metadata() -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Child".to_string(),
+            symbol: "CHILD".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 8,
+        }
+    }
+
+    #[test]
+    fn test_create_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.set_token_code(vec![0; 100].into());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.create_token("child".to_string(), metadata(), 1_000.into());
+        assert!(get_logs()
+            .iter()
+            .any(|log| log.contains("token_creation_started")));
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        for (prefix, created) in [("a", true), ("b", false), ("c", true)] {
+            testing_env!(
+                context
+                    .predecessor_account_id(contract_account())
+                    .block_timestamp(7)
+                    .build(),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                vec![if created {
+                    PromiseResult::Successful(vec![])
+                } else {
+                    PromiseResult::Failed
+                }]
+            );
+            let metadata = metadata();
+            let summary = TokenSummary {
+                name: metadata.name,
+                symbol: metadata.symbol,
+                decimals: metadata.decimals,
+            };
+            let token_id = format!("{}.{}", prefix, contract_account());
+            assert_eq!(
+                contract.on_token_created(accounts(1).into(), token_id, summary, 1.into()),
+                created
+            );
+        }
+        assert_eq!(contract.get_registered_token_count().0, 2);
+        let page = contract.get_registered_tokens(1.into(), 10.into());
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].token_id, format!("c.{}", contract_account()));
+        assert_eq!(page[0].created_at.0, 7);
+        assert_eq!(page[0].summary.symbol, "CHILD");
+        assert_eq!(
+            contract.get_registered_tokens_by_creator(accounts(1)).len(),
+            2
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_PREFIX")]
+    fn test_create_token_nested_prefix() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.set_token_code(vec![0; 100].into());
+        contract.create_token("a.b".to_string(), metadata(), 1_000.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_create_token_without_deposit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.set_token_code(vec![0; 100].into());
+        testing_env!(context.attached_deposit(1).build());
+        contract.create_token("child".to_string(), metadata(), 1_000.into());
+    }
+}
@@ -0,0 +1,108 @@
+//! Faucet for testnet deployments, so integrators can get tokens without asking for a mint.
+//!
+//! Disabled by default. Once the owner sets an amount, every account may claim it once per
+//! cooldown, a day by default.
+//! Claims are minted against `max_supply` like any other mint. The claimer pays for the storage
+//! of the claim, including the registration of a new account.
+
+use crate::config::ConfigUpdate;
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::U64;
+
+/// Default time between two claims of an account.
+pub(crate) const FAUCET_COOLDOWN: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the amount handed out per claim. `None` disables the faucet.
+    #[payable]
+    pub fn set_faucet_amount(&mut self, amount: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            faucet_amount: Some(amount),
+            ..Default::default()
+        });
+    }
+
+    /// Mints the faucet amount to the predecessor, registering the account if needed. The
+    /// attached deposit covers the storage; the rest is refunded.
+    #[payable]
+    pub fn faucet_claim(&mut self) -> U128 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_not_paused(PauseSurface::Mint);
+        let amount = self.config.faucet_amount.expect("ERR_FAUCET_DISABLED");
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let now = env::block_timestamp();
+        if let Some(last_claim) = self.faucet_claims.get(&account_id) {
+            assert!(
+                now >= last_claim + self.config.faucet_cooldown,
+                "ERR_FAUCET_COOLDOWN"
+            );
+        }
+        self.faucet_claims.insert(&account_id, &now);
+        self.internal_mint(&account_id, amount, Some("faucet"));
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        amount.into()
+    }
+
+    pub fn get_faucet_amount(&self) -> Option<U128> {
+        self.config.faucet_amount.map(|amount| amount.into())
+    }
+
+    /// Timestamp after which the account can claim from the faucet again.
+    pub fn get_faucet_next_claim(&self, account_id: ValidAccountId) -> U64 {
+        self.faucet_claims
+            .get(account_id.as_ref())
+            .map_or(0, |last_claim| last_claim + self.config.faucet_cooldown)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    #[should_panic(expected = "ERR_FAUCET_COOLDOWN")]
+    fn test_faucet_once_a_day() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_faucet_amount(Some(10.into()));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.faucet_claim();
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10);
+        testing_env!(context.block_timestamp(FAUCET_COOLDOWN).build());
+        contract.faucet_claim();
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 20);
+        contract.faucet_claim();
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_faucet_requires_storage_deposit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_faucet_amount(Some(10.into()));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.faucet_claim();
+    }
+}
@@ -0,0 +1,152 @@
+//! Fee holidays: windows, e.g. promotional weekends, during which holder transfers are charged no
+//! transfer fee, including its burned part.
+//!
+//! Holidays are scheduled on chain before they start, so integrators can verify the schedule,
+//! and can only be cancelled before they start. At most `MAX_FEE_HOLIDAYS` upcoming or running
+//! holidays are kept; ended ones are dropped when a new one is scheduled. Transfers escrowed
+//! during a holiday, e.g. held for acceptance, keep their zero fee when they complete.
+
+use crate::*;
+use near_sdk::serde_json::json;
+
+const MAX_FEE_HOLIDAYS: usize = 10;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeHoliday {
+    pub start_at: U64,
+    /// Exclusive.
+    pub end_at: U64,
+}
+
+impl Contract {
+    pub(crate) fn internal_is_fee_holiday(&self) -> bool {
+        let now = env::block_timestamp();
+        self.fee_holidays
+            .iter()
+            .any(|holiday| holiday.start_at.0 <= now && now < holiday.end_at.0)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedules a fee holiday from `start_at` until `end_at`, in nanoseconds. It must start in
+    /// the future and not overlap other holidays.
+    #[payable]
+    pub fn schedule_fee_holiday(&mut self, start_at: U64, end_at: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        let now = env::block_timestamp();
+        assert!(start_at.0 > now, "ERR_HOLIDAY_IN_PAST");
+        assert!(end_at.0 > start_at.0, "ERR_INVALID_HOLIDAY");
+        self.fee_holidays.retain(|holiday| holiday.end_at.0 > now);
+        assert!(
+            self.fee_holidays
+                .iter()
+                .all(|holiday| end_at.0 <= holiday.start_at.0 || holiday.end_at.0 <= start_at.0),
+            "ERR_HOLIDAY_OVERLAP"
+        );
+        assert!(
+            self.fee_holidays.len() < MAX_FEE_HOLIDAYS,
+            "ERR_TOO_MANY_HOLIDAYS"
+        );
+        let index = self
+            .fee_holidays
+            .iter()
+            .position(|holiday| holiday.start_at.0 > start_at.0)
+            .unwrap_or(self.fee_holidays.len());
+        self.fee_holidays
+            .insert(index, FeeHoliday { start_at, end_at });
+        events::emit_event(
+            "fee_holiday_scheduled",
+            json!({ "start_at": start_at, "end_at": end_at }),
+        );
+    }
+
+    /// Cancels the fee holiday starting at `start_at`, which must not have started yet.
+    #[payable]
+    pub fn cancel_fee_holiday(&mut self, start_at: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        let index = self
+            .fee_holidays
+            .iter()
+            .position(|holiday| holiday.start_at == start_at)
+            .expect("ERR_NO_HOLIDAY");
+        assert!(start_at.0 > env::block_timestamp(), "ERR_HOLIDAY_STARTED");
+        self.fee_holidays.remove(index);
+        events::emit_event("fee_holiday_cancelled", json!({ "start_at": start_at }));
+    }
+
+    /// Upcoming and running fee holidays, by start time.
+    pub fn get_fee_holidays(&self) -> Vec<FeeHoliday> {
+        let now = env::block_timestamp();
+        self.fee_holidays
+            .iter()
+            .filter(|holiday| holiday.end_at.0 > now)
+            .copied()
+            .collect()
+    }
+
+    pub fn is_fee_holiday(&self) -> bool {
+        self.internal_is_fee_holiday()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::fees::{FeeDestination, FeeRoute, TransferFee};
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(10_000, &[(accounts(1), 1_000), (accounts(2), 0)]);
+        contract.set_transfer_fee(Some(TransferFee {
+            fee_bps: 1_000,
+            routes: vec![FeeRoute {
+                destination: FeeDestination::Burn,
+                weight_bps: 10_000,
+            }],
+        }));
+        contract.schedule_fee_holiday(100.into(), 200.into());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_no_fee_during_holiday() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(100)
+            .build());
+        assert!(contract.is_fee_holiday());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 900);
+
+        testing_env!(context.block_timestamp(200).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 790);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_HOLIDAY_OVERLAP")]
+    fn test_overlapping_holiday() {
+        let (_, mut contract) = setup();
+        contract.schedule_fee_holiday(150.into(), 300.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_HOLIDAY_STARTED")]
+    fn test_cancel_started_holiday() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(150).build());
+        contract.cancel_fee_holiday(100.into());
+    }
+}
@@ -0,0 +1,265 @@
+//! Transfer fee: holder-initiated transfers can be charged a fee in basis points of the amount,
+//! paid by the sender on top of it, so the receiver always gets the full amount.
+//!
+//! The fee is split across several destinations by basis-point weights: accounts such as the
+//! treasury, the staking reward reserve, or burned. The rate and the routes are one config field,
+//! so they always change together. Each part is rounded down and the remainder goes to the first
+//! route. No fee is charged while `transfer_fee` is unset or during a fee holiday, and contract
+//! payouts never pay one. Accounts the fee is routed to can't unregister, as charging the fee
+//! would fail for every transfer.
+
+use crate::config::ConfigUpdate;
+use crate::events::BurnReason;
+use crate::interest::BPS_DENOMINATOR;
+use crate::utils::mul_div;
+use crate::*;
+use near_sdk::serde::Deserialize;
+
+pub const MAX_TRANSFER_FEE_BPS: u32 = 1_000;
+const MAX_FEE_ROUTES: usize = 5;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum FeeDestination {
+    Account(AccountId),
+    /// Staking reward reserve, paid out to stakers with their rewards.
+    StakersPool,
+    Burn,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeRoute {
+    pub destination: FeeDestination,
+    pub weight_bps: u32,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferFee {
+    pub fee_bps: u32,
+    /// Weights add up to 10000.
+    pub routes: Vec<FeeRoute>,
+}
+
+impl TransferFee {
+    pub fn assert_valid(&self) {
+        assert!(
+            self.fee_bps > 0 && self.fee_bps <= MAX_TRANSFER_FEE_BPS,
+            "ERR_INVALID_FEE"
+        );
+        assert!(
+            !self.routes.is_empty() && self.routes.len() <= MAX_FEE_ROUTES,
+            "ERR_INVALID_FEE_ROUTES"
+        );
+        assert!(
+            self.routes.iter().all(|route| route.weight_bps > 0)
+                && self
+                    .routes
+                    .iter()
+                    .map(|route| route.weight_bps as u128)
+                    .sum::<u128>()
+                    == BPS_DENOMINATOR,
+            "ERR_INVALID_FEE_ROUTES"
+        );
+        #[cfg(not(feature = "staking"))]
+        assert!(
+            self.routes
+                .iter()
+                .all(|route| route.destination != FeeDestination::StakersPool),
+            "ERR_STAKING_DISABLED"
+        );
+    }
+
+    fn fee(&self, amount: Balance) -> Balance {
+        mul_div(amount, self.fee_bps as u128, BPS_DENOMINATOR, false)
+    }
+
+    /// `fee` split by route.
+    fn parts(&self, fee: Balance) -> Vec<(&FeeDestination, Balance)> {
+        let mut parts: Vec<(&FeeDestination, Balance)> = self
+            .routes
+            .iter()
+            .map(|route| {
+                let part = mul_div(fee, route.weight_bps as u128, BPS_DENOMINATOR, false);
+                (&route.destination, part)
+            })
+            .collect();
+        parts[0].1 += fee - parts.iter().map(|(_, part)| part).sum::<Balance>();
+        parts
+    }
+}
+
+impl Contract {
+    /// Fee charged on a holder transfer of `amount`, none during a fee holiday.
+    pub(crate) fn internal_transfer_fee(&self, amount: Balance) -> Balance {
+        if self.internal_is_fee_holiday() {
+            return 0;
+        }
+        self.config
+            .transfer_fee
+            .as_ref()
+            .map_or(0, |fee| fee.fee(amount))
+    }
+
+    /// Shares `payer_id` is debited for `fee`, with the same rounding as
+    /// `internal_charge_transfer_fee`.
+    pub(crate) fn internal_transfer_fee_shares(&self, payer_id: &AccountId, fee: Balance) -> u128 {
+        self.config.transfer_fee.as_ref().map_or(0, |transfer_fee| {
+            transfer_fee
+                .parts(fee)
+                .into_iter()
+                .filter(|(destination, part)| {
+                    *part > 0 && **destination != FeeDestination::Account(payer_id.clone())
+                })
+                .map(|(_, part)| self.amount_to_shares(part, true))
+                .sum()
+        })
+    }
+
+    /// Takes `fee` from `payer_id` and routes it to the fee destinations. The payer may be the
+    /// contract account, holding the fee in escrow.
+    pub(crate) fn internal_charge_transfer_fee(&mut self, payer_id: &AccountId, fee: Balance) {
+        let parts: Vec<(FeeDestination, Balance)> = match self.config.transfer_fee.as_ref() {
+            Some(transfer_fee) if fee > 0 => transfer_fee
+                .parts(fee)
+                .into_iter()
+                .map(|(destination, part)| (destination.clone(), part))
+                .collect(),
+            _ => return,
+        };
+        for (destination, part) in parts {
+            if part == 0 {
+                continue;
+            }
+            match destination {
+                FeeDestination::Account(account_id) => {
+                    if account_id != *payer_id {
+                        self.internal_transfer(payer_id, &account_id, part, None);
+                    }
+                }
+                #[cfg(feature = "staking")]
+                FeeDestination::StakersPool => {
+                    let contract_id = env::current_account_id();
+                    if *payer_id != contract_id {
+                        self.internal_escrow_deposit(payer_id, part);
+                    }
+                    self.staking.reward_reserve += part;
+                }
+                #[cfg(not(feature = "staking"))]
+                FeeDestination::StakersPool => unreachable!(),
+                FeeDestination::Burn => {
+                    self.internal_burn(payer_id, part, None, Some(BurnReason::TransferFee));
+                }
+            }
+        }
+    }
+
+    /// Whether the transfer fee is routed to `account_id`.
+    pub(crate) fn internal_is_fee_account(&self, account_id: &AccountId) -> bool {
+        self.config.transfer_fee.as_ref().is_some_and(|fee| {
+            fee.routes
+                .iter()
+                .any(|route| route.destination == FeeDestination::Account(account_id.clone()))
+        })
+    }
+
+    /// Validates a new transfer fee and registers the accounts it pays to.
+    pub(crate) fn internal_prepare_transfer_fee(&mut self, fee: &TransferFee) {
+        fee.assert_valid();
+        for route in &fee.routes {
+            if let FeeDestination::Account(account_id) = &route.destination {
+                self.internal_ensure_registered(account_id);
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the transfer fee and its routes, or removes the fee with `None`.
+    #[payable]
+    pub fn set_transfer_fee(&mut self, transfer_fee: Option<TransferFee>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            transfer_fee: Some(transfer_fee),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_transfer_fee(&self) -> Option<TransferFee> {
+        self.config.transfer_fee.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn route(destination: FeeDestination, weight_bps: u32) -> FeeRoute {
+        FeeRoute {
+            destination,
+            weight_bps,
+        }
+    }
+
+    #[test]
+    fn test_transfer_fee_routes() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(10_000, &[(accounts(1), 5_000), (accounts(2), 0)]);
+        contract.set_transfer_fee(Some(TransferFee {
+            fee_bps: 100,
+            routes: vec![
+                route(FeeDestination::Account(accounts(3).into()), 7_500),
+                route(FeeDestination::Burn, 2_500),
+            ],
+        }));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let simulation = contract.simulate_transfer(accounts(1), accounts(2), 2_000.into());
+        assert_eq!(simulation.fee.0, 20);
+        assert_eq!(simulation.sender_balance.0, 2_980);
+        contract.ft_transfer(accounts(2), 2_000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 2_980);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 2_000);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 15);
+        assert_eq!(contract.ft_total_supply().0, 4_995);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_FEE_ACCOUNT")]
+    fn test_fee_account_cannot_unregister() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10_000);
+        contract.set_transfer_fee(Some(TransferFee {
+            fee_bps: 100,
+            routes: vec![route(FeeDestination::Account(accounts(3).into()), 10_000)],
+        }));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_FEE_ROUTES")]
+    fn test_transfer_fee_weights() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10_000);
+        contract.set_transfer_fee(Some(TransferFee {
+            fee_bps: 100,
+            routes: vec![route(FeeDestination::Burn, 9_000)],
+        }));
+    }
+}
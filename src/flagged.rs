@@ -0,0 +1,119 @@
+//! Flagged accounts: the owner or the compliance officer can flag an account under review so
+//! that every holder transfer sent or received by it must carry a non-empty memo, e.g. a case
+//! number, and is reported in a `flagged_transfer` event.
+//!
+//! Transfers only look up the flags while some account is flagged.
+
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde_json::json;
+
+impl Contract {
+    /// Requires a memo on a holder transfer involving a flagged account, and reports it.
+    pub(crate) fn internal_check_flagged_transfer(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<&str>,
+    ) {
+        if self.flagged_count == 0 {
+            return;
+        }
+        let sender_flagged = self.flagged_accounts.contains(sender_id);
+        let receiver_flagged = self.flagged_accounts.contains(receiver_id);
+        if !sender_flagged && !receiver_flagged {
+            return;
+        }
+        assert!(
+            memo.is_some_and(|memo| !memo.trim().is_empty()),
+            "ERR_MEMO_REQUIRED"
+        );
+        events::emit_event(
+            "flagged_transfer",
+            json!({
+                "sender_id": sender_id,
+                "receiver_id": receiver_id,
+                "amount": U128(amount),
+                "memo": memo,
+                "sender_flagged": sender_flagged,
+                "receiver_flagged": receiver_flagged,
+            }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Flags `account_id` or clears its flag. The attached deposit covers the storage of a new
+    /// flag; the rest is refunded.
+    #[payable]
+    pub fn set_account_flagged(&mut self, account_id: ValidAccountId, flagged: bool) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_compliance_officer();
+        let changed = if flagged {
+            self.flagged_accounts.insert(account_id.as_ref())
+        } else {
+            self.flagged_accounts.remove(account_id.as_ref())
+        };
+        if changed {
+            if flagged {
+                self.flagged_count += 1;
+            } else {
+                self.flagged_count -= 1;
+            }
+            events::emit_event(
+                "account_flagged",
+                json!({ "account_id": account_id, "flagged": flagged }),
+            );
+        }
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn is_account_flagged(&self, account_id: ValidAccountId) -> bool {
+        self.flagged_accounts.contains(account_id.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+    use crate::Contract;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+        contract.set_account_flagged(accounts(2), true);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_flagged_transfer_with_memo() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(2), 100.into(), Some("case 12".to_string()));
+        assert!(get_logs()
+            .iter()
+            .any(|log| log.contains(r#""event":"flagged_transfer""#)));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MEMO_REQUIRED")]
+    fn test_flagged_transfer_without_memo() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(2), 100.into(), Some(" ".to_string()));
+    }
+}
@@ -0,0 +1,588 @@
+//! NEP-141 core methods. The public interface works with rebased balances while
+//! `token.accounts` stores shares, so every balance change goes through the helpers below
+//! instead of calling into `FungibleToken` directly.
+
+use crate::config::ConfigUpdate;
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::volume_limit::OutgoingVolume;
+use crate::*;
+use near_sdk::{ext_contract, Gas, Promise, PromiseOrValue, PromiseResult};
+
+const GAS_FOR_RESOLVE_TRANSFER: Gas = 5_000_000_000_000;
+pub(crate) const GAS_FOR_FT_TRANSFER_CALL: Gas = 25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER;
+
+const NO_DEPOSIT: Balance = 0;
+
+#[ext_contract(ext_self)]
+trait SelfCallbacks {
+    fn ft_resolve_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128;
+}
+
+#[ext_contract(ext_fungible_token_receiver)]
+trait FungibleTokenReceiver {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128>;
+}
+
+impl Contract {
+    /// Mints `amount` to `account_id` within `max_supply`, registering the account if needed.
+    /// Supply reserved by pending mint claims counts against the cap. Emits `ft_mint`.
+    pub(crate) fn internal_mint(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        memo: Option<&str>,
+    ) {
+        self.internal_ensure_registered(account_id);
+        self.internal_mint_registered(account_id, amount, memo);
+    }
+
+    /// Like `internal_mint` for an account known to be registered, saving the lookup. Returns
+    /// the new balance of the account.
+    pub(crate) fn internal_mint_registered(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        memo: Option<&str>,
+    ) -> Balance {
+        let next_total_supply: Balance = self
+            .internal_total_supply()
+            .checked_add(self.mint_claims_reserved)
+            .and_then(|supply| supply.checked_add(amount))
+            .unwrap();
+        assert!(next_total_supply <= self.max_supply, "Overflow");
+        let balance = self.internal_deposit(account_id, amount);
+        self.stats.mint_count += 1;
+        events::emit_ft_mint(account_id, amount, memo);
+        balance
+    }
+
+    /// Adds shares to a registered account and returns its new shares. Every share change goes
+    /// through this or `internal_withdraw_shares` to keep checkpoints in sync.
+    ///
+    /// Reads and writes the account once, where the standard `internal_deposit` plus the
+    /// checkpoint lookup would read it three times.
+    pub(crate) fn internal_deposit_shares(
+        &mut self,
+        account_id: &AccountId,
+        shares: Balance,
+    ) -> Balance {
+        let previous_shares = self.token.internal_unwrap_balance_of(account_id);
+        let new_shares = previous_shares
+            .checked_add(shares)
+            .unwrap_or_else(|| env::panic(b"Balance overflow"));
+        self.token.accounts.insert(account_id, &new_shares);
+        self.token.total_supply = self
+            .token
+            .total_supply
+            .checked_add(shares)
+            .expect("Total supply overflow");
+        self.internal_checkpoint_account(account_id, previous_shares, new_shares);
+        self.internal_record_twab(account_id, previous_shares, new_shares);
+        new_shares
+    }
+
+    pub(crate) fn internal_withdraw_shares(
+        &mut self,
+        account_id: &AccountId,
+        shares: Balance,
+    ) -> Balance {
+        let previous_shares = self.token.internal_unwrap_balance_of(account_id);
+        let new_shares = previous_shares
+            .checked_sub(shares)
+            .unwrap_or_else(|| env::panic(b"The account doesn't have enough balance"));
+        self.token.accounts.insert(account_id, &new_shares);
+        self.token.total_supply = self
+            .token
+            .total_supply
+            .checked_sub(shares)
+            .expect("Total supply overflow");
+        self.internal_checkpoint_account(account_id, previous_shares, new_shares);
+        self.internal_record_twab(account_id, previous_shares, new_shares);
+        new_shares
+    }
+
+    /// Credits `amount` to a registered account and returns its new balance. Shares are rounded
+    /// down.
+    pub(crate) fn internal_deposit(&mut self, account_id: &AccountId, amount: Balance) -> Balance {
+        self.internal_accrue_interest();
+        if *account_id == env::current_account_id() {
+            return self.internal_credit_escrow(amount);
+        }
+        self.internal_settle_demurrage(account_id);
+        let shares = self.amount_to_shares(amount, false);
+        let new_shares = self.internal_deposit_shares(account_id, shares);
+        self.shares_to_amount(new_shares)
+    }
+
+    /// Debits `amount` from a registered account. Shares are rounded up, so the account never
+    /// pays less than `amount`.
+    pub(crate) fn internal_withdraw(&mut self, account_id: &AccountId, amount: Balance) {
+        self.internal_accrue_interest();
+        if *account_id == env::current_account_id() {
+            return self.internal_debit_escrow(amount);
+        }
+        self.internal_settle_demurrage(account_id);
+        let shares = self.amount_to_shares(amount, true);
+        self.internal_withdraw_shares(account_id, shares);
+    }
+
+    pub(crate) fn internal_burn(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+        reason: Option<BurnReason>,
+    ) {
+        assert!(amount > 0, "The amount should be a positive number");
+        self.internal_withdraw(account_id, amount);
+        self.stats.burn_count += 1;
+        events::emit_ft_burn(account_id, amount, memo.as_deref(), reason);
+    }
+
+    pub(crate) fn internal_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        assert_ne!(
+            sender_id, receiver_id,
+            "Sender and receiver should be different"
+        );
+        assert!(amount > 0, "The amount should be a positive number");
+        self.internal_accrue_interest();
+        self.internal_settle_demurrage(sender_id);
+        self.internal_settle_demurrage(receiver_id);
+        let contract_id = env::current_account_id();
+        let shares = if *sender_id == contract_id {
+            self.internal_debit_escrow(amount);
+            self.amount_to_shares(amount, false)
+        } else {
+            let shares = self.amount_to_shares(amount, true);
+            self.internal_withdraw_shares(sender_id, shares);
+            shares
+        };
+        if *receiver_id == contract_id {
+            self.internal_credit_escrow(amount);
+        } else {
+            self.internal_deposit_shares(receiver_id, shares);
+        }
+        log!("Transfer {} from {} to {}", amount, sender_id, receiver_id);
+        if let Some(memo) = memo {
+            log!("Memo: {}", memo);
+        }
+    }
+}
+
+impl Contract {
+    /// The contract's own account holds the tokens escrowed by modules like payroll. Their
+    /// ledgers record amounts, so the account keeps an amount in `escrow_balance` instead of
+    /// shares: rebases and interest don't apply to it and every escrow can always be released.
+    fn internal_credit_escrow(&mut self, amount: Balance) -> Balance {
+        let previous_amount = self.escrow_balance;
+        self.escrow_balance = previous_amount
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic(b"Balance overflow"));
+        self.internal_checkpoint_escrow(previous_amount, self.escrow_balance);
+        self.escrow_balance
+    }
+
+    fn internal_debit_escrow(&mut self, amount: Balance) {
+        let previous_amount = self.escrow_balance;
+        self.escrow_balance = previous_amount
+            .checked_sub(amount)
+            .unwrap_or_else(|| env::panic(b"The account doesn't have enough balance"));
+        self.internal_checkpoint_escrow(previous_amount, self.escrow_balance);
+    }
+
+    /// Moves `amount` from `account_id` into the contract's own account.
+    pub(crate) fn internal_escrow_deposit(&mut self, account_id: &AccountId, amount: Balance) {
+        let contract_id = env::current_account_id();
+        self.internal_ensure_registered(&contract_id);
+        self.internal_transfer(account_id, &contract_id, amount, None);
+    }
+
+    /// Pays `amount` of escrowed tokens out to `account_id`.
+    pub(crate) fn internal_escrow_release(&mut self, account_id: &AccountId, amount: Balance) {
+        self.internal_transfer(&env::current_account_id(), account_id, amount, None);
+    }
+}
+
+/// Calls `ft_on_transfer` on the receiver of a completed transfer and resolves the unused amount
+/// afterwards.
+pub(crate) fn internal_call_receiver(
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    amount: U128,
+    msg: String,
+) -> Promise {
+    ext_fungible_token_receiver::ft_on_transfer(
+        sender_id.clone(),
+        amount,
+        msg,
+        &receiver_id,
+        NO_DEPOSIT,
+        env::prepaid_gas() - GAS_FOR_FT_TRANSFER_CALL,
+    )
+    .then(ext_self::ft_resolve_transfer(
+        sender_id,
+        receiver_id,
+        amount,
+        &env::current_account_id(),
+        NO_DEPOSIT,
+        GAS_FOR_RESOLVE_TRANSFER,
+    ))
+}
+
+impl Contract {
+    /// Transfers on behalf of a holder after applying the transfer restrictions: minimum amount,
+    /// KYC, blacklist, tag rules, tier and volume limits, memos for flagged accounts, and the
+    /// compliance registry if one is set. Keeps the transfer pending if it needs a co-signature,
+    /// and holds it if the receiver has to accept it. With `msg` set, the receiver is called as
+    /// in `ft_transfer_call`. Pauses are checked by the callers, since they differ per entry
+    /// point.
+    pub(crate) fn internal_holder_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: Option<String>,
+    ) -> PromiseOrValue<U128> {
+        self.internal_apply_holder_transfer_checks(
+            &sender_id,
+            &receiver_id,
+            amount.into(),
+            memo.as_deref(),
+        );
+        let cosign = self.internal_requires_cosignature(&sender_id, amount.into());
+        assert!(!(cosign && msg.is_some()), "ERR_COSIGNATURE_REQUIRED");
+        let hold = self.internal_requires_acceptance(&receiver_id, amount.into());
+        assert!(!(hold && msg.is_some()), "ERR_ACCEPTANCE_REQUIRED");
+        assert!(
+            !hold || self.internal_can_hold_transfer(&receiver_id),
+            "ERR_TOO_MANY_HELD_TRANSFERS"
+        );
+        if let Some(registry_id) = self.config.compliance_registry.clone() {
+            return self
+                .internal_checked_transfer(&registry_id, sender_id, receiver_id, amount, memo, msg)
+                .into();
+        }
+        if cosign || hold {
+            assert_ne!(
+                sender_id, receiver_id,
+                "Sender and receiver should be different"
+            );
+            let fee = self.internal_transfer_fee(amount.into());
+            self.internal_escrow_deposit(&sender_id, amount.0 + fee);
+            if cosign {
+                self.internal_pend_cosigned_transfer(
+                    sender_id,
+                    receiver_id,
+                    amount.into(),
+                    fee,
+                    memo,
+                );
+            } else {
+                self.internal_hold_transfer(sender_id, receiver_id, amount.into(), fee, memo);
+            }
+            return PromiseOrValue::Value(amount);
+        }
+        self.internal_transfer(&sender_id, &receiver_id, amount.into(), memo);
+        self.internal_on_transfer_completed(&sender_id, &receiver_id, amount.into());
+        let fee = self.internal_transfer_fee(amount.into());
+        self.internal_charge_transfer_fee(&sender_id, fee);
+        match msg {
+            Some(msg) => internal_call_receiver(sender_id, receiver_id, amount, msg).into(),
+            None => {
+                self.internal_record_incoming(&sender_id, &receiver_id, amount.into());
+                PromiseOrValue::Value(amount)
+            }
+        }
+    }
+
+    /// Applies `check_holder_transfer` and stores the sender's outgoing volume, requires memos
+    /// on transfers involving flagged accounts, and activates the sender's pending allocation.
+    pub(crate) fn internal_apply_holder_transfer_checks(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<&str>,
+    ) {
+        let volume = self
+            .check_holder_transfer(sender_id, receiver_id, amount)
+            .unwrap_or_else(|reason| env::panic(reason.as_bytes()));
+        if let Some(volume) = volume {
+            self.outgoing_volumes.insert(sender_id, &volume);
+        }
+        self.internal_check_flagged_transfer(sender_id, receiver_id, amount, memo);
+        self.internal_activate_allocation(sender_id);
+    }
+
+    /// Bookkeeping once a holder transfer reached its receiver: stats, large-transfer alerts,
+    /// transfer rebates and loyalty points.
+    pub(crate) fn internal_on_transfer_completed(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.internal_record_transfer_stats(sender_id, amount);
+        self.internal_alert_large_transfer(sender_id, receiver_id, amount);
+        self.internal_accrue_rebate(sender_id, amount);
+        self.internal_accrue_points(sender_id, receiver_id, amount);
+    }
+
+    /// Checks a holder-initiated transfer against the restrictions on holders, without changing
+    /// state. Returns the sender's outgoing volume including the transfer if a volume limit
+    /// applies to it, for the caller to store.
+    pub(crate) fn check_holder_transfer(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Result<Option<OutgoingVolume>, &'static str> {
+        if amount < self.config.min_transfer_amount {
+            return Err("ERR_BELOW_MIN_TRANSFER");
+        }
+        if self.internal_kyc_required(receiver_id, amount) {
+            return Err("ERR_KYC_REQUIRED");
+        }
+        if self.blacklist.contains(sender_id) || self.blacklist.contains(receiver_id) {
+            return Err("ERR_BLACKLISTED");
+        }
+        self.check_tag_rules(sender_id, receiver_id, amount)?;
+        let tier_limits = self.internal_tier_limits(sender_id);
+        tier_limits.check_max_transfer(amount)?;
+        self.internal_outgoing_volume_after(sender_id, &tier_limits, amount)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the smallest amount accepted by `ft_transfer` and `ft_transfer_call`.
+    #[payable]
+    pub fn set_min_transfer_amount(&mut self, amount: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            min_transfer_amount: Some(amount),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_min_transfer_amount(&self) -> U128 {
+        self.config.min_transfer_amount.into()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Receives NEP-141 tokens sent with `ft_transfer_call`: the legacy token of the migration
+    /// swap, payments filling OTC orders, or a payment token of the sale.
+    pub fn ft_on_transfer(
+        &mut self,
+        sender_id: ValidAccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_id = env::predecessor_account_id();
+        if self.internal_is_legacy_token(&token_id) {
+            return self.internal_swap_legacy(sender_id.into(), amount.into());
+        }
+        if let Some(order_id) = crate::otc::parse_fill_msg(&msg) {
+            return self.internal_fill_otc_order_ft(token_id, sender_id.into(), amount.0, order_id);
+        }
+        #[cfg(feature = "sale")]
+        return self.internal_sale_ft_payment(token_id, sender_id, amount, msg);
+        #[cfg(not(feature = "sale"))]
+        {
+            let _ = msg;
+            env::panic(b"ERR_TOKEN_NOT_ACCEPTED")
+        }
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenCore for Contract {
+    #[payable]
+    /// A transfer held for the receiver's acceptance stays in storage until the receiver
+    /// decides, so the sender attaches a deposit for it instead of 1 yoctoNEAR.
+    fn ft_transfer(&mut self, receiver_id: ValidAccountId, amount: U128, memo: Option<String>) {
+        let initial_storage_usage = env::storage_usage();
+        let receiver_id: AccountId = receiver_id.into();
+        let hold = self.internal_requires_acceptance(&receiver_id, amount.into());
+        if hold {
+            assert!(
+                env::attached_deposit() >= 1,
+                "Requires attached deposit of at least 1 yoctoNEAR"
+            );
+        } else {
+            assert_one_yocto();
+        }
+        self.assert_not_paused(PauseSurface::Transfers);
+        let sender_id = env::predecessor_account_id();
+        self.internal_holder_transfer(sender_id, receiver_id, amount, memo, None);
+        if hold {
+            refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        }
+    }
+
+    #[payable]
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        self.assert_not_paused(PauseSurface::FtTransferCall);
+        let sender_id = env::predecessor_account_id();
+        self.internal_holder_transfer(sender_id, receiver_id.into(), amount, memo, Some(msg))
+    }
+
+    fn ft_total_supply(&self) -> U128 {
+        self.internal_total_supply().into()
+    }
+
+    fn ft_balance_of(&self, account_id: ValidAccountId) -> U128 {
+        self.internal_balance_of(account_id.as_ref()).into()
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenResolver for Contract {
+    /// Refunds the unused part of an `ft_transfer_call` back to the sender. If the sender has
+    /// unregistered in the meantime, the refund is burned instead.
+    #[private]
+    fn ft_resolve_transfer(
+        &mut self,
+        sender_id: ValidAccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+    ) -> U128 {
+        let sender_id: AccountId = sender_id.into();
+        let receiver_id: AccountId = receiver_id.into();
+        let amount: Balance = amount.into();
+
+        let unused_amount = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => {
+                if let Ok(unused_amount) = near_sdk::serde_json::from_slice::<U128>(&value) {
+                    std::cmp::min(amount, unused_amount.0)
+                } else {
+                    amount
+                }
+            }
+            PromiseResult::Failed => amount,
+        };
+
+        if unused_amount > 0 {
+            self.internal_accrue_interest();
+            let receiver_balance = self.internal_balance_of(&receiver_id);
+            if receiver_balance > 0 && receiver_id == env::current_account_id() {
+                // Held as an amount, see `internal_credit_escrow`.
+                let refund_amount = std::cmp::min(receiver_balance, unused_amount);
+                if self.token.accounts.contains_key(&sender_id) {
+                    self.internal_escrow_release(&sender_id, refund_amount);
+                    log!(
+                        "Refund {} from {} to {}",
+                        refund_amount,
+                        receiver_id,
+                        sender_id
+                    );
+                    return (amount - refund_amount).into();
+                }
+                self.internal_debit_escrow(refund_amount);
+                log!("The account of the sender was deleted");
+                self.on_tokens_burned(sender_id, refund_amount);
+                return amount.into();
+            }
+            if receiver_balance > 0 {
+                let refund_amount = std::cmp::min(receiver_balance, unused_amount);
+                let refund_shares = std::cmp::min(
+                    self.amount_to_shares(refund_amount, true),
+                    self.token.accounts.get(&receiver_id).unwrap_or(0),
+                );
+                self.internal_withdraw_shares(&receiver_id, refund_shares);
+                if self.token.accounts.contains_key(&sender_id) {
+                    self.internal_deposit_shares(&sender_id, refund_shares);
+                    log!(
+                        "Refund {} from {} to {}",
+                        refund_amount,
+                        receiver_id,
+                        sender_id
+                    );
+                    return (amount - refund_amount).into();
+                } else {
+                    log!("The account of the sender was deleted");
+                    self.on_tokens_burned(sender_id, refund_amount);
+                    return amount.into();
+                }
+            }
+        }
+        amount.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    #[should_panic(expected = "ERR_BELOW_MIN_TRANSFER")]
+    fn test_min_transfer_amount() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.set_min_transfer_amount(10.into());
+        contract.burn(accounts(1), 1.into(), None, None);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        contract.ft_transfer(accounts(2), 9.into(), None);
+    }
+    /// Gas the mocked blockchain charges for host functions, mostly storage access. Wasm
+    /// execution isn't counted, so these only catch extra trie reads and writes.
+    const MINT_GAS_BUDGET: Gas = 500_000_000_000;
+    const TRANSFER_GAS_BUDGET: Gas = 600_000_000_000;
+
+    #[test]
+    fn test_hot_path_gas() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+        contract.internal_ensure_registered(accounts(2).as_ref());
+        let nonce = next_mint_nonce(&contract);
+        let used_gas = env::used_gas();
+        contract.mint(accounts(1), 10.into(), None, nonce);
+        assert!(env::used_gas() - used_gas <= MINT_GAS_BUDGET);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let used_gas = env::used_gas();
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        assert!(env::used_gas() - used_gas <= TRANSFER_GAS_BUDGET);
+    }
+}
@@ -0,0 +1,251 @@
+//! Genesis distribution: before launch, the owner can replicate an existing token's balances
+//! with `bootstrap_balances`, in as many batches as needed. `finalize_genesis` closes the
+//! bootstrap for good.
+//!
+//! Large migrations use `import_balances` instead, which takes numbered chunks in order, each
+//! with a checksum of its content so a corrupted or misassembled chunk is rejected.
+//! `finalize_import` locks the import forever once the imported total matches the supply
+//! declared for the migration.
+//!
+//! Bootstrapped and imported balances are minted like any other, within `max_supply` and with
+//! `ft_mint` events. Accounts are registered as they're credited and the owner pays for their
+//! storage.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+
+const MAX_BOOTSTRAP_BATCH: usize = 100;
+
+/// Progress of the chunked import.
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct ImportState {
+    pub next_chunk: u32,
+    pub imported_total: Balance,
+    pub finalized: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ImportStatus {
+    /// Index the next chunk must have.
+    pub next_chunk: u32,
+    pub imported_total: U128,
+    pub finalized: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ImportChunk {
+    /// Position of the chunk, starting at 0.
+    pub index: u32,
+    pub balances: Vec<(ValidAccountId, U128)>,
+    /// SHA-256 of one `<account_id>:<amount>\n` line per balance, in order.
+    pub checksum: Base64VecU8,
+}
+
+impl ImportChunk {
+    fn hash(&self) -> Vec<u8> {
+        let lines: String = self
+            .balances
+            .iter()
+            .map(|(account_id, amount)| format!("{}:{}\n", account_id.as_ref(), amount.0))
+            .collect();
+        env::sha256(lines.as_bytes())
+    }
+}
+
+impl Contract {
+    /// Mints each amount to its account and returns the total.
+    fn internal_mint_balances(
+        &mut self,
+        balances: Vec<(ValidAccountId, U128)>,
+        memo: &str,
+    ) -> Balance {
+        assert!(
+            !balances.is_empty() && balances.len() <= MAX_BOOTSTRAP_BATCH,
+            "ERR_INVALID_BATCH_SIZE"
+        );
+        self.assert_not_paused(PauseSurface::Mint);
+        let mut total: Balance = 0;
+        for (account_id, amount) in balances {
+            assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+            self.internal_mint(account_id.as_ref(), amount.into(), Some(memo));
+            total += amount.0;
+        }
+        total
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Mints each amount to its account, registering accounts as needed. The attached deposit
+    /// covers the registrations; the rest is refunded.
+    #[payable]
+    pub fn bootstrap_balances(&mut self, balances: Vec<(ValidAccountId, U128)>) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        assert!(!self.genesis_finalized, "ERR_GENESIS_FINALIZED");
+        self.internal_mint_balances(balances, "genesis");
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Ends the bootstrap. Can't be undone.
+    #[payable]
+    pub fn finalize_genesis(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(!self.genesis_finalized, "ERR_GENESIS_FINALIZED");
+        self.genesis_finalized = true;
+        events::emit_event(
+            "genesis_finalized",
+            json!({ "total_supply": U128(self.internal_total_supply()) }),
+        );
+    }
+
+    pub fn is_genesis_finalized(&self) -> bool {
+        self.genesis_finalized
+    }
+
+    /// Mints the balances of the next chunk of the import. The attached deposit covers the
+    /// registrations; the rest is refunded.
+    #[payable]
+    pub fn import_balances(&mut self, chunk: ImportChunk) -> ImportStatus {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        assert!(!self.import.finalized, "ERR_IMPORT_FINALIZED");
+        assert_eq!(chunk.index, self.import.next_chunk, "ERR_UNEXPECTED_CHUNK");
+        let checksum: Vec<u8> = chunk.checksum.clone().into();
+        assert_eq!(chunk.hash(), checksum, "ERR_CHECKSUM_MISMATCH");
+        let count = chunk.balances.len();
+        let total = self.internal_mint_balances(chunk.balances, "import");
+        self.import.next_chunk += 1;
+        self.import.imported_total += total;
+        events::emit_event(
+            "balances_imported",
+            json!({
+                "chunk": chunk.index,
+                "checksum": chunk.checksum,
+                "count": count,
+                "total": U128(total),
+            }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        self.get_import_status()
+    }
+
+    /// Locks `import_balances` forever, provided the imported total is `declared_supply`.
+    #[payable]
+    pub fn finalize_import(&mut self, declared_supply: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(!self.import.finalized, "ERR_IMPORT_FINALIZED");
+        assert_eq!(
+            self.import.imported_total, declared_supply.0,
+            "ERR_SUPPLY_MISMATCH"
+        );
+        self.import.finalized = true;
+        events::emit_event(
+            "import_finalized",
+            json!({ "chunks": self.import.next_chunk, "total": declared_supply }),
+        );
+    }
+
+    pub fn get_import_status(&self) -> ImportStatus {
+        ImportStatus {
+            next_chunk: self.import.next_chunk,
+            imported_total: self.import.imported_total.into(),
+            finalized: self.import.finalized,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn chunk(index: u32, balances: Vec<(ValidAccountId, U128)>) -> ImportChunk {
+        let mut chunk = ImportChunk {
+            index,
+            balances,
+            checksum: vec![].into(),
+        };
+        chunk.checksum = chunk.hash().into();
+        chunk
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_GENESIS_FINALIZED")]
+    fn test_bootstrap_balances() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.bootstrap_balances(vec![(accounts(1), 300.into()), (accounts(2), 200.into())]);
+        contract.bootstrap_balances(vec![(accounts(1), 100.into())]);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert_eq!(contract.ft_total_supply().0, 600);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.finalize_genesis();
+        assert!(contract.is_genesis_finalized());
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        contract.bootstrap_balances(vec![(accounts(3), 100.into())]);
+    }
+
+    #[test]
+    fn test_import_balances() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.import_balances(chunk(0, vec![(accounts(1), 300.into())]));
+        let status = contract.import_balances(chunk(
+            1,
+            vec![(accounts(2), 200.into()), (accounts(1), 50.into())],
+        ));
+        assert_eq!(status.next_chunk, 2);
+        assert_eq!(status.imported_total.0, 550);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 350);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.finalize_import(550.into());
+        assert!(contract.get_import_status().finalized);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_CHECKSUM_MISMATCH")]
+    fn test_import_checksum_mismatch() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        let mut chunk = chunk(0, vec![(accounts(1), 300.into())]);
+        chunk.balances[0].1 = 301.into();
+        contract.import_balances(chunk);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SUPPLY_MISMATCH")]
+    fn test_finalize_import_supply_mismatch() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.import_balances(chunk(0, vec![(accounts(1), 300.into())]));
+        testing_env!(context.attached_deposit(1).build());
+        contract.finalize_import(400.into());
+    }
+}
@@ -0,0 +1,289 @@
+//! Gifts: tokens sent to an account that it has to claim before they expire. Unclaimed gifts go
+//! back to the sender with `reclaim_gift` once expired.
+//!
+//! Gifted tokens are escrowed on the contract account until claimed or reclaimed. The sender
+//! pays for the storage of the gift and the recipient for its own registration if needed.
+//! Sending a gift is a holder transfer to the recipient: the transfer restrictions and the
+//! transfer fee apply as for `ft_transfer`, and the fee isn't returned when the gift is reclaimed.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+
+pub type GiftId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Gift {
+    pub sender_id: AccountId,
+    pub recipient_id: AccountId,
+    pub amount: Balance,
+    pub expires_at: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GiftView {
+    pub gift_id: U64,
+    pub sender_id: AccountId,
+    pub recipient_id: AccountId,
+    pub amount: U128,
+    pub expires_at: U64,
+    pub expired: bool,
+}
+
+impl Contract {
+    fn internal_remove_gift(&mut self, gift_id: GiftId) -> Gift {
+        let gift = self.gifts.remove(&gift_id).expect("ERR_NO_GIFT");
+        for (index, account_id) in [
+            (&mut self.gifts_by_sender, &gift.sender_id),
+            (&mut self.gifts_by_recipient, &gift.recipient_id),
+        ] {
+            let mut gift_ids = index.get(account_id).unwrap_or_default();
+            gift_ids.retain(|id| *id != gift_id);
+            if gift_ids.is_empty() {
+                index.remove(account_id);
+            } else {
+                index.insert(account_id, &gift_ids);
+            }
+        }
+        gift
+    }
+
+    fn internal_gift_views(&self, gift_ids: Vec<GiftId>) -> Vec<GiftView> {
+        let now = env::block_timestamp();
+        gift_ids
+            .into_iter()
+            .map(|gift_id| {
+                let gift = self.gifts.get(&gift_id).expect("ERR_NO_GIFT");
+                GiftView {
+                    gift_id: gift_id.into(),
+                    amount: gift.amount.into(),
+                    expires_at: gift.expires_at.into(),
+                    expired: gift.expires_at <= now,
+                    sender_id: gift.sender_id,
+                    recipient_id: gift.recipient_id,
+                }
+            })
+            .collect()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `amount` of the predecessor's tokens for `recipient_id` to claim before
+    /// `expires_at`. Transfers that need a co-signature or a compliance check can't be gifted.
+    /// The attached deposit covers the storage of the gift; the rest is refunded.
+    #[payable]
+    pub fn send_gift(
+        &mut self,
+        recipient_id: ValidAccountId,
+        amount: U128,
+        expires_at: U64,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_not_paused(PauseSurface::Transfers);
+        let sender_id = env::predecessor_account_id();
+        let recipient_id: AccountId = recipient_id.into();
+        assert_ne!(sender_id, recipient_id, "ERR_SAME_ACCOUNT");
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        assert!(expires_at.0 > env::block_timestamp(), "ERR_EXPIRED");
+        self.internal_apply_holder_transfer_checks(&sender_id, &recipient_id, amount.into(), None);
+        assert!(
+            !self.internal_requires_cosignature(&sender_id, amount.into()),
+            "ERR_COSIGNATURE_REQUIRED"
+        );
+        assert!(
+            self.config.compliance_registry.is_none(),
+            "ERR_COMPLIANCE_CHECK_REQUIRED"
+        );
+        self.internal_escrow_deposit(&sender_id, amount.into());
+        let fee = self.internal_transfer_fee(amount.into());
+        self.internal_charge_transfer_fee(&sender_id, fee);
+        let gift_id = self.next_gift_id;
+        self.next_gift_id += 1;
+        for (index, account_id) in [
+            (&mut self.gifts_by_sender, &sender_id),
+            (&mut self.gifts_by_recipient, &recipient_id),
+        ] {
+            let mut gift_ids = index.get(account_id).unwrap_or_default();
+            gift_ids.push(gift_id);
+            index.insert(account_id, &gift_ids);
+        }
+        events::emit_event(
+            "gift_sent",
+            json!({
+                "gift_id": U64(gift_id),
+                "sender_id": sender_id,
+                "recipient_id": recipient_id,
+                "amount": amount,
+                "expires_at": expires_at,
+            }),
+        );
+        self.gifts.insert(
+            &gift_id,
+            &Gift {
+                sender_id,
+                recipient_id,
+                amount: amount.into(),
+                expires_at: expires_at.into(),
+            },
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        gift_id.into()
+    }
+
+    /// Claims a gift sent to the predecessor. An unregistered recipient must attach enough
+    /// deposit to cover its registration.
+    #[payable]
+    pub fn claim_gift(&mut self, gift_id: U64) -> U128 {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_not_paused(PauseSurface::Transfers);
+        let gift = self.gifts.get(&gift_id.0).expect("ERR_NO_GIFT");
+        let recipient_id = env::predecessor_account_id();
+        assert_eq!(recipient_id, gift.recipient_id, "ERR_NOT_ALLOWED");
+        assert!(gift.expires_at > env::block_timestamp(), "ERR_EXPIRED");
+        self.assert_not_blacklisted(&recipient_id);
+        self.assert_kyc_for_receipt(&recipient_id, gift.amount);
+        self.internal_remove_gift(gift_id.0);
+        self.internal_ensure_registered(&recipient_id);
+        self.internal_escrow_release(&recipient_id, gift.amount);
+        events::emit_event(
+            "gift_claimed",
+            json!({ "gift_id": gift_id, "recipient_id": recipient_id }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        gift.amount.into()
+    }
+
+    /// Returns an expired gift to the predecessor, who sent it.
+    pub fn reclaim_gift(&mut self, gift_id: U64) -> U128 {
+        let gift = self.gifts.get(&gift_id.0).expect("ERR_NO_GIFT");
+        assert_eq!(
+            env::predecessor_account_id(),
+            gift.sender_id,
+            "ERR_NOT_ALLOWED"
+        );
+        assert!(gift.expires_at <= env::block_timestamp(), "ERR_NOT_EXPIRED");
+        self.internal_remove_gift(gift_id.0);
+        self.internal_escrow_release(&gift.sender_id, gift.amount);
+        events::emit_event("gift_reclaimed", json!({ "gift_id": gift_id }));
+        gift.amount.into()
+    }
+
+    /// Pending gifts sent to `account_id`, including expired ones not reclaimed yet.
+    pub fn get_incoming_gifts(&self, account_id: ValidAccountId) -> Vec<GiftView> {
+        let gift_ids = self
+            .gifts_by_recipient
+            .get(account_id.as_ref())
+            .unwrap_or_default();
+        self.internal_gift_views(gift_ids)
+    }
+
+    /// Pending gifts sent by `account_id`, including expired ones not reclaimed yet.
+    pub fn get_outgoing_gifts(&self, account_id: ValidAccountId) -> Vec<GiftView> {
+        let gift_ids = self
+            .gifts_by_sender
+            .get(account_id.as_ref())
+            .unwrap_or_default();
+        self.internal_gift_views(gift_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::fees::{FeeDestination, FeeRoute, TransferFee};
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_claim_and_reclaim_gifts() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 500)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let claimed = contract.send_gift(accounts(2), 100.into(), 50.into());
+        let expired = contract.send_gift(accounts(2), 200.into(), 50.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 200);
+        assert_eq!(contract.get_outgoing_gifts(accounts(1)).len(), 2);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert_eq!(contract.claim_gift(claimed).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        let incoming = contract.get_incoming_gifts(accounts(2));
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(incoming[0].gift_id, expired);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(50)
+            .attached_deposit(0)
+            .build());
+        assert!(contract.get_outgoing_gifts(accounts(1))[0].expired);
+        assert_eq!(contract.reclaim_gift(expired).0, 200);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert!(contract.get_outgoing_gifts(accounts(1)).is_empty());
+        assert!(contract.get_incoming_gifts(accounts(2)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_DAILY_VOLUME_CAP")]
+    fn test_gift_counts_against_volume_cap() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 500)]);
+        contract.set_daily_volume_cap(Some(150.into()));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.send_gift(accounts(2), 100.into(), 50.into());
+        contract.send_gift(accounts(2), 100.into(), 50.into());
+    }
+
+    #[test]
+    fn test_gift_pays_transfer_fee() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 500)]);
+        contract.set_transfer_fee(Some(TransferFee {
+            fee_bps: 1_000,
+            routes: vec![FeeRoute {
+                destination: FeeDestination::Burn,
+                weight_bps: 10_000,
+            }],
+        }));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.send_gift(accounts(2), 100.into(), 50.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 390);
+        assert_eq!(contract.ft_total_supply().0, 490);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_EXPIRED")]
+    fn test_reclaim_before_expiry() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 500)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let gift_id = contract.send_gift(accounts(2), 100.into(), 50.into());
+        contract.reclaim_gift(gift_id);
+    }
+}
@@ -0,0 +1,93 @@
+//! Holder tiers for token-gating: the owner configures named thresholds and `get_tier` maps an
+//! account's balance plus staked amount onto them, so partner dApps don't need to duplicate
+//! the logic.
+
+use crate::*;
+use near_sdk::serde::Deserialize;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HolderTier {
+    pub name: String,
+    /// Balance plus staked amount needed to reach the tier.
+    pub min_amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Replaces the holder tiers. Thresholds must be strictly increasing.
+    #[payable]
+    pub fn set_holder_tiers(&mut self, tiers: Vec<HolderTier>) {
+        assert_one_yocto();
+        self.assert_owner();
+        for pair in tiers.windows(2) {
+            assert!(
+                pair[0].min_amount.0 < pair[1].min_amount.0,
+                "ERR_TIERS_NOT_SORTED"
+            );
+        }
+        events::emit_config_changed("holder_tiers", &tiers);
+        self.holder_tiers = tiers;
+    }
+
+    pub fn get_holder_tiers(&self) -> Vec<HolderTier> {
+        self.holder_tiers.clone()
+    }
+
+    /// Highest tier reached by the account's balance plus staked amount, `None` if below all.
+    pub fn get_tier(&self, account_id: ValidAccountId) -> Option<HolderTier> {
+        #[cfg(feature = "staking")]
+        let staked = self
+            .stakes
+            .get(account_id.as_ref())
+            .map_or(0, |stake| stake.amount);
+        #[cfg(not(feature = "staking"))]
+        let staked = 0;
+        let amount = self.internal_balance_of(account_id.as_ref()) + staked;
+        self.holder_tiers
+            .iter()
+            .rev()
+            .find(|tier| tier.min_amount.0 <= amount)
+            .cloned()
+    }
+}
+
+#[cfg(all(test, feature = "staking"))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_get_tier() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(0), 0);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.set_holder_tiers(vec![
+            HolderTier {
+                name: "silver".to_string(),
+                min_amount: 500.into(),
+            },
+            HolderTier {
+                name: "gold".to_string(),
+                min_amount: 1_000.into(),
+            },
+        ]);
+        assert!(contract.get_tier(accounts(2)).is_none());
+        assert_eq!(contract.get_tier(accounts(1)).unwrap().name, "gold");
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(400.into(), None);
+        assert_eq!(contract.get_tier(accounts(1)).unwrap().name, "gold");
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(0), 300.into(), None);
+        assert_eq!(contract.get_tier(accounts(1)).unwrap().name, "silver");
+    }
+}
@@ -0,0 +1,143 @@
+//! Optional interest on balances for savings-token deployments.
+//!
+//! Interest is applied to the share index rather than to individual balances, so it accrues for
+//! every holder without touching their accounts. Views compute the accrued index lazily and
+//! every balance change persists it first. Accrual mints new supply, so the index is capped at
+//! the value where the total supply reaches `max_supply` net of the supply reserved by mint
+//! claims. Tokens in escrow don't earn interest.
+
+use crate::config::ConfigUpdate;
+use crate::utils::mul_div;
+use crate::*;
+
+/// Basis points denominator of `Config::interest_rate_bps`.
+pub const BPS_DENOMINATOR: u128 = 10_000;
+pub(crate) const MAX_INTEREST_RATE_BPS: u32 = 10_000;
+pub(crate) const NANOSECONDS_PER_YEAR: u128 = 365 * 24 * 60 * 60 * 1_000_000_000;
+
+impl Contract {
+    /// Share index including the interest accrued since the last persisted accrual.
+    pub(crate) fn effective_share_index(&self) -> u128 {
+        let elapsed = env::block_timestamp().saturating_sub(self.interest_accrued_at);
+        if self.config.interest_rate_bps == 0 || elapsed == 0 {
+            return self.share_index;
+        }
+        let growth = mul_div(
+            self.share_index,
+            self.config.interest_rate_bps as u128 * elapsed as u128,
+            BPS_DENOMINATOR * NANOSECONDS_PER_YEAR,
+            false,
+        );
+        let share_index = self.share_index.saturating_add(growth);
+        if self.token.total_supply == 0 {
+            return share_index;
+        }
+        let max_index = mul_div(
+            self.max_supply
+                .saturating_sub(self.escrow_balance + self.mint_claims_reserved),
+            SHARE_INDEX_DENOMINATOR,
+            self.token.total_supply,
+            false,
+        );
+        std::cmp::max(self.share_index, std::cmp::min(share_index, max_index))
+    }
+
+    /// Persists the accrued interest. Must run before anything changes the total shares.
+    pub(crate) fn internal_accrue_interest(&mut self) {
+        let share_index = self.effective_share_index();
+        if share_index != self.share_index {
+            let total_supply = self.internal_total_supply();
+            self.internal_set_share_index(share_index);
+            log!(
+                "Accrued interest: total supply {} -> {}",
+                total_supply,
+                self.internal_total_supply()
+            );
+        }
+        self.interest_accrued_at = env::block_timestamp();
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the annual interest rate in basis points. Interest accrued under the previous rate is
+    /// settled first. A rate of 0 disables accrual.
+    #[payable]
+    pub fn set_interest_rate(&mut self, rate_bps: u32) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            interest_rate_bps: Some(rate_bps),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_interest_rate(&self) -> u32 {
+        self.config.interest_rate_bps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_interest_accrues_lazily() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(2_000_000);
+        mint_to(&mut contract, accounts(1), 1_000_000);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.set_interest_rate(1_000);
+
+        testing_env!(context
+            .block_timestamp(NANOSECONDS_PER_YEAR as u64 / 2)
+            .build());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_050_000);
+        assert_eq!(contract.ft_total_supply().0, 1_050_000);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 52_500.into(), None);
+        assert_eq!(
+            contract.get_share_index().0,
+            SHARE_INDEX_DENOMINATOR * 105 / 100
+        );
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 997_500);
+        assert_eq!(contract.ft_shares_of(accounts(2)).0, 50_000);
+    }
+
+    #[test]
+    fn test_interest_capped_by_max_supply() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_100_000);
+        mint_to(&mut contract, accounts(1), 1_000_000);
+        contract.set_interest_rate(10_000);
+
+        testing_env!(context.block_timestamp(NANOSECONDS_PER_YEAR as u64).build());
+        assert_eq!(contract.ft_total_supply().0, 1_100_000);
+    }
+
+    #[test]
+    fn test_interest_keeps_mint_claims_reserved() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_100_000);
+        mint_to(&mut contract, accounts(1), 1_000_000);
+        contract.create_mint_claim(accounts(2), 50_000.into(), u64::MAX.into());
+        contract.set_interest_rate(10_000);
+
+        testing_env!(context.block_timestamp(NANOSECONDS_PER_YEAR as u64).build());
+        assert_eq!(contract.ft_total_supply().0, 1_050_000);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        assert_eq!(contract.claim_mint().0, 50_000);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_050_000);
+    }
+}
@@ -0,0 +1,241 @@
+//! Invoices: an issuer bills a payer for an amount due by a date, and the payer settles it with
+//! `pay_invoice`, which transfers the amount to the issuer and marks the invoice paid.
+//!
+//! Paid invoices are kept with their payment time so both parties can reconcile them on chain;
+//! only open invoices can be cancelled by their issuer. Invoices are listed per account, as
+//! issuer or payer. The issuer pays for their storage.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+
+pub type InvoiceId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Invoice {
+    pub issuer_id: AccountId,
+    pub payer_id: AccountId,
+    pub amount: Balance,
+    pub memo: String,
+    /// Block timestamp by which the invoice should be paid.
+    pub due: u64,
+    pub paid_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvoiceView {
+    pub invoice_id: U64,
+    pub issuer_id: AccountId,
+    pub payer_id: AccountId,
+    pub amount: U128,
+    pub memo: String,
+    pub due: U64,
+    pub paid_at: Option<U64>,
+    pub overdue: bool,
+}
+
+impl Contract {
+    fn internal_get_invoice(&self, invoice_id: InvoiceId) -> Invoice {
+        self.invoices.get(&invoice_id).expect("ERR_NO_INVOICE")
+    }
+
+    /// Invoices issued to or by `account_id`, paid or open.
+    fn internal_account_invoices(&self, account_id: &AccountId, paid: bool) -> Vec<InvoiceView> {
+        let now = env::block_timestamp();
+        self.invoices_by_account
+            .get(account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|invoice_id| {
+                let invoice = self.internal_get_invoice(invoice_id);
+                if invoice.paid_at.is_some() != paid {
+                    return None;
+                }
+                Some(InvoiceView {
+                    invoice_id: invoice_id.into(),
+                    amount: invoice.amount.into(),
+                    due: invoice.due.into(),
+                    paid_at: invoice.paid_at.map(|paid_at| paid_at.into()),
+                    overdue: invoice.paid_at.is_none() && invoice.due <= now,
+                    issuer_id: invoice.issuer_id,
+                    payer_id: invoice.payer_id,
+                    memo: invoice.memo,
+                })
+            })
+            .collect()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Bills `payer_id` for `amount` due by `due`, payable to the predecessor. The attached
+    /// deposit covers the storage of the invoice; the rest is refunded.
+    #[payable]
+    pub fn create_invoice(
+        &mut self,
+        payer_id: ValidAccountId,
+        amount: U128,
+        memo: String,
+        due: U64,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let issuer_id = env::predecessor_account_id();
+        let payer_id: AccountId = payer_id.into();
+        assert_ne!(issuer_id, payer_id, "ERR_SAME_ACCOUNT");
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        let invoice_id = self.next_invoice_id;
+        self.next_invoice_id += 1;
+        for account_id in [&issuer_id, &payer_id] {
+            let mut invoice_ids = self.invoices_by_account.get(account_id).unwrap_or_default();
+            invoice_ids.push(invoice_id);
+            self.invoices_by_account.insert(account_id, &invoice_ids);
+        }
+        events::emit_event(
+            "invoice_created",
+            json!({
+                "invoice_id": U64(invoice_id),
+                "issuer_id": issuer_id,
+                "payer_id": payer_id,
+                "amount": amount,
+                "due": due,
+            }),
+        );
+        self.invoices.insert(
+            &invoice_id,
+            &Invoice {
+                issuer_id,
+                payer_id,
+                amount: amount.into(),
+                memo,
+                due: due.into(),
+                paid_at: None,
+            },
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        invoice_id.into()
+    }
+
+    /// Pays an invoice billed to the predecessor, transferring its amount to the issuer.
+    #[payable]
+    pub fn pay_invoice(&mut self, invoice_id: U64) {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        let mut invoice = self.internal_get_invoice(invoice_id.0);
+        assert_eq!(
+            env::predecessor_account_id(),
+            invoice.payer_id,
+            "ERR_NOT_ALLOWED"
+        );
+        assert!(invoice.paid_at.is_none(), "ERR_INVOICE_PAID");
+        invoice.paid_at = Some(env::block_timestamp());
+        self.invoices.insert(&invoice_id.0, &invoice);
+        events::emit_event("invoice_paid", json!({ "invoice_id": invoice_id }));
+        self.internal_holder_transfer(
+            invoice.payer_id,
+            invoice.issuer_id,
+            invoice.amount.into(),
+            Some(format!("invoice {}", invoice_id.0)),
+            None,
+        );
+    }
+
+    /// Withdraws an open invoice issued by the predecessor.
+    #[payable]
+    pub fn cancel_invoice(&mut self, invoice_id: U64) {
+        assert_one_yocto();
+        let invoice = self.internal_get_invoice(invoice_id.0);
+        assert_eq!(
+            env::predecessor_account_id(),
+            invoice.issuer_id,
+            "ERR_NOT_ALLOWED"
+        );
+        assert!(invoice.paid_at.is_none(), "ERR_INVOICE_PAID");
+        self.invoices.remove(&invoice_id.0);
+        for account_id in [&invoice.issuer_id, &invoice.payer_id] {
+            let mut invoice_ids = self.invoices_by_account.get(account_id).unwrap_or_default();
+            invoice_ids.retain(|id| *id != invoice_id.0);
+            if invoice_ids.is_empty() {
+                self.invoices_by_account.remove(account_id);
+            } else {
+                self.invoices_by_account.insert(account_id, &invoice_ids);
+            }
+        }
+        events::emit_event("invoice_cancelled", json!({ "invoice_id": invoice_id }));
+    }
+
+    /// Unpaid invoices issued to or by `account_id`.
+    pub fn get_open_invoices(&self, account_id: ValidAccountId) -> Vec<InvoiceView> {
+        self.internal_account_invoices(account_id.as_ref(), false)
+    }
+
+    /// Paid invoices issued to or by `account_id`.
+    pub fn get_paid_invoices(&self, account_id: ValidAccountId) -> Vec<InvoiceView> {
+        self.internal_account_invoices(account_id.as_ref(), true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_pay_invoice() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 0), (accounts(2), 500)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let paid = contract.create_invoice(accounts(2), 300.into(), "march".to_string(), 10.into());
+        contract.create_invoice(accounts(2), 100.into(), "april".to_string(), 20.into());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .block_timestamp(10)
+            .build());
+        let open = contract.get_open_invoices(accounts(2));
+        assert_eq!(open.len(), 2);
+        assert!(open[0].overdue && !open[1].overdue);
+        contract.pay_invoice(paid);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 300);
+        let paid_invoices = contract.get_paid_invoices(accounts(1));
+        assert_eq!(paid_invoices.len(), 1);
+        assert_eq!(paid_invoices[0].memo, "march");
+        assert_eq!(paid_invoices[0].paid_at, Some(10.into()));
+        assert_eq!(contract.get_open_invoices(accounts(1)).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVOICE_PAID")]
+    fn test_pay_invoice_twice() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 0), (accounts(2), 500)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let invoice_id = contract.create_invoice(accounts(2), 300.into(), String::new(), 10.into());
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.pay_invoice(invoice_id);
+        contract.pay_invoice(invoice_id);
+    }
+}
@@ -0,0 +1,348 @@
+//! Gas-bounded batch jobs over all registered accounts.
+//!
+//! Work over thousands of accounts can't fit in one call. `job_start` records a job and
+//! `job_continue` processes accounts in `account_index` order for as long as the attached gas
+//! allows, persisting the last processed account as the cursor. Anyone can continue a job, e.g.
+//! a keeper bot, since what it does is fixed when it starts. Accounts registered behind the
+//! cursor while a job runs are skipped. A finished job is removed and emits `job_completed`.
+//!
+//! Jobs:
+//! - `merkle_snapshot` commits a Merkle root over all balances; see `merkle`.
+//! - `dust_sweep` moves the dust balances of all consenting accounts to the treasury.
+//! - `airdrop` mints a fixed amount to every registered account. It can't continue while
+//!   minting is paused, skips blacklisted accounts and accounts the amount would take over the
+//!   KYC threshold, and ends early once the next mint wouldn't fit under `max_supply`.
+
+use crate::merkle::{balance_leaf, MerkleBuild};
+use crate::pause::PauseSurface;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json::json;
+use near_sdk::Gas;
+
+pub type JobId = u64;
+
+/// Gas kept for processing one more account.
+pub(crate) const GAS_FOR_JOB_STEP: Gas = 5_000_000_000_000;
+/// Gas kept for persisting or finishing the job after the last step.
+pub(crate) const GAS_FOR_JOB_FINISH: Gas = 10_000_000_000_000;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    MerkleSnapshot,
+    DustSweep,
+    Airdrop { amount: U128 },
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub enum JobState {
+    MerkleSnapshot(MerkleBuild),
+    DustSweep { swept: Balance },
+    Airdrop { amount: Balance, minted: Balance },
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Job {
+    pub state: JobState,
+    /// Last processed account.
+    pub cursor: Option<AccountId>,
+    pub processed: u64,
+    pub started_at_block: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct JobView {
+    pub job_id: U64,
+    pub kind: JobKind,
+    pub cursor: Option<AccountId>,
+    pub processed: U64,
+    pub started_at_block: U64,
+    pub finished: bool,
+}
+
+impl Job {
+    fn view(&self, job_id: JobId, finished: bool) -> JobView {
+        let kind = match &self.state {
+            JobState::MerkleSnapshot(_) => JobKind::MerkleSnapshot,
+            JobState::DustSweep { .. } => JobKind::DustSweep,
+            JobState::Airdrop { amount, .. } => JobKind::Airdrop {
+                amount: (*amount).into(),
+            },
+        };
+        JobView {
+            job_id: job_id.into(),
+            kind,
+            cursor: self.cursor.clone(),
+            processed: self.processed.into(),
+            started_at_block: self.started_at_block.into(),
+            finished,
+        }
+    }
+}
+
+impl Contract {
+    /// Processes one account. Returns false if the job can't go on and has to end.
+    fn internal_job_step(&mut self, state: &mut JobState, account_id: &AccountId) -> bool {
+        match state {
+            JobState::MerkleSnapshot(build) => {
+                let balance = self.internal_balance_of_at(account_id, build.started_at_block);
+                build.push_leaf(balance_leaf(account_id, balance));
+            }
+            JobState::DustSweep { swept } => {
+                let treasury_id = self.treasury_id.clone().expect("ERR_NO_TREASURY");
+                *swept += self.internal_sweep_dust(account_id, &treasury_id);
+            }
+            JobState::Airdrop { amount, minted } => {
+                if *account_id == env::current_account_id()
+                    || self.blacklist.contains(account_id)
+                    || self.internal_kyc_required(account_id, *amount)
+                {
+                    return true;
+                }
+                let next_total_supply = self
+                    .internal_total_supply()
+                    .checked_add(self.mint_claims_reserved)
+                    .and_then(|supply| supply.checked_add(*amount));
+                if next_total_supply.is_none_or(|supply| supply > self.max_supply) {
+                    log!("Airdrop ended early: max supply reached");
+                    return false;
+                }
+                self.internal_mint(account_id, *amount, Some("airdrop"));
+                *minted += *amount;
+            }
+        }
+        true
+    }
+
+    fn internal_finish_job(&mut self, job_id: JobId, job: &Job) {
+        let result = match &job.state {
+            JobState::MerkleSnapshot(build) => {
+                self.internal_commit_merkle_build(build);
+                json!({ "leaf_count": U64(build.leaf_count) })
+            }
+            JobState::DustSweep { swept } => json!({ "swept": U128(*swept) }),
+            JobState::Airdrop { minted, .. } => json!({ "minted": U128(*minted) }),
+        };
+        self.jobs.remove(&job_id);
+        events::emit_event(
+            "job_completed",
+            json!({
+                "job_id": U64(job_id),
+                "kind": job.view(job_id, true).kind,
+                "processed": U64(job.processed),
+                "result": result,
+            }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Starts a job and returns its ID. Merkle snapshots can be started by the Merkle keeper,
+    /// other jobs only by the owner.
+    pub fn job_start(&mut self, kind: JobKind) -> U64 {
+        self.assert_enumerable();
+        let state = match kind.clone() {
+            JobKind::MerkleSnapshot => {
+                self.assert_merkle_keeper();
+                assert!(
+                    self.checkpoints_enabled_at.is_some(),
+                    "ERR_CHECKPOINTS_DISABLED"
+                );
+                JobState::MerkleSnapshot(MerkleBuild::new())
+            }
+            JobKind::DustSweep => {
+                self.assert_owner();
+                assert!(self.treasury_id.is_some(), "ERR_NO_TREASURY");
+                JobState::DustSweep { swept: 0 }
+            }
+            JobKind::Airdrop { amount } => {
+                self.assert_owner();
+                self.assert_not_paused(PauseSurface::Mint);
+                assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+                let total = amount
+                    .0
+                    .checked_mul(Balance::from(self.account_index.len()))
+                    .and_then(|total| total.checked_add(self.internal_total_supply()))
+                    .and_then(|total| total.checked_add(self.mint_claims_reserved));
+                assert!(
+                    total.is_some_and(|total| total <= self.max_supply),
+                    "ERR_AIRDROP_EXCEEDS_SUPPLY"
+                );
+                JobState::Airdrop {
+                    amount: amount.into(),
+                    minted: 0,
+                }
+            }
+        };
+        let job_id = self.next_job_id;
+        self.next_job_id += 1;
+        self.jobs.insert(
+            &job_id,
+            &Job {
+                state,
+                cursor: None,
+                processed: 0,
+                started_at_block: env::block_index(),
+            },
+        );
+        events::emit_event(
+            "job_started",
+            json!({ "job_id": U64(job_id), "kind": kind }),
+        );
+        job_id.into()
+    }
+
+    /// Processes accounts of the job until the remaining gas runs low, at least one per call.
+    /// Returns the progress, with `finished` set once every account is processed.
+    pub fn job_continue(&mut self, job_id: U64) -> JobView {
+        let mut job = self.jobs.get(&job_id.0).expect("ERR_NO_JOB");
+        if let JobState::Airdrop { .. } = job.state {
+            self.assert_not_paused(PauseSurface::Mint);
+        }
+        loop {
+            let next = match job.cursor.as_ref() {
+                Some(cursor) => self.account_index.higher(cursor),
+                None => self.account_index.min(),
+            };
+            let account_id = match next {
+                Some(account_id) => account_id,
+                None => {
+                    self.internal_finish_job(job_id.0, &job);
+                    return job.view(job_id.0, true);
+                }
+            };
+            let proceed = self.internal_job_step(&mut job.state, &account_id);
+            job.cursor = Some(account_id);
+            job.processed += 1;
+            if !proceed {
+                self.internal_finish_job(job_id.0, &job);
+                return job.view(job_id.0, true);
+            }
+            let remaining_gas = env::prepaid_gas().saturating_sub(env::used_gas());
+            if remaining_gas < GAS_FOR_JOB_STEP + GAS_FOR_JOB_FINISH {
+                break;
+            }
+        }
+        self.jobs.insert(&job_id.0, &job);
+        job.view(job_id.0, false)
+    }
+
+    pub fn get_job(&self, job_id: U64) -> Option<JobView> {
+        self.jobs
+            .get(&job_id.0)
+            .map(|job| job.view(job_id.0, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_airdrop_over_several_calls() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        for i in 1..4 {
+            mint_to(&mut contract, accounts(i), 0);
+        }
+        let job_id = contract.job_start(JobKind::Airdrop { amount: 10.into() });
+
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .prepaid_gas(GAS_FOR_JOB_STEP + GAS_FOR_JOB_FINISH)
+            .build());
+        let mut calls = 0;
+        while !contract.job_continue(job_id).finished {
+            calls += 1;
+        }
+        assert_eq!(calls, 3);
+        assert!(contract.get_job(job_id).is_none());
+        for i in 1..4 {
+            assert_eq!(contract.ft_balance_of(accounts(i)).0, 10);
+        }
+        assert!(get_logs().iter().any(|log| log.contains("job_completed")));
+    }
+
+    #[test]
+    fn test_dust_sweep_job() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 5);
+        mint_to(&mut contract, accounts(2), 5);
+        mint_to(&mut contract, accounts(3), 0);
+        contract.set_treasury(accounts(3));
+        contract.set_dust_threshold(10.into());
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_dust_sweep_consent(true);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let job_id = contract.job_start(JobKind::DustSweep);
+        assert!(contract.job_continue(job_id).finished);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 5);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 5);
+    }
+
+    #[test]
+    fn test_airdrop_skips_blacklisted() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 0);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.update_blacklist_batch(vec![accounts(1)], vec![]);
+        let job_id = contract.job_start(JobKind::Airdrop { amount: 10.into() });
+        assert!(contract.job_continue(job_id).finished);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+    }
+
+    #[test]
+    fn test_airdrop_ends_at_max_supply() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        for i in 1..4 {
+            mint_to(&mut contract, accounts(i), 0);
+        }
+        let job_id = contract.job_start(JobKind::Airdrop { amount: 100.into() });
+        mint_to(&mut contract, accounts(0), 750);
+
+        let job = contract.job_continue(job_id);
+        assert!(job.finished);
+        assert!(contract.get_job(job_id).is_none());
+        assert_eq!(contract.ft_total_supply().0, 950);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSED")]
+    fn test_airdrop_while_mint_paused() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 0);
+        let job_id = contract.job_start(JobKind::Airdrop { amount: 10.into() });
+        contract.pause(Some(vec![PauseSurface::Mint]));
+        contract.job_continue(job_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_AIRDROP_EXCEEDS_SUPPLY")]
+    fn test_airdrop_exceeds_supply() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 900);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.job_start(JobKind::Airdrop { amount: 60.into() });
+    }
+}
@@ -0,0 +1,182 @@
+//! KYC attestations pushed on-chain by a trusted issuer.
+//!
+//! The issuer records with `attest` until when an account's KYC is valid. While
+//! `config.kyc_threshold` is set, transfers and mints that would take the receiver's balance above
+//! the threshold require a valid attestation, so amounts below it can still move freely. The
+//! contract's own account is exempt, since it only holds escrowed tokens.
+
+use crate::config::ConfigUpdate;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde_json::json;
+
+impl Contract {
+    fn assert_kyc_issuer(&self) {
+        assert_eq!(
+            Some(env::predecessor_account_id()),
+            self.kyc_issuer,
+            "ERR_NOT_ALLOWED"
+        );
+    }
+
+    /// Whether crediting `amount` to `account_id` takes its balance above the KYC threshold
+    /// while the account holds no valid attestation.
+    pub(crate) fn internal_kyc_required(&self, account_id: &AccountId, amount: Balance) -> bool {
+        let threshold = match self.config.kyc_threshold {
+            Some(threshold) => threshold,
+            None => return false,
+        };
+        if *account_id == env::current_account_id() || self.internal_has_valid_kyc(account_id) {
+            return false;
+        }
+        let next_balance = self.internal_balance_of(account_id).saturating_add(amount);
+        next_balance > threshold
+    }
+
+    pub(crate) fn assert_kyc_for_receipt(&self, account_id: &AccountId, amount: Balance) {
+        assert!(
+            !self.internal_kyc_required(account_id, amount),
+            "ERR_KYC_REQUIRED"
+        );
+    }
+
+    fn internal_has_valid_kyc(&self, account_id: &AccountId) -> bool {
+        self.kyc_attestations
+            .get(account_id)
+            .is_some_and(|expiry| expiry > env::block_timestamp())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account trusted to record KYC attestations.
+    #[payable]
+    pub fn set_kyc_issuer(&mut self, issuer_id: Option<ValidAccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.kyc_issuer = issuer_id.map(|a| a.into());
+        events::emit_role_changed("kyc_issuer", self.kyc_issuer.as_ref());
+    }
+
+    pub fn get_kyc_issuer(&self) -> Option<AccountId> {
+        self.kyc_issuer.clone()
+    }
+
+    /// Sets the balance accounts may hold without a valid attestation, or turns the requirement
+    /// off with `None`.
+    #[payable]
+    pub fn set_kyc_threshold(&mut self, threshold: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            kyc_threshold: Some(threshold),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_kyc_threshold(&self) -> Option<U128> {
+        self.config.kyc_threshold.map(|threshold| threshold.into())
+    }
+
+    /// Records that the KYC of `account_id` is valid until `expiry`, in nanoseconds, replacing
+    /// an earlier attestation. The issuer pays for the storage out of the attached deposit.
+    #[payable]
+    pub fn attest(&mut self, account_id: ValidAccountId, expiry: U64) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_kyc_issuer();
+        assert!(expiry.0 > env::block_timestamp(), "ERR_EXPIRY_IN_PAST");
+        self.kyc_attestations.insert(account_id.as_ref(), &expiry.0);
+        events::emit_event(
+            "kyc_attested",
+            json!({ "account_id": account_id, "expiry": expiry }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    #[payable]
+    pub fn revoke_attestation(&mut self, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_kyc_issuer();
+        assert!(
+            self.kyc_attestations.remove(account_id.as_ref()).is_some(),
+            "ERR_NO_ATTESTATION"
+        );
+        events::emit_event("kyc_revoked", json!({ "account_id": account_id }));
+    }
+
+    /// Returns when the attestation of `account_id` expires, even if it already has.
+    pub fn get_attestation(&self, account_id: ValidAccountId) -> Option<U64> {
+        self.kyc_attestations
+            .get(account_id.as_ref())
+            .map(|expiry| expiry.into())
+    }
+
+    pub fn is_kyc_valid(&self, account_id: ValidAccountId) -> bool {
+        self.internal_has_valid_kyc(account_id.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.set_kyc_issuer(Some(accounts(3)));
+        contract.set_kyc_threshold(Some(100.into()));
+        (context, contract)
+    }
+
+    #[test]
+    fn test_receive_up_to_threshold() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_KYC_REQUIRED")]
+    fn test_receive_above_threshold() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 60.into(), None);
+        contract.ft_transfer(accounts(2), 60.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_KYC_REQUIRED")]
+    fn test_expired_attestation() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.attest(accounts(2), 10.into());
+        assert!(contract.is_kyc_valid(accounts(2)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(2), 200.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 200);
+
+        testing_env!(context.block_timestamp(10).build());
+        assert!(!contract.is_kyc_valid(accounts(2)));
+        contract.ft_transfer(accounts(2), 1.into(), None);
+    }
+}
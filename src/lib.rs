@@ -3,8 +3,9 @@ Fungible Token implementation with JSON serialization.
 NOTES:
   - The maximum balance value is limited by U128 (2**128 - 1).
   - JSON calls should pass U128 as a base-10 string. E.g. "100".
-  - The contract optimizes the inner trie structure by hashing account IDs. It will prevent some
-    abuse of deep tries. Shouldn't be an issue, once NEAR clients implement full hashing of keys.
+  - Balances are stored under the key `accounts_prefix ++ borsh(account_id)`, `accounts_prefix`
+    being an init option that defaults to `a`. Account IDs aren't hashed, so the state can be
+    inspected and diffed by raw key.
   - The contract tracks the change in storage before and after the call. If the storage increases,
     the contract requires the caller of the contract to attach enough deposit to the function call
     to cover the storage cost.
@@ -14,27 +15,448 @@ NOTES:
     attach more deposit than required.
   - To prevent the deployed contract from being modified or deleted, it should not have any access
     keys on its account.
+  - Callers are identified by the predecessor, never the signer, so every method can be called
+    through a relayer as a NEP-366 delegate action. Methods requiring 1 yoctoNEAR still need the
+    delegate action to be signed with a full access key. Deposits attached in a delegate action
+    are paid by the relayer, but refunds go to the delegating account. The only signer check is
+    the referrer check of `buy`, which fails with `ERR_REFERRER_IS_SIGNER` when a relayer refers
+    a purchase it relays.
+  - The `vesting`, `staking` and `sale` modules are behind Cargo features of the same names, all
+    enabled by default. Building without the unused ones keeps the wasm, and with it the deploy
+    storage cost, smaller. Their state is left out too, so a deployment must keep its features
+    across upgrades.
 */
 use near_contract_standards::fungible_token::metadata::{
     FungibleTokenMetadata, FungibleTokenMetadataProvider, FT_METADATA_SPEC,
 };
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
 use near_contract_standards::fungible_token::FungibleToken;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LazyOption;
-use near_sdk::json_types::{ValidAccountId, U128};
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, TreeMap, Vector};
+use near_sdk::json_types::{ValidAccountId, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    assert_one_yocto, env, log, near_bindgen, AccountId, Balance, PanicOnDefault, PromiseOrValue,
+    assert_one_yocto, env, log, near_bindgen, AccountId, Balance, BorshStorageKey, PanicOnDefault,
 };
-use std::convert::TryInto;
-use std::u128;
+
+pub use crate::events::BurnReason;
+pub use crate::rebase::SHARE_INDEX_DENOMINATOR;
+
+mod acceptance;
+mod account_tiers;
+mod admin;
+mod allowances;
+mod billing;
+mod blacklist;
+mod bridges;
+mod burners;
+mod checkpoints;
+mod clawbacks;
+mod compliance;
+mod compliance_burns;
+mod compose;
+mod config;
+mod cosign;
+mod daily_stats;
+mod demurrage;
+mod dust;
+mod event_log;
+pub mod events;
+mod expiring_mints;
+mod export;
+mod factory;
+mod faucet;
+mod fee_holidays;
+mod fees;
+mod flagged;
+mod ft_core;
+mod genesis;
+mod gifts;
+mod holder_tiers;
+mod interest;
+mod invoices;
+mod jobs;
+mod kyc;
+mod linkdrop;
+mod lockup;
+mod loyalty;
+mod merkle;
+mod meta_tx;
+mod mint_claims;
+mod mint_rate;
+mod monitor;
+mod otc;
+mod pause;
+mod payroll;
+mod pool;
+mod price_feed;
+mod rebase;
+mod rebates;
+mod ref_finance;
+mod reserves;
+mod returns;
+#[cfg(feature = "sale")]
+mod sale;
+mod session_keys;
+mod simulate;
+mod snapshots;
+mod splitter;
+#[cfg(feature = "staking")]
+mod staking;
+mod stats;
+mod storage;
+mod storage_cost;
+mod swap;
+mod tags;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+mod twab;
+mod utils;
+#[cfg(feature = "vesting")]
+mod vesting;
+mod volume_limit;
+mod vouchers;
+
+/// Returned by `mint`, so callers don't need follow-up view calls.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintReceipt {
+    pub minted: U128,
+    pub new_balance: U128,
+    pub new_total_supply: U128,
+}
+
+/// Deployment options fixed at initialization.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(default)]
+pub struct InitOptions {
+    /// Keeps registered accounts in `account_index`, which Merkle snapshots, batch jobs and the
+    /// state export walk. Costs one index entry per account, charged in `storage_deposit`.
+    pub enumerable_accounts: bool,
+    /// Storage key prefix of the balances. 1 to 8 ASCII alphanumerics starting with a lowercase
+    /// letter other than `m`, so it can't collide with the other collections.
+    pub accounts_prefix: String,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        Self {
+            enumerable_accounts: true,
+            accounts_prefix: "a".to_string(),
+        }
+    }
+}
+
+impl InitOptions {
+    pub fn assert_valid(&self) {
+        let prefix = self.accounts_prefix.as_bytes();
+        assert!(
+            (1..=8).contains(&prefix.len())
+                && prefix[0].is_ascii_lowercase()
+                && prefix[0] != b'm'
+                && prefix.iter().all(u8::is_ascii_alphanumeric),
+            "ERR_INVALID_ACCOUNTS_PREFIX"
+        );
+    }
+}
+
+/// Keys of feature-gated modules stay when their feature is off, so the prefixes of the others
+/// don't depend on the features.
+#[allow(dead_code)]
+#[derive(BorshSerialize, BorshStorageKey)]
+pub(crate) enum StorageKey {
+    PayrollStreams,
+    EmployerStreams,
+    EmployeeStreams,
+    PayrollPools,
+    Drops,
+    ReferralRewards,
+    FaucetClaims,
+    MintClaims,
+    DustSweepConsents,
+    AccountIndex,
+    CheckpointCounts,
+    Checkpoints,
+    VestingGrants,
+    BeneficiaryGrants,
+    Locks,
+    Stakes,
+    RewardRateSegments,
+    SalePurchases,
+    SaleTokenPrices,
+    SaleTokenProceeds,
+    SaleWithdrawals,
+    TokenCode,
+    RegisteredTokens,
+    RegisteredTokensByCreator,
+    KycAttestations,
+    Blacklist,
+    AccountTiers,
+    TierLimits,
+    OutgoingVolumes,
+    VolumeCapExemptions,
+    MintRates,
+    Allowances,
+    AllowanceExpiries,
+    SigningKeys,
+    TransferNonces,
+    Sessions,
+    Jobs,
+    Vouchers,
+    Gifts,
+    SenderGifts,
+    RecipientGifts,
+    BillingPlans,
+    Subscriptions,
+    Invoices,
+    AccountInvoices,
+    Splitters,
+    OtcOrders,
+    OtcUnpaid,
+    ReserveAttestations,
+    Bridges,
+    Allocations,
+    AcceptanceThresholds,
+    HeldTransfers,
+    ReceiverHeldTransfers,
+    RecentIncoming,
+    AccountTags,
+    FlaggedAccounts,
+    DailyVolumes,
+    SenderLastDay,
+    EventLogHeader,
+    EventLog,
+    CustodyPolicies,
+    CosignedTransfers,
+    SenderCosignedTransfers,
+    DemurrageSettledAt,
+    RebateAccounts,
+    LoyaltyRates,
+    LoyaltyPoints,
+    BurnedByAccount,
+    TopBurners,
+    TwabCounts,
+    TwabObservations,
+    Snapshots,
+    ComplianceBurns,
+    Clawbacks,
+    SaleFailedRefunds,
+}
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
+    /// Balances stored in `token` are shares; see `rebase` for the share to balance conversion.
     token: FungibleToken,
     owner_id: AccountId,
     metadata: LazyOption<FungibleTokenMetadata>,
-    max_supply: Balance
+    max_supply: Balance,
+    config: config::Config,
+    /// Balance of a single share, scaled by `SHARE_INDEX_DENOMINATOR`.
+    share_index: u128,
+    rebase_enabled: bool,
+    rebase_oracle: Option<AccountId>,
+    index_manager: Option<AccountId>,
+    /// Block timestamp of the last persisted interest accrual.
+    interest_accrued_at: u64,
+    payroll_streams: LookupMap<payroll::StreamId, payroll::PayrollStream>,
+    payroll_by_employer: LookupMap<AccountId, Vec<payroll::StreamId>>,
+    payroll_by_employee: LookupMap<AccountId, Vec<payroll::StreamId>>,
+    /// Escrowed tokens per employer, including salary accrued but not withdrawn yet.
+    payroll_pools: LookupMap<AccountId, Balance>,
+    next_payroll_stream_id: payroll::StreamId,
+    drops: LookupMap<near_sdk::PublicKey, linkdrop::Drop>,
+    #[cfg(feature = "sale")]
+    sale: Option<sale::Sale>,
+    #[cfg(feature = "sale")]
+    sale_oracle: Option<sale::SaleOracle>,
+    /// Referral rewards escrowed on the contract account until claimed.
+    #[cfg(feature = "sale")]
+    referral_rewards: LookupMap<AccountId, Balance>,
+    /// Purchases per sale ID and account.
+    #[cfg(feature = "sale")]
+    sale_purchases: LookupMap<(u64, AccountId), sale::SalePurchase>,
+    /// Price per whole token in units of each accepted NEP-141 payment token, per sale ID.
+    #[cfg(feature = "sale")]
+    sale_token_prices: LookupMap<(u64, AccountId), Balance>,
+    /// Payment tokens collected by sales and not requested for withdrawal yet.
+    #[cfg(feature = "sale")]
+    sale_token_proceeds: LookupMap<AccountId, Balance>,
+    /// Pending withdrawals of sale proceeds per payment token, `None` for NEAR.
+    #[cfg(feature = "sale")]
+    sale_withdrawals: LookupMap<Option<AccountId>, sale::SaleWithdrawal>,
+    /// Payment-token refunds of a cancelled sale whose transfer failed, per buyer and token.
+    #[cfg(feature = "sale")]
+    sale_failed_refunds: LookupMap<(AccountId, AccountId), Balance>,
+    /// Timestamp of the last faucet claim per account.
+    faucet_claims: LookupMap<AccountId, u64>,
+    mint_claims: LookupMap<AccountId, mint_claims::MintClaim>,
+    /// Supply reserved under `max_supply` by pending mint claims.
+    mint_claims_reserved: Balance,
+    treasury_id: Option<AccountId>,
+    dust_sweep_consents: LookupSet<AccountId>,
+    /// Registered accounts in ID order, since `token.accounts` can't be iterated.
+    account_index: TreeMap<AccountId, ()>,
+    /// Whether `account_index` is maintained.
+    enumerable_accounts: bool,
+    merkle_keeper: Option<AccountId>,
+    merkle_commitment: Option<merkle::MerkleCommitment>,
+    /// Block height checkpoints were enabled at, `None` while disabled.
+    checkpoints_enabled_at: Option<u64>,
+    checkpoint_counts: LookupMap<checkpoints::CheckpointKey, u32>,
+    checkpoints: LookupMap<(checkpoints::CheckpointKey, u32), checkpoints::Checkpoint>,
+    #[cfg(feature = "vesting")]
+    vesting_grants: LookupMap<vesting::GrantId, vesting::VestingGrant>,
+    #[cfg(feature = "vesting")]
+    vesting_by_beneficiary: LookupMap<AccountId, Vec<vesting::GrantId>>,
+    #[cfg(feature = "vesting")]
+    next_vesting_grant_id: vesting::GrantId,
+    locks: LookupMap<AccountId, lockup::Lock>,
+    #[cfg(feature = "staking")]
+    staking: staking::StakingPool,
+    #[cfg(feature = "staking")]
+    stakes: LookupMap<AccountId, staking::Stake>,
+    holder_tiers: Vec<holder_tiers::HolderTier>,
+    /// Accounts allowed to pause, and nothing else.
+    guardians: Vec<AccountId>,
+    /// Wasm deployed by the token factory.
+    token_code: LazyOption<Vec<u8>>,
+    /// Tokens created through the factory, in creation order.
+    registered_tokens: Vector<factory::RegisteredToken>,
+    registered_tokens_by_creator: LookupMap<AccountId, Vec<u64>>,
+    /// Account trusted to record KYC attestations.
+    kyc_issuer: Option<AccountId>,
+    /// Expiry of the KYC attestation per account.
+    kyc_attestations: LookupMap<AccountId, u64>,
+    /// Account that may update the blacklist in addition to the owner.
+    blacklist_manager: Option<AccountId>,
+    blacklist: LookupSet<AccountId>,
+    /// Bumped by every blacklist update.
+    blacklist_version: u64,
+    /// Accounts with a tier other than retail.
+    account_tiers: LookupMap<AccountId, account_tiers::AccountTier>,
+    tier_limits: LookupMap<account_tiers::AccountTier, account_tiers::TierLimits>,
+    /// Volume sent within the last 24 hours by accounts subject to a volume limit.
+    outgoing_volumes: LookupMap<AccountId, volume_limit::OutgoingVolume>,
+    /// Accounts exempt from `Config::daily_volume_cap`.
+    volume_cap_exemptions: LookupSet<AccountId>,
+    /// Last nonce and call rate per minter.
+    mint_rates: LookupMap<AccountId, mint_rate::MintRate>,
+    /// Allowances per owner and spender.
+    allowances: LookupMap<(AccountId, AccountId), allowances::Allowance>,
+    /// Allowances with an expiry, ordered by it.
+    allowance_expiries: TreeMap<(u64, AccountId, AccountId), ()>,
+    /// Ed25519 keys that named accounts sign transfers with.
+    signing_keys: LookupMap<AccountId, Vec<u8>>,
+    /// Last nonce used by signed transfers per sender.
+    transfer_nonces: LookupMap<AccountId, u64>,
+    /// Session keys per holder and session account.
+    sessions: LookupMap<(AccountId, AccountId), session_keys::Session>,
+    /// Batch jobs in progress.
+    jobs: LookupMap<jobs::JobId, jobs::Job>,
+    next_job_id: jobs::JobId,
+    /// Set by `finalize_genesis`, after which balances can't be bootstrapped anymore.
+    genesis_finalized: bool,
+    import: genesis::ImportState,
+    /// Migration from the legacy token, if one was started.
+    swap: Option<swap::Swap>,
+    /// Vouchers by SHA-256 of their code.
+    vouchers: LookupMap<Vec<u8>, vouchers::Voucher>,
+    gifts: LookupMap<gifts::GiftId, gifts::Gift>,
+    gifts_by_sender: LookupMap<AccountId, Vec<gifts::GiftId>>,
+    gifts_by_recipient: LookupMap<AccountId, Vec<gifts::GiftId>>,
+    next_gift_id: gifts::GiftId,
+    billing_plans: LookupMap<billing::PlanId, billing::BillingPlan>,
+    next_billing_plan_id: billing::PlanId,
+    /// Subscriptions per customer and plan.
+    subscriptions: LookupMap<(AccountId, billing::PlanId), billing::Subscription>,
+    invoices: LookupMap<invoices::InvoiceId, invoices::Invoice>,
+    /// Invoices per issuer and per payer.
+    invoices_by_account: LookupMap<AccountId, Vec<invoices::InvoiceId>>,
+    next_invoice_id: invoices::InvoiceId,
+    splitters: LookupMap<splitter::SplitterId, splitter::Splitter>,
+    next_splitter_id: splitter::SplitterId,
+    otc_orders: LookupMap<otc::OtcOrderId, otc::OtcOrder>,
+    next_otc_order_id: otc::OtcOrderId,
+    /// Token payments per seller and payment token that couldn't be sent yet.
+    otc_unpaid: LookupMap<(AccountId, AccountId), Balance>,
+    pool: pool::Pool,
+    price_feed: Option<price_feed::PriceFeed>,
+    /// Last reference price from the price feed.
+    price: Option<price_feed::TokenPrice>,
+    reserve_attestor: Option<AccountId>,
+    reserve_attestations: Vector<reserves::ReserveAttestation>,
+    /// Bridge connectors allowed to mint, by account.
+    bridges: LookupMap<AccountId, bridges::Bridge>,
+    next_bridge_burn_id: u64,
+    /// Expiring mints not activated yet, by recipient.
+    allocations: LookupMap<AccountId, expiring_mints::Allocation>,
+    pending_allocations: u64,
+    /// Amounts above which incoming transfers are held, per account that opted in.
+    acceptance_thresholds: LookupMap<AccountId, Balance>,
+    acceptance_accounts: u64,
+    held_transfers: LookupMap<acceptance::HeldTransferId, acceptance::HeldTransfer>,
+    held_by_receiver: LookupMap<AccountId, Vec<acceptance::HeldTransferId>>,
+    next_held_transfer_id: acceptance::HeldTransferId,
+    /// How long plain transfers can be returned by their receiver, in nanoseconds.
+    return_window: Option<u64>,
+    /// Returnable incoming transfers per receiver.
+    recent_incoming: LookupMap<AccountId, Vec<returns::IncomingTransfer>>,
+    next_transfer_ref: returns::TransferRef,
+    compliance_officer: Option<AccountId>,
+    account_tags: LookupMap<AccountId, Vec<String>>,
+    tag_rules: Vec<tags::TagRule>,
+    /// Accounts whose transfers must carry a memo.
+    flagged_accounts: LookupSet<AccountId>,
+    flagged_count: u64,
+    stats: stats::Stats,
+    /// Per-day transfer analytics, recorded while enabled.
+    daily_stats_enabled: bool,
+    daily_stats_last_day: u64,
+    daily_volumes: LookupMap<u64, daily_stats::DailyVolume>,
+    sender_last_day: LookupMap<AccountId, u64>,
+    transfer_monitor: Option<monitor::TransferMonitorConfig>,
+    /// Co-signature policies of custodial accounts and the transfers waiting for co-signers.
+    custody_policies: LookupMap<AccountId, cosign::CustodyPolicy>,
+    custodial_accounts: u64,
+    cosigned_transfers: LookupMap<cosign::CosignedTransferId, acceptance::HeldTransfer>,
+    cosign_by_sender: LookupMap<AccountId, Vec<cosign::CosignedTransferId>>,
+    next_cosigned_transfer_id: cosign::CosignedTransferId,
+    demurrage: Option<demurrage::DemurrageConfig>,
+    /// Block timestamp demurrage was turned on at, for accounts that never settled.
+    demurrage_since: u64,
+    demurrage_settled_at: LookupMap<AccountId, u64>,
+    /// Upcoming and running fee holidays, by start time.
+    fee_holidays: Vec<fee_holidays::FeeHoliday>,
+    rebate_program: Option<rebates::RebateProgram>,
+    /// Escrowed rebate funding not earned yet.
+    rebate_pool: Balance,
+    rebate_accounts: LookupMap<AccountId, rebates::RebateAccount>,
+    rebate_participants: u64,
+    /// Loyalty accrual rates of merchants and the points of their customers.
+    loyalty_rates: LookupMap<AccountId, u32>,
+    loyalty_merchants: u64,
+    loyalty_points: LookupMap<AccountId, u128>,
+    /// Voluntary burns per account and the largest burners.
+    burned_by_account: LookupMap<AccountId, Balance>,
+    top_burners: LazyOption<Vec<burners::BurnerView>>,
+    /// When average balance tracking was enabled, if it was.
+    twab_enabled_at: Option<u64>,
+    twab_counts: LookupMap<AccountId, u64>,
+    twab_observations: LookupMap<(AccountId, u64), twab::TwabObservation>,
+    snapshots: Vector<snapshots::Snapshot>,
+    /// Account that may burn from blacklisted accounts, and its pending burns.
+    compliance_burner: Option<AccountId>,
+    compliance_burns:
+        LookupMap<compliance_burns::ComplianceBurnId, compliance_burns::ComplianceBurn>,
+    next_compliance_burn_id: compliance_burns::ComplianceBurnId,
+    /// Clawbacks initiated and not executed or cancelled yet.
+    clawbacks: LookupMap<clawbacks::ClawbackId, clawbacks::Clawback>,
+    next_clawback_id: clawbacks::ClawbackId,
+    /// Tokens held by the contract account, as an amount rather than shares.
+    escrow_balance: Balance,
 }
 
 const DATA_IMAGE_SVG_NEAR_ICON: &str = "data:image/svg+xml,%3Csvg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 288 288'%3E%3Cg id='l' data-name='l'%3E%3Cpath d='M187.58,79.81l-30.1,44.69a3.2,3.2,0,0,0,4.75,4.2L191.86,103a1.2,1.2,0,0,1,2,.91v80.46a1.2,1.2,0,0,1-2.12.77L102.18,77.93A15.35,15.35,0,0,0,90.47,72.5H87.34A15.34,15.34,0,0,0,72,87.84V201.16A15.34,15.34,0,0,0,87.34,216.5h0a15.35,15.35,0,0,0,13.08-7.31l30.1-44.69a3.2,3.2,0,0,0-4.75-4.2L96.14,186a1.2,1.2,0,0,1-2-.91V104.61a1.2,1.2,0,0,1,2.12-.77l89.55,107.23a15.35,15.35,0,0,0,11.71,5.43h3.13A15.34,15.34,0,0,0,216,201.16V87.84A15.34,15.34,0,0,0,200.66,72.5h0A15.35,15.35,0,0,0,187.58,79.81Z'/%3E%3C/g%3E%3C/svg%3E";
@@ -60,78 +482,297 @@ impl Contract {
         )
     }
 
-    pub fn set_owner(&mut self, owner_id: AccountId) -> AccountId {
+    pub(crate) fn assert_owner(&self) {
         assert_eq!(
             env::predecessor_account_id(),
             self.owner_id,
             "ERR_NOT_ALLOWED"
         );
-        self.owner_id = owner_id.into();
-        self.owner_id.clone().try_into().unwrap()
     }
 
-    pub fn get_owner(&mut self) -> AccountId {
-        let owner: AccountId = self.owner_id.clone().try_into().unwrap();
-        return owner;
+    pub fn set_owner(&mut self, owner_id: AccountId) -> AccountId {
+        self.assert_owner();
+        events::emit_ownership_transferred(&self.owner_id, &owner_id);
+        self.owner_id = owner_id;
+        self.owner_id.clone()
+    }
+
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    #[payable]
+    pub fn set_treasury(&mut self, treasury_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.treasury_id = Some(treasury_id.into());
+        events::emit_role_changed("treasury", self.treasury_id.as_ref());
+    }
+
+    pub fn get_treasury(&self) -> Option<AccountId> {
+        self.treasury_id.clone()
     }
 
     /// Initializes the contract with the given total supply owned by the given `owner_id` with
     /// the given fungible token metadata.
     #[init]
     pub fn new(owner_id: AccountId, metadata: FungibleTokenMetadata, max_supply: Balance) -> Self {
+        Self::new_with_options(owner_id, metadata, max_supply, InitOptions::default())
+    }
+
+    /// Like `new`, with non-default deployment options.
+    #[init]
+    pub fn new_with_options(
+        owner_id: AccountId,
+        metadata: FungibleTokenMetadata,
+        max_supply: Balance,
+        options: InitOptions,
+    ) -> Self {
         assert!(!env::state_exists(), "Already initialized");
         metadata.assert_valid();
+        options.assert_valid();
         let mut this = Self {
-            token: FungibleToken::new(b"a".to_vec()),
+            token: FungibleToken::new(options.accounts_prefix.into_bytes()),
             metadata: LazyOption::new(b"m".to_vec(), Some(&metadata)),
-            owner_id: owner_id,
-            max_supply: max_supply
+            owner_id,
+            max_supply,
+            config: config::Config::default(),
+            share_index: SHARE_INDEX_DENOMINATOR,
+            rebase_enabled: false,
+            rebase_oracle: None,
+            index_manager: None,
+            interest_accrued_at: env::block_timestamp(),
+            payroll_streams: LookupMap::new(StorageKey::PayrollStreams),
+            payroll_by_employer: LookupMap::new(StorageKey::EmployerStreams),
+            payroll_by_employee: LookupMap::new(StorageKey::EmployeeStreams),
+            payroll_pools: LookupMap::new(StorageKey::PayrollPools),
+            next_payroll_stream_id: 0,
+            drops: LookupMap::new(StorageKey::Drops),
+            #[cfg(feature = "sale")]
+            sale: None,
+            #[cfg(feature = "sale")]
+            sale_oracle: None,
+            #[cfg(feature = "sale")]
+            referral_rewards: LookupMap::new(StorageKey::ReferralRewards),
+            #[cfg(feature = "sale")]
+            sale_purchases: LookupMap::new(StorageKey::SalePurchases),
+            #[cfg(feature = "sale")]
+            sale_token_prices: LookupMap::new(StorageKey::SaleTokenPrices),
+            #[cfg(feature = "sale")]
+            sale_token_proceeds: LookupMap::new(StorageKey::SaleTokenProceeds),
+            #[cfg(feature = "sale")]
+            sale_withdrawals: LookupMap::new(StorageKey::SaleWithdrawals),
+            #[cfg(feature = "sale")]
+            sale_failed_refunds: LookupMap::new(StorageKey::SaleFailedRefunds),
+            faucet_claims: LookupMap::new(StorageKey::FaucetClaims),
+            mint_claims: LookupMap::new(StorageKey::MintClaims),
+            mint_claims_reserved: 0,
+            treasury_id: None,
+            dust_sweep_consents: LookupSet::new(StorageKey::DustSweepConsents),
+            account_index: TreeMap::new(StorageKey::AccountIndex),
+            enumerable_accounts: options.enumerable_accounts,
+            merkle_keeper: None,
+            merkle_commitment: None,
+            checkpoints_enabled_at: None,
+            checkpoint_counts: LookupMap::new(StorageKey::CheckpointCounts),
+            checkpoints: LookupMap::new(StorageKey::Checkpoints),
+            #[cfg(feature = "vesting")]
+            vesting_grants: LookupMap::new(StorageKey::VestingGrants),
+            #[cfg(feature = "vesting")]
+            vesting_by_beneficiary: LookupMap::new(StorageKey::BeneficiaryGrants),
+            #[cfg(feature = "vesting")]
+            next_vesting_grant_id: 0,
+            locks: LookupMap::new(StorageKey::Locks),
+            #[cfg(feature = "staking")]
+            staking: staking::StakingPool::new(),
+            #[cfg(feature = "staking")]
+            stakes: LookupMap::new(StorageKey::Stakes),
+            holder_tiers: vec![],
+            guardians: vec![],
+            token_code: LazyOption::new(StorageKey::TokenCode, None),
+            registered_tokens: Vector::new(StorageKey::RegisteredTokens),
+            registered_tokens_by_creator: LookupMap::new(StorageKey::RegisteredTokensByCreator),
+            kyc_issuer: None,
+            kyc_attestations: LookupMap::new(StorageKey::KycAttestations),
+            blacklist_manager: None,
+            blacklist: LookupSet::new(StorageKey::Blacklist),
+            blacklist_version: 0,
+            account_tiers: LookupMap::new(StorageKey::AccountTiers),
+            tier_limits: LookupMap::new(StorageKey::TierLimits),
+            outgoing_volumes: LookupMap::new(StorageKey::OutgoingVolumes),
+            volume_cap_exemptions: LookupSet::new(StorageKey::VolumeCapExemptions),
+            mint_rates: LookupMap::new(StorageKey::MintRates),
+            allowances: LookupMap::new(StorageKey::Allowances),
+            allowance_expiries: TreeMap::new(StorageKey::AllowanceExpiries),
+            signing_keys: LookupMap::new(StorageKey::SigningKeys),
+            transfer_nonces: LookupMap::new(StorageKey::TransferNonces),
+            sessions: LookupMap::new(StorageKey::Sessions),
+            jobs: LookupMap::new(StorageKey::Jobs),
+            next_job_id: 0,
+            genesis_finalized: false,
+            import: genesis::ImportState::default(),
+            swap: None,
+            vouchers: LookupMap::new(StorageKey::Vouchers),
+            gifts: LookupMap::new(StorageKey::Gifts),
+            gifts_by_sender: LookupMap::new(StorageKey::SenderGifts),
+            gifts_by_recipient: LookupMap::new(StorageKey::RecipientGifts),
+            next_gift_id: 0,
+            billing_plans: LookupMap::new(StorageKey::BillingPlans),
+            next_billing_plan_id: 0,
+            subscriptions: LookupMap::new(StorageKey::Subscriptions),
+            invoices: LookupMap::new(StorageKey::Invoices),
+            invoices_by_account: LookupMap::new(StorageKey::AccountInvoices),
+            next_invoice_id: 0,
+            splitters: LookupMap::new(StorageKey::Splitters),
+            next_splitter_id: 0,
+            otc_orders: LookupMap::new(StorageKey::OtcOrders),
+            next_otc_order_id: 0,
+            otc_unpaid: LookupMap::new(StorageKey::OtcUnpaid),
+            pool: pool::Pool::default(),
+            price_feed: None,
+            price: None,
+            reserve_attestor: None,
+            reserve_attestations: Vector::new(StorageKey::ReserveAttestations),
+            bridges: LookupMap::new(StorageKey::Bridges),
+            next_bridge_burn_id: 0,
+            allocations: LookupMap::new(StorageKey::Allocations),
+            pending_allocations: 0,
+            acceptance_thresholds: LookupMap::new(StorageKey::AcceptanceThresholds),
+            acceptance_accounts: 0,
+            held_transfers: LookupMap::new(StorageKey::HeldTransfers),
+            held_by_receiver: LookupMap::new(StorageKey::ReceiverHeldTransfers),
+            next_held_transfer_id: 0,
+            return_window: None,
+            recent_incoming: LookupMap::new(StorageKey::RecentIncoming),
+            next_transfer_ref: 0,
+            compliance_officer: None,
+            account_tags: LookupMap::new(StorageKey::AccountTags),
+            tag_rules: Vec::new(),
+            flagged_accounts: LookupSet::new(StorageKey::FlaggedAccounts),
+            flagged_count: 0,
+            stats: stats::Stats::default(),
+            daily_stats_enabled: false,
+            daily_stats_last_day: 0,
+            daily_volumes: LookupMap::new(StorageKey::DailyVolumes),
+            sender_last_day: LookupMap::new(StorageKey::SenderLastDay),
+            transfer_monitor: None,
+            custody_policies: LookupMap::new(StorageKey::CustodyPolicies),
+            custodial_accounts: 0,
+            cosigned_transfers: LookupMap::new(StorageKey::CosignedTransfers),
+            cosign_by_sender: LookupMap::new(StorageKey::SenderCosignedTransfers),
+            next_cosigned_transfer_id: 0,
+            demurrage: None,
+            demurrage_since: 0,
+            demurrage_settled_at: LookupMap::new(StorageKey::DemurrageSettledAt),
+            fee_holidays: Vec::new(),
+            rebate_program: None,
+            rebate_pool: 0,
+            rebate_accounts: LookupMap::new(StorageKey::RebateAccounts),
+            rebate_participants: 0,
+            loyalty_rates: LookupMap::new(StorageKey::LoyaltyRates),
+            loyalty_merchants: 0,
+            loyalty_points: LookupMap::new(StorageKey::LoyaltyPoints),
+            burned_by_account: LookupMap::new(StorageKey::BurnedByAccount),
+            top_burners: LazyOption::new(StorageKey::TopBurners, None),
+            twab_enabled_at: None,
+            twab_counts: LookupMap::new(StorageKey::TwabCounts),
+            twab_observations: LookupMap::new(StorageKey::TwabObservations),
+            snapshots: Vector::new(StorageKey::Snapshots),
+            compliance_burner: None,
+            compliance_burns: LookupMap::new(StorageKey::ComplianceBurns),
+            next_compliance_burn_id: 0,
+            clawbacks: LookupMap::new(StorageKey::Clawbacks),
+            next_clawback_id: 0,
+            escrow_balance: 0,
         };
+        if this.enumerable_accounts {
+            this.measure_account_index_storage_usage();
+        }
         this
     }
 
-    pub fn mint(&mut self, account_id: ValidAccountId, amount: U128) -> U128 {
+    /// Mints `amount` to a registered `account_id`. The optional `memo` (e.g. a bridge tx hash or an invoice
+    /// id) is included in the `ft_mint` event. `nonce` must be higher than the one of the previous mint.
+    /// Callable by the owner and by bridges within their cap.
+    pub fn mint(
+        &mut self,
+        account_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+        nonce: U64,
+    ) -> MintReceipt {
         // assert_one_yocto();
         // assert_eq!(false, true, "Revert");
-        assert_eq!(
-            env::predecessor_account_id(),
-            self.owner_id,
-            "ERR_NOT_ALLOWED"
+        self.internal_assert_minter(amount.into());
+        self.assert_not_paused(pause::PauseSurface::Mint);
+        self.internal_record_mint_call(nonce.into());
+        assert!(
+            self.token.accounts.contains_key(account_id.as_ref()),
+            "ERR_NOT_REGISTERED"
         );
-        let next_total_supply:Balance = self.token.total_supply.checked_add(amount.into()).unwrap();
-        assert!(next_total_supply<=self.max_supply, "Overflow");
-        let account = self.token.accounts.get(account_id.as_ref());
-        if account == None {
-            self.token.internal_register_account(account_id.as_ref());
+        self.assert_not_blacklisted(account_id.as_ref());
+        self.assert_kyc_for_receipt(account_id.as_ref(), amount.into());
+        let new_balance =
+            self.internal_mint_registered(account_id.as_ref(), amount.into(), memo.as_deref());
+        MintReceipt {
+            minted: amount,
+            new_balance: new_balance.into(),
+            new_total_supply: self.internal_total_supply().into(),
         }
-        self.token
-            .internal_deposit(account_id.as_ref(), amount.into());
-        amount
     }
 
-    pub fn burn(&mut self, account_id: ValidAccountId, amount: U128) {
+    /// Registers `account_id` if needed, paying its storage out of the attached deposit, and
+    /// mints `amount` to it. The rest of the deposit is refunded.
+    #[payable]
+    pub fn register_and_mint(
+        &mut self,
+        account_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+        nonce: U64,
+    ) -> MintReceipt {
+        let initial_storage_usage = env::storage_usage();
+        self.internal_ensure_registered(account_id.as_ref());
+        let receipt = self.mint(account_id, amount, memo, nonce);
+        utils::refund_deposit(env::storage_usage() - initial_storage_usage);
+        receipt
+    }
+
+    #[payable]
+    pub fn burn(
+        &mut self,
+        account_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+        reason: Option<BurnReason>,
+    ) {
         assert_one_yocto();
-        assert_eq!(
-            env::predecessor_account_id(),
-            self.owner_id,
-            "ERR_NOT_ALLOWED"
-        );
-        self.token
-            .internal_withdraw(account_id.as_ref(), amount.into());
+        self.assert_owner();
+        self.internal_burn(account_id.as_ref(), amount.into(), memo, reason);
+    }
+
+    /// Burns `amount` of the predecessor's own tokens.
+    #[payable]
+    pub fn self_burn(&mut self, amount: U128, memo: Option<String>, reason: Option<BurnReason>) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_burn(&account_id, amount.into(), memo, reason);
+        self.internal_record_self_burn(&account_id, amount.into());
     }
 
+    #[payable]
     pub fn change_max_supply(&mut self, max_supply: Balance) {
         assert_one_yocto();
-        assert_eq!(
-            env::predecessor_account_id(),
-            self.owner_id,
-            "ERR_NOT_ALLOWED"
-        );
+        self.assert_owner();
+        events::emit_max_supply_changed(self.max_supply, max_supply);
         self.max_supply = max_supply;
     }
 
+    /// `balance` comes straight from `token.accounts`, so it's in shares.
     fn on_account_closed(&mut self, account_id: AccountId, balance: Balance) {
-        log!("Closed @{} with {}", account_id, balance);
+        self.demurrage_settled_at.remove(&account_id);
+        log!("Closed @{} with {}", account_id, self.shares_to_amount(balance));
+        self.internal_sweep_closed_dust(balance);
     }
 
     fn on_tokens_burned(&mut self, account_id: AccountId, amount: Balance) {
@@ -139,21 +780,17 @@ impl Contract {
     }
 }
 
-near_contract_standards::impl_fungible_token_core!(Contract, token);
-near_contract_standards::impl_fungible_token_storage!(Contract, token);
-
 #[near_bindgen]
 impl FungibleTokenMetadataProvider for Contract {
     fn ft_metadata(&self) -> FungibleTokenMetadata {
-        let metadata = self.metadata.get().unwrap();
-        metadata
+        self.metadata.get().unwrap()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{env, testing_env, MockedBlockchain};
+    use near_sdk::{testing_env, MockedBlockchain};
 
     use super::*;
 
@@ -187,7 +824,13 @@ mod tests {
         // contract.mint(accounts(0), 1_000_000.into());
         // assert_eq!(contract.ft_balance_of(accounts(0)), 1_000_000.into());
         contract.change_max_supply(1_000_000);
-        contract.mint(accounts(0), 1_000_000.into());
+        testing_env!(context
+            .attached_deposit(10u128.pow(24))
+            .predecessor_account_id(accounts(0))
+            .build());
+        let receipt = contract.register_and_mint(accounts(0), 1_000_000.into(), None, 1.into());
+        assert_eq!(receipt.new_balance, 1_000_000.into());
+        assert_eq!(receipt.new_total_supply, 1_000_000.into());
         println!("MintedValue: {:?}", contract.ft_balance_of(accounts(0)));
         // assert_eq!(contract.ft_balance_of(accounts(0)), 2_000_000.into());
         // contract.burn(accounts(0), 1_000_000.into());
@@ -206,4 +849,33 @@ mod tests {
         // contract.burn(accounts(1), 500.into());
         // assert_eq!(contract.ft_balance_of(accounts(1)), 500.into());
     }
+
+    #[test]
+    fn test_accounts_prefix() {
+        testing_env!(crate::test_utils::get_context(accounts(0)).build());
+        let mut contract = crate::test_utils::new_contract_with_options(
+            1_000,
+            InitOptions {
+                accounts_prefix: "bal".to_string(),
+                ..Default::default()
+            },
+        );
+        crate::test_utils::mint_to(&mut contract, accounts(1), 10);
+        let mut key = b"bal".to_vec();
+        key.extend(AccountId::from(accounts(1)).try_to_vec().unwrap());
+        assert_eq!(env::storage_read(&key), Some(10u128.try_to_vec().unwrap()));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_ACCOUNTS_PREFIX")]
+    fn test_accounts_prefix_collision() {
+        testing_env!(crate::test_utils::get_context(accounts(0)).build());
+        crate::test_utils::new_contract_with_options(
+            1_000,
+            InitOptions {
+                accounts_prefix: "meta".to_string(),
+                ..Default::default()
+            },
+        );
+    }
 }
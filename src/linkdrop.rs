@@ -0,0 +1,134 @@
+//! Linkdrop-style claim codes for onboarding users that hold neither our token nor NEAR.
+//!
+//! `create_drop` escrows the tokens and adds a function-call access key for `public_key` on this
+//! contract, limited to `claim`. Whoever holds the matching private key proves it by signing a
+//! `claim` transaction with that key, so no signature needs to be verified in the contract.
+//! The key is deleted once the drop is claimed.
+
+use crate::utils::refund_deposit_with_fee;
+use crate::*;
+use near_sdk::json_types::Base58PublicKey;
+use near_sdk::serde::Serialize;
+use near_sdk::{Promise, PublicKey};
+
+/// Gas allowance of the claim key, paid by the creator of the drop.
+const ACCESS_KEY_ALLOWANCE: Balance = 100_000_000_000_000_000_000_000;
+/// Storage taken by the claim key on the contract account.
+const ACCESS_KEY_STORAGE: u64 = 200;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Drop {
+    pub creator_id: AccountId,
+    pub amount: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DropView {
+    pub creator_id: AccountId,
+    pub amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `amount` of the predecessor's tokens claimable with the private key of
+    /// `public_key`. The attached deposit must cover the claim key allowance, the storage of the
+    /// drop and the registration of the account that will claim it. The drop counts against the
+    /// creator's volume limits.
+    #[payable]
+    pub fn create_drop(&mut self, public_key: Base58PublicKey, amount: U128) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        let public_key: PublicKey = public_key.0;
+        assert!(!self.drops.contains_key(&public_key), "ERR_DROP_EXISTS");
+        let creator_id = env::predecessor_account_id();
+        // The claimer isn't known yet, so the restrictions apply as for a transfer to the
+        // contract.
+        self.internal_apply_holder_transfer_checks(
+            &creator_id,
+            &env::current_account_id(),
+            amount.into(),
+            None,
+        );
+        self.internal_escrow_deposit(&creator_id, amount.into());
+        self.drops.insert(
+            &public_key,
+            &Drop {
+                creator_id,
+                amount: amount.into(),
+            },
+        );
+        let storage_used = env::storage_usage() - initial_storage_usage
+            + ACCESS_KEY_STORAGE
+            + self.token.account_storage_usage;
+        refund_deposit_with_fee(storage_used, ACCESS_KEY_ALLOWANCE);
+        Promise::new(env::current_account_id()).add_access_key(
+            public_key,
+            ACCESS_KEY_ALLOWANCE,
+            env::current_account_id(),
+            b"claim".to_vec(),
+        );
+    }
+
+    /// Claims the drop of the key that signed the transaction to `account_id`, registering the
+    /// account if needed. Must be called by the contract itself with the drop's access key.
+    pub fn claim(&mut self, account_id: ValidAccountId) -> U128 {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "ERR_CLAIM_WITH_DROP_KEY"
+        );
+        let public_key = env::signer_account_pk();
+        let drop = self.drops.remove(&public_key).expect("ERR_NO_DROP");
+        self.assert_not_blacklisted(&drop.creator_id);
+        self.assert_not_blacklisted(account_id.as_ref());
+        self.internal_ensure_registered(account_id.as_ref());
+        self.internal_escrow_release(account_id.as_ref(), drop.amount);
+        Promise::new(env::current_account_id()).delete_key(public_key);
+        drop.amount.into()
+    }
+
+    pub fn get_drop(&self, public_key: Base58PublicKey) -> Option<DropView> {
+        self.drops.get(&public_key.0).map(|drop| DropView {
+            creator_id: drop.creator_id,
+            amount: drop.amount.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_create_and_claim_drop() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+
+        let public_key =
+            Base58PublicKey::try_from("ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp")
+                .unwrap();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(ACCESS_KEY_ALLOWANCE + env::storage_byte_cost() * 1_000)
+            .build());
+        contract.create_drop(public_key.clone(), 300.into());
+        assert_eq!(contract.get_drop(public_key.clone()).unwrap().amount.0, 300);
+
+        testing_env!(context
+            .predecessor_account_id(contract_account())
+            .signer_account_pk(public_key.0.clone())
+            .attached_deposit(0)
+            .build());
+        assert_eq!(contract.claim(accounts(3)).0, 300);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 300);
+        assert!(contract.get_drop(public_key).is_none());
+    }
+}
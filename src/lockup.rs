@@ -0,0 +1,224 @@
+//! Lockup: holders lock tokens until a chosen time in exchange for boosted voting power.
+//!
+//! Locked tokens are escrowed on the contract account. Voting power is the holder's balance plus
+//! the locked amount times a multiplier that grows with the remaining lock time, up to
+//! `MAX_LOCK_MULTIPLIER_BPS` for a lock of `MAX_LOCK_DURATION`, and decays linearly to 1x as the
+//! lock approaches expiry.
+
+use crate::interest::BPS_DENOMINATOR;
+use crate::utils::{mul_div, refund_deposit};
+use crate::*;
+use near_sdk::json_types::U64;
+
+const MAX_LOCK_DURATION: u64 = 4 * 365 * 24 * 60 * 60 * 1_000_000_000;
+/// Multiplier of a lock with `MAX_LOCK_DURATION` left, in basis points.
+const MAX_LOCK_MULTIPLIER_BPS: u128 = 40_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Lock {
+    pub amount: U128,
+    pub unlock_at: U64,
+}
+
+impl Lock {
+    /// Voting power of the locked tokens at `now`.
+    fn voting_power_at(&self, now: u64) -> Balance {
+        let remaining = self.unlock_at.0.saturating_sub(now) as u128;
+        let boost = mul_div(
+            self.amount.0,
+            (MAX_LOCK_MULTIPLIER_BPS - BPS_DENOMINATOR) * remaining,
+            BPS_DENOMINATOR * MAX_LOCK_DURATION as u128,
+            false,
+        );
+        self.amount.0 + boost
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `amount` more of the predecessor's tokens until `unlock_at`. An existing lock can
+    /// only be extended, never shortened. The attached deposit covers the storage of the lock.
+    #[payable]
+    pub fn lock_tokens(&mut self, amount: U128, unlock_at: U64) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let now = env::block_timestamp();
+        assert!(unlock_at.0 > now, "ERR_UNLOCK_IN_PAST");
+        assert!(unlock_at.0 - now <= MAX_LOCK_DURATION, "ERR_LOCK_TOO_LONG");
+        let previous_lock = self.locks.get(&account_id);
+        let mut lock = previous_lock.clone().unwrap_or(Lock {
+            amount: 0.into(),
+            unlock_at,
+        });
+        assert!(unlock_at.0 >= lock.unlock_at.0, "ERR_LOCK_SHORTENED");
+        if amount.0 > 0 {
+            self.internal_escrow_deposit(&account_id, amount.into());
+        }
+        lock.amount = (lock.amount.0 + amount.0).into();
+        assert!(lock.amount.0 > 0, "ERR_ZERO_AMOUNT");
+        lock.unlock_at = unlock_at;
+        self.locks.insert(&account_id, &lock);
+        self.internal_checkpoint_lock(&account_id, previous_lock.as_ref(), Some(&lock));
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Returns the predecessor's locked tokens once the lock expired.
+    #[payable]
+    pub fn unlock_tokens(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let lock = self.locks.get(&account_id).expect("ERR_NO_LOCK");
+        assert!(
+            env::block_timestamp() >= lock.unlock_at.0,
+            "ERR_STILL_LOCKED"
+        );
+        self.locks.remove(&account_id);
+        self.internal_checkpoint_lock(&account_id, Some(&lock), None);
+        self.internal_escrow_release(&account_id, lock.amount.into());
+        lock.amount
+    }
+
+    pub fn get_lock(&self, account_id: ValidAccountId) -> Option<Lock> {
+        self.locks.get(account_id.as_ref())
+    }
+
+    /// Balance plus the boosted power of the account's lock.
+    pub fn get_voting_power(&self, account_id: ValidAccountId) -> U128 {
+        let lock_power = self
+            .locks
+            .get(account_id.as_ref())
+            .map_or(0, |lock| lock.voting_power_at(env::block_timestamp()));
+        (self.internal_balance_of(account_id.as_ref()) + lock_power).into()
+    }
+
+    /// Voting power of the account at the end of `block_height`: its balance then plus the power
+    /// of the lock it had then. Requires checkpoints.
+    pub fn get_voting_power_at(&self, account_id: ValidAccountId, block_height: U64) -> U128 {
+        let balance = self.internal_balance_of_at(account_id.as_ref(), block_height.0);
+        let lock_power = self
+            .internal_lock_at(account_id.as_ref(), block_height.0)
+            .map_or(0, |lock| {
+                lock.voting_power_at(self.internal_timestamp_at(block_height.0))
+            });
+        (balance + lock_power).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_lock_voting_power() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.lock_tokens(400.into(), MAX_LOCK_DURATION.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 600);
+        assert_eq!(contract.get_voting_power(accounts(1)).0, 600 + 1_600);
+
+        testing_env!(context.block_timestamp(MAX_LOCK_DURATION / 2).build());
+        assert_eq!(contract.get_voting_power(accounts(1)).0, 600 + 1_000);
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(MAX_LOCK_DURATION)
+            .build());
+        assert_eq!(contract.get_voting_power(accounts(1)).0, 1_000);
+        assert_eq!(contract.unlock_tokens().0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+    }
+
+    #[test]
+    fn test_voting_power_at() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_index(10).build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.enable_checkpoints();
+
+        testing_env!(context
+            .block_index(20)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.lock_tokens(400.into(), MAX_LOCK_DURATION.into());
+        testing_env!(context
+            .block_index(30)
+            .block_timestamp(MAX_LOCK_DURATION / 2)
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.take_snapshot();
+        testing_env!(context
+            .block_index(40)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .block_timestamp(MAX_LOCK_DURATION)
+            .build());
+        contract.unlock_tokens();
+
+        assert_eq!(
+            contract.get_voting_power_at(accounts(1), 10.into()).0,
+            1_000
+        );
+        assert_eq!(
+            contract.get_voting_power_at(accounts(1), 20.into()).0,
+            600 + 1_600
+        );
+        assert_eq!(
+            contract.get_voting_power_at(accounts(1), 30.into()).0,
+            600 + 1_000
+        );
+        assert_eq!(
+            contract.get_voting_power_at(accounts(1), 40.into()).0,
+            1_000
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_STILL_LOCKED")]
+    fn test_unlock_before_expiry() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.lock_tokens(400.into(), 100.into());
+        testing_env!(context.attached_deposit(1).block_timestamp(99).build());
+        contract.unlock_tokens();
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BLACKLISTED")]
+    fn test_lock_while_blacklisted() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.update_blacklist_batch(vec![accounts(1)], vec![]);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.lock_tokens(400.into(), 100.into());
+    }
+}
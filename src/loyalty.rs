@@ -0,0 +1,204 @@
+//! Loyalty points: a non-transferable points balance per account, earned by paying merchants in
+//! the token, so merchants can build loyalty programs on it.
+//!
+//! The owner enrolls merchants with an accrual rate: every completed holder transfer to a
+//! merchant earns the sender `rate_bps` basis points of the amount in points. Points can't be
+//! transferred, only redeemed at a merchant with `redeem_points`, which calls the merchant's
+//! `on_points_redeemed` hook and restores the points if the call fails. Transfers only look up
+//! the receiver's rate while some merchant is enrolled.
+
+use crate::interest::BPS_DENOMINATOR;
+use crate::utils::{mul_div, refund_deposit};
+use crate::*;
+use near_sdk::serde_json::json;
+use near_sdk::{ext_contract, Gas, Promise, PromiseResult};
+
+const MAX_LOYALTY_RATE_BPS: u32 = 10_000;
+const GAS_FOR_ON_POINTS_REDEEMED: Gas = 10_000_000_000_000;
+const GAS_FOR_RESOLVE_REDEMPTION: Gas = 5_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+
+#[ext_contract(ext_loyalty_merchant)]
+trait LoyaltyMerchant {
+    fn on_points_redeemed(&mut self, account_id: AccountId, points: U128, msg: String);
+}
+
+#[ext_contract(ext_self)]
+trait LoyaltyCallbacks {
+    fn resolve_points_redemption(&mut self, account_id: AccountId, points: U128) -> bool;
+}
+
+impl Contract {
+    /// Credits the points `sender_id` earned by paying `amount` to `receiver_id`.
+    pub(crate) fn internal_accrue_points(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        if self.loyalty_merchants == 0 {
+            return;
+        }
+        let rate_bps = match self.loyalty_rates.get(receiver_id) {
+            Some(rate_bps) => rate_bps,
+            None => return,
+        };
+        let points = mul_div(amount, rate_bps as u128, BPS_DENOMINATOR, false);
+        if points > 0 {
+            let balance = self.loyalty_points.get(sender_id).unwrap_or(0);
+            self.loyalty_points.insert(sender_id, &(balance + points));
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Enrolls `merchant_id` with an accrual rate in basis points of the amount paid, or removes
+    /// it with `None`. The attached deposit covers the storage; the rest is refunded.
+    #[payable]
+    pub fn set_loyalty_rate(&mut self, merchant_id: ValidAccountId, rate_bps: Option<u32>) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        let previous = match rate_bps {
+            Some(rate_bps) => {
+                assert!(
+                    rate_bps > 0 && rate_bps <= MAX_LOYALTY_RATE_BPS,
+                    "ERR_INVALID_RATE"
+                );
+                self.loyalty_rates.insert(merchant_id.as_ref(), &rate_bps)
+            }
+            None => self.loyalty_rates.remove(merchant_id.as_ref()),
+        };
+        match (previous.is_some(), rate_bps.is_some()) {
+            (false, true) => self.loyalty_merchants += 1,
+            (true, false) => self.loyalty_merchants -= 1,
+            _ => {}
+        }
+        events::emit_event(
+            "loyalty_rate_changed",
+            json!({ "merchant_id": merchant_id, "rate_bps": rate_bps }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn get_loyalty_rate(&self, merchant_id: ValidAccountId) -> Option<u32> {
+        self.loyalty_rates.get(merchant_id.as_ref())
+    }
+
+    pub fn get_loyalty_points(&self, account_id: ValidAccountId) -> U128 {
+        self.loyalty_points
+            .get(account_id.as_ref())
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Redeems `points` of the predecessor at an enrolled merchant, passing `msg` to the
+    /// merchant's `on_points_redeemed`. Resolves to whether the merchant accepted them.
+    #[payable]
+    pub fn redeem_points(
+        &mut self,
+        merchant_id: ValidAccountId,
+        points: U128,
+        msg: String,
+    ) -> Promise {
+        assert_one_yocto();
+        assert!(
+            self.loyalty_rates.contains_key(merchant_id.as_ref()),
+            "ERR_NOT_MERCHANT"
+        );
+        assert!(points.0 > 0, "ERR_ZERO_POINTS");
+        let account_id = env::predecessor_account_id();
+        let balance = self.loyalty_points.get(&account_id).unwrap_or(0);
+        assert!(balance >= points.0, "ERR_NOT_ENOUGH_POINTS");
+        self.loyalty_points
+            .insert(&account_id, &(balance - points.0));
+        ext_loyalty_merchant::on_points_redeemed(
+            account_id.clone(),
+            points,
+            msg,
+            merchant_id.as_ref(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_POINTS_REDEEMED,
+        )
+        .then(ext_self::resolve_points_redemption(
+            account_id,
+            points,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_REDEMPTION,
+        ))
+    }
+
+    /// Restores the points if the merchant's hook failed.
+    #[private]
+    pub fn resolve_points_redemption(&mut self, account_id: AccountId, points: U128) -> bool {
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                events::emit_event(
+                    "points_redeemed",
+                    json!({ "account_id": account_id, "points": points }),
+                );
+                true
+            }
+            _ => {
+                let balance = self.loyalty_points.get(&account_id).unwrap_or(0);
+                self.loyalty_points
+                    .insert(&account_id, &(balance + points.0));
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract =
+            new_contract_with_balances(10_000, &[(accounts(1), 1_000), (accounts(2), 0)]);
+        contract.set_loyalty_rate(accounts(2), Some(500));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(2), 400.into(), None);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_points_accrue_and_redeem() {
+        let (mut context, mut contract) = setup();
+        assert_eq!(contract.get_loyalty_points(accounts(1)).0, 20);
+        contract.redeem_points(accounts(2), 15.into(), String::new());
+        assert_eq!(contract.get_loyalty_points(accounts(1)).0, 5);
+
+        testing_env!(
+            context.predecessor_account_id(contract_account()).build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        assert!(!contract.resolve_points_redemption(accounts(1).into(), 15.into()));
+        assert_eq!(contract.get_loyalty_points(accounts(1)).0, 20);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_POINTS")]
+    fn test_redeem_more_than_balance() {
+        let (_, mut contract) = setup();
+        contract.redeem_points(accounts(2), 21.into(), String::new());
+    }
+}
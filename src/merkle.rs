@@ -0,0 +1,219 @@
+//! Merkle root commitment over all balances, so off-chain systems can verify inclusion proofs.
+//!
+//! The tree is built incrementally over `account_index` by a `MerkleSnapshot` job, like the
+//! incremental Merkle tree of the Ethereum deposit contract: it has a fixed depth of
+//! `MERKLE_TREE_DEPTH`, unused leaves are zero and only one branch node per level is kept in
+//! state. A leaf is `sha256(account_id || balance)` with the balance as 16 little-endian bytes
+//! and inner nodes are `sha256(left || right)`. Every leaf is the balance at the end of the
+//! block the build started in, read from the checkpoints, which must be enabled; so transfers
+//! while the build runs over several calls don't skew the commitment.
+
+use crate::*;
+use near_sdk::json_types::{Base58CryptoHash, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::CryptoHash;
+use std::convert::TryInto;
+
+pub const MERKLE_TREE_DEPTH: usize = 32;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MerkleBuild {
+    pub started_at_block: u64,
+    pub leaf_count: u64,
+    pub branch: Vec<CryptoHash>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MerkleCommitment {
+    pub root: Base58CryptoHash,
+    pub leaf_count: U64,
+    pub started_at_block: U64,
+    pub finished_at_block: U64,
+}
+
+fn hash_pair(left: &CryptoHash, right: &CryptoHash) -> CryptoHash {
+    let mut value = left.to_vec();
+    value.extend_from_slice(right);
+    env::sha256(&value).try_into().unwrap()
+}
+
+pub fn balance_leaf(account_id: &AccountId, balance: Balance) -> CryptoHash {
+    let mut value = account_id.as_bytes().to_vec();
+    value.extend_from_slice(&balance.to_le_bytes());
+    env::sha256(&value).try_into().unwrap()
+}
+
+impl MerkleBuild {
+    pub(crate) fn new() -> Self {
+        Self {
+            started_at_block: env::block_index(),
+            leaf_count: 0,
+            branch: vec![[0u8; 32]; MERKLE_TREE_DEPTH],
+        }
+    }
+
+    pub(crate) fn push_leaf(&mut self, leaf: CryptoHash) {
+        self.leaf_count += 1;
+        let mut size = self.leaf_count;
+        let mut node = leaf;
+        for level in 0..MERKLE_TREE_DEPTH {
+            if size & 1 == 1 {
+                self.branch[level] = node;
+                return;
+            }
+            node = hash_pair(&self.branch[level], &node);
+            size /= 2;
+        }
+        env::panic(b"ERR_MERKLE_TREE_FULL");
+    }
+
+    fn root(&self) -> CryptoHash {
+        let mut zero_hash = [0u8; 32];
+        let mut node = [0u8; 32];
+        let mut size = self.leaf_count;
+        for level in 0..MERKLE_TREE_DEPTH {
+            node = if size & 1 == 1 {
+                hash_pair(&self.branch[level], &node)
+            } else {
+                hash_pair(&node, &zero_hash)
+            };
+            zero_hash = hash_pair(&zero_hash, &zero_hash);
+            size /= 2;
+        }
+        node
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_merkle_keeper(&self) {
+        let predecessor_id = env::predecessor_account_id();
+        assert!(
+            predecessor_id == self.owner_id || self.merkle_keeper.as_ref() == Some(&predecessor_id),
+            "ERR_NOT_ALLOWED"
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn set_merkle_keeper(&mut self, keeper_id: Option<ValidAccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.merkle_keeper = keeper_id.map(|a| a.into());
+        events::emit_role_changed("merkle_keeper", self.merkle_keeper.as_ref());
+    }
+
+    pub fn get_merkle_commitment(&self) -> Option<MerkleCommitment> {
+        self.merkle_commitment.clone()
+    }
+
+    pub fn get_merkle_keeper(&self) -> Option<AccountId> {
+        self.merkle_keeper.clone()
+    }
+}
+
+impl Contract {
+    /// Commits the root of a finished build.
+    pub(crate) fn internal_commit_merkle_build(&mut self, build: &MerkleBuild) {
+        let commitment = MerkleCommitment {
+            root: build.root().into(),
+            leaf_count: build.leaf_count.into(),
+            started_at_block: build.started_at_block.into(),
+            finished_at_block: env::block_index().into(),
+        };
+        log!(
+            "Committed balances Merkle root {} over {} accounts",
+            String::from(&commitment.root),
+            build.leaf_count
+        );
+        self.merkle_commitment = Some(commitment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::jobs::{JobKind, GAS_FOR_JOB_FINISH, GAS_FOR_JOB_STEP};
+    use crate::test_utils::*;
+
+    /// Root of the tree over `leaves`, computed level by level.
+    fn reference_root(leaves: Vec<CryptoHash>) -> CryptoHash {
+        let mut level = leaves;
+        let mut zero_hash = [0u8; 32];
+        for _ in 0..MERKLE_TREE_DEPTH {
+            if level.len() % 2 == 1 {
+                level.push(zero_hash);
+            }
+            level = level
+                .chunks(2)
+                .map(|pair| hash_pair(&pair[0], &pair[1]))
+                .collect();
+            zero_hash = hash_pair(&zero_hash, &zero_hash);
+        }
+        level[0]
+    }
+
+    fn new_contract_with_checkpoints() -> Contract {
+        let mut contract = new_contract(1_000);
+        for i in 1..4 {
+            mint_to(&mut contract, accounts(i), i as u128);
+        }
+        contract.enable_checkpoints();
+        contract
+    }
+
+    #[test]
+    fn test_incremental_root_matches_full_tree() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract_with_checkpoints();
+        let job_id = contract.job_start(JobKind::MerkleSnapshot);
+        assert!(contract.job_continue(job_id).finished);
+        let commitment = contract.get_merkle_commitment().unwrap();
+        assert_eq!(commitment.leaf_count.0, 3);
+
+        let leaves = (1..4)
+            .map(|i| balance_leaf(&accounts(i).into(), i as u128))
+            .collect();
+        assert_eq!(CryptoHash::from(commitment.root), reference_root(leaves));
+    }
+
+    #[test]
+    fn test_transfer_during_build() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_index(10).build());
+        let mut contract = new_contract_with_checkpoints();
+        let job_id = contract.job_start(JobKind::MerkleSnapshot);
+        testing_env!(context
+            .prepaid_gas(GAS_FOR_JOB_STEP + GAS_FOR_JOB_FINISH)
+            .build());
+        assert!(!contract.job_continue(job_id).finished);
+
+        testing_env!(context
+            .block_index(11)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.ft_transfer(accounts(2), 3.into(), None);
+        while !contract.job_continue(job_id).finished {}
+
+        let commitment = contract.get_merkle_commitment().unwrap();
+        let leaves = (1..4)
+            .map(|i| balance_leaf(&accounts(i).into(), i as u128))
+            .collect();
+        assert_eq!(CryptoHash::from(commitment.root), reference_root(leaves));
+        assert_eq!(commitment.started_at_block.0, 10);
+        assert_eq!(commitment.finished_at_block.0, 11);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_CHECKPOINTS_DISABLED")]
+    fn test_build_requires_checkpoints() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract(1_000);
+        contract.job_start(JobKind::MerkleSnapshot);
+    }
+}
@@ -0,0 +1,263 @@
+//! Meta-transactions: transfers signed off-chain by the holder and submitted by anyone, e.g. a
+//! relayer paying the gas for holders without NEAR.
+//!
+//! The holder signs the Borsh serialization of a `SignedTransfer` with an ed25519 key. Implicit
+//! accounts sign with the key their account ID encodes until they register another one or
+//! remove theirs, e.g. after rotating the account's access keys; named accounts first register a
+//! signing key with `set_signing_key`. The payload names this contract, so it can't be replayed on
+//! another token, and carries a nonce that must be higher than the holder's previous one and a
+//! deadline after which it can't be submitted anymore.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use ed25519_dalek::Verifier;
+use near_sdk::json_types::{Base58PublicKey, Base64VecU8, U64};
+use near_sdk::serde::Deserialize;
+
+/// Curve type prefix of ed25519 keys in `Base58PublicKey`.
+const ED25519_KEY_TYPE: u8 = 0;
+
+#[derive(BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SignedTransfer {
+    /// Token the transfer is meant for.
+    pub contract_id: AccountId,
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    pub nonce: U64,
+    /// Last block timestamp the transfer can be submitted at.
+    pub deadline: U64,
+}
+
+/// Decodes the ed25519 key of an implicit account, whose ID is the key in hex.
+fn implicit_account_key(account_id: &str) -> Option<Vec<u8>> {
+    if account_id.len() != 64 {
+        return None;
+    }
+    (0..32)
+        .map(|i| u8::from_str_radix(&account_id[2 * i..2 * i + 2], 16).ok())
+        .collect()
+}
+
+impl Contract {
+    /// An empty registered key marks an implicit account that removed its key, which disables
+    /// the fallback to the key of its account ID.
+    fn internal_signing_key(&self, account_id: &AccountId) -> Option<Vec<u8>> {
+        match self.signing_keys.get(account_id) {
+            Some(key) if key.is_empty() => None,
+            Some(key) => Some(key),
+            None => implicit_account_key(account_id),
+        }
+    }
+
+    fn assert_valid_signature(&self, payload: &SignedTransfer, signature: &[u8]) {
+        let key = self
+            .internal_signing_key(&payload.sender_id)
+            .expect("ERR_NO_SIGNING_KEY");
+        let key = ed25519_dalek::PublicKey::from_bytes(&key).expect("ERR_INVALID_SIGNING_KEY");
+        let signature =
+            ed25519_dalek::Signature::from_bytes(signature).expect("ERR_INVALID_SIGNATURE");
+        let message = payload.try_to_vec().unwrap();
+        assert!(
+            key.verify(&message, &signature).is_ok(),
+            "ERR_INVALID_SIGNATURE"
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the ed25519 key the predecessor signs transfers with, or removes it with `None`.
+    /// Implicit accounts without a registered key use the key of their account ID; removing the
+    /// key of an implicit account disables signed transfers until it sets one again.
+    #[payable]
+    pub fn set_signing_key(&mut self, public_key: Option<Base58PublicKey>) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let account_id = env::predecessor_account_id();
+        match public_key {
+            Some(public_key) => {
+                let key: Vec<u8> = public_key.into();
+                assert!(
+                    key.len() == 33 && key[0] == ED25519_KEY_TYPE,
+                    "ERR_INVALID_SIGNING_KEY"
+                );
+                self.signing_keys.insert(&account_id, &key[1..].to_vec());
+            }
+            None if implicit_account_key(&account_id).is_some() => {
+                self.signing_keys.insert(&account_id, &vec![]);
+            }
+            None => {
+                self.signing_keys.remove(&account_id);
+            }
+        }
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Returns the ed25519 key `account_id` signs transfers with, if any.
+    pub fn get_signing_key(&self, account_id: ValidAccountId) -> Option<Base58PublicKey> {
+        self.internal_signing_key(account_id.as_ref()).map(|key| {
+            let mut bytes = vec![ED25519_KEY_TYPE];
+            bytes.extend(key);
+            Base58PublicKey(bytes)
+        })
+    }
+
+    /// Last nonce used by a signed transfer of `account_id`, 0 before the first one.
+    pub fn get_transfer_nonce(&self, account_id: ValidAccountId) -> U64 {
+        self.transfer_nonces
+            .get(account_id.as_ref())
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Executes a transfer signed by its sender. Anyone can submit it; no deposit is needed.
+    pub fn ft_transfer_signed(&mut self, payload: SignedTransfer, signature: Base64VecU8) {
+        self.assert_not_paused(PauseSurface::Transfers);
+        assert_eq!(
+            payload.contract_id,
+            env::current_account_id(),
+            "ERR_WRONG_CONTRACT"
+        );
+        assert!(
+            env::block_timestamp() <= payload.deadline.0,
+            "ERR_DEADLINE_PASSED"
+        );
+        let last_nonce = self.transfer_nonces.get(&payload.sender_id).unwrap_or(0);
+        assert!(payload.nonce.0 > last_nonce, "ERR_INVALID_NONCE");
+        self.assert_valid_signature(&payload, &signature.0);
+        self.transfer_nonces
+            .insert(&payload.sender_id, &payload.nonce.0);
+        self.internal_holder_transfer(
+            payload.sender_id,
+            payload.receiver_id,
+            payload.amount,
+            payload.memo,
+            None,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn keypair() -> Keypair {
+        let secret = SecretKey::from_bytes(&[7; 32]).unwrap();
+        let public = (&secret).into();
+        Keypair { secret, public }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn payload(sender_id: &str, nonce: u64) -> SignedTransfer {
+        SignedTransfer {
+            contract_id: contract_account().into(),
+            sender_id: sender_id.to_string(),
+            receiver_id: accounts(2).into(),
+            amount: 100.into(),
+            memo: None,
+            nonce: nonce.into(),
+            deadline: 10.into(),
+        }
+    }
+
+    fn sign(payload: &SignedTransfer) -> Base64VecU8 {
+        keypair()
+            .sign(&payload.try_to_vec().unwrap())
+            .to_bytes()
+            .to_vec()
+            .into()
+    }
+
+    fn setup(sender_id: &ValidAccountId) -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, sender_id.clone(), 500);
+        mint_to(&mut contract, accounts(2), 0);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(0)
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_signed_transfer_implicit_account() {
+        let sender_id = ValidAccountId::try_from(hex(keypair().public.as_bytes())).unwrap();
+        let (_, mut contract) = setup(&sender_id);
+        let payload = payload(sender_id.as_ref(), 1);
+        contract.ft_transfer_signed(payload.clone(), sign(&payload));
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        assert_eq!(contract.get_transfer_nonce(sender_id).0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_SIGNING_KEY")]
+    fn test_implicit_account_removed_key() {
+        let sender_id = ValidAccountId::try_from(hex(keypair().public.as_bytes())).unwrap();
+        let (mut context, mut contract) = setup(&sender_id);
+        testing_env!(context
+            .predecessor_account_id(sender_id.clone())
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.set_signing_key(None);
+        assert!(contract.get_signing_key(sender_id.clone()).is_none());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(0)
+            .build());
+        let payload = payload(sender_id.as_ref(), 1);
+        contract.ft_transfer_signed(payload.clone(), sign(&payload));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_NONCE")]
+    fn test_signed_transfer_replay() {
+        let (mut context, mut contract) = setup(&accounts(1));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let mut key = vec![ED25519_KEY_TYPE];
+        key.extend(keypair().public.as_bytes());
+        contract.set_signing_key(Some(Base58PublicKey(key)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(0)
+            .build());
+        let payload = payload(accounts(1).as_ref(), 1);
+        contract.ft_transfer_signed(payload.clone(), sign(&payload));
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        contract.ft_transfer_signed(payload.clone(), sign(&payload));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_SIGNATURE")]
+    fn test_signed_transfer_tampered() {
+        let sender_id = ValidAccountId::try_from(hex(keypair().public.as_bytes())).unwrap();
+        let (_, mut contract) = setup(&sender_id);
+        let payload = payload(sender_id.as_ref(), 1);
+        let signature = sign(&payload);
+        let mut tampered = payload;
+        tampered.amount = 500.into();
+        contract.ft_transfer_signed(tampered, signature);
+    }
+}
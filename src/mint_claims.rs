@@ -0,0 +1,165 @@
+//! Pull-based mints: the owner allocates tokens and the recipient claims them, paying for their
+//! own registration.
+//!
+//! Pending allocations reserve headroom under `max_supply` until they are claimed. Once an
+//! allocation expires, anyone can release it to return the headroom.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde::Serialize;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintClaim {
+    pub amount: U128,
+    pub expires_at: U64,
+}
+
+impl Contract {
+    /// Interest is settled before the reserved supply changes, since it caps the accrual.
+    fn internal_remove_mint_claim(&mut self, account_id: &AccountId) -> MintClaim {
+        let claim = self
+            .mint_claims
+            .remove(account_id)
+            .expect("ERR_NO_MINT_CLAIM");
+        self.internal_accrue_interest();
+        self.mint_claims_reserved -= claim.amount.0;
+        claim
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Allocates `amount` for `account_id` to claim with `claim_mint` before `expires_at`.
+    #[payable]
+    pub fn create_mint_claim(&mut self, account_id: ValidAccountId, amount: U128, expires_at: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        assert!(expires_at.0 > env::block_timestamp(), "ERR_EXPIRED");
+        if let Some(claim) = self.mint_claims.get(account_id.as_ref()) {
+            assert!(
+                claim.expires_at.0 <= env::block_timestamp(),
+                "ERR_MINT_CLAIM_EXISTS"
+            );
+            self.internal_remove_mint_claim(account_id.as_ref());
+        }
+        self.internal_accrue_interest();
+        let next_total_supply = self.internal_total_supply() + self.mint_claims_reserved + amount.0;
+        assert!(next_total_supply <= self.max_supply, "Overflow");
+        self.mint_claims_reserved += amount.0;
+        self.mint_claims
+            .insert(account_id.as_ref(), &MintClaim { amount, expires_at });
+    }
+
+    /// Mints the predecessor's allocation. An unregistered account must attach enough deposit
+    /// to cover its registration.
+    #[payable]
+    pub fn claim_mint(&mut self) -> U128 {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_not_paused(PauseSurface::Mint);
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let claim = self.internal_remove_mint_claim(&account_id);
+        assert!(claim.expires_at.0 > env::block_timestamp(), "ERR_EXPIRED");
+        self.assert_kyc_for_receipt(&account_id, claim.amount.0);
+        self.internal_mint(&account_id, claim.amount.into(), Some("mint claim"));
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        claim.amount
+    }
+
+    /// Drops an expired allocation and returns its headroom under `max_supply`.
+    pub fn release_expired_mint_claim(&mut self, account_id: ValidAccountId) {
+        let claim = self
+            .mint_claims
+            .get(account_id.as_ref())
+            .expect("ERR_NO_MINT_CLAIM");
+        assert!(
+            claim.expires_at.0 <= env::block_timestamp(),
+            "ERR_NOT_EXPIRED"
+        );
+        self.internal_remove_mint_claim(account_id.as_ref());
+    }
+
+    pub fn get_mint_claim(&self, account_id: ValidAccountId) -> Option<MintClaim> {
+        self.mint_claims.get(account_id.as_ref())
+    }
+
+    /// Supply reserved by pending allocations.
+    pub fn get_mint_claims_reserved(&self) -> U128 {
+        self.mint_claims_reserved.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_claim_and_expire() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.create_mint_claim(accounts(1), 600.into(), 100.into());
+        contract.create_mint_claim(accounts(2), 400.into(), 100.into());
+        assert_eq!(contract.get_mint_claims_reserved().0, 1_000);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        assert_eq!(contract.claim_mint().0, 600);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 600);
+
+        testing_env!(context.block_timestamp(100).attached_deposit(0).build());
+        contract.release_expired_mint_claim(accounts(2));
+        assert_eq!(contract.get_mint_claims_reserved().0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSED")]
+    fn test_claim_while_mint_paused() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.create_mint_claim(accounts(1), 600.into(), 100.into());
+        contract.pause(Some(vec![PauseSurface::Mint]));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.claim_mint();
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_KYC_REQUIRED")]
+    fn test_claim_requires_kyc() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_kyc_threshold(Some(500.into()));
+        contract.create_mint_claim(accounts(1), 600.into(), 100.into());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.claim_mint();
+    }
+
+    #[test]
+    #[should_panic(expected = "Overflow")]
+    fn test_mint_respects_reserved() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract(1_000);
+        contract.create_mint_claim(accounts(1), 600.into(), 100.into());
+        mint_to(&mut contract, accounts(2), 500);
+    }
+}
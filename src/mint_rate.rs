@@ -0,0 +1,115 @@
+//! Limits how often `mint` can be called, independent of the amounts minted.
+//!
+//! Every mint carries a nonce that must be higher than the minter's previous one, so a script
+//! retrying or replaying the same call can't mint twice. With `Config::max_mints_per_hour` set, a
+//! minter can call `mint` at most that many times in an hour counted from the first call of the
+//! window.
+
+use crate::config::ConfigUpdate;
+use crate::*;
+use near_sdk::json_types::U64;
+
+const HOUR: u64 = 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct MintRate {
+    pub last_nonce: u64,
+    pub window_started_at: u64,
+    /// Mints since `window_started_at`.
+    pub calls: u32,
+}
+
+impl Contract {
+    /// Checks the nonce and the call rate of the minting predecessor and records the call.
+    pub(crate) fn internal_record_mint_call(&mut self, nonce: u64) {
+        let minter_id = env::predecessor_account_id();
+        let mut rate = self.mint_rates.get(&minter_id).unwrap_or_default();
+        assert!(nonce > rate.last_nonce, "ERR_INVALID_NONCE");
+        rate.last_nonce = nonce;
+        let now = env::block_timestamp();
+        if now >= rate.window_started_at.saturating_add(HOUR) || rate.calls == 0 {
+            rate.window_started_at = now;
+            rate.calls = 0;
+        }
+        rate.calls += 1;
+        if let Some(max_calls) = self.config.max_mints_per_hour {
+            assert!(rate.calls <= max_calls, "ERR_MINT_RATE_EXCEEDED");
+        }
+        self.mint_rates.insert(&minter_id, &rate);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets how many times a minter may call `mint` per hour, unlimited with `None`.
+    #[payable]
+    pub fn set_max_mints_per_hour(&mut self, max_calls: Option<u32>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            max_mints_per_hour: Some(max_calls),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_max_mints_per_hour(&self) -> Option<u32> {
+        self.config.max_mints_per_hour
+    }
+
+    /// Last nonce used by `minter_id`, 0 before its first mint.
+    pub fn get_mint_nonce(&self, minter_id: ValidAccountId) -> U64 {
+        self.mint_rates
+            .get(minter_id.as_ref())
+            .map_or(0, |rate| rate.last_nonce)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_mint_rate_window() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.internal_ensure_registered(accounts(1).as_ref());
+        contract.set_max_mints_per_hour(Some(2));
+        contract.mint(accounts(1), 10.into(), None, 2.into());
+        contract.mint(accounts(1), 10.into(), None, 5.into());
+        assert_eq!(contract.get_mint_nonce(accounts(0)).0, 5);
+
+        testing_env!(context.block_timestamp(HOUR).build());
+        contract.mint(accounts(1), 10.into(), None, 6.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 30);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_MINT_RATE_EXCEEDED")]
+    fn test_mint_rate_exceeded() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.internal_ensure_registered(accounts(1).as_ref());
+        contract.set_max_mints_per_hour(Some(2));
+        contract.mint(accounts(1), 10.into(), None, 2.into());
+        contract.mint(accounts(1), 10.into(), None, 3.into());
+        contract.mint(accounts(1), 10.into(), None, 4.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_NONCE")]
+    fn test_reused_nonce() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.internal_ensure_registered(accounts(1).as_ref());
+        contract.mint(accounts(1), 10.into(), None, 2.into());
+        contract.mint(accounts(1), 10.into(), None, 2.into());
+    }
+}
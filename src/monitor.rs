@@ -0,0 +1,101 @@
+//! Large-transfer alerts: the owner registers a monitoring contract and a threshold, and every
+//! completed holder transfer above the threshold calls the monitor's `on_large_transfer` with the
+//! transfer details, e.g. to trip an automated circuit breaker.
+//!
+//! The notification is fire-and-forget: its outcome doesn't affect the transfer. Transfers held
+//! for acceptance or a compliance check notify once they complete. The settings live in the
+//! contract state, so transfers below the threshold cost nothing extra.
+
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::{ext_contract, Gas};
+
+const GAS_FOR_ON_LARGE_TRANSFER: Gas = 5_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+
+#[ext_contract(ext_transfer_monitor)]
+trait TransferMonitor {
+    fn on_large_transfer(&mut self, sender_id: AccountId, receiver_id: AccountId, amount: U128);
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferMonitorConfig {
+    pub monitor_id: AccountId,
+    /// Transfers above this amount are reported.
+    pub threshold: U128,
+}
+
+impl Contract {
+    /// Notifies the monitor of a completed holder transfer above the threshold.
+    pub(crate) fn internal_alert_large_transfer(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        let monitor = match &self.transfer_monitor {
+            Some(monitor) if amount > monitor.threshold.0 => monitor,
+            _ => return,
+        };
+        ext_transfer_monitor::on_large_transfer(
+            sender_id.clone(),
+            receiver_id.clone(),
+            amount.into(),
+            &monitor.monitor_id,
+            NO_DEPOSIT,
+            GAS_FOR_ON_LARGE_TRANSFER,
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers the monitoring contract notified of transfers above `threshold`, or stops the
+    /// alerts with `None`.
+    #[payable]
+    pub fn set_transfer_monitor(&mut self, monitor: Option<TransferMonitorConfig>) {
+        assert_one_yocto();
+        self.assert_owner();
+        if let Some(monitor) = &monitor {
+            assert!(
+                env::is_valid_account_id(monitor.monitor_id.as_bytes()),
+                "ERR_INVALID_ACCOUNT"
+            );
+        }
+        self.transfer_monitor = monitor;
+        events::emit_config_changed("transfer_monitor", &self.transfer_monitor);
+    }
+
+    pub fn get_transfer_monitor(&self) -> Option<TransferMonitorConfig> {
+        self.transfer_monitor.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, get_created_receipts};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_large_transfer_alert() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+        contract.set_transfer_monitor(Some(TransferMonitorConfig {
+            monitor_id: accounts(4).into(),
+            threshold: 100.into(),
+        }));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        assert!(get_created_receipts().is_empty());
+        contract.ft_transfer(accounts(2), 101.into(), None);
+        assert_eq!(get_created_receipts().len(), 1);
+    }
+}
@@ -0,0 +1,379 @@
+//! OTC orders: a seller escrows tokens for a fixed price in NEAR or in a NEP-141 token, either
+//! for a specific counterparty or for anyone, and a buyer fills the whole order by paying it.
+//!
+//! NEAR orders are filled with `fill_otc_order` and the exact price attached. Token orders are
+//! filled by sending the exact price with `ft_transfer_call` and `{"otc_order_id": "<id>"}` as
+//! `msg`. Either way the tokens go to the buyer and the payment to the seller in the same call.
+//! A token payment the seller can't receive, e.g. because it isn't registered with the payment
+//! token, is kept for the seller to withdraw with `withdraw_otc_payment`. Buyers must be
+//! registered. The seller pays for the storage of the order.
+//!
+//! Handing the tokens to the buyer is a holder transfer from the seller: it has to pass the
+//! transfer restrictions at fill time and is charged the transfer fee, which the seller escrows
+//! along with the order. Transfers that need a co-signature or a compliance check can't be made
+//! through OTC orders.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde_json::json;
+use near_sdk::{ext_contract, Gas, Promise, PromiseOrValue, PromiseResult};
+
+const GAS_FOR_FT_TRANSFER: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_OTC_PAYMENT: Gas = 10_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+
+pub type OtcOrderId = u64;
+
+#[ext_contract(ext_payment_token)]
+trait PaymentToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+#[ext_contract(ext_self)]
+trait OtcCallbacks {
+    fn on_otc_payment(&mut self, seller_id: AccountId, token_id: AccountId, amount: U128);
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OtcOrder {
+    pub seller_id: AccountId,
+    pub amount: Balance,
+    /// Transfer fee escrowed along with the amount.
+    pub fee: Balance,
+    pub price: Balance,
+    /// NEP-141 token the price is in, NEAR if `None`.
+    pub payment_token_id: Option<AccountId>,
+    /// Only account allowed to fill the order, anyone if `None`.
+    pub counterparty_id: Option<AccountId>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OtcOrderView {
+    pub order_id: U64,
+    pub seller_id: AccountId,
+    pub amount: U128,
+    pub price: U128,
+    pub payment_token_id: Option<AccountId>,
+    pub counterparty_id: Option<AccountId>,
+}
+
+/// `msg` of an `ft_transfer_call` filling an order.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(deny_unknown_fields)]
+struct OtcFillMsg {
+    otc_order_id: U64,
+}
+
+/// Order filled by an `ft_transfer_call` with `msg`, if it's meant to fill one.
+pub(crate) fn parse_fill_msg(msg: &str) -> Option<OtcOrderId> {
+    near_sdk::serde_json::from_str::<OtcFillMsg>(msg)
+        .ok()
+        .map(|fill| fill.otc_order_id.0)
+}
+
+impl Contract {
+    /// Removes an order being filled by `buyer_id` with `payment` in `payment_token_id`, and
+    /// hands the tokens to the buyer.
+    fn internal_fill_otc_order(
+        &mut self,
+        order_id: OtcOrderId,
+        buyer_id: &AccountId,
+        payment_token_id: Option<&AccountId>,
+        payment: Balance,
+    ) -> OtcOrder {
+        self.assert_not_paused(PauseSurface::Transfers);
+        let order = self.otc_orders.remove(&order_id).expect("ERR_NO_ORDER");
+        assert_eq!(
+            order.payment_token_id.as_ref(),
+            payment_token_id,
+            "ERR_WRONG_PAYMENT_TOKEN"
+        );
+        assert_eq!(payment, order.price, "ERR_WRONG_PAYMENT");
+        if let Some(counterparty_id) = order.counterparty_id.as_ref() {
+            assert_eq!(buyer_id, counterparty_id, "ERR_NOT_COUNTERPARTY");
+        }
+        assert!(
+            self.token.accounts.contains_key(buyer_id),
+            "ERR_NOT_REGISTERED"
+        );
+        self.internal_apply_holder_transfer_checks(&order.seller_id, buyer_id, order.amount, None);
+        assert!(
+            !self.internal_requires_cosignature(&order.seller_id, order.amount),
+            "ERR_COSIGNATURE_REQUIRED"
+        );
+        assert!(
+            self.config.compliance_registry.is_none(),
+            "ERR_COMPLIANCE_CHECK_REQUIRED"
+        );
+        self.internal_escrow_release(buyer_id, order.amount);
+        self.internal_on_transfer_completed(&order.seller_id, buyer_id, order.amount);
+        self.internal_charge_escrowed_fee(&order.seller_id, order.fee);
+        events::emit_event(
+            "otc_order_filled",
+            json!({
+                "order_id": U64(order_id),
+                "seller_id": order.seller_id,
+                "buyer_id": buyer_id,
+                "amount": U128(order.amount),
+                "price": U128(order.price),
+                "payment_token_id": order.payment_token_id,
+            }),
+        );
+        order
+    }
+
+    /// Fills a token order with a payment received through `ft_on_transfer`.
+    pub(crate) fn internal_fill_otc_order_ft(
+        &mut self,
+        token_id: AccountId,
+        buyer_id: AccountId,
+        payment: Balance,
+        order_id: OtcOrderId,
+    ) -> PromiseOrValue<U128> {
+        let order = self.internal_fill_otc_order(order_id, &buyer_id, Some(&token_id), payment);
+        self.internal_pay_otc_seller(order.seller_id, token_id, payment);
+        PromiseOrValue::Value(0.into())
+    }
+
+    fn internal_pay_otc_seller(&self, seller_id: AccountId, token_id: AccountId, amount: Balance) {
+        ext_payment_token::ft_transfer(
+            seller_id.clone(),
+            amount.into(),
+            Some("otc order".to_string()),
+            &token_id,
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+        .then(ext_self::on_otc_payment(
+            seller_id,
+            token_id,
+            amount.into(),
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_OTC_PAYMENT,
+        ));
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `amount` of the predecessor's tokens and the transfer fee on them for sale at
+    /// `price`, in yoctoNEAR or in `payment_token_id`, to `counterparty_id` or anyone. The
+    /// attached deposit covers the storage of the order; the rest is refunded.
+    #[payable]
+    pub fn create_otc_order(
+        &mut self,
+        amount: U128,
+        price: U128,
+        payment_token_id: Option<ValidAccountId>,
+        counterparty_id: Option<ValidAccountId>,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_not_paused(PauseSurface::Transfers);
+        assert!(amount.0 > 0 && price.0 > 0, "ERR_ZERO_AMOUNT");
+        let seller_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&seller_id);
+        let fee = self.internal_transfer_fee(amount.into());
+        self.internal_escrow_deposit(&seller_id, amount.0 + fee);
+        let order_id = self.next_otc_order_id;
+        self.next_otc_order_id += 1;
+        self.otc_orders.insert(
+            &order_id,
+            &OtcOrder {
+                seller_id,
+                amount: amount.into(),
+                fee,
+                price: price.into(),
+                payment_token_id: payment_token_id.map(|token_id| token_id.into()),
+                counterparty_id: counterparty_id.map(|account_id| account_id.into()),
+            },
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        order_id.into()
+    }
+
+    /// Withdraws an unfilled order of the predecessor, returning the escrowed tokens.
+    #[payable]
+    pub fn cancel_otc_order(&mut self, order_id: U64) {
+        assert_one_yocto();
+        let order = self.otc_orders.get(&order_id.0).expect("ERR_NO_ORDER");
+        assert_eq!(
+            env::predecessor_account_id(),
+            order.seller_id,
+            "ERR_NOT_ALLOWED"
+        );
+        self.otc_orders.remove(&order_id.0);
+        self.internal_escrow_release(&order.seller_id, order.amount + order.fee);
+    }
+
+    /// Fills a NEAR order with the attached deposit, which must be the exact price.
+    #[payable]
+    pub fn fill_otc_order(&mut self, order_id: U64) -> Promise {
+        let buyer_id = env::predecessor_account_id();
+        let order =
+            self.internal_fill_otc_order(order_id.0, &buyer_id, None, env::attached_deposit());
+        Promise::new(order.seller_id).transfer(order.price)
+    }
+
+    /// Retries paying out token payments that couldn't be sent to the predecessor.
+    pub fn withdraw_otc_payment(&mut self, token_id: ValidAccountId) {
+        let seller_id = env::predecessor_account_id();
+        let key = (seller_id.clone(), token_id.into());
+        let amount = self.otc_unpaid.remove(&key).expect("ERR_NO_PAYMENT");
+        self.internal_pay_otc_seller(seller_id, key.1, amount);
+    }
+
+    /// Keeps a token payment the seller couldn't receive for `withdraw_otc_payment`.
+    #[private]
+    pub fn on_otc_payment(&mut self, seller_id: AccountId, token_id: AccountId, amount: U128) {
+        if let PromiseResult::Failed = env::promise_result(0) {
+            let key = (seller_id, token_id);
+            let unpaid = self.otc_unpaid.get(&key).unwrap_or(0);
+            self.otc_unpaid.insert(&key, &(unpaid + amount.0));
+            log!("Payment of {} {} to {} failed", amount.0, key.1, key.0);
+        }
+    }
+
+    pub fn get_otc_order(&self, order_id: U64) -> Option<OtcOrderView> {
+        self.otc_orders.get(&order_id.0).map(|order| OtcOrderView {
+            order_id,
+            seller_id: order.seller_id,
+            amount: order.amount.into(),
+            price: order.price.into(),
+            payment_token_id: order.payment_token_id,
+            counterparty_id: order.counterparty_id,
+        })
+    }
+
+    /// Token payment waiting for `seller_id` to withdraw it.
+    pub fn get_otc_unpaid(&self, seller_id: ValidAccountId, token_id: ValidAccountId) -> U128 {
+        self.otc_unpaid
+            .get(&(seller_id.into(), token_id.into()))
+            .unwrap_or(0)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::fees::{FeeDestination, FeeRoute, TransferFee};
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_fill_near_order() {
+        let (mut context, mut contract) = setup();
+        let order_id = contract.create_otc_order(200.into(), 5_000.into(), None, None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 300);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(5_000)
+            .build());
+        contract.fill_otc_order(order_id);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 200);
+        assert!(contract.get_otc_order(order_id).is_none());
+    }
+
+    #[test]
+    fn test_fill_token_order() {
+        let (mut context, mut contract) = setup();
+        let order_id =
+            contract.create_otc_order(200.into(), 70.into(), Some(accounts(4)), Some(accounts(2)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(0)
+            .build());
+        let msg = json!({ "otc_order_id": order_id }).to_string();
+        contract.ft_on_transfer(accounts(2), 70.into(), msg);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 200);
+    }
+
+    #[test]
+    fn test_fill_charges_transfer_fee() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.set_transfer_fee(Some(TransferFee {
+            fee_bps: 1_000,
+            routes: vec![FeeRoute {
+                destination: FeeDestination::Burn,
+                weight_bps: 10_000,
+            }],
+        }));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let order_id = contract.create_otc_order(200.into(), 5_000.into(), None, None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 280);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(5_000)
+            .build());
+        contract.fill_otc_order(order_id);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 200);
+        assert_eq!(contract.ft_total_supply().0, 480);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BELOW_MIN_TRANSFER")]
+    fn test_fill_below_min_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.set_min_transfer_amount(10.into());
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let order_id = contract.create_otc_order(1.into(), 1.into(), None, Some(accounts(2)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.fill_otc_order(order_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_COUNTERPARTY")]
+    fn test_fill_order_of_other_counterparty() {
+        let (mut context, mut contract) = setup();
+        let order_id = contract.create_otc_order(200.into(), 5_000.into(), None, Some(accounts(3)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(5_000)
+            .build());
+        contract.fill_otc_order(order_id);
+    }
+}
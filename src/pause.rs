@@ -0,0 +1,516 @@
+//! Emergency stop. Guardians, e.g. a monitoring bot, can pause the token but can't do anything
+//! else; only the owner manages guardians and unpauses.
+//!
+//! Surfaces are paused independently: transfers (both `ft_transfer` and `ft_transfer_call`),
+//! `ft_transfer_call` alone, minting, sale purchases and new stakes. `get_paused_mask` shows
+//! which are paused as a bitmask of `PauseSurface::bit`.
+//!
+//! With `Config::max_pause_duration` set, a pause expires on its own after that long, so
+//! neither a guardian nor a compromised owner can freeze the token indefinitely. A surface that
+//! is paused already can't be paused again, which would extend its pause. Once a pause expired or
+//! was lifted, the surface can only be paused again after a cooldown as long as
+//! `max_pause_duration`, by the owner too. Once set, `max_pause_duration` can only be lowered.
+//!
+//! Emergency mode is a softer circuit breaker for incident response: staking, the sale and
+//! payroll reject new deposits, but everyone can still exit by unstaking, claiming, withdrawing
+//! or reclaiming refunds. Early unstakes aren't penalized meanwhile. Guardians may enter it,
+//! only the owner leaves it.
+
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json::json;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum PauseSurface {
+    Transfers,
+    FtTransferCall,
+    Mint,
+    Sale,
+    Staking,
+}
+
+pub const ALL_SURFACES: [PauseSurface; 5] = [
+    PauseSurface::Transfers,
+    PauseSurface::FtTransferCall,
+    PauseSurface::Mint,
+    PauseSurface::Sale,
+    PauseSurface::Staking,
+];
+
+impl PauseSurface {
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SurfacePause {
+    pub surface: PauseSurface,
+    /// When the pause expires on its own, never if `None`.
+    pub paused_until: Option<U64>,
+}
+
+impl SurfacePause {
+    fn is_active(&self, now: u64) -> bool {
+        self.paused_until
+            .is_none_or(|paused_until| now < paused_until.0)
+    }
+}
+
+impl Contract {
+    /// Pauses in effect, i.e. not expired.
+    pub(crate) fn internal_active_pauses(&self) -> Vec<SurfacePause> {
+        let now = env::block_timestamp();
+        self.config
+            .pauses
+            .iter()
+            .filter(|pause| pause.is_active(now))
+            .cloned()
+            .collect()
+    }
+
+    /// Pauses in effect or expired less than the re-pause cooldown ago, which is as long as
+    /// `Config::max_pause_duration`.
+    fn internal_recent_pauses(&self, now: u64) -> Vec<SurfacePause> {
+        let cooldown = self.config.max_pause_duration.unwrap_or(0);
+        self.config
+            .pauses
+            .iter()
+            .filter(|pause| {
+                pause
+                    .paused_until
+                    .is_none_or(|paused_until| now < paused_until.0.saturating_add(cooldown))
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn internal_is_paused(&self, surface: PauseSurface) -> bool {
+        self.internal_active_pauses()
+            .iter()
+            .any(|pause| pause.surface == surface)
+    }
+
+    pub(crate) fn assert_not_paused(&self, surface: PauseSurface) {
+        assert!(!self.internal_is_paused(surface), "ERR_PAUSED");
+    }
+
+    /// Rejects new deposits into staking, the sale and payroll during emergency mode.
+    pub(crate) fn assert_not_emergency(&self) {
+        assert!(!self.config.emergency_mode, "ERR_EMERGENCY_MODE");
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_add_guardian(&mut self, account_id: AccountId) {
+        assert!(!self.guardians.contains(&account_id), "ERR_GUARDIAN_EXISTS");
+        self.guardians.push(account_id.clone());
+        events::emit_event("guardian_added", json!({ "account_id": account_id }));
+    }
+
+    pub(crate) fn internal_remove_guardian(&mut self, account_id: AccountId) {
+        let index = self
+            .guardians
+            .iter()
+            .position(|guardian_id| guardian_id == &account_id)
+            .expect("ERR_NOT_GUARDIAN");
+        self.guardians.swap_remove(index);
+        events::emit_event("guardian_removed", json!({ "account_id": account_id }));
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn add_guardian(&mut self, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_add_guardian(account_id.into());
+    }
+
+    #[payable]
+    pub fn remove_guardian(&mut self, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_remove_guardian(account_id.into());
+    }
+
+    pub fn get_guardians(&self) -> Vec<AccountId> {
+        self.guardians.clone()
+    }
+
+    /// Pauses the given surfaces, all of them if `None`. Callable by the owner and the guardians,
+    /// who both have to wait for the cooldown after a pause of the surface ended.
+    #[payable]
+    pub fn pause(&mut self, surfaces: Option<Vec<PauseSurface>>) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(
+            account_id == self.owner_id || self.guardians.contains(&account_id),
+            "ERR_NOT_ALLOWED"
+        );
+        let surfaces = surfaces.unwrap_or_else(|| ALL_SURFACES.to_vec());
+        assert!(!surfaces.is_empty(), "ERR_NO_SURFACES");
+        let now = env::block_timestamp();
+        let paused_until = self
+            .config
+            .max_pause_duration
+            .map(|duration| U64(now + duration));
+        let mut pauses = self.internal_recent_pauses(now);
+        for surface in surfaces.iter() {
+            assert!(
+                pauses
+                    .iter()
+                    .all(|pause| pause.surface != *surface || !pause.is_active(now)),
+                "ERR_PAUSED"
+            );
+            assert!(
+                pauses.iter().all(|pause| pause.surface != *surface),
+                "ERR_PAUSE_COOLDOWN"
+            );
+            pauses.retain(|pause| pause.surface != *surface);
+            pauses.push(SurfacePause {
+                surface: *surface,
+                paused_until,
+            });
+        }
+        self.config.pauses = pauses;
+        events::emit_event(
+            "paused",
+            json!({
+                "account_id": account_id,
+                "surfaces": surfaces,
+                "paused_until": paused_until,
+            }),
+        );
+    }
+
+    /// Lifts the pause of the given surfaces, of all of them if `None`. The cooldown before they
+    /// can be paused again starts now.
+    #[payable]
+    pub fn unpause(&mut self, surfaces: Option<Vec<PauseSurface>>) {
+        assert_one_yocto();
+        self.assert_owner();
+        let now = env::block_timestamp();
+        let mut pauses = self.internal_recent_pauses(now);
+        let surfaces = surfaces.unwrap_or_else(|| {
+            self.internal_active_pauses()
+                .iter()
+                .map(|pause| pause.surface)
+                .collect()
+        });
+        assert!(!surfaces.is_empty(), "ERR_NOT_PAUSED");
+        for surface in surfaces.iter() {
+            let pause = pauses
+                .iter_mut()
+                .find(|pause| pause.surface == *surface && pause.is_active(now))
+                .expect("ERR_NOT_PAUSED");
+            pause.paused_until = Some(now.into());
+        }
+        self.config.pauses = pauses;
+        events::emit_event(
+            "unpaused",
+            json!({ "account_id": self.owner_id, "surfaces": surfaces }),
+        );
+    }
+
+    /// Enters or leaves emergency mode. Guardians may only enter it.
+    #[payable]
+    pub fn set_emergency_mode(&mut self, enabled: bool) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(
+            account_id == self.owner_id || (enabled && self.guardians.contains(&account_id)),
+            "ERR_NOT_ALLOWED"
+        );
+        assert_ne!(self.config.emergency_mode, enabled, "ERR_NO_CHANGE");
+        self.config.emergency_mode = enabled;
+        events::emit_event(
+            "emergency_mode_changed",
+            json!({ "account_id": account_id, "enabled": enabled }),
+        );
+    }
+
+    pub fn is_emergency_mode(&self) -> bool {
+        self.config.emergency_mode
+    }
+
+    /// Whether the surface is paused, or any surface if `None`.
+    pub fn is_paused(&self, surface: Option<PauseSurface>) -> bool {
+        match surface {
+            Some(surface) => self.internal_is_paused(surface),
+            None => !self.internal_active_pauses().is_empty(),
+        }
+    }
+
+    /// Paused surfaces as a bitmask of `PauseSurface::bit`.
+    pub fn get_paused_mask(&self) -> u8 {
+        self.internal_active_pauses()
+            .iter()
+            .fold(0, |mask, pause| mask | pause.surface.bit())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::config::ConfigUpdate;
+    #[cfg(feature = "staking")]
+    use crate::staking::{PenaltyDestination, StakingLockTier};
+    use crate::test_utils::*;
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSED")]
+    fn test_guardian_pause() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 100);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.add_guardian(accounts(3));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.pause(None);
+        assert!(contract.is_paused(None));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ALLOWED")]
+    fn test_guardian_cannot_unpause() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.add_guardian(accounts(3));
+        contract.pause(None);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.unpause(None);
+    }
+
+    #[test]
+    fn test_pause_expires() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 100);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.update_config(ConfigUpdate {
+            max_pause_duration: Some(Some(100.into())),
+            ..Default::default()
+        });
+        testing_env!(context.block_timestamp(10).build());
+        contract.pause(None);
+        assert_eq!(
+            contract.get_config().pauses[0].paused_until,
+            Some(110.into())
+        );
+
+        testing_env!(context.block_timestamp(109).build());
+        assert!(contract.is_paused(None));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(110)
+            .build());
+        assert!(!contract.is_paused(None));
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+    }
+
+    #[test]
+    fn test_pause_surfaces() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 100);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.pause(Some(vec![PauseSurface::Mint, PauseSurface::Staking]));
+        assert_eq!(
+            contract.get_paused_mask(),
+            PauseSurface::Mint.bit() | PauseSurface::Staking.bit()
+        );
+        assert!(contract.is_paused(Some(PauseSurface::Mint)));
+        assert!(!contract.is_paused(Some(PauseSurface::Transfers)));
+
+        contract.unpause(Some(vec![PauseSurface::Mint]));
+        assert_eq!(contract.get_paused_mask(), PauseSurface::Staking.bit());
+        contract.mint(accounts(1), 10.into(), None, next_mint_nonce(&contract));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+    }
+
+    #[test]
+    fn test_guardian_repause_after_cooldown() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.add_guardian(accounts(3));
+        contract.update_config(ConfigUpdate {
+            max_pause_duration: Some(Some(100.into())),
+            ..Default::default()
+        });
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.pause(Some(vec![PauseSurface::Transfers]));
+
+        testing_env!(context.block_timestamp(150).build());
+        assert!(!contract.is_paused(None));
+        contract.pause(Some(vec![PauseSurface::Mint]));
+        testing_env!(context.block_timestamp(200).build());
+        contract.pause(Some(vec![PauseSurface::Transfers]));
+        assert!(contract.is_paused(Some(PauseSurface::Transfers)));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSE_COOLDOWN")]
+    fn test_guardian_repause_right_after_expiry() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.add_guardian(accounts(3));
+        contract.update_config(ConfigUpdate {
+            max_pause_duration: Some(Some(100.into())),
+            ..Default::default()
+        });
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.pause(None);
+        testing_env!(context.block_timestamp(100).build());
+        contract.pause(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSE_COOLDOWN")]
+    fn test_owner_repause_right_after_expiry() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.update_config(ConfigUpdate {
+            max_pause_duration: Some(Some(100.into())),
+            ..Default::default()
+        });
+        contract.pause(None);
+        testing_env!(context.block_timestamp(100).build());
+        contract.pause(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSE_COOLDOWN")]
+    fn test_repause_right_after_unpause() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.update_config(ConfigUpdate {
+            max_pause_duration: Some(Some(100.into())),
+            ..Default::default()
+        });
+        contract.pause(None);
+        testing_env!(context.block_timestamp(50).build());
+        contract.unpause(None);
+        assert!(!contract.is_paused(None));
+        contract.pause(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSE_DURATION_INCREASE")]
+    fn test_max_pause_duration_cannot_be_lifted() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.update_config(ConfigUpdate {
+            max_pause_duration: Some(Some(100.into())),
+            ..Default::default()
+        });
+        contract.update_config(ConfigUpdate {
+            max_pause_duration: Some(Some(50.into())),
+            ..Default::default()
+        });
+        contract.update_config(ConfigUpdate {
+            max_pause_duration: Some(None),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSED")]
+    fn test_repause_does_not_extend() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.pause(Some(vec![PauseSurface::Sale]));
+        contract.pause(Some(vec![PauseSurface::Mint, PauseSurface::Sale]));
+    }
+
+    #[test]
+    #[cfg(feature = "staking")]
+    fn test_emergency_mode_allows_exit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.set_staking_lock_tiers(vec![StakingLockTier {
+            duration: 1_000.into(),
+            penalty_bps: 1_000,
+            penalty_destination: PenaltyDestination::Burn,
+        }]);
+        contract.add_guardian(accounts(3));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(500.into(), Some(0));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(1)
+            .build());
+        contract.set_emergency_mode(true);
+        assert!(contract.get_config().emergency_mode);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        assert_eq!(contract.unstake(500.into()).0, 500);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+    }
+
+    #[test]
+    #[cfg(feature = "staking")]
+    #[should_panic(expected = "ERR_EMERGENCY_MODE")]
+    fn test_emergency_mode_rejects_deposits() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.set_emergency_mode(true);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(500.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ALLOWED")]
+    fn test_guardian_cannot_leave_emergency_mode() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.add_guardian(accounts(3));
+        contract.set_emergency_mode(true);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.set_emergency_mode(false);
+    }
+}
@@ -0,0 +1,319 @@
+//! Payroll: employers fund a pool and pay each employee a salary that streams per second.
+//!
+//! Funded tokens are escrowed on the contract account. An employee may hold several concurrent
+//! streams, from the same or from different employers, and withdraws whatever has accrued on
+//! each of them. Paused streams stop accruing until resumed, terminated streams stop for good
+//! but keep what was accrued until it's withdrawn.
+
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde::Serialize;
+
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+pub type StreamId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PayrollStream {
+    pub employer_id: AccountId,
+    pub employee_id: AccountId,
+    pub rate_per_second: Balance,
+    /// Salary settled up to `settled_at` and not withdrawn yet.
+    pub accrued: Balance,
+    pub settled_at: u64,
+    pub paused: bool,
+    pub terminated: bool,
+}
+
+impl PayrollStream {
+    /// Salary accrued up to `now`, including the part that hasn't been settled yet.
+    fn accrued_at(&self, now: u64) -> Balance {
+        if self.paused || self.terminated {
+            return self.accrued;
+        }
+        let seconds = now.saturating_sub(self.settled_at) / NANOSECONDS_PER_SECOND;
+        self.accrued + self.rate_per_second * seconds as u128
+    }
+
+    fn settle(&mut self, now: u64) {
+        let seconds = now.saturating_sub(self.settled_at) / NANOSECONDS_PER_SECOND;
+        self.accrued = self.accrued_at(now);
+        self.settled_at += seconds * NANOSECONDS_PER_SECOND;
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayrollStreamView {
+    pub stream_id: U64,
+    pub employer_id: AccountId,
+    pub employee_id: AccountId,
+    pub rate_per_second: U128,
+    pub withdrawable: U128,
+    pub paused: bool,
+    pub terminated: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EmployerPayrollView {
+    /// Escrowed tokens, including salary accrued but not withdrawn yet.
+    pub pool: U128,
+    /// Salary owed to employees right now.
+    pub owed: U128,
+    pub streams: Vec<PayrollStreamView>,
+}
+
+impl Contract {
+    fn internal_get_stream(&self, stream_id: StreamId) -> PayrollStream {
+        self.payroll_streams.get(&stream_id).expect("ERR_NO_STREAM")
+    }
+
+    fn internal_stream_view(&self, stream_id: StreamId) -> PayrollStreamView {
+        let stream = self.internal_get_stream(stream_id);
+        PayrollStreamView {
+            stream_id: stream_id.into(),
+            withdrawable: stream.accrued_at(env::block_timestamp()).into(),
+            rate_per_second: stream.rate_per_second.into(),
+            paused: stream.paused,
+            terminated: stream.terminated,
+            employer_id: stream.employer_id,
+            employee_id: stream.employee_id,
+        }
+    }
+
+    /// Settles the stream and checks that the predecessor is its employer.
+    fn internal_employer_stream(&self, stream_id: StreamId) -> PayrollStream {
+        let mut stream = self.internal_get_stream(stream_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            stream.employer_id,
+            "ERR_NOT_ALLOWED"
+        );
+        stream.settle(env::block_timestamp());
+        stream
+    }
+
+    fn internal_payroll_owed(&self, employer_id: &AccountId) -> Balance {
+        let now = env::block_timestamp();
+        self.payroll_by_employer
+            .get(employer_id)
+            .unwrap_or_default()
+            .iter()
+            .map(|stream_id| self.internal_get_stream(*stream_id).accrued_at(now))
+            .sum()
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `amount` of the employer's tokens into their payroll pool. The funding counts
+    /// against the employer's volume limits, as the pool is paid out to employees.
+    #[payable]
+    pub fn payroll_fund(&mut self, amount: U128) {
+        assert_one_yocto();
+        self.assert_not_emergency();
+        let employer_id = env::predecessor_account_id();
+        self.internal_apply_holder_transfer_checks(
+            &employer_id,
+            &env::current_account_id(),
+            amount.into(),
+            None,
+        );
+        self.internal_escrow_deposit(&employer_id, amount.into());
+        let pool = self.payroll_pools.get(&employer_id).unwrap_or(0);
+        self.payroll_pools.insert(&employer_id, &(pool + amount.0));
+    }
+
+    /// Returns `amount` of the pool that isn't owed to employees back to the employer.
+    #[payable]
+    pub fn payroll_defund(&mut self, amount: U128) {
+        assert_one_yocto();
+        let employer_id = env::predecessor_account_id();
+        let pool = self.payroll_pools.get(&employer_id).unwrap_or(0);
+        let available = pool.saturating_sub(self.internal_payroll_owed(&employer_id));
+        assert!(amount.0 <= available, "ERR_NOT_ENOUGH_POOL");
+        self.payroll_pools.insert(&employer_id, &(pool - amount.0));
+        self.internal_escrow_release(&employer_id, amount.into());
+    }
+
+    /// Starts paying `employee_id` at `rate_per_second` out of the predecessor's pool.
+    /// The attached deposit covers the storage of the stream.
+    #[payable]
+    pub fn payroll_add_employee(
+        &mut self,
+        employee_id: ValidAccountId,
+        rate_per_second: U128,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_not_emergency();
+        assert!(rate_per_second.0 > 0, "ERR_ZERO_RATE");
+        let employer_id = env::predecessor_account_id();
+        let employee_id: AccountId = employee_id.into();
+        let stream_id = self.next_payroll_stream_id;
+        self.next_payroll_stream_id += 1;
+        self.payroll_streams.insert(
+            &stream_id,
+            &PayrollStream {
+                employer_id: employer_id.clone(),
+                employee_id: employee_id.clone(),
+                rate_per_second: rate_per_second.into(),
+                accrued: 0,
+                settled_at: env::block_timestamp(),
+                paused: false,
+                terminated: false,
+            },
+        );
+        let mut employer_streams = self
+            .payroll_by_employer
+            .get(&employer_id)
+            .unwrap_or_default();
+        employer_streams.push(stream_id);
+        self.payroll_by_employer
+            .insert(&employer_id, &employer_streams);
+        let mut employee_streams = self
+            .payroll_by_employee
+            .get(&employee_id)
+            .unwrap_or_default();
+        employee_streams.push(stream_id);
+        self.payroll_by_employee
+            .insert(&employee_id, &employee_streams);
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+        stream_id.into()
+    }
+
+    #[payable]
+    pub fn payroll_pause(&mut self, stream_id: U64) {
+        assert_one_yocto();
+        let mut stream = self.internal_employer_stream(stream_id.into());
+        assert!(!stream.terminated, "ERR_TERMINATED");
+        stream.paused = true;
+        self.payroll_streams.insert(&stream_id.into(), &stream);
+    }
+
+    #[payable]
+    pub fn payroll_resume(&mut self, stream_id: U64) {
+        assert_one_yocto();
+        let mut stream = self.internal_employer_stream(stream_id.into());
+        assert!(!stream.terminated, "ERR_TERMINATED");
+        stream.paused = false;
+        stream.settled_at = env::block_timestamp();
+        self.payroll_streams.insert(&stream_id.into(), &stream);
+    }
+
+    /// Stops the stream for good. Salary accrued so far stays withdrawable.
+    #[payable]
+    pub fn payroll_terminate(&mut self, stream_id: U64) {
+        assert_one_yocto();
+        let mut stream = self.internal_employer_stream(stream_id.into());
+        stream.terminated = true;
+        self.payroll_streams.insert(&stream_id.into(), &stream);
+    }
+
+    /// Withdraws the accrued salary of the stream, limited by what's left in the employer's
+    /// pool. Returns the withdrawn amount.
+    #[payable]
+    pub fn payroll_withdraw(&mut self, stream_id: U64) -> U128 {
+        assert_one_yocto();
+        let stream_id: StreamId = stream_id.into();
+        let mut stream = self.internal_get_stream(stream_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            stream.employee_id,
+            "ERR_NOT_ALLOWED"
+        );
+        self.assert_not_blacklisted(&stream.employee_id);
+        self.assert_not_blacklisted(&stream.employer_id);
+        stream.settle(env::block_timestamp());
+        let pool = self.payroll_pools.get(&stream.employer_id).unwrap_or(0);
+        let amount = std::cmp::min(stream.accrued, pool);
+        assert!(amount > 0, "ERR_NOTHING_TO_WITHDRAW");
+        stream.accrued -= amount;
+        self.payroll_pools
+            .insert(&stream.employer_id, &(pool - amount));
+        self.payroll_streams.insert(&stream_id, &stream);
+        self.internal_escrow_release(&stream.employee_id, amount);
+        amount.into()
+    }
+
+    pub fn get_payroll_stream(&self, stream_id: U64) -> PayrollStreamView {
+        self.internal_stream_view(stream_id.into())
+    }
+
+    pub fn get_employer_payroll(&self, employer_id: ValidAccountId) -> EmployerPayrollView {
+        let streams = self
+            .payroll_by_employer
+            .get(employer_id.as_ref())
+            .unwrap_or_default();
+        EmployerPayrollView {
+            pool: self
+                .payroll_pools
+                .get(employer_id.as_ref())
+                .unwrap_or(0)
+                .into(),
+            owed: self.internal_payroll_owed(employer_id.as_ref()).into(),
+            streams: streams
+                .into_iter()
+                .map(|id| self.internal_stream_view(id))
+                .collect(),
+        }
+    }
+
+    pub fn get_employee_payroll(&self, employee_id: ValidAccountId) -> Vec<PayrollStreamView> {
+        self.payroll_by_employee
+            .get(employee_id.as_ref())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| self.internal_stream_view(id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_payroll_streams() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        mint_to(&mut contract, accounts(2), 0);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        let stream_id = contract.payroll_add_employee(accounts(2), 10.into());
+        testing_env!(context.attached_deposit(1).build());
+        contract.payroll_fund(500.into());
+
+        testing_env!(context.block_timestamp(20 * NANOSECONDS_PER_SECOND).build());
+        contract.payroll_pause(stream_id);
+        assert_eq!(contract.get_employer_payroll(accounts(1)).owed.0, 200);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(60 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.payroll_withdraw(stream_id).0, 200);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 200);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.payroll_resume(stream_id);
+        contract.payroll_defund(300.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 800);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(70 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.get_payroll_stream(stream_id).withdrawable.0, 100);
+    }
+}
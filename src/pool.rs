@@ -0,0 +1,343 @@
+//! Built-in NEAR/token pool giving the token liquidity without an external DEX.
+//!
+//! The owner seeds the pool with NEAR and tokens and may add to it or withdraw from it later.
+//! Anyone can swap either way at the constant-product price, `near_reserve * token_reserve`
+//! staying constant apart from the fee, which stays in the pool. Swaps take a minimum output so
+//! they fail instead of filling at a worse price than expected. Pool tokens are escrowed on the
+//! contract account; the NEAR reserve is tracked separately from the contract's own balance.
+//!
+//! The owner can lock the pool's liquidity until a date with `lock_liquidity`, which
+//! `get_liquidity_lock` proves publicly. While locked, the owner can't bring the token reserve
+//! below the locked amount, and can only withdraw NEAR along with at least the same share of the
+//! tokens, so the pool can't be drained of NEAR either. Swaps still move the reserves freely.
+
+use crate::interest::BPS_DENOMINATOR;
+use crate::pause::PauseSurface;
+use crate::utils::mul_div;
+use crate::*;
+use near_sdk::serde_json::json;
+use near_sdk::Promise;
+
+/// Swap fee in basis points of the input.
+pub const POOL_FEE_BPS: u128 = 30;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct Pool {
+    pub near_reserve: Balance,
+    pub token_reserve: Balance,
+    pub lock: Option<LiquidityLock>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LiquidityLock {
+    /// Token reserve the owner can't withdraw below.
+    pub amount: Balance,
+    pub locked_at: u64,
+    pub unlock_at: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LiquidityLockView {
+    pub amount: U128,
+    pub locked_at: U64,
+    pub unlock_at: U64,
+    pub active: bool,
+    pub near_reserve: U128,
+    pub token_reserve: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolView {
+    pub near_reserve: U128,
+    pub token_reserve: U128,
+    pub fee_bps: u32,
+}
+
+/// Output of a constant-product swap of `amount_in` after the fee.
+fn swap_output(amount_in: Balance, reserve_in: Balance, reserve_out: Balance) -> Balance {
+    let amount_in = mul_div(
+        amount_in,
+        BPS_DENOMINATOR - POOL_FEE_BPS,
+        BPS_DENOMINATOR,
+        false,
+    );
+    mul_div(reserve_out, amount_in, reserve_in + amount_in, false)
+}
+
+fn emit_pool_swap(account_id: &AccountId, near: Balance, tokens: Balance, buy: bool) {
+    events::emit_event(
+        "pool_swap",
+        json!({
+            "account_id": account_id,
+            "side": if buy { "buy" } else { "sell" },
+            "near": U128(near),
+            "tokens": U128(tokens),
+        }),
+    );
+}
+
+impl Contract {
+    fn internal_pool(&self) -> &Pool {
+        let pool = &self.pool;
+        assert!(
+            pool.near_reserve > 0 && pool.token_reserve > 0,
+            "ERR_POOL_EMPTY"
+        );
+        pool
+    }
+
+    /// Checks that the owner may withdraw `near_amount` and `token_amount` from the pool under
+    /// the liquidity lock.
+    fn internal_assert_pool_unlocked(&self, near_amount: Balance, token_amount: Balance) {
+        let lock = match self.pool.lock.as_ref() {
+            Some(lock) if env::block_timestamp() < lock.unlock_at => lock,
+            _ => return,
+        };
+        assert!(
+            self.pool.token_reserve.saturating_sub(token_amount) >= lock.amount,
+            "ERR_LIQUIDITY_LOCKED"
+        );
+        // The NEAR share withdrawn is at most the token share withdrawn.
+        assert!(
+            near_amount == 0
+                || mul_div(
+                    near_amount,
+                    self.pool.token_reserve,
+                    self.pool.near_reserve,
+                    true
+                ) <= token_amount,
+            "ERR_LIQUIDITY_LOCKED"
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds the attached NEAR and `token_amount` of the owner's tokens to the pool.
+    #[payable]
+    pub fn add_pool_liquidity(&mut self, token_amount: U128) -> PoolView {
+        self.assert_owner();
+        let near_amount = env::attached_deposit();
+        assert!(near_amount > 0 || token_amount.0 > 0, "ERR_ZERO_AMOUNT");
+        if token_amount.0 > 0 {
+            self.internal_escrow_deposit(&self.owner_id.clone(), token_amount.into());
+        }
+        self.pool.near_reserve += near_amount;
+        self.pool.token_reserve += token_amount.0;
+        self.get_pool()
+    }
+
+    /// Withdraws NEAR and tokens from the pool to the owner.
+    #[payable]
+    pub fn remove_pool_liquidity(&mut self, near_amount: U128, token_amount: U128) -> PoolView {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_assert_pool_unlocked(near_amount.0, token_amount.0);
+        self.pool.near_reserve = self
+            .pool
+            .near_reserve
+            .checked_sub(near_amount.0)
+            .expect("ERR_NOT_ENOUGH_LIQUIDITY");
+        self.pool.token_reserve = self
+            .pool
+            .token_reserve
+            .checked_sub(token_amount.0)
+            .expect("ERR_NOT_ENOUGH_LIQUIDITY");
+        if token_amount.0 > 0 {
+            self.internal_escrow_release(&self.owner_id.clone(), token_amount.into());
+        }
+        if near_amount.0 > 0 {
+            Promise::new(self.owner_id.clone()).transfer(near_amount.0);
+        }
+        self.get_pool()
+    }
+
+    /// Buys tokens with the attached NEAR, failing if that buys less than `min_amount_out`.
+    /// Returns the amount bought.
+    #[payable]
+    pub fn swap_near_for_token(&mut self, min_amount_out: U128) -> U128 {
+        self.assert_not_paused(PauseSurface::Transfers);
+        let account_id = env::predecessor_account_id();
+        let near_in = env::attached_deposit();
+        assert!(near_in > 0, "ERR_ZERO_AMOUNT");
+        let pool = self.internal_pool();
+        let tokens_out = swap_output(near_in, pool.near_reserve, pool.token_reserve);
+        assert!(
+            tokens_out > 0 && tokens_out >= min_amount_out.0,
+            "ERR_SLIPPAGE"
+        );
+        assert!(
+            self.token.accounts.contains_key(&account_id),
+            "ERR_NOT_REGISTERED"
+        );
+        self.assert_not_blacklisted(&account_id);
+        self.assert_kyc_for_receipt(&account_id, tokens_out);
+        self.pool.near_reserve += near_in;
+        self.pool.token_reserve -= tokens_out;
+        self.internal_escrow_release(&account_id, tokens_out);
+        emit_pool_swap(&account_id, near_in, tokens_out, true);
+        tokens_out.into()
+    }
+
+    /// Sells `amount` of the predecessor's tokens for NEAR, failing if that yields less than
+    /// `min_amount_out`. Returns the NEAR received.
+    #[payable]
+    pub fn swap_token_for_near(&mut self, amount: U128, min_amount_out: U128) -> U128 {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        let account_id = env::predecessor_account_id();
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        let pool = self.internal_pool();
+        let near_out = swap_output(amount.0, pool.token_reserve, pool.near_reserve);
+        assert!(near_out > 0 && near_out >= min_amount_out.0, "ERR_SLIPPAGE");
+        self.assert_not_blacklisted(&account_id);
+        self.internal_escrow_deposit(&account_id, amount.into());
+        self.pool.token_reserve += amount.0;
+        self.pool.near_reserve -= near_out;
+        Promise::new(account_id.clone()).transfer(near_out);
+        emit_pool_swap(&account_id, near_out, amount.0, false);
+        near_out.into()
+    }
+
+    /// Locks `amount` of the pool's token reserve, with the NEAR paired with it, until
+    /// `unlock_at`. An existing lock can only be raised and extended.
+    #[payable]
+    pub fn lock_liquidity(&mut self, amount: U128, unlock_at: U64) -> LiquidityLockView {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        assert!(
+            amount.0 <= self.pool.token_reserve,
+            "ERR_NOT_ENOUGH_LIQUIDITY"
+        );
+        assert!(unlock_at.0 > env::block_timestamp(), "ERR_UNLOCK_IN_PAST");
+        if let Some(lock) = self
+            .pool
+            .lock
+            .as_ref()
+            .filter(|lock| env::block_timestamp() < lock.unlock_at)
+        {
+            assert!(
+                amount.0 >= lock.amount && unlock_at.0 >= lock.unlock_at,
+                "ERR_LOCK_DECREASE"
+            );
+        }
+        self.pool.lock = Some(LiquidityLock {
+            amount: amount.into(),
+            locked_at: env::block_timestamp(),
+            unlock_at: unlock_at.into(),
+        });
+        events::emit_event(
+            "liquidity_locked",
+            json!({ "amount": amount, "unlock_at": unlock_at }),
+        );
+        self.get_liquidity_lock().unwrap()
+    }
+
+    /// The liquidity lock with the current reserves, `None` if liquidity was never locked.
+    pub fn get_liquidity_lock(&self) -> Option<LiquidityLockView> {
+        self.pool.lock.as_ref().map(|lock| LiquidityLockView {
+            amount: lock.amount.into(),
+            locked_at: lock.locked_at.into(),
+            unlock_at: lock.unlock_at.into(),
+            active: env::block_timestamp() < lock.unlock_at,
+            near_reserve: self.pool.near_reserve.into(),
+            token_reserve: self.pool.token_reserve.into(),
+        })
+    }
+
+    pub fn get_pool(&self) -> PoolView {
+        PoolView {
+            near_reserve: self.pool.near_reserve.into(),
+            token_reserve: self.pool.token_reserve.into(),
+            fee_bps: POOL_FEE_BPS as u32,
+        }
+    }
+
+    /// Tokens `swap_near_for_token` would buy with `near_amount` right now.
+    pub fn quote_near_for_token(&self, near_amount: U128) -> U128 {
+        let pool = self.internal_pool();
+        swap_output(near_amount.0, pool.near_reserve, pool.token_reserve).into()
+    }
+
+    /// NEAR `swap_token_for_near` would pay for `amount` tokens right now.
+    pub fn quote_token_for_near(&self, amount: U128) -> U128 {
+        let pool = self.internal_pool();
+        swap_output(amount.0, pool.token_reserve, pool.near_reserve).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000_000, &[(accounts(0), 100_000), (accounts(1), 0)]);
+        testing_env!(context.attached_deposit(1_000_000).build());
+        contract.add_pool_liquidity(100_000.into());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_swap_both_ways() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(100_000)
+            .build());
+        assert_eq!(contract.quote_near_for_token(100_000.into()).0, 9_066);
+        let bought = contract.swap_near_for_token(9_000.into()).0;
+        assert_eq!(bought, 9_066);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, bought);
+
+        testing_env!(context.attached_deposit(1).build());
+        let near_out = contract.swap_token_for_near(bought.into(), 0.into()).0;
+        assert!(near_out < 100_000);
+        let pool = contract.get_pool();
+        assert_eq!(pool.token_reserve.0, 100_000);
+        assert_eq!(pool.near_reserve.0, 1_100_000 - near_out);
+    }
+
+    #[test]
+    fn test_liquidity_lock() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).build());
+        contract.lock_liquidity(60_000.into(), 100.into());
+        assert!(contract.get_liquidity_lock().unwrap().active);
+        contract.remove_pool_liquidity(400_000.into(), 40_000.into());
+
+        testing_env!(context.block_timestamp(100).build());
+        assert!(!contract.get_liquidity_lock().unwrap().active);
+        contract.remove_pool_liquidity(600_000.into(), 60_000.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_LIQUIDITY_LOCKED")]
+    fn test_locked_liquidity_near_drain() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).build());
+        contract.lock_liquidity(60_000.into(), 100.into());
+        contract.remove_pool_liquidity(500_000.into(), 40_000.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SLIPPAGE")]
+    fn test_swap_slippage() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(100_000)
+            .build());
+        contract.swap_near_for_token(9_067.into());
+    }
+}
@@ -0,0 +1,229 @@
+//! Reference price: the token's USD price from a designated oracle, kept on chain so integrators
+//! read it from one place.
+//!
+//! The oracle either pushes prices with `push_price`, or anyone pulls its latest one with
+//! `refresh_price`, which calls the oracle's `get_token_price` for this token. Only prices newer
+//! than the stored one are kept. `get_price` flags the price as stale once it is older than the
+//! feed's `max_staleness`. Changing the oracle drops the price of the previous one.
+
+use crate::*;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{ext_contract, Gas, Promise, PromiseResult};
+
+const GAS_FOR_GET_TOKEN_PRICE: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_TOKEN_PRICE: Gas = 10_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceFeed {
+    pub oracle_id: AccountId,
+    /// Age after which the price is stale, in nanoseconds.
+    pub max_staleness: U64,
+}
+
+/// Token price as pushed by the oracle or returned by its `get_token_price`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenPrice {
+    /// USD per whole token, scaled by `10^decimals`.
+    pub price: U128,
+    pub decimals: u8,
+    pub updated_at: U64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceView {
+    pub oracle_id: AccountId,
+    pub price: U128,
+    pub decimals: u8,
+    pub updated_at: U64,
+    pub stale: bool,
+}
+
+#[ext_contract(ext_token_price_oracle)]
+trait TokenPriceOracle {
+    fn get_token_price(&self, token_id: AccountId) -> TokenPrice;
+}
+
+#[ext_contract(ext_self)]
+trait PriceFeedCallbacks {
+    fn on_token_price(&mut self, oracle_id: AccountId) -> bool;
+}
+
+impl Contract {
+    fn internal_price_feed(&self) -> &PriceFeed {
+        self.price_feed.as_ref().expect("ERR_NO_PRICE_FEED")
+    }
+
+    /// Stores `price` if it is newer than the current one. Returns whether it was stored.
+    fn internal_record_price(&mut self, price: TokenPrice) -> bool {
+        assert!(price.price.0 > 0, "ERR_ZERO_PRICE");
+        if let Some(current) = self.price.as_ref() {
+            if current.updated_at.0 >= price.updated_at.0 {
+                return false;
+            }
+        }
+        events::emit_event("price_updated", &price);
+        self.price = Some(price);
+        true
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the oracle of the reference price and the age after which its price is stale.
+    #[payable]
+    pub fn set_price_feed(&mut self, oracle_id: ValidAccountId, max_staleness: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        let oracle_id: AccountId = oracle_id.into();
+        if self.price_feed.as_ref().map(|feed| &feed.oracle_id) != Some(&oracle_id) {
+            self.price = None;
+        }
+        self.price_feed = Some(PriceFeed {
+            oracle_id,
+            max_staleness,
+        });
+        events::emit_config_changed("price_feed", self.price_feed.as_ref());
+    }
+
+    /// Records a price pushed by the oracle, timestamped with the current block.
+    pub fn push_price(&mut self, price: U128, decimals: u8) -> bool {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.internal_price_feed().oracle_id,
+            "ERR_NOT_ORACLE"
+        );
+        self.internal_record_price(TokenPrice {
+            price,
+            decimals,
+            updated_at: env::block_timestamp().into(),
+        })
+    }
+
+    /// Pulls the oracle's latest price. Resolves to whether it was newer than the stored one.
+    pub fn refresh_price(&mut self) -> Promise {
+        let oracle_id = self.internal_price_feed().oracle_id.clone();
+        ext_token_price_oracle::get_token_price(
+            env::current_account_id(),
+            &oracle_id,
+            NO_DEPOSIT,
+            GAS_FOR_GET_TOKEN_PRICE,
+        )
+        .then(ext_self::on_token_price(
+            oracle_id,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_TOKEN_PRICE,
+        ))
+    }
+
+    /// Records a pulled price, unless the oracle changed in the meantime.
+    #[private]
+    pub fn on_token_price(&mut self, oracle_id: AccountId) -> bool {
+        let price = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<TokenPrice>(&value).ok()
+            }
+            _ => None,
+        };
+        match price {
+            Some(price) if self.internal_price_feed().oracle_id == oracle_id => {
+                self.internal_record_price(price)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn get_price_feed(&self) -> Option<PriceFeed> {
+        self.price_feed.clone()
+    }
+
+    /// Last reference price, if the oracle provided one.
+    pub fn get_price(&self) -> Option<PriceView> {
+        let feed = self.price_feed.as_ref()?;
+        let price = self.price.clone()?;
+        let age = env::block_timestamp().saturating_sub(price.updated_at.0);
+        Some(PriceView {
+            oracle_id: feed.oracle_id.clone(),
+            price: price.price,
+            decimals: price.decimals,
+            updated_at: price.updated_at,
+            stale: age > feed.max_staleness.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_pushed_price_goes_stale() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_price_feed(accounts(3), 60.into());
+        assert!(contract.get_price().is_none());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(100)
+            .build());
+        assert!(contract.push_price(1_500_000.into(), 6));
+        let price = contract.get_price().unwrap();
+        assert_eq!(price.price.0, 1_500_000);
+        assert_eq!(price.updated_at.0, 100);
+        assert!(!price.stale);
+
+        testing_env!(context.block_timestamp(161).build());
+        assert!(contract.get_price().unwrap().stale);
+    }
+
+    #[test]
+    fn test_pulled_price_must_be_newer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_price_feed(accounts(3), 60.into());
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(100)
+            .build());
+        contract.push_price(1_500_000.into(), 6);
+
+        let pulled = TokenPrice {
+            price: 1_400_000.into(),
+            decimals: 6,
+            updated_at: 90.into(),
+        };
+        testing_env!(
+            context.predecessor_account_id(contract_account()).build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                near_sdk::serde_json::to_vec(&pulled).unwrap()
+            )]
+        );
+        assert!(!contract.on_token_price(accounts(3).into()));
+        assert_eq!(contract.get_price().unwrap().price.0, 1_500_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ORACLE")]
+    fn test_push_price_by_other_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_price_feed(accounts(3), 60.into());
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.push_price(1_500_000.into(), 6);
+    }
+}
@@ -0,0 +1,309 @@
+//! Opt-in elastic supply.
+//!
+//! `token.accounts` stores shares and the balance of an account is
+//! `shares * share_index / SHARE_INDEX_DENOMINATOR`. A rebase changes the total supply by
+//! scaling `share_index`, so every holder keeps the same fraction of the supply without
+//! touching individual balances.
+//!
+//! The same index also backs yield-bearing accounting (like stNEAR): instead of rebasing by a
+//! supply delta, the index manager publishes an ever-growing exchange index directly.
+
+use crate::utils::mul_div;
+use crate::*;
+use near_sdk::json_types::I128;
+
+/// Fixed-point denominator of `share_index`. While no rebase has happened, one share is worth
+/// exactly one token unit.
+pub const SHARE_INDEX_DENOMINATOR: u128 = 1_000_000_000_000_000_000;
+
+impl Contract {
+    pub(crate) fn shares_to_amount(&self, shares: Balance) -> Balance {
+        mul_div(
+            shares,
+            self.effective_share_index(),
+            SHARE_INDEX_DENOMINATOR,
+            false,
+        )
+    }
+
+    pub(crate) fn amount_to_shares(&self, amount: Balance, round_up: bool) -> Balance {
+        mul_div(
+            amount,
+            SHARE_INDEX_DENOMINATOR,
+            self.effective_share_index(),
+            round_up,
+        )
+    }
+
+    /// Includes the tokens in escrow, which aren't shares.
+    pub(crate) fn internal_total_supply(&self) -> Balance {
+        self.shares_to_amount(self.token.total_supply) + self.escrow_balance
+    }
+
+    /// Balance of `account_id`, net of demurrage not settled yet.
+    pub(crate) fn internal_balance_of(&self, account_id: &AccountId) -> Balance {
+        let shares = self.token.accounts.get(account_id).unwrap_or(0);
+        let balance = self.shares_to_amount(shares - self.demurrage_due(account_id, shares));
+        if *account_id == env::current_account_id() {
+            balance + self.escrow_balance
+        } else {
+            balance
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn enable_rebase(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.rebase_enabled = true;
+        events::emit_config_changed("rebase_enabled", true);
+    }
+
+    /// Sets the account that may call `rebase` in addition to the owner.
+    #[payable]
+    pub fn set_rebase_oracle(&mut self, oracle_id: Option<ValidAccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.rebase_oracle = oracle_id.map(|a| a.into());
+        events::emit_role_changed("rebase_oracle", self.rebase_oracle.as_ref());
+    }
+
+    /// Sets the account that may publish a new share index with `set_share_index`.
+    #[payable]
+    pub fn set_index_manager(&mut self, manager_id: Option<ValidAccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.index_manager = manager_id.map(|a| a.into());
+        events::emit_role_changed("index_manager", self.index_manager.as_ref());
+    }
+
+    /// Publishes a new exchange index (balance per share, scaled by `SHARE_INDEX_DENOMINATOR`).
+    /// The index may only grow and the resulting total supply must stay within `max_supply`,
+    /// net of the supply reserved by mint claims.
+    pub fn set_share_index(&mut self, share_index: U128) -> U128 {
+        assert!(self.rebase_enabled, "ERR_REBASE_DISABLED");
+        assert_eq!(
+            Some(env::predecessor_account_id()),
+            self.index_manager,
+            "ERR_NOT_ALLOWED"
+        );
+        self.internal_accrue_interest();
+        assert!(share_index.0 >= self.share_index, "ERR_INDEX_DECREASE");
+        let previous_index = self.share_index;
+        self.internal_set_share_index(share_index.0);
+        assert!(
+            self.internal_total_supply() + self.mint_claims_reserved <= self.max_supply,
+            "ERR_EXCEEDS_MAX_SUPPLY"
+        );
+        log!("Share index {} -> {}", previous_index, self.share_index);
+        self.internal_total_supply().into()
+    }
+
+    /// Changes the total supply by `delta` by scaling every balance proportionally, except the
+    /// tokens in escrow. The supply reserved by mint claims stays available. Returns the new
+    /// total supply.
+    pub fn rebase(&mut self, delta: I128) -> U128 {
+        assert!(self.rebase_enabled, "ERR_REBASE_DISABLED");
+        let predecessor_id = env::predecessor_account_id();
+        assert!(
+            predecessor_id == self.owner_id || self.rebase_oracle.as_ref() == Some(&predecessor_id),
+            "ERR_NOT_ALLOWED"
+        );
+        self.internal_accrue_interest();
+        let total_supply = self.internal_total_supply();
+        let rebased_supply = self.shares_to_amount(self.token.total_supply);
+        assert!(rebased_supply > 0, "ERR_ZERO_SUPPLY");
+        let next_rebased_supply = if delta.0 >= 0 {
+            rebased_supply.checked_add(delta.0 as u128)
+        } else {
+            rebased_supply.checked_sub(delta.0.unsigned_abs())
+        }
+        .expect("ERR_REBASE_OUT_OF_RANGE");
+        assert!(
+            next_rebased_supply > 0
+                && next_rebased_supply
+                    <= self
+                        .max_supply
+                        .saturating_sub(self.escrow_balance + self.mint_claims_reserved),
+            "ERR_REBASE_OUT_OF_RANGE"
+        );
+        self.internal_set_share_index(mul_div(
+            self.share_index,
+            next_rebased_supply,
+            rebased_supply,
+            false,
+        ));
+        assert!(self.share_index > 0, "ERR_REBASE_OUT_OF_RANGE");
+        let total_supply_after = self.internal_total_supply();
+        log!(
+            "Rebase by {}: total supply {} -> {}",
+            delta.0,
+            total_supply,
+            total_supply_after
+        );
+        total_supply_after.into()
+    }
+
+    pub fn is_rebase_enabled(&self) -> bool {
+        self.rebase_enabled
+    }
+
+    pub fn get_rebase_oracle(&self) -> Option<AccountId> {
+        self.rebase_oracle.clone()
+    }
+
+    /// Balance of a single share, scaled by `SHARE_INDEX_DENOMINATOR`.
+    pub fn get_share_index(&self) -> U128 {
+        self.effective_share_index().into()
+    }
+
+    pub fn get_index_manager(&self) -> Option<AccountId> {
+        self.index_manager.clone()
+    }
+
+    /// Number of shares `amount` is worth at the current index.
+    pub fn ft_amount_to_shares(&self, amount: U128) -> U128 {
+        self.amount_to_shares(amount.into(), false).into()
+    }
+
+    /// Balance `shares` are worth at the current index.
+    pub fn ft_shares_to_amount(&self, shares: U128) -> U128 {
+        self.shares_to_amount(shares.into()).into()
+    }
+
+    pub fn ft_total_shares(&self) -> U128 {
+        self.token.total_supply.into()
+    }
+
+    /// Raw shares of the account, as opposed to the rebased `ft_balance_of`.
+    pub fn ft_shares_of(&self, account_id: ValidAccountId) -> U128 {
+        self.token
+            .accounts
+            .get(account_id.as_ref())
+            .unwrap_or(0)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_rebase_scales_balances() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        mint_to(&mut contract, accounts(2), 3_000);
+        contract.enable_rebase();
+        contract.rebase(I128(4_000));
+        assert_eq!(contract.ft_total_supply().0, 8_000);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 2_000);
+        assert_eq!(contract.ft_shares_of(accounts(1)).0, 1_000);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer(accounts(1), 2_000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 4_000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 4_000);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.rebase(I128(-6_000));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, 2_000);
+    }
+
+    #[test]
+    fn test_negative_rebase_keeps_escrow_releasable() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(10_000, &[(accounts(1), 1_000), (accounts(2), 1_000)]);
+        contract.internal_escrow_deposit(&accounts(1).into(), 500);
+        contract.internal_escrow_deposit(&accounts(2).into(), 500);
+        contract.enable_rebase();
+        contract.rebase(I128(-500));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 250);
+        assert_eq!(contract.ft_balance_of(contract_account()).0, 1_000);
+
+        contract.internal_escrow_release(&accounts(1).into(), 500);
+        contract.internal_escrow_release(&accounts(2).into(), 500);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 750);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 750);
+        assert_eq!(contract.ft_balance_of(contract_account()).0, 0);
+    }
+
+    #[test]
+    fn test_share_index_growth() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.enable_rebase();
+        contract.set_index_manager(Some(accounts(3)));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.set_share_index((SHARE_INDEX_DENOMINATOR * 3 / 2).into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_500);
+        assert_eq!(contract.ft_amount_to_shares(1_500.into()).0, 1_000);
+        assert_eq!(contract.ft_shares_to_amount(10.into()).0, 15);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INDEX_DECREASE")]
+    fn test_share_index_cannot_decrease() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10_000);
+        contract.enable_rebase();
+        contract.set_index_manager(Some(accounts(3)));
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.set_share_index((SHARE_INDEX_DENOMINATOR - 1).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_REBASE_OUT_OF_RANGE")]
+    fn test_rebase_keeps_mint_claims_reserved() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.create_mint_claim(accounts(2), 5_000.into(), 100.into());
+        contract.enable_rebase();
+        contract.rebase(I128(4_001));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_EXCEEDS_MAX_SUPPLY")]
+    fn test_share_index_keeps_mint_claims_reserved() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.create_mint_claim(accounts(2), 5_000.into(), 100.into());
+        contract.enable_rebase();
+        contract.set_index_manager(Some(accounts(3)));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.set_share_index((SHARE_INDEX_DENOMINATOR * 6).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ALLOWED")]
+    fn test_rebase_not_oracle() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.enable_rebase();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.rebase(I128(100));
+    }
+}
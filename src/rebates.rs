@@ -0,0 +1,282 @@
+//! Transfer rebates for the launch: accounts that join the program earn a small token rebate for
+//! every qualifying transfer they send, and claim what they earned once per claim interval.
+//!
+//! Rebates are paid from a pool the owner funds with its own tokens, held in escrow on the
+//! contract, so the total paid out is capped by the funding. Each rebate is reserved from the
+//! pool when it's earned, so claims never fail for lack of funds; once the pool is empty,
+//! transfers stop earning. An account earns at most `max_accrued` between two claims, so a
+//! single account can't drain the pool with many small transfers. Transfers only look up the
+//! sender while someone has joined.
+
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde_json::json;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RebateProgram {
+    /// Rebate per qualifying transfer.
+    pub rebate: U128,
+    /// Smallest transfer that qualifies.
+    pub min_transfer_amount: U128,
+    /// Time between two claims of an account, in nanoseconds.
+    pub claim_interval: U64,
+    /// Most an account earns between two claims.
+    pub max_accrued: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RebateAccount {
+    pub accrued: Balance,
+    pub last_claim_at: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RebateAccountView {
+    pub accrued: U128,
+    /// Block timestamp from which the account can claim again.
+    pub next_claim_at: U64,
+}
+
+impl Contract {
+    /// Credits the rebate for a completed transfer of `amount` sent by `sender_id`.
+    pub(crate) fn internal_accrue_rebate(&mut self, sender_id: &AccountId, amount: Balance) {
+        if self.rebate_participants == 0 || self.rebate_pool == 0 {
+            return;
+        }
+        let program = match &self.rebate_program {
+            Some(program) if amount >= program.min_transfer_amount.0 => program,
+            _ => return,
+        };
+        let mut account = match self.rebate_accounts.get(sender_id) {
+            Some(account) => account,
+            None => return,
+        };
+        let rebate = std::cmp::min(
+            std::cmp::min(program.rebate.0, self.rebate_pool),
+            program.max_accrued.0.saturating_sub(account.accrued),
+        );
+        if rebate == 0 {
+            return;
+        }
+        account.accrued += rebate;
+        self.rebate_pool -= rebate;
+        self.rebate_accounts.insert(sender_id, &account);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the rebate terms, or stops transfers from earning rebates with `None`. Rebates
+    /// already earned stay claimable.
+    #[payable]
+    pub fn set_rebate_program(&mut self, program: Option<RebateProgram>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.rebate_program = program;
+        events::emit_config_changed("rebate_program", &self.rebate_program);
+    }
+
+    pub fn get_rebate_program(&self) -> Option<RebateProgram> {
+        self.rebate_program.clone()
+    }
+
+    /// Moves `amount` of the owner's tokens into the rebate pool.
+    #[payable]
+    pub fn fund_rebate_pool(&mut self, amount: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(amount.0 > 0, "The amount should be a positive number");
+        self.internal_escrow_deposit(&self.owner_id.clone(), amount.into());
+        self.rebate_pool += amount.0;
+        events::emit_event(
+            "rebate_pool_funded",
+            json!({ "amount": amount, "pool": U128(self.rebate_pool) }),
+        );
+    }
+
+    /// Returns `amount` of the pool not earned yet to the owner.
+    #[payable]
+    pub fn withdraw_rebate_pool(&mut self, amount: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(amount.0 <= self.rebate_pool, "ERR_NOT_ENOUGH_POOL");
+        self.rebate_pool -= amount.0;
+        self.internal_escrow_release(&self.owner_id.clone(), amount.into());
+    }
+
+    pub fn get_rebate_pool(&self) -> U128 {
+        self.rebate_pool.into()
+    }
+
+    /// Joins the program. The attached deposit covers the storage; the rest is refunded.
+    #[payable]
+    pub fn join_rebate_program(&mut self) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.token.accounts.contains_key(&account_id),
+            "The account is not registered"
+        );
+        assert!(
+            self.rebate_accounts
+                .insert(
+                    &account_id,
+                    &RebateAccount {
+                        accrued: 0,
+                        last_claim_at: env::block_timestamp(),
+                    },
+                )
+                .is_none(),
+            "ERR_ALREADY_JOINED"
+        );
+        self.rebate_participants += 1;
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Leaves the program. Rebates not claimed yet go back to the pool.
+    #[payable]
+    pub fn leave_rebate_program(&mut self) {
+        assert_one_yocto();
+        let account = self
+            .rebate_accounts
+            .remove(&env::predecessor_account_id())
+            .expect("ERR_NOT_JOINED");
+        self.rebate_participants -= 1;
+        self.rebate_pool += account.accrued;
+    }
+
+    /// Pays out the predecessor's rebates, at most once per claim interval.
+    #[payable]
+    pub fn claim_rebates(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let mut account = self
+            .rebate_accounts
+            .get(&account_id)
+            .expect("ERR_NOT_JOINED");
+        let interval = self
+            .rebate_program
+            .as_ref()
+            .map_or(0, |program| program.claim_interval.0);
+        let now = env::block_timestamp();
+        assert!(
+            now >= account.last_claim_at.saturating_add(interval),
+            "ERR_CLAIM_TOO_EARLY"
+        );
+        let amount = account.accrued;
+        assert!(amount > 0, "ERR_NOTHING_TO_CLAIM");
+        account.accrued = 0;
+        account.last_claim_at = now;
+        self.rebate_accounts.insert(&account_id, &account);
+        self.internal_escrow_release(&account_id, amount);
+        events::emit_event(
+            "rebates_claimed",
+            json!({ "account_id": account_id, "amount": U128(amount) }),
+        );
+        amount.into()
+    }
+
+    pub fn get_rebate_account(&self, account_id: ValidAccountId) -> Option<RebateAccountView> {
+        let interval = self
+            .rebate_program
+            .as_ref()
+            .map_or(0, |program| program.claim_interval.0);
+        self.rebate_accounts
+            .get(account_id.as_ref())
+            .map(|account| RebateAccountView {
+                accrued: account.accrued.into(),
+                next_claim_at: account.last_claim_at.saturating_add(interval).into(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(
+            10_000,
+            &[(accounts(0), 15), (accounts(1), 1_000), (accounts(2), 0)],
+        );
+        contract.set_rebate_program(Some(RebateProgram {
+            rebate: 10.into(),
+            min_transfer_amount: 100.into(),
+            claim_interval: 1_000.into(),
+            max_accrued: 20.into(),
+        }));
+        contract.fund_rebate_pool(15.into());
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.join_rebate_program();
+        testing_env!(context.attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_rebates_capped_by_pool() {
+        let (mut context, mut contract) = setup();
+        contract.ft_transfer(accounts(2), 99.into(), None);
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        assert_eq!(
+            contract.get_rebate_account(accounts(1)).unwrap().accrued.0,
+            15
+        );
+        assert_eq!(contract.get_rebate_pool().0, 0);
+
+        testing_env!(context.block_timestamp(1_000).build());
+        assert_eq!(contract.claim_rebates().0, 15);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 616);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_CLAIM_TOO_EARLY")]
+    fn test_claim_too_early() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        contract.claim_rebates();
+    }
+
+    #[test]
+    fn test_rebates_capped_per_claim_interval() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        mint_to(&mut contract, accounts(0), 100);
+        contract.fund_rebate_pool(100.into());
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        for _ in 0..5 {
+            contract.ft_transfer(accounts(2), 100.into(), None);
+        }
+        assert_eq!(
+            contract.get_rebate_account(accounts(1)).unwrap().accrued.0,
+            20
+        );
+        assert_eq!(contract.get_rebate_pool().0, 95);
+
+        testing_env!(context.block_timestamp(1_000).build());
+        assert_eq!(contract.claim_rebates().0, 20);
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        assert_eq!(
+            contract.get_rebate_account(accounts(1)).unwrap().accrued.0,
+            10
+        );
+    }
+}
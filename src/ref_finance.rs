@@ -0,0 +1,266 @@
+//! Ref Finance helper: moves owner tokens into a Ref pool in one call instead of a hand-written
+//! `storage_deposit` and `ft_transfer_call` to the exchange.
+//!
+//! `deposit_to_ref_pool` registers the exchange with this token and the owner with the exchange,
+//! checks the pool holds this token, then deposits the tokens with an `ft_transfer_call` whose
+//! empty `msg` credits them to the owner's exchange account. Ref only lets an account add
+//! liquidity for itself, so the call returns the `add_liquidity` arguments for the owner to
+//! submit: the other amounts at the pool's current ratio, and minimums within the slippage. The
+//! owner must have the token registered on Ref (`register_tokens`) unless Ref whitelists it;
+//! otherwise the deposit is refunded.
+
+use crate::interest::BPS_DENOMINATOR;
+use crate::pause::PauseSurface;
+use crate::utils::mul_div;
+use crate::*;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::{ext_contract, Gas, Promise, PromiseResult};
+
+pub const MAX_REF_SLIPPAGE_BPS: u32 = 1_000;
+const GAS_FOR_STORAGE_DEPOSIT: Gas = 10_000_000_000_000;
+const GAS_FOR_GET_POOL: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_REF_POOL: Gas = 80_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+
+/// Pool as returned by the exchange's `get_pool`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RefPoolInfo {
+    pub token_account_ids: Vec<AccountId>,
+    pub amounts: Vec<U128>,
+}
+
+/// Arguments of the exchange's `add_liquidity` matching a deposit.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RefLiquidityArgs {
+    pub pool_id: U64,
+    pub amounts: Vec<U128>,
+    pub min_amounts: Vec<U128>,
+}
+
+#[ext_contract(ext_ref_exchange)]
+trait RefExchange {
+    fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>);
+
+    fn get_pool(&self, pool_id: u64) -> RefPoolInfo;
+}
+
+#[ext_contract(ext_self)]
+trait RefCallbacks {
+    fn on_ref_pool(
+        &mut self,
+        ref_id: AccountId,
+        pool_id: U64,
+        amount: U128,
+        slippage_bps: u32,
+    ) -> RefLiquidityArgs;
+}
+
+/// `add_liquidity` arguments for depositing `amount` of `token_id` into `pool` at its current
+/// ratio, with minimums `slippage_bps` below the amounts.
+fn liquidity_args(
+    pool: &RefPoolInfo,
+    pool_id: U64,
+    token_id: &AccountId,
+    amount: Balance,
+    slippage_bps: u32,
+) -> RefLiquidityArgs {
+    assert_eq!(
+        pool.token_account_ids.len(),
+        pool.amounts.len(),
+        "ERR_INVALID_REF_POOL"
+    );
+    let index = pool
+        .token_account_ids
+        .iter()
+        .position(|id| id == token_id)
+        .expect("ERR_TOKEN_NOT_IN_POOL");
+    let reserve = pool.amounts[index].0;
+    assert!(reserve > 0, "ERR_POOL_EMPTY");
+    let amounts: Vec<Balance> = pool
+        .amounts
+        .iter()
+        .map(|other| mul_div(amount, other.0, reserve, false))
+        .collect();
+    let keep_bps = BPS_DENOMINATOR - slippage_bps as u128;
+    RefLiquidityArgs {
+        pool_id,
+        min_amounts: amounts
+            .iter()
+            .map(|amount| mul_div(*amount, keep_bps, BPS_DENOMINATOR, false).into())
+            .collect(),
+        amounts: amounts.into_iter().map(U128).collect(),
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Deposits `amount` of the owner's tokens into their account on the Ref exchange `ref_id`
+    /// for pool `pool_id`, and returns the `add_liquidity` arguments to add them to the pool with
+    /// at most `slippage_bps` of slippage. The attached deposit pays for registering the exchange
+    /// with this token, and the rest goes to the owner's storage on the exchange.
+    #[payable]
+    pub fn deposit_to_ref_pool(
+        &mut self,
+        ref_id: ValidAccountId,
+        pool_id: U64,
+        amount: U128,
+        slippage_bps: u32,
+    ) -> Promise {
+        self.assert_owner();
+        self.assert_not_paused(PauseSurface::Transfers);
+        self.assert_not_paused(PauseSurface::FtTransferCall);
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        assert!(slippage_bps <= MAX_REF_SLIPPAGE_BPS, "ERR_INVALID_SLIPPAGE");
+        assert!(
+            amount.0 <= self.internal_balance_of(&self.owner_id),
+            "ERR_NOT_ENOUGH_BALANCE"
+        );
+        let ref_id: AccountId = ref_id.into();
+        let initial_storage_usage = env::storage_usage();
+        self.internal_ensure_registered(&ref_id);
+        let storage_cost = env::storage_byte_cost()
+            * Balance::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        assert!(
+            env::attached_deposit() > storage_cost,
+            "ERR_NOT_ENOUGH_DEPOSIT"
+        );
+        ext_ref_exchange::storage_deposit(
+            Some(self.owner_id.clone()),
+            None,
+            &ref_id,
+            env::attached_deposit() - storage_cost,
+            GAS_FOR_STORAGE_DEPOSIT,
+        )
+        .and(ext_ref_exchange::get_pool(
+            pool_id.0,
+            &ref_id,
+            NO_DEPOSIT,
+            GAS_FOR_GET_POOL,
+        ))
+        .then(ext_self::on_ref_pool(
+            ref_id.clone(),
+            pool_id,
+            amount,
+            slippage_bps,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_REF_POOL,
+        ))
+    }
+
+    /// Checks the pool and deposits the owner's tokens into their exchange account.
+    #[private]
+    pub fn on_ref_pool(
+        &mut self,
+        ref_id: AccountId,
+        pool_id: U64,
+        amount: U128,
+        slippage_bps: u32,
+    ) -> RefLiquidityArgs {
+        let pool = match env::promise_result(1) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<RefPoolInfo>(&value)
+                    .expect("ERR_INVALID_REF_POOL")
+            }
+            _ => env::panic(b"ERR_NO_REF_POOL"),
+        };
+        let args = liquidity_args(
+            &pool,
+            pool_id,
+            &env::current_account_id(),
+            amount.0,
+            slippage_bps,
+        );
+        events::emit_event(
+            "ref_pool_deposit",
+            json!({
+                "ref_id": ref_id,
+                "pool_id": pool_id,
+                "amount": amount,
+            }),
+        );
+        self.internal_holder_transfer(
+            self.owner_id.clone(),
+            ref_id,
+            amount,
+            Some(format!("ref pool {}", pool_id.0)),
+            Some(String::new()),
+        );
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn pool(token_id: AccountId) -> RefPoolInfo {
+        RefPoolInfo {
+            token_account_ids: vec!["wrap.near".to_string(), token_id],
+            amounts: vec![2_000.into(), 1_000.into()],
+        }
+    }
+
+    #[test]
+    fn test_liquidity_args() {
+        let token_id: AccountId = contract_account().into();
+        let args = liquidity_args(&pool(token_id.clone()), 7.into(), &token_id, 100, 50);
+        assert_eq!(
+            args,
+            RefLiquidityArgs {
+                pool_id: 7.into(),
+                amounts: vec![200.into(), 100.into()],
+                min_amounts: vec![199.into(), 99.into()],
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_NOT_IN_POOL")]
+    fn test_pool_without_token() {
+        let token_id: AccountId = contract_account().into();
+        liquidity_args(
+            &pool("other.near".to_string()),
+            7.into(),
+            &token_id,
+            100,
+            50,
+        );
+    }
+
+    #[test]
+    fn test_on_ref_pool_deposits_owner_tokens() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(0), 1_000), (accounts(3), 0)]);
+
+        testing_env!(
+            context
+                .predecessor_account_id(contract_account())
+                .prepaid_gas(300_000_000_000_000)
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(vec![]),
+                PromiseResult::Successful(
+                    near_sdk::serde_json::to_vec(&pool(contract_account().into())).unwrap()
+                ),
+            ]
+        );
+        let args = contract.on_ref_pool(accounts(3).into(), 7.into(), 100.into(), 50);
+        assert_eq!(args.amounts, vec![200.into(), 100.into()]);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 900);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 100);
+    }
+}
@@ -0,0 +1,156 @@
+//! Proof of reserve: for a token wrapping or bridging an asset held elsewhere, a designated
+//! attestor periodically publishes how much of it backs the supply, so holders can check the
+//! backing on chain.
+//!
+//! Each attestation records the reserve amount in token units, the chain holding it, and a
+//! reference to the evidence, e.g. a signature or the hash of an audit report, together with
+//! the total supply at publication. Attestations are append-only; the attestor pays for their
+//! storage.
+
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde::Serialize;
+
+const MAX_SOURCE_CHAIN_LEN: usize = 64;
+const MAX_REFERENCE_LEN: usize = 256;
+const MAX_ATTESTATIONS_LIMIT: u64 = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReserveAttestation {
+    pub attestor_id: AccountId,
+    pub reserve: U128,
+    pub source_chain: String,
+    pub reference: String,
+    pub total_supply: U128,
+    pub attested_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account publishing reserve attestations, or stops accepting them with `None`.
+    #[payable]
+    pub fn set_reserve_attestor(&mut self, attestor_id: Option<ValidAccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.reserve_attestor = attestor_id.map(|account_id| account_id.into());
+        events::emit_config_changed("reserve_attestor", &self.reserve_attestor);
+    }
+
+    pub fn get_reserve_attestor(&self) -> Option<AccountId> {
+        self.reserve_attestor.clone()
+    }
+
+    /// Publishes the `reserve` held on `source_chain`, with a `reference` to its evidence. The
+    /// attached deposit covers the storage of the attestation; the rest is refunded.
+    #[payable]
+    pub fn publish_reserve_attestation(
+        &mut self,
+        reserve: U128,
+        source_chain: String,
+        reference: String,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let attestor_id = env::predecessor_account_id();
+        assert_eq!(
+            Some(&attestor_id),
+            self.reserve_attestor.as_ref(),
+            "ERR_NOT_ATTESTOR"
+        );
+        assert!(
+            !source_chain.is_empty() && source_chain.len() <= MAX_SOURCE_CHAIN_LEN,
+            "ERR_INVALID_SOURCE_CHAIN"
+        );
+        assert!(
+            !reference.is_empty() && reference.len() <= MAX_REFERENCE_LEN,
+            "ERR_INVALID_REFERENCE"
+        );
+        let attestation = ReserveAttestation {
+            attestor_id,
+            reserve,
+            source_chain,
+            reference,
+            total_supply: self.internal_total_supply().into(),
+            attested_at: env::block_timestamp().into(),
+        };
+        events::emit_event("reserve_attested", &attestation);
+        let index = self.reserve_attestations.len();
+        self.reserve_attestations.push(&attestation);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        index.into()
+    }
+
+    pub fn get_latest_reserve_attestation(&self) -> Option<ReserveAttestation> {
+        match self.reserve_attestations.len() {
+            0 => None,
+            len => self.reserve_attestations.get(len - 1),
+        }
+    }
+
+    pub fn get_reserve_attestation_count(&self) -> U64 {
+        self.reserve_attestations.len().into()
+    }
+
+    /// Returns up to `limit` attestations in publication order, starting with the `from`th.
+    pub fn get_reserve_attestations(&self, from: U64, limit: U64) -> Vec<ReserveAttestation> {
+        let limit = std::cmp::min(limit.0, MAX_ATTESTATIONS_LIMIT);
+        let end = std::cmp::min(
+            from.0.saturating_add(limit),
+            self.reserve_attestations.len(),
+        );
+        (from.0..end)
+            .filter_map(|index| self.reserve_attestations.get(index))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_publish_reserve_attestations() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 400)]);
+        contract.set_reserve_attestor(Some(accounts(3)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(10u128.pow(24))
+            .block_timestamp(5)
+            .build());
+        contract.publish_reserve_attestation(400.into(), "ethereum".into(), "0xabc".into());
+        let index =
+            contract.publish_reserve_attestation(420.into(), "ethereum".into(), "0xdef".into());
+        assert_eq!(index.0, 1);
+        let latest = contract.get_latest_reserve_attestation().unwrap();
+        assert_eq!(latest.reserve.0, 420);
+        assert_eq!(latest.total_supply.0, 400);
+        assert_eq!(latest.attested_at.0, 5);
+        let page = contract.get_reserve_attestations(0.into(), 10.into());
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].reference, "0xabc");
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ATTESTOR")]
+    fn test_publish_by_other_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        contract.set_reserve_attestor(Some(accounts(3)));
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.publish_reserve_attestation(400.into(), "ethereum".into(), "0xabc".into());
+    }
+}
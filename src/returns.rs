@@ -0,0 +1,251 @@
+//! Returning unwanted tokens: while the owner has set a return window, plain transfers to
+//! receivers that opted in are recorded in a short-lived index per receiver, and the receiver can
+//! bounce any of them back to the sender with `return_transfer` until the window closes.
+//!
+//! Receivers opt in with `set_returns_enabled`, attaching a deposit for `RECENT_TRANSFERS_STORAGE`,
+//! the most their index can take. Opting out drops the index and refunds the deposit.
+//!
+//! Each recorded transfer gets a `tx_ref`, published in a `returnable_transfer` event. An account
+//! keeps at most its latest `MAX_RECENT_TRANSFERS` incoming transfers, and expired ones are
+//! dropped whenever a new one is recorded, so the index stays small. Returns are not charged the
+//! transfer fee. Nothing is recorded while the window is unset.
+
+use crate::pause::PauseSurface;
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::*;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::json;
+
+const MAX_RECENT_TRANSFERS: usize = 10;
+/// Upper bound of the storage an index of `MAX_RECENT_TRANSFERS` transfers takes.
+const RECENT_TRANSFERS_STORAGE: u64 = 1_200;
+
+pub type TransferRef = u64;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct IncomingTransfer {
+    pub tx_ref: TransferRef,
+    pub sender_id: AccountId,
+    pub amount: Balance,
+    pub received_at: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IncomingTransferView {
+    pub tx_ref: U64,
+    pub sender_id: AccountId,
+    pub amount: U128,
+    pub returnable_until: U64,
+}
+
+impl Contract {
+    /// Records a completed plain transfer as returnable, if a return window is set and the
+    /// receiver opted in.
+    pub(crate) fn internal_record_incoming(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        let window = match self.return_window {
+            Some(window) => window,
+            None => return,
+        };
+        let mut transfers = match self.recent_incoming.get(receiver_id) {
+            Some(transfers) => transfers,
+            None => return,
+        };
+        let now = env::block_timestamp();
+        transfers.retain(|transfer| transfer.received_at.saturating_add(window) > now);
+        if transfers.len() >= MAX_RECENT_TRANSFERS {
+            transfers.remove(0);
+        }
+        let tx_ref = self.next_transfer_ref;
+        self.next_transfer_ref += 1;
+        transfers.push(IncomingTransfer {
+            tx_ref,
+            sender_id: sender_id.clone(),
+            amount,
+            received_at: now,
+        });
+        self.recent_incoming.insert(receiver_id, &transfers);
+        events::emit_event(
+            "returnable_transfer",
+            json!({
+                "tx_ref": U64(tx_ref),
+                "sender_id": sender_id,
+                "receiver_id": receiver_id,
+                "amount": U128(amount),
+            }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets how long receivers can return incoming transfers, in nanoseconds, or stops recording
+    /// them with `None`.
+    #[payable]
+    pub fn set_return_window(&mut self, window: Option<U64>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.return_window = window.map(|window| window.into());
+        events::emit_config_changed("return_window", window);
+    }
+
+    pub fn get_return_window(&self) -> Option<U64> {
+        self.return_window.map(|window| window.into())
+    }
+
+    /// Starts or stops recording the incoming transfers of the predecessor as returnable.
+    /// Opting in requires a deposit for the storage of the index; the rest is refunded. Opting
+    /// out refunds it.
+    #[payable]
+    pub fn set_returns_enabled(&mut self, enabled: bool) {
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let account_id = env::predecessor_account_id();
+        let was_enabled = self.recent_incoming.contains_key(&account_id);
+        if enabled && !was_enabled {
+            self.recent_incoming.insert(&account_id, &vec![]);
+            refund_deposit(RECENT_TRANSFERS_STORAGE);
+        } else {
+            assert_one_yocto();
+            if !enabled && was_enabled {
+                self.recent_incoming.remove(&account_id);
+                refund_released_storage(&account_id, RECENT_TRANSFERS_STORAGE);
+            }
+        }
+    }
+
+    pub fn get_returns_enabled(&self, account_id: ValidAccountId) -> bool {
+        self.recent_incoming.contains_key(account_id.as_ref())
+    }
+
+    /// Sends a recent incoming transfer of the predecessor back to its sender.
+    #[payable]
+    pub fn return_transfer(&mut self, tx_ref: U64) {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        let receiver_id = env::predecessor_account_id();
+        let mut transfers = self.recent_incoming.get(&receiver_id).unwrap_or_default();
+        let index = transfers
+            .iter()
+            .position(|transfer| transfer.tx_ref == tx_ref.0)
+            .expect("ERR_NO_TRANSFER");
+        let transfer = transfers.remove(index);
+        let window = self.return_window.expect("ERR_RETURNS_DISABLED");
+        assert!(
+            env::block_timestamp() < transfer.received_at.saturating_add(window),
+            "ERR_RETURN_WINDOW_CLOSED"
+        );
+        self.recent_incoming.insert(&receiver_id, &transfers);
+        self.assert_not_blacklisted(&receiver_id);
+        self.assert_not_blacklisted(&transfer.sender_id);
+        self.internal_transfer(
+            &receiver_id,
+            &transfer.sender_id,
+            transfer.amount,
+            Some(format!("return of {}", tx_ref.0)),
+        );
+        events::emit_event("transfer_returned", json!({ "tx_ref": tx_ref }));
+    }
+
+    /// Incoming transfers `account_id` can still return.
+    pub fn get_returnable_transfers(
+        &self,
+        account_id: ValidAccountId,
+    ) -> Vec<IncomingTransferView> {
+        let window = match self.return_window {
+            Some(window) => window,
+            None => return vec![],
+        };
+        let now = env::block_timestamp();
+        self.recent_incoming
+            .get(account_id.as_ref())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|transfer| transfer.received_at.saturating_add(window) > now)
+            .map(|transfer| IncomingTransferView {
+                tx_ref: transfer.tx_ref.into(),
+                sender_id: transfer.sender_id,
+                amount: transfer.amount.into(),
+                returnable_until: transfer.received_at.saturating_add(window).into(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+    use crate::Contract;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+        contract.set_return_window(Some(100.into()));
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.set_returns_enabled(true);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(2), 200.into(), None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_returns_opt_in() {
+        let (mut context, mut contract) = setup();
+        contract.set_returns_enabled(false);
+        assert!(!contract.get_returns_enabled(accounts(2)));
+        assert!(contract.get_returnable_transfers(accounts(2)).is_empty());
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        assert!(contract.get_returnable_transfers(accounts(2)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_returns_opt_in_requires_deposit() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.set_returns_enabled(true);
+    }
+
+    #[test]
+    fn test_return_transfer() {
+        let (_, mut contract) = setup();
+        let returnable = contract.get_returnable_transfers(accounts(2));
+        assert_eq!(returnable.len(), 1);
+        assert_eq!(returnable[0].returnable_until.0, 100);
+        contract.return_transfer(returnable[0].tx_ref);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 500);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+        assert!(contract.get_returnable_transfers(accounts(2)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RETURN_WINDOW_CLOSED")]
+    fn test_return_after_window() {
+        let (mut context, mut contract) = setup();
+        let tx_ref = contract.get_returnable_transfers(accounts(2))[0].tx_ref;
+        testing_env!(context.block_timestamp(100).build());
+        contract.return_transfer(tx_ref);
+    }
+}
@@ -0,0 +1,1564 @@
+//! Token sale for NEAR with referral rewards.
+//!
+//! Purchased tokens are minted against `max_supply`. A buyer may name a referrer, who earns
+//! `referral_bps` of the purchased amount on top. Referral rewards are minted into escrow at
+//! purchase time, so they are covered by the supply cap, and paid out with
+//! `claim_referral_rewards`.
+//!
+//! A sale may release only part of each purchase right away and vest the rest linearly over a
+//! fixed duration from the purchase. The vesting part is minted into escrow and handed to the
+//! vesting engine as a grant from the contract. `buy` sets aside `SALE_GRANT_STORAGE` of the
+//! deposit for the grant before pricing the purchase.
+//!
+//! The owner may bound how much each account buys over the whole sale. Purchases are tracked
+//! cumulatively per account, so the bounds can't be bypassed with repeated buys.
+//!
+//! The price can also be set in USD. Each purchase then queries the configured price oracle for
+//! the NEAR price and completes in a callback, which refunds the payment if the oracle failed,
+//! its price is stale, or the purchase isn't possible anymore.
+//!
+//! Other NEP-141 tokens, e.g. stablecoins, are accepted through `ft_transfer_call` at a price the
+//! owner sets per payment token. Token payments don't cover storage, so the buyer must be
+//! registered and the sale may not vest.
+//!
+//! Proceeds stay on the contract until withdrawn, and withdrawing is timelocked: the owner first
+//! requests the withdrawal of everything collected in one currency, which emits an event, and
+//! can only execute it `Config::sale_withdrawal_delay` later. NEAR goes to the owner, payment tokens to
+//! the treasury. The delay can only ever be raised.
+//!
+//! The owner may cancel a sale instead of ending it. Buyers then reclaim their payments with
+//! `claim_sale_refund`: the unclaimed part of their sale grants is burned along with the
+//! released tokens they still hold, and the refund is pro rata to what was burned. Refunds are
+//! paid from the proceeds first and then from pending withdrawals. They are open for
+//! `SALE_REFUND_PERIOD` after the cancellation; afterwards the owner can withdraw the rest of
+//! the proceeds and start a new sale. A payment-token refund whose transfer fails stays due to
+//! the buyer, who retries it with `retry_sale_refund`.
+
+use crate::config::ConfigUpdate;
+use crate::interest::BPS_DENOMINATOR;
+use crate::pause::PauseSurface;
+use crate::utils::{mul_div, refund_deposit};
+use crate::vesting::{GrantId, VestingFunding, VestingSchedule};
+use crate::volume_limit::DAY;
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+use near_sdk::{ext_contract, Gas, Promise, PromiseOrValue, PromiseResult};
+
+const MAX_REFERRAL_BPS: u32 = 2_000;
+/// Decimals of USD prices: a USD-priced sale's price is in millionths of a dollar.
+const USD_DECIMALS: u32 = 6;
+const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+const GAS_FOR_GET_PRICE: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_SALE_PRICE: Gas = 30_000_000_000_000;
+const GAS_FOR_FT_TRANSFER: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_SALE_PROCEEDS_WITHDRAWN: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_SALE_REFUND_TRANSFERRED: Gas = 10_000_000_000_000;
+/// How long buyers of a cancelled sale can reclaim their payments.
+pub const SALE_REFUND_PERIOD: u64 = 30 * DAY;
+const NO_DEPOSIT: Balance = 0;
+/// Upper bound of the storage a vesting sale's grant takes: the grant, the buyer's grant index
+/// and the purchase's reference to the grant.
+const SALE_GRANT_STORAGE: u64 = 600;
+
+/// NEAR price as returned by the price oracle's `get_near_usd_price`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OraclePrice {
+    /// USD per NEAR, scaled by `10^decimals`.
+    pub price: U128,
+    pub decimals: u8,
+    pub updated_at: U64,
+}
+
+#[ext_contract(ext_price_oracle)]
+trait PriceOracle {
+    fn get_near_usd_price(&self) -> OraclePrice;
+}
+
+#[ext_contract(ext_payment_token)]
+trait PaymentToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+#[ext_contract(ext_self)]
+trait SaleCallbacks {
+    fn on_sale_proceeds_withdrawn(&mut self, token_id: AccountId, amount: U128);
+
+    fn on_sale_refund_transferred(
+        &mut self,
+        buyer_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+    );
+
+    fn on_sale_price(
+        &mut self,
+        buyer_id: AccountId,
+        referrer_id: Option<AccountId>,
+        payment: U128,
+    ) -> U128;
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum SalePricing {
+    /// `price` is in yoctoNEAR per whole token.
+    Near,
+    /// `price` is in millionths of a USD per whole token.
+    Usd,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleOracle {
+    pub oracle_id: AccountId,
+    /// Oldest accepted oracle price, in nanoseconds.
+    pub max_staleness: U64,
+}
+
+/// `msg` of an `ft_transfer_call` buying from the sale.
+#[derive(Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+struct SalePaymentMsg {
+    referrer_id: Option<ValidAccountId>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleVesting {
+    /// Share of each purchase released right away.
+    pub immediate_bps: u32,
+    /// Duration the rest vests over, from the purchase.
+    pub duration: U64,
+}
+
+/// Proceeds requested for withdrawal, in NEAR or a payment token.
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleWithdrawal {
+    pub amount: U128,
+    pub available_at: U64,
+}
+
+/// What an account bought in a sale and paid for it.
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct SalePurchase {
+    pub amount: Balance,
+    /// NEAR added to the proceeds, i.e. without the storage the purchase used.
+    pub paid_near: Balance,
+    pub paid_tokens: Vec<(AccountId, Balance)>,
+    /// Grants vesting the part of the purchase that wasn't released right away.
+    pub grant_ids: Vec<GrantId>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct Sale {
+    /// Increments with every sale, keys the per-account purchases.
+    pub id: u64,
+    /// Price per whole token (10^decimals units), in the unit given by `pricing`.
+    pub price: Balance,
+    pub pricing: SalePricing,
+    pub start_at: u64,
+    /// No end if `None`.
+    pub end_at: Option<u64>,
+    pub referral_bps: u32,
+    pub sold: Balance,
+    /// NEAR received and not withdrawn by the owner yet.
+    pub proceeds: Balance,
+    pub active: bool,
+    /// Set when the sale is cancelled: buyers may reclaim their payments until then.
+    pub refunds_end_at: Option<u64>,
+    pub vesting: Option<SaleVesting>,
+    /// Smallest total an account may buy over the sale.
+    pub min_purchase: Balance,
+    /// Largest total an account may buy over the sale, unbounded if `None`.
+    pub max_purchase: Option<Balance>,
+}
+
+impl Sale {
+    fn refunds_open(&self, now: u64) -> bool {
+        self.refunds_end_at.is_some_and(|end_at| now < end_at)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleView {
+    pub price: U128,
+    pub pricing: SalePricing,
+    pub start_at: U64,
+    pub end_at: Option<U64>,
+    pub referral_bps: u32,
+    pub sold: U128,
+    pub proceeds: U128,
+    pub active: bool,
+    pub cancelled: bool,
+    pub refunds_end_at: Option<U64>,
+    pub vesting: Option<SaleVesting>,
+    pub min_purchase: U128,
+    pub max_purchase: Option<U128>,
+}
+
+impl Contract {
+    /// Buys tokens with a payment of `amount` in the NEP-141 `token_id`, received through
+    /// `ft_on_transfer`. `msg` is empty or `{"referrer_id": ...}`. The whole payment is used; if
+    /// the purchase fails the payment token refunds it.
+    pub(crate) fn internal_sale_ft_payment(
+        &mut self,
+        token_id: AccountId,
+        sender_id: ValidAccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        self.assert_not_paused(PauseSurface::Sale);
+        self.assert_not_emergency();
+        let buyer_id: AccountId = sender_id.into();
+        let sale = self.internal_get_sale();
+        let price = self
+            .sale_token_prices
+            .get(&(sale.id, token_id.clone()))
+            .expect("ERR_TOKEN_NOT_ACCEPTED");
+        assert!(sale.vesting.is_none(), "ERR_VESTING_SALE");
+        assert!(
+            self.token.accounts.contains_key(&buyer_id),
+            "ERR_NOT_REGISTERED"
+        );
+        let msg: SalePaymentMsg = if msg.is_empty() {
+            SalePaymentMsg::default()
+        } else {
+            near_sdk::serde_json::from_str(&msg).expect("ERR_INVALID_MSG")
+        };
+        let referrer_id: Option<AccountId> = msg.referrer_id.map(|a| a.into());
+        if let Some(referrer_id) = referrer_id.as_ref() {
+            assert!(referrer_id != &buyer_id, "ERR_SELF_REFERRAL");
+        }
+        self.internal_sale_purchase(&buyer_id, referrer_id, amount.0, price, Some(&token_id))
+            .unwrap_or_else(|err| env::panic(err.as_bytes()));
+        PromiseOrValue::Value(0.into())
+    }
+
+    pub(crate) fn internal_get_sale(&self) -> Sale {
+        self.sale
+            .as_ref()
+            .map(|sale| Sale { ..*sale })
+            .expect("ERR_NO_SALE")
+    }
+
+    fn internal_check_sale_open(&self, sale: &Sale) -> Result<(), &'static str> {
+        let now = env::block_timestamp();
+        if self.internal_is_paused(PauseSurface::Mint) {
+            Err("ERR_PAUSED")
+        } else if !sale.active {
+            Err("ERR_SALE_CLOSED")
+        } else if now < sale.start_at {
+            Err("ERR_SALE_NOT_STARTED")
+        } else if sale.end_at.is_some_and(|end_at| now >= end_at) {
+            Err("ERR_SALE_ENDED")
+        } else {
+            Ok(())
+        }
+    }
+
+    fn internal_assert_sale_open(&self, sale: &Sale) {
+        if let Err(err) = self.internal_check_sale_open(sale) {
+            env::panic(err.as_bytes());
+        }
+    }
+
+    /// Takes `amount` of the sale funds in the payment token, or NEAR if `token_id` is `None`,
+    /// from the proceeds first and then from the pending withdrawal.
+    fn internal_take_sale_funds(&mut self, token_id: &Option<AccountId>, amount: Balance) {
+        let proceeds = match token_id {
+            None => self.internal_get_sale().proceeds,
+            Some(token_id) => self.sale_token_proceeds.get(token_id).unwrap_or(0),
+        };
+        let from_proceeds = std::cmp::min(proceeds, amount);
+        match token_id {
+            None => {
+                let mut sale = self.internal_get_sale();
+                sale.proceeds -= from_proceeds;
+                self.sale = Some(sale);
+            }
+            Some(token_id) => {
+                self.sale_token_proceeds
+                    .insert(token_id, &(proceeds - from_proceeds));
+            }
+        }
+        let from_withdrawal = amount - from_proceeds;
+        if from_withdrawal > 0 {
+            let mut withdrawal = self
+                .sale_withdrawals
+                .get(token_id)
+                .expect("ERR_NOT_ENOUGH_FUNDS");
+            withdrawal.amount = withdrawal
+                .amount
+                .0
+                .checked_sub(from_withdrawal)
+                .expect("ERR_NOT_ENOUGH_FUNDS")
+                .into();
+            self.sale_withdrawals.insert(token_id, &withdrawal);
+        }
+    }
+
+    /// Transfers a payment-token refund, keeping it due to the buyer if the transfer fails.
+    fn internal_transfer_sale_refund(
+        &self,
+        buyer_id: AccountId,
+        token_id: AccountId,
+        refund: Balance,
+    ) -> Promise {
+        ext_payment_token::ft_transfer(
+            buyer_id.clone(),
+            refund.into(),
+            Some("sale refund".to_string()),
+            &token_id,
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+        .then(ext_self::on_sale_refund_transferred(
+            buyer_id,
+            token_id,
+            refund.into(),
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_SALE_REFUND_TRANSFERRED,
+        ))
+    }
+
+    /// yoctoNEAR per whole token of a USD-priced sale at the oracle's `price`.
+    fn internal_usd_to_near_price(
+        &self,
+        sale: &Sale,
+        price: OraclePrice,
+    ) -> Result<Balance, &'static str> {
+        let max_staleness = self
+            .sale_oracle
+            .as_ref()
+            .ok_or("ERR_NO_ORACLE")?
+            .max_staleness
+            .0;
+        if env::block_timestamp().saturating_sub(price.updated_at.0) > max_staleness {
+            return Err("ERR_STALE_PRICE");
+        }
+        let price_scale = 10u128
+            .checked_pow(price.decimals as u32)
+            .ok_or("ERR_INVALID_PRICE")?;
+        let usd_per_near = price.price.0;
+        if usd_per_near == 0 {
+            return Err("ERR_INVALID_PRICE");
+        }
+        let near_price = mul_div(sale.price, ONE_NEAR, 10u128.pow(USD_DECIMALS), false);
+        Ok(mul_div(near_price, price_scale, usd_per_near, false))
+    }
+
+    /// Sells tokens worth `payment` at `price` per whole token. Checks everything that could
+    /// fail up front, so callbacks can refund instead of panicking. The payment is in NEAR
+    /// unless `payment_token_id` is given.
+    fn internal_sale_purchase(
+        &mut self,
+        buyer_id: &AccountId,
+        referrer_id: Option<AccountId>,
+        payment: Balance,
+        price: Balance,
+        payment_token_id: Option<&AccountId>,
+    ) -> Result<Balance, &'static str> {
+        let mut sale = self.internal_get_sale();
+        self.internal_check_sale_open(&sale)?;
+        let one_token = 10u128.pow(self.metadata.get().unwrap().decimals as u32);
+        let amount = mul_div(payment, one_token, price, false);
+        if amount == 0 {
+            return Err("ERR_DEPOSIT_TOO_LOW");
+        }
+        let purchase_key = (sale.id, buyer_id.clone());
+        let mut purchase = self.sale_purchases.get(&purchase_key).unwrap_or_default();
+        let purchased = purchase.amount + amount;
+        if sale.vesting.is_some() && !self.token.accounts.contains_key(buyer_id) {
+            // The buyer unregistered before the oracle answered.
+            return Err("ERR_NOT_REGISTERED");
+        }
+        if purchased < sale.min_purchase {
+            return Err("ERR_BELOW_MIN_PURCHASE");
+        }
+        if sale
+            .max_purchase
+            .is_some_and(|max_purchase| purchased > max_purchase)
+        {
+            return Err("ERR_ABOVE_MAX_PURCHASE");
+        }
+        let reward = if referrer_id.is_some() {
+            amount * sale.referral_bps as u128 / BPS_DENOMINATOR
+        } else {
+            0
+        };
+        let next_total_supply =
+            self.internal_total_supply() + self.mint_claims_reserved + amount + reward;
+        if next_total_supply > self.max_supply {
+            return Err("ERR_SOLD_OUT");
+        }
+
+        let immediate = sale.vesting.map_or(amount, |vesting| {
+            mul_div(
+                amount,
+                vesting.immediate_bps as u128,
+                BPS_DENOMINATOR,
+                false,
+            )
+        });
+        if immediate > 0 {
+            self.internal_mint(buyer_id, immediate, Some("sale"));
+        }
+        if let Some(vesting) = sale.vesting.filter(|_| amount > immediate) {
+            let contract_id = env::current_account_id();
+            self.internal_mint(&contract_id, amount - immediate, Some("sale"));
+            let now = env::block_timestamp();
+            let grant_id = self.internal_create_vesting_grant(
+                &contract_id,
+                buyer_id,
+                amount - immediate,
+                VestingSchedule {
+                    start_at: now.into(),
+                    cliff_at: now.into(),
+                    end_at: (now + vesting.duration.0).into(),
+                },
+                None,
+                VestingFunding::Escrow,
+            );
+            purchase.grant_ids.push(grant_id);
+        }
+        purchase.amount = purchased;
+        match payment_token_id {
+            None => {
+                purchase.paid_near += payment;
+                sale.proceeds += payment;
+            }
+            Some(token_id) => {
+                match purchase
+                    .paid_tokens
+                    .iter_mut()
+                    .find(|(paid_token_id, _)| paid_token_id == token_id)
+                {
+                    Some((_, paid)) => *paid += payment,
+                    None => purchase.paid_tokens.push((token_id.clone(), payment)),
+                }
+                let proceeds = self.sale_token_proceeds.get(token_id).unwrap_or(0);
+                self.sale_token_proceeds
+                    .insert(token_id, &(proceeds + payment));
+            }
+        }
+        self.sale_purchases.insert(&purchase_key, &purchase);
+        if let Some(referrer_id) = referrer_id.filter(|_| reward > 0) {
+            self.internal_mint(&env::current_account_id(), reward, Some("referral reward"));
+            let rewards = self.referral_rewards.get(&referrer_id).unwrap_or(0);
+            self.referral_rewards
+                .insert(&referrer_id, &(rewards + reward));
+            log!("Referral reward {} to {}", reward, referrer_id);
+        }
+        sale.sold += amount;
+        self.sale = Some(sale);
+        log!("Sold {} to {} for {}", amount, buyer_id, payment);
+        Ok(amount)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Opens a sale at `price` per whole token, in yoctoNEAR unless `pricing` says USD.
+    /// Starts right away unless `start_at` is given. With `vesting`, only part of each purchase
+    /// is released right away. Proceeds of a previous sale must be withdrawn first, which for a
+    /// cancelled sale is only possible once its refunds closed.
+    #[payable]
+    pub fn start_sale(
+        &mut self,
+        price: U128,
+        start_at: Option<U64>,
+        end_at: Option<U64>,
+        referral_bps: u32,
+        vesting: Option<SaleVesting>,
+        pricing: Option<SalePricing>,
+    ) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(price.0 > 0, "ERR_ZERO_PRICE");
+        let pricing = pricing.unwrap_or(SalePricing::Near);
+        if pricing == SalePricing::Usd {
+            assert!(self.sale_oracle.is_some(), "ERR_NO_ORACLE");
+        }
+        assert!(referral_bps <= MAX_REFERRAL_BPS, "ERR_REFERRAL_TOO_HIGH");
+        if let Some(vesting) = vesting.as_ref() {
+            assert!(
+                vesting.immediate_bps as u128 <= BPS_DENOMINATOR,
+                "ERR_INVALID_VESTING"
+            );
+            assert!(vesting.duration.0 > 0, "ERR_INVALID_VESTING");
+        }
+        assert!(
+            self.sale
+                .as_ref()
+                .is_none_or(|sale| !sale.refunds_open(env::block_timestamp())),
+            "ERR_REFUNDS_OPEN"
+        );
+        assert!(
+            self.sale.as_ref().is_none_or(|sale| sale.proceeds == 0),
+            "ERR_PROCEEDS_NOT_WITHDRAWN"
+        );
+        self.sale = Some(Sale {
+            id: self.sale.as_ref().map_or(0, |sale| sale.id + 1),
+            price: price.into(),
+            pricing,
+            start_at: start_at.map_or(env::block_timestamp(), |start_at| start_at.into()),
+            end_at: end_at.map(|end_at| end_at.into()),
+            referral_bps,
+            sold: 0,
+            proceeds: 0,
+            active: true,
+            refunds_end_at: None,
+            vesting,
+            min_purchase: 0,
+            max_purchase: None,
+        });
+    }
+
+    /// Bounds the total each account may buy over the current sale.
+    #[payable]
+    pub fn set_sale_purchase_limits(&mut self, min_purchase: U128, max_purchase: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(
+            max_purchase.is_none_or(|max_purchase| min_purchase.0 <= max_purchase.0),
+            "ERR_INVALID_LIMITS"
+        );
+        let mut sale = self.internal_get_sale();
+        sale.min_purchase = min_purchase.into();
+        sale.max_purchase = max_purchase.map(|max_purchase| max_purchase.into());
+        self.sale = Some(sale);
+        events::emit_config_changed(
+            "sale_purchase_limits",
+            json!({ "min_purchase": min_purchase, "max_purchase": max_purchase }),
+        );
+    }
+
+    /// Sets the price oracle of USD-priced sales and the oldest price it accepts.
+    #[payable]
+    pub fn set_sale_oracle(&mut self, oracle_id: ValidAccountId, max_staleness: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.sale_oracle = Some(SaleOracle {
+            oracle_id: oracle_id.into(),
+            max_staleness,
+        });
+        events::emit_config_changed("sale_oracle", self.sale_oracle.as_ref());
+    }
+
+    pub fn get_sale_oracle(&self) -> Option<SaleOracle> {
+        self.sale_oracle.clone()
+    }
+
+    /// Accepts the NEP-141 `token_id` as payment in the current sale at `price` of its units per
+    /// whole token, or stops accepting it if `price` is `None`.
+    #[payable]
+    pub fn set_sale_token_price(&mut self, token_id: ValidAccountId, price: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        let sale = self.internal_get_sale();
+        let key = (sale.id, token_id.clone().into());
+        match price {
+            Some(price) => {
+                assert!(price.0 > 0, "ERR_ZERO_PRICE");
+                assert!(sale.vesting.is_none(), "ERR_VESTING_SALE");
+                self.sale_token_prices.insert(&key, &price.0);
+            }
+            None => {
+                self.sale_token_prices.remove(&key);
+            }
+        }
+        events::emit_config_changed(
+            "sale_token_price",
+            json!({ "token_id": token_id, "price": price }),
+        );
+    }
+
+    pub fn get_sale_token_price(&self, token_id: ValidAccountId) -> Option<U128> {
+        self.sale.as_ref().and_then(|sale| {
+            self.sale_token_prices
+                .get(&(sale.id, token_id.into()))
+                .map(|price| price.into())
+        })
+    }
+
+    /// Raises the delay between requesting and executing a withdrawal of sale proceeds.
+    #[payable]
+    pub fn set_sale_withdrawal_delay(&mut self, delay: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            sale_withdrawal_delay: Some(delay),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_sale_withdrawal_delay(&self) -> U64 {
+        self.config.sale_withdrawal_delay.into()
+    }
+
+    /// Starts the timelock on withdrawing all proceeds collected in the payment token, or in
+    /// NEAR if `token_id` is `None`. Requested proceeds add to a pending withdrawal, which
+    /// restarts its timelock.
+    /// Not possible while a cancelled sale is refunding.
+    ///
+    /// Refunds of a cancelled sale are paid out of these proceeds, so they can only be withdrawn
+    /// once the refunds closed.
+    #[payable]
+    pub fn request_sale_withdrawal(&mut self, token_id: Option<ValidAccountId>) -> SaleWithdrawal {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        assert!(
+            self.sale
+                .as_ref()
+                .is_none_or(|sale| !sale.refunds_open(env::block_timestamp())),
+            "ERR_REFUNDS_OPEN"
+        );
+        let token_id: Option<AccountId> = token_id.map(|a| a.into());
+        let amount = match token_id.as_ref() {
+            None => {
+                let mut sale = self.internal_get_sale();
+                let proceeds = sale.proceeds;
+                sale.proceeds = 0;
+                self.sale = Some(sale);
+                proceeds
+            }
+            Some(token_id) => self.sale_token_proceeds.remove(token_id).unwrap_or(0),
+        };
+        assert!(amount > 0, "ERR_NOTHING_TO_WITHDRAW");
+        let pending = self
+            .sale_withdrawals
+            .get(&token_id)
+            .map_or(0, |withdrawal| withdrawal.amount.0);
+        let withdrawal = SaleWithdrawal {
+            amount: (pending + amount).into(),
+            available_at: (env::block_timestamp() + self.config.sale_withdrawal_delay).into(),
+        };
+        self.sale_withdrawals.insert(&token_id, &withdrawal);
+        events::emit_event(
+            "sale_withdrawal_requested",
+            json!({
+                "token_id": token_id,
+                "amount": withdrawal.amount,
+                "available_at": withdrawal.available_at,
+            }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        withdrawal
+    }
+
+    pub fn get_sale_withdrawal(&self, token_id: Option<ValidAccountId>) -> Option<SaleWithdrawal> {
+        self.sale_withdrawals.get(&token_id.map(|a| a.into()))
+    }
+
+    pub fn get_sale_token_proceeds(&self, token_id: ValidAccountId) -> U128 {
+        self.sale_token_proceeds
+            .get(token_id.as_ref())
+            .unwrap_or(0)
+            .into()
+    }
+
+    #[payable]
+    pub fn end_sale(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        let mut sale = self.internal_get_sale();
+        sale.active = false;
+        self.sale = Some(sale);
+    }
+
+    /// Stops the sale for good and lets buyers reclaim their payments during
+    /// `SALE_REFUND_PERIOD`.
+    #[payable]
+    pub fn cancel_sale(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        let mut sale = self.internal_get_sale();
+        assert!(sale.refunds_end_at.is_none(), "ERR_SALE_CANCELLED");
+        sale.active = false;
+        let refunds_end_at = env::block_timestamp() + SALE_REFUND_PERIOD;
+        sale.refunds_end_at = Some(refunds_end_at);
+        events::emit_event(
+            "sale_cancelled",
+            json!({ "sold": U128(sale.sold), "refunds_end_at": U64(refunds_end_at) }),
+        );
+        self.sale = Some(sale);
+    }
+
+    /// Refunds the predecessor's purchases in the cancelled sale. Burns the unclaimed part of the
+    /// sale grants and up to the released amount from the balance, and refunds the payments pro
+    /// rata to the burned tokens. Returns the burned amount.
+    #[payable]
+    pub fn claim_sale_refund(&mut self) -> U128 {
+        assert_one_yocto();
+        let mut sale = self.internal_get_sale();
+        let refunds_end_at = sale.refunds_end_at.expect("ERR_SALE_NOT_CANCELLED");
+        assert!(
+            env::block_timestamp() < refunds_end_at,
+            "ERR_REFUNDS_CLOSED"
+        );
+        let buyer_id = env::predecessor_account_id();
+        let purchase = self
+            .sale_purchases
+            .remove(&(sale.id, buyer_id.clone()))
+            .expect("ERR_NOTHING_TO_REFUND");
+        let contract_id = env::current_account_id();
+        let mut unclaimed = 0;
+        for grant_id in purchase.grant_ids.iter() {
+            unclaimed += self.internal_cancel_vesting_grant(*grant_id);
+        }
+        if unclaimed > 0 {
+            self.internal_burn(
+                &contract_id,
+                unclaimed,
+                Some("sale refund".to_string()),
+                None,
+            );
+        }
+        let released = std::cmp::min(
+            purchase.amount - unclaimed,
+            self.internal_balance_of(&buyer_id),
+        );
+        if released > 0 {
+            self.internal_burn(&buyer_id, released, Some("sale refund".to_string()), None);
+        }
+        let burned = unclaimed + released;
+        assert!(burned > 0, "ERR_NOTHING_TO_REFUND");
+        sale.sold -= purchase.amount;
+        self.sale = Some(sale);
+
+        let refund_near = mul_div(purchase.paid_near, burned, purchase.amount, false);
+        if refund_near > 0 {
+            self.internal_take_sale_funds(&None, refund_near);
+            Promise::new(buyer_id.clone()).transfer(refund_near);
+        }
+        for (token_id, paid) in purchase.paid_tokens {
+            let refund = mul_div(paid, burned, purchase.amount, false);
+            if refund == 0 {
+                continue;
+            }
+            self.internal_take_sale_funds(&Some(token_id.clone()), refund);
+            self.internal_transfer_sale_refund(buyer_id.clone(), token_id, refund);
+        }
+        events::emit_event(
+            "sale_refunded",
+            json!({
+                "account_id": buyer_id,
+                "burned": U128(burned),
+                "refund_near": U128(refund_near),
+            }),
+        );
+        burned.into()
+    }
+
+    /// Buys tokens for the attached deposit. If the buyer isn't registered yet, the storage
+    /// cost of the registration is taken out of the deposit first, as is the storage of the
+    /// grant if the sale vests. Returns the bought amount,
+    /// including the part that vests. USD-priced sales complete in `on_sale_price`.
+    #[payable]
+    pub fn buy(&mut self, referrer_id: Option<ValidAccountId>) -> PromiseOrValue<U128> {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_not_paused(PauseSurface::Sale);
+        self.assert_not_emergency();
+        let sale = self.internal_get_sale();
+        self.internal_assert_sale_open(&sale);
+        let buyer_id = env::predecessor_account_id();
+        let referrer_id: Option<AccountId> = referrer_id.map(|a| a.into());
+        if let Some(referrer_id) = referrer_id.as_ref() {
+            assert!(referrer_id != &buyer_id, "ERR_SELF_REFERRAL");
+            // Catches self-referrals through a proxy contract. Also rejects relayers referring
+            // purchases they relay as NEP-366 delegate actions, since they sign those.
+            assert!(
+                referrer_id != &env::signer_account_id(),
+                "ERR_REFERRER_IS_SIGNER"
+            );
+            if !self.referral_rewards.contains_key(referrer_id) {
+                self.referral_rewards.insert(referrer_id, &0);
+            }
+        }
+        self.internal_ensure_registered(&buyer_id);
+        let purchase_key = (sale.id, buyer_id.clone());
+        if !self.sale_purchases.contains_key(&purchase_key) {
+            self.sale_purchases
+                .insert(&purchase_key, &SalePurchase::default());
+        }
+        let mut storage_usage = env::storage_usage() - initial_storage_usage;
+        if sale.vesting.is_some() {
+            storage_usage += SALE_GRANT_STORAGE;
+        }
+        let storage_cost = env::storage_byte_cost() * Balance::from(storage_usage);
+        let payment = env::attached_deposit()
+            .checked_sub(storage_cost)
+            .expect("ERR_DEPOSIT_TOO_LOW");
+        match sale.pricing {
+            SalePricing::Near => {
+                let amount = self
+                    .internal_sale_purchase(&buyer_id, referrer_id, payment, sale.price, None)
+                    .unwrap_or_else(|err| env::panic(err.as_bytes()));
+                PromiseOrValue::Value(amount.into())
+            }
+            SalePricing::Usd => {
+                let oracle = self.sale_oracle.as_ref().expect("ERR_NO_ORACLE");
+                ext_price_oracle::get_near_usd_price(
+                    &oracle.oracle_id,
+                    NO_DEPOSIT,
+                    GAS_FOR_GET_PRICE,
+                )
+                .then(ext_self::on_sale_price(
+                    buyer_id,
+                    referrer_id,
+                    payment.into(),
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_ON_SALE_PRICE,
+                ))
+                .into()
+            }
+        }
+    }
+
+    /// Completes a purchase in a USD-priced sale at the oracle's price, or refunds the payment.
+    /// Returns the bought amount, zero if refunded.
+    #[private]
+    pub fn on_sale_price(
+        &mut self,
+        buyer_id: AccountId,
+        referrer_id: Option<AccountId>,
+        payment: U128,
+    ) -> U128 {
+        let price = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<OraclePrice>(&value).ok()
+            }
+            _ => None,
+        };
+        let sale = self.internal_get_sale();
+        let result = price
+            .ok_or("ERR_ORACLE_FAILED")
+            .and_then(|price| self.internal_usd_to_near_price(&sale, price))
+            .and_then(|near_price| {
+                self.internal_sale_purchase(&buyer_id, referrer_id, payment.0, near_price, None)
+            });
+        match result {
+            Ok(amount) => amount.into(),
+            Err(err) => {
+                log!("Refund {} to {}: {}", payment.0, buyer_id, err);
+                Promise::new(buyer_id).transfer(payment.0);
+                0.into()
+            }
+        }
+    }
+
+    /// Pays out the referral rewards of the predecessor. An unregistered referrer must attach
+    /// enough deposit to cover its registration.
+    #[payable]
+    pub fn claim_referral_rewards(&mut self) -> U128 {
+        let initial_storage_usage = env::storage_usage();
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let rewards = self.referral_rewards.get(&account_id).unwrap_or(0);
+        assert!(rewards > 0, "ERR_NOTHING_TO_CLAIM");
+        self.referral_rewards.insert(&account_id, &0);
+        self.internal_ensure_registered(&account_id);
+        self.internal_escrow_release(&account_id, rewards);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        rewards.into()
+    }
+
+    /// Executes a requested withdrawal once its timelock passed. NEAR goes to the owner,
+    /// payment tokens to the treasury. Not possible while a cancelled sale is refunding, since
+    /// refunds are also paid out of pending withdrawals.
+    #[payable]
+    pub fn withdraw_sale_proceeds(&mut self, token_id: Option<ValidAccountId>) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(
+            self.sale
+                .as_ref()
+                .is_none_or(|sale| !sale.refunds_open(env::block_timestamp())),
+            "ERR_REFUNDS_OPEN"
+        );
+        let token_id: Option<AccountId> = token_id.map(|a| a.into());
+        let withdrawal = self
+            .sale_withdrawals
+            .get(&token_id)
+            .expect("ERR_NOTHING_TO_WITHDRAW");
+        assert!(
+            env::block_timestamp() >= withdrawal.available_at.0,
+            "ERR_WITHDRAWAL_LOCKED"
+        );
+        self.sale_withdrawals.remove(&token_id);
+        events::emit_event(
+            "sale_proceeds_withdrawn",
+            json!({ "token_id": token_id, "amount": withdrawal.amount }),
+        );
+        match token_id {
+            None => Promise::new(self.owner_id.clone()).transfer(withdrawal.amount.0),
+            Some(token_id) => {
+                let treasury_id = self.treasury_id.clone().expect("ERR_NO_TREASURY");
+                ext_payment_token::ft_transfer(
+                    treasury_id,
+                    withdrawal.amount,
+                    Some("sale proceeds".to_string()),
+                    &token_id,
+                    1,
+                    GAS_FOR_FT_TRANSFER,
+                )
+                .then(ext_self::on_sale_proceeds_withdrawn(
+                    token_id,
+                    withdrawal.amount,
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_ON_SALE_PROCEEDS_WITHDRAWN,
+                ))
+            }
+        }
+    }
+
+    /// Transfers the predecessor's payment-token refund whose transfer failed before.
+    #[payable]
+    pub fn retry_sale_refund(&mut self, token_id: ValidAccountId) -> Promise {
+        assert_one_yocto();
+        let buyer_id = env::predecessor_account_id();
+        let token_id: AccountId = token_id.into();
+        let refund = self
+            .sale_failed_refunds
+            .remove(&(buyer_id.clone(), token_id.clone()))
+            .expect("ERR_NOTHING_TO_REFUND");
+        self.internal_transfer_sale_refund(buyer_id, token_id, refund)
+    }
+
+    /// Payment-token refund due to the account after its transfer failed.
+    pub fn get_sale_failed_refund(
+        &self,
+        account_id: ValidAccountId,
+        token_id: ValidAccountId,
+    ) -> U128 {
+        self.sale_failed_refunds
+            .get(&(account_id.into(), token_id.into()))
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Keeps a payment-token refund that couldn't be transferred due to the buyer.
+    #[private]
+    pub fn on_sale_refund_transferred(
+        &mut self,
+        buyer_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+    ) {
+        if let PromiseResult::Failed = env::promise_result(0) {
+            let key = (buyer_id, token_id);
+            let due = self.sale_failed_refunds.get(&key).unwrap_or(0);
+            self.sale_failed_refunds.insert(&key, &(due + amount.0));
+            log!("Refund of {} {} to {} failed", amount.0, key.1, key.0);
+        }
+    }
+
+    /// Puts payment tokens that couldn't be withdrawn back into the proceeds.
+    #[private]
+    pub fn on_sale_proceeds_withdrawn(&mut self, token_id: AccountId, amount: U128) {
+        if let PromiseResult::Failed = env::promise_result(0) {
+            let proceeds = self.sale_token_proceeds.get(&token_id).unwrap_or(0);
+            self.sale_token_proceeds
+                .insert(&token_id, &(proceeds + amount.0));
+            log!("Withdrawal of {} {} failed", amount.0, token_id);
+        }
+    }
+
+    pub fn get_sale(&self) -> Option<SaleView> {
+        self.sale.as_ref().map(|sale| SaleView {
+            price: sale.price.into(),
+            pricing: sale.pricing,
+            start_at: sale.start_at.into(),
+            end_at: sale.end_at.map(|end_at| end_at.into()),
+            referral_bps: sale.referral_bps,
+            sold: sale.sold.into(),
+            proceeds: sale.proceeds.into(),
+            active: sale.active,
+            cancelled: sale.refunds_end_at.is_some(),
+            refunds_end_at: sale.refunds_end_at.map(|end_at| end_at.into()),
+            vesting: sale.vesting,
+            min_purchase: sale.min_purchase.into(),
+            max_purchase: sale.max_purchase.map(|max_purchase| max_purchase.into()),
+        })
+    }
+
+    /// Total bought by the account over the current sale.
+    pub fn get_sale_purchased(&self, account_id: ValidAccountId) -> U128 {
+        self.sale
+            .as_ref()
+            .and_then(|sale| self.sale_purchases.get(&(sale.id, account_id.into())))
+            .map_or(0, |purchase| purchase.amount)
+            .into()
+    }
+
+    pub fn get_referral_rewards(&self, account_id: ValidAccountId) -> U128 {
+        self.referral_rewards
+            .get(account_id.as_ref())
+            .unwrap_or(0)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_created_receipts};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn value(result: PromiseOrValue<U128>) -> U128 {
+        match result {
+            PromiseOrValue::Value(value) => value,
+            PromiseOrValue::Promise(_) => panic!("Expected a value"),
+        }
+    }
+
+    #[test]
+    fn test_buy_with_referral() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        // One whole token (10^8 units) costs 1 NEAR.
+        contract.start_sale(ONE_NEAR.into(), None, None, 1_000, None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .signer_account_id(accounts(1))
+            .attached_deposit(2 * ONE_NEAR)
+            .build());
+        let bought = value(contract.buy(Some(accounts(2))));
+        assert!(bought.0 < 2 * 10u128.pow(8));
+        assert_eq!(contract.ft_balance_of(accounts(1)), bought);
+        assert_eq!(contract.get_referral_rewards(accounts(2)).0, bought.0 / 10);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .signer_account_id(accounts(2))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.claim_referral_rewards();
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, bought.0 / 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_claim_referral_rewards_pays_registration() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        contract.start_sale(ONE_NEAR.into(), None, None, 1_000, None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .signer_account_id(accounts(1))
+            .attached_deposit(2 * ONE_NEAR)
+            .build());
+        contract.buy(Some(accounts(2)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .signer_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.claim_referral_rewards();
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SELF_REFERRAL")]
+    fn test_self_referral() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        contract.start_sale(ONE_NEAR.into(), None, None, 1_000, None, None);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .signer_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.buy(Some(accounts(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_REFERRER_IS_SIGNER")]
+    fn test_relayer_referral() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        contract.start_sale(ONE_NEAR.into(), None, None, 1_000, None, None);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .signer_account_id(accounts(3))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.buy(Some(accounts(3)));
+    }
+
+    #[test]
+    fn test_buy_with_vesting() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        contract.start_sale(
+            ONE_NEAR.into(),
+            None,
+            None,
+            0,
+            Some(SaleVesting {
+                immediate_bps: 2_000,
+                duration: 100.into(),
+            }),
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .signer_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        let bought = value(contract.buy(None));
+        let immediate = bought.0 / 5;
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, immediate);
+        let grants = contract.get_vesting_grants(accounts(1));
+        assert_eq!(grants[0].total.0, bought.0 - immediate);
+        assert!(contract.get_sale().unwrap().proceeds.0 < ONE_NEAR);
+
+        testing_env!(context.attached_deposit(1).block_timestamp(50).build());
+        contract.claim_vested(grants[0].grant_id, None);
+        assert_eq!(
+            contract.ft_balance_of(accounts(1)).0,
+            immediate + (bought.0 - immediate) / 2
+        );
+    }
+
+    #[test]
+    fn test_purchase_limits() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        contract.start_sale(ONE_NEAR.into(), None, None, 0, None, None);
+        contract.set_sale_purchase_limits(10u128.pow(8).into(), Some((3 * 10u128.pow(8)).into()));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .signer_account_id(accounts(1))
+            .attached_deposit(2 * ONE_NEAR)
+            .build());
+        contract.buy(None);
+        testing_env!(context.attached_deposit(ONE_NEAR / 2).build());
+        contract.buy(None);
+        assert!(contract.get_sale_purchased(accounts(1)).0 < 3 * 10u128.pow(8));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ABOVE_MAX_PURCHASE")]
+    fn test_above_max_purchase() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        contract.start_sale(ONE_NEAR.into(), None, None, 0, None, None);
+        contract.set_sale_purchase_limits(0.into(), Some(10u128.pow(8).into()));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .signer_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR / 2)
+            .build());
+        contract.buy(None);
+        contract.buy(None);
+        contract.buy(None);
+    }
+
+    fn start_token_sale(contract: &mut Contract) {
+        contract.set_treasury(accounts(4));
+        contract.start_sale(ONE_NEAR.into(), None, None, 1_000, None, None);
+        // One whole token costs 2 whole units of a 6 decimals stablecoin.
+        contract.set_sale_token_price(accounts(3), Some(2_000_000.into()));
+    }
+
+    #[test]
+    fn test_buy_with_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        start_token_sale(&mut contract);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let msg = json!({ "referrer_id": accounts(2) }).to_string();
+        let unused = value(contract.ft_on_transfer(accounts(1), 10_000_000.into(), msg));
+        assert_eq!(unused.0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 5 * 10u128.pow(8));
+        assert_eq!(
+            contract.get_referral_rewards(accounts(2)).0,
+            5 * 10u128.pow(7)
+        );
+        assert_eq!(contract.get_sale_token_proceeds(accounts(3)).0, 10_000_000);
+        assert_eq!(contract.get_sale().unwrap().proceeds.0, 0);
+    }
+
+    #[test]
+    fn test_timelocked_withdrawal() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        start_token_sale(&mut contract);
+        contract.set_sale_withdrawal_delay(100.into());
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.ft_on_transfer(accounts(1), 10_000_000.into(), String::new());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .block_timestamp(10)
+            .build());
+        let withdrawal = contract.request_sale_withdrawal(Some(accounts(3)));
+        assert_eq!(withdrawal.amount.0, 10_000_000);
+        assert_eq!(withdrawal.available_at.0, 110);
+        assert_eq!(contract.get_sale_token_proceeds(accounts(3)).0, 0);
+
+        testing_env!(context.attached_deposit(1).block_timestamp(110).build());
+        contract.withdraw_sale_proceeds(Some(accounts(3)));
+        assert!(contract.get_sale_withdrawal(Some(accounts(3))).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_WITHDRAWAL_LOCKED")]
+    fn test_withdrawal_before_timelock() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        contract.start_sale(ONE_NEAR.into(), None, None, 0, None, None);
+        contract.set_sale_withdrawal_delay(100.into());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .signer_account_id(accounts(1))
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.buy(None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .signer_account_id(accounts(0))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.request_sale_withdrawal(None);
+        testing_env!(context.attached_deposit(1).block_timestamp(99).build());
+        contract.withdraw_sale_proceeds(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOKEN_NOT_ACCEPTED")]
+    fn test_buy_with_unaccepted_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        start_token_sale(&mut contract);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_on_transfer(accounts(1), 10_000_000.into(), String::new());
+    }
+
+    #[test]
+    fn test_cancel_sale_refund() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(0), 0);
+        mint_to(&mut contract, accounts(1), 0);
+        contract.start_sale(
+            ONE_NEAR.into(),
+            None,
+            None,
+            0,
+            Some(SaleVesting {
+                immediate_bps: 5_000,
+                duration: 100.into(),
+            }),
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .signer_account_id(accounts(1))
+            .attached_deposit(2 * ONE_NEAR)
+            .build());
+        let bought = value(contract.buy(None)).0;
+        let proceeds = contract.get_sale().unwrap().proceeds.0;
+        let grant_id = contract.get_vesting_grants(accounts(1))[0].grant_id;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .signer_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.cancel_sale();
+
+        // Half of the released tokens are gone, so only three quarters are refunded.
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(0), (bought / 4).into(), None);
+        let supply = contract.ft_total_supply().0;
+        let burned = bought - bought / 4;
+        assert_eq!(contract.claim_sale_refund().0, burned);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_total_supply().0, supply - burned);
+        assert!(contract.get_vesting_grant(grant_id).revoked);
+        assert_eq!(contract.get_vesting_grant(grant_id).remaining.0, 0);
+        let sale = contract.get_sale().unwrap();
+        assert_eq!(sale.sold.0, 0);
+        assert_eq!(
+            sale.proceeds.0,
+            proceeds - mul_div(proceeds, burned, bought, false)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SALE_NOT_CANCELLED")]
+    fn test_refund_without_cancel() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        contract.start_sale(ONE_NEAR.into(), None, None, 0, None, None);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.claim_sale_refund();
+    }
+
+    #[test]
+    fn test_withdraw_after_refunds_closed() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        contract.start_sale(ONE_NEAR.into(), None, None, 0, None, None);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(2 * ONE_NEAR)
+            .build());
+        contract.buy(None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.cancel_sale();
+        testing_env!(context
+            .attached_deposit(10u128.pow(24))
+            .block_timestamp(SALE_REFUND_PERIOD)
+            .build());
+        assert!(contract.request_sale_withdrawal(None).amount.0 > 0);
+        testing_env!(context.attached_deposit(1).build());
+        contract.withdraw_sale_proceeds(None);
+        contract.start_sale(ONE_NEAR.into(), None, None, 0, None, None);
+        assert!(!contract.get_sale().unwrap().cancelled);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_REFUNDS_OPEN")]
+    fn test_withdraw_while_refunds_open() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        contract.start_sale(ONE_NEAR.into(), None, None, 0, None, None);
+        contract.cancel_sale();
+        testing_env!(context
+            .attached_deposit(10u128.pow(24))
+            .block_timestamp(SALE_REFUND_PERIOD - 1)
+            .build());
+        contract.request_sale_withdrawal(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_REFUNDS_OPEN")]
+    fn test_pending_withdrawal_while_refunds_open() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        contract.start_sale(ONE_NEAR.into(), None, None, 0, None, None);
+        contract.set_sale_withdrawal_delay(100.into());
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(2 * ONE_NEAR)
+            .build());
+        contract.buy(None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.request_sale_withdrawal(None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.cancel_sale();
+        testing_env!(context.block_timestamp(100).build());
+        contract.withdraw_sale_proceeds(None);
+    }
+
+    #[test]
+    fn test_failed_token_refund_stays_due() {
+        let mut context = get_context(accounts(0));
+        testing_env!(
+            context.predecessor_account_id(contract_account()).build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        let mut contract = new_contract(10u128.pow(12));
+        contract.on_sale_refund_transferred(accounts(1).into(), accounts(2).into(), 500.into());
+        assert_eq!(
+            contract.get_sale_failed_refund(accounts(1), accounts(2)).0,
+            500
+        );
+        assert_eq!(contract.get_sale_token_proceeds(accounts(2)).0, 0);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.retry_sale_refund(accounts(2));
+        assert_eq!(
+            contract.get_sale_failed_refund(accounts(1), accounts(2)).0,
+            0
+        );
+    }
+
+    fn oracle_price(updated_at: u64) -> Vec<PromiseResult> {
+        // 1 NEAR = 5 USD.
+        let price = OraclePrice {
+            price: 5_000_000.into(),
+            decimals: 6,
+            updated_at: updated_at.into(),
+        };
+        vec![PromiseResult::Successful(
+            near_sdk::serde_json::to_vec(&price).unwrap(),
+        )]
+    }
+
+    fn start_usd_sale(contract: &mut Contract) {
+        contract.set_sale_oracle(accounts(3), 60.into());
+        // One whole token costs 1 USD.
+        contract.start_sale(
+            1_000_000.into(),
+            None,
+            None,
+            0,
+            None,
+            Some(SalePricing::Usd),
+        );
+    }
+
+    #[test]
+    fn test_usd_priced_sale() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        start_usd_sale(&mut contract);
+
+        testing_env!(
+            context
+                .predecessor_account_id(contract_account())
+                .attached_deposit(0)
+                .block_timestamp(100)
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            oracle_price(50)
+        );
+        let bought = contract.on_sale_price(accounts(1).into(), None, ONE_NEAR.into());
+        assert_eq!(bought.0, 5 * 10u128.pow(8));
+        assert_eq!(contract.ft_balance_of(accounts(1)), bought);
+        assert_eq!(contract.get_sale().unwrap().proceeds.0, ONE_NEAR);
+    }
+
+    #[test]
+    fn test_usd_priced_sale_stale_price() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        start_usd_sale(&mut contract);
+
+        testing_env!(
+            context
+                .predecessor_account_id(contract_account())
+                .attached_deposit(0)
+                .block_timestamp(100)
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            oracle_price(10)
+        );
+        assert_eq!(
+            contract
+                .on_sale_price(accounts(1).into(), None, ONE_NEAR.into())
+                .0,
+            0
+        );
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.get_sale().unwrap().proceeds.0, 0);
+    }
+
+    #[test]
+    fn test_usd_priced_sale_payment_too_low() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(10u128.pow(12));
+        mint_to(&mut contract, accounts(1), 0);
+        contract.set_sale_oracle(accounts(3), 60.into());
+        contract.start_sale(
+            1_000_000.into(),
+            None,
+            None,
+            0,
+            Some(SaleVesting {
+                immediate_bps: 2_000,
+                duration: 100.into(),
+            }),
+            Some(SalePricing::Usd),
+        );
+
+        testing_env!(
+            context
+                .predecessor_account_id(contract_account())
+                .attached_deposit(0)
+                .block_timestamp(100)
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            oracle_price(50)
+        );
+        let payment = 10u128.pow(10);
+        assert_eq!(
+            contract
+                .on_sale_price(accounts(1).into(), None, payment.into())
+                .0,
+            0
+        );
+        assert_eq!(get_created_receipts().len(), 1);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert!(contract.get_vesting_grants(accounts(1)).is_empty());
+        assert_eq!(contract.get_sale().unwrap().proceeds.0, 0);
+    }
+}
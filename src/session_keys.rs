@@ -0,0 +1,212 @@
+//! Session keys: a holder authorizes another account, e.g. a game client, to spend up to a daily
+//! limit of its tokens with `session_transfer`, until the holder revokes it.
+//!
+//! `session_transfer` requires no deposit, so the session account can call it with a
+//! function-call access key and the user isn't prompted for every move. The daily limit applies
+//! to a 24-hour window starting with the first transfer after the previous window ended.
+
+use crate::pause::PauseSurface;
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::volume_limit::DAY;
+use crate::*;
+use near_sdk::serde_json::json;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Session {
+    pub daily_limit: Balance,
+    pub window_started_at: u64,
+    /// Spent since `window_started_at`.
+    pub spent: Balance,
+}
+
+impl Session {
+    fn spent_now(&self) -> Balance {
+        if env::block_timestamp() >= self.window_started_at.saturating_add(DAY) {
+            0
+        } else {
+            self.spent
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SessionView {
+    pub daily_limit: U128,
+    /// Amount that can still be spent in the current window.
+    pub remaining: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Authorizes `session_id` to spend up to `daily_limit` of the predecessor's tokens per day,
+    /// replacing an earlier limit. The holder pays for the storage out of the attached deposit.
+    #[payable]
+    pub fn authorize_session(&mut self, session_id: ValidAccountId, daily_limit: U128) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let holder_id = env::predecessor_account_id();
+        assert_ne!(&holder_id, session_id.as_ref(), "ERR_SELF_SESSION");
+        assert!(daily_limit.0 > 0, "ERR_ZERO_LIMIT");
+        let key = (holder_id.clone(), session_id.to_string());
+        let session = match self.sessions.get(&key) {
+            Some(session) => Session {
+                daily_limit: daily_limit.into(),
+                ..session
+            },
+            None => Session {
+                daily_limit: daily_limit.into(),
+                window_started_at: 0,
+                spent: 0,
+            },
+        };
+        self.sessions.insert(&key, &session);
+        events::emit_event(
+            "session_authorized",
+            json!({ "holder_id": holder_id, "session_id": session_id, "daily_limit": daily_limit }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Revokes the authorization of `session_id` and refunds the holder for its storage.
+    #[payable]
+    pub fn revoke_session(&mut self, session_id: ValidAccountId) {
+        assert_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let holder_id = env::predecessor_account_id();
+        assert!(
+            self.sessions
+                .remove(&(holder_id.clone(), session_id.to_string()))
+                .is_some(),
+            "ERR_NO_SESSION"
+        );
+        events::emit_event(
+            "session_revoked",
+            json!({ "holder_id": holder_id, "session_id": session_id }),
+        );
+        refund_released_storage(
+            &holder_id,
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+    }
+
+    /// Transfers `amount` of `holder_id`'s tokens to `receiver_id` within the daily limit the
+    /// holder authorized the predecessor for.
+    pub fn session_transfer(
+        &mut self,
+        holder_id: ValidAccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        self.assert_not_paused(PauseSurface::Transfers);
+        let session_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&session_id);
+        let key = (holder_id.to_string(), session_id);
+        let mut session = self.sessions.get(&key).expect("ERR_NO_SESSION");
+        let now = env::block_timestamp();
+        if now >= session.window_started_at.saturating_add(DAY) {
+            session.window_started_at = now;
+            session.spent = 0;
+        }
+        session.spent = session.spent.checked_add(amount.0).expect("ERR_OVERFLOW");
+        assert!(
+            session.spent <= session.daily_limit,
+            "ERR_SESSION_LIMIT_EXCEEDED"
+        );
+        self.sessions.insert(&key, &session);
+        self.internal_holder_transfer(holder_id.into(), receiver_id.into(), amount, memo, None);
+    }
+
+    pub fn get_session(
+        &self,
+        holder_id: ValidAccountId,
+        session_id: ValidAccountId,
+    ) -> Option<SessionView> {
+        self.sessions
+            .get(&(holder_id.into(), session_id.into()))
+            .map(|session| SessionView {
+                daily_limit: session.daily_limit.into(),
+                remaining: session
+                    .daily_limit
+                    .saturating_sub(session.spent_now())
+                    .into(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+        mint_to(&mut contract, accounts(3), 0);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.authorize_session(accounts(2), 100.into());
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .block_timestamp(5)
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_session_transfer() {
+        let (mut context, mut contract) = setup();
+        contract.session_transfer(accounts(1), accounts(3), 60.into(), None);
+        contract.session_transfer(accounts(1), accounts(3), 40.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 100);
+        assert_eq!(
+            contract
+                .get_session(accounts(1), accounts(2))
+                .unwrap()
+                .remaining
+                .0,
+            0
+        );
+
+        testing_env!(context.block_timestamp(5 + DAY).build());
+        contract.session_transfer(accounts(1), accounts(3), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 200);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SESSION_LIMIT_EXCEEDED")]
+    fn test_session_limit() {
+        let (_, mut contract) = setup();
+        contract.session_transfer(accounts(1), accounts(3), 60.into(), None);
+        contract.session_transfer(accounts(1), accounts(3), 41.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_SESSION")]
+    fn test_revoked_session() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.revoke_session(accounts(2));
+        assert_eq!(get_created_receipts().len(), 1);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(0)
+            .build());
+        contract.session_transfer(accounts(1), accounts(3), 10.into(), None);
+    }
+}
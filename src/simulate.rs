@@ -0,0 +1,140 @@
+//! Dry run of a transfer for frontend pre-flight checks.
+//!
+//! `simulate_transfer` goes through the same checks as `ft_transfer` in the same order and
+//! reports the first one that fails instead of panicking. Failures raised by the contract's own
+//! checks carry the error the call would fail with; those of the underlying NEP-141 transfer are
+//! reported as `ERR_SAME_ACCOUNT`, `ERR_ZERO_AMOUNT`, `ERR_NOT_REGISTERED` and
+//! `ERR_INSUFFICIENT_BALANCE`. The sender needs the transfer fee on top of the amount.
+
+use crate::pause::PauseSurface;
+use crate::*;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferSimulation {
+    /// Error the transfer would fail with, `None` if it would succeed.
+    pub failure: Option<String>,
+    /// Balances after the transfer, or the current ones if it would fail.
+    pub sender_balance: U128,
+    pub receiver_balance: U128,
+    /// Transfer fee the sender pays on top of the amount.
+    pub fee: U128,
+    /// Whether the compliance registry still has to allow the transfer, which may reject it.
+    pub compliance_check: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Outcome of `ft_transfer` of `amount` from `sender_id` to `receiver_id` at the current
+    /// block.
+    pub fn simulate_transfer(
+        &self,
+        sender_id: ValidAccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+    ) -> TransferSimulation {
+        let (sender_id, receiver_id): (AccountId, AccountId) =
+            (sender_id.into(), receiver_id.into());
+        let sender_balance = self.internal_balance_of(&sender_id);
+        let receiver_balance = self.internal_balance_of(&receiver_id);
+        let compliance_check = self.config.compliance_registry.is_some();
+        let fee = self.internal_transfer_fee(amount.0);
+        let failure = self.internal_transfer_failure(&sender_id, &receiver_id, amount.0);
+        if let Some(failure) = failure {
+            return TransferSimulation {
+                failure: Some(failure.to_string()),
+                sender_balance: sender_balance.into(),
+                receiver_balance: receiver_balance.into(),
+                fee: fee.into(),
+                compliance_check,
+            };
+        }
+        // Same rounding as `internal_transfer`.
+        let shares = self.amount_to_shares(amount.0, true);
+        let fee_shares = self.internal_transfer_fee_shares(&sender_id, fee);
+        let sender_shares = self.token.accounts.get(&sender_id).unwrap_or(0);
+        let receiver_shares = self.token.accounts.get(&receiver_id).unwrap_or(0);
+        TransferSimulation {
+            failure: None,
+            sender_balance: self
+                .shares_to_amount(sender_shares - shares - fee_shares)
+                .into(),
+            receiver_balance: self.shares_to_amount(receiver_shares + shares).into(),
+            fee: fee.into(),
+            compliance_check,
+        }
+    }
+}
+
+impl Contract {
+    fn internal_transfer_failure(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Option<&'static str> {
+        if self.internal_is_paused(PauseSurface::Transfers) {
+            return Some("ERR_PAUSED");
+        }
+        if let Err(reason) = self.check_holder_transfer(sender_id, receiver_id, amount) {
+            return Some(reason);
+        }
+        if sender_id == receiver_id {
+            return Some("ERR_SAME_ACCOUNT");
+        }
+        if amount == 0 {
+            return Some("ERR_ZERO_AMOUNT");
+        }
+        let sender_shares = match self.token.accounts.get(sender_id) {
+            Some(shares) => shares,
+            None => return Some("ERR_NOT_REGISTERED"),
+        };
+        let fee = self.internal_transfer_fee(amount);
+        if sender_shares
+            < self.amount_to_shares(amount, true)
+                + self.internal_transfer_fee_shares(sender_id, fee)
+        {
+            return Some("ERR_INSUFFICIENT_BALANCE");
+        }
+        if !self.token.accounts.contains_key(receiver_id) {
+            return Some("ERR_NOT_REGISTERED");
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_simulate_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 100)]);
+
+        let simulation = contract.simulate_transfer(accounts(1), accounts(2), 200.into());
+        assert!(simulation.failure.is_none());
+        assert_eq!(simulation.sender_balance.0, 300);
+        assert_eq!(simulation.receiver_balance.0, 300);
+        assert!(!simulation.compliance_check);
+
+        let simulation = contract.simulate_transfer(accounts(1), accounts(2), 600.into());
+        assert_eq!(
+            simulation.failure.as_deref(),
+            Some("ERR_INSUFFICIENT_BALANCE")
+        );
+        assert_eq!(simulation.sender_balance.0, 500);
+        let simulation = contract.simulate_transfer(accounts(1), accounts(3), 10.into());
+        assert_eq!(simulation.failure.as_deref(), Some("ERR_NOT_REGISTERED"));
+
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        contract.update_blacklist_batch(vec![accounts(2)], vec![]);
+        let simulation = contract.simulate_transfer(accounts(1), accounts(2), 10.into());
+        assert_eq!(simulation.failure.as_deref(), Some("ERR_BLACKLISTED"));
+    }
+}
@@ -0,0 +1,122 @@
+//! Numbered balance snapshots for dividend and governance tooling, which refer to "snapshot 3"
+//! rather than to a block height.
+//!
+//! A snapshot only records the block height it was taken at; balances are read from the
+//! checkpoints, which must be enabled. Like checkpoints, a snapshot reflects balances at the end
+//! of its block, so transfers later in the same block are included. Snapshot IDs are sequential
+//! from 0.
+
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde_json::json;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Snapshot {
+    pub block_height: U64,
+    pub taken_at: U64,
+}
+
+impl Contract {
+    fn internal_snapshot(&self, snapshot_id: U64) -> Snapshot {
+        self.snapshots.get(snapshot_id.0).expect("ERR_NO_SNAPSHOT")
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Takes a snapshot of all balances and returns its ID. The attached deposit covers the
+    /// storage; the rest is refunded.
+    #[payable]
+    pub fn take_snapshot(&mut self) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        assert!(
+            self.checkpoints_enabled_at.is_some(),
+            "ERR_CHECKPOINTS_DISABLED"
+        );
+        let snapshot = Snapshot {
+            block_height: env::block_index().into(),
+            taken_at: env::block_timestamp().into(),
+        };
+        let snapshot_id = U64(self.snapshots.len());
+        self.snapshots.push(&snapshot);
+        self.internal_checkpoint_clock();
+        events::emit_event(
+            "snapshot_taken",
+            json!({ "snapshot_id": snapshot_id, "block_height": snapshot.block_height }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        snapshot_id
+    }
+
+    pub fn get_snapshot(&self, snapshot_id: U64) -> Option<Snapshot> {
+        self.snapshots.get(snapshot_id.0)
+    }
+
+    pub fn get_snapshot_count(&self) -> U64 {
+        self.snapshots.len().into()
+    }
+
+    /// Balance of the account at snapshot `snapshot_id`.
+    pub fn balance_at_snapshot(&self, account_id: ValidAccountId, snapshot_id: U64) -> U128 {
+        let snapshot = self.internal_snapshot(snapshot_id);
+        self.balance_of_at(account_id, snapshot.block_height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_balance_at_snapshot() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_index(10).build());
+        let mut contract =
+            new_contract_with_balances(10_000, &[(accounts(1), 1_000), (accounts(2), 0)]);
+        contract.enable_checkpoints();
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        assert_eq!(contract.take_snapshot().0, 0);
+
+        testing_env!(context
+            .block_index(20)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(2), 300.into(), None);
+        testing_env!(context
+            .block_index(30)
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        assert_eq!(contract.take_snapshot().0, 1);
+        testing_env!(context
+            .block_index(40)
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.ft_transfer(accounts(1), 50.into(), None);
+
+        assert_eq!(contract.balance_at_snapshot(accounts(1), 0.into()).0, 1_000);
+        assert_eq!(contract.balance_at_snapshot(accounts(2), 0.into()).0, 0);
+        assert_eq!(contract.balance_at_snapshot(accounts(1), 1.into()).0, 700);
+        assert_eq!(contract.get_snapshot_count().0, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_CHECKPOINTS_DISABLED")]
+    fn test_snapshot_requires_checkpoints() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract(10_000);
+        contract.take_snapshot();
+    }
+}
@@ -0,0 +1,187 @@
+//! Payment splitters: a group of recipients with share weights that `split_transfer` pays out
+//! proportionally in a single call.
+//!
+//! Anyone can split a transfer to any group; only the creator of a group can change its
+//! recipients. Each share is rounded down and the remainder goes to the first recipient, so the
+//! whole amount is always paid out. Every share is a transfer by the caller and goes through the
+//! same restrictions. The creator pays for the storage of the group.
+
+use crate::pause::PauseSurface;
+use crate::utils::{mul_div, refund_deposit};
+use crate::*;
+use near_sdk::serde::Serialize;
+
+const MAX_SPLIT_RECIPIENTS: usize = 20;
+
+pub type SplitterId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Splitter {
+    pub owner_id: AccountId,
+    /// Recipients with their share weights.
+    pub recipients: Vec<(AccountId, u32)>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SplitterView {
+    pub owner_id: AccountId,
+    pub recipients: Vec<(AccountId, u32)>,
+}
+
+fn validate_recipients(recipients: Vec<(ValidAccountId, u32)>) -> Vec<(AccountId, u32)> {
+    assert!(
+        !recipients.is_empty() && recipients.len() <= MAX_SPLIT_RECIPIENTS,
+        "ERR_INVALID_RECIPIENTS"
+    );
+    let mut validated: Vec<(AccountId, u32)> = Vec::with_capacity(recipients.len());
+    for (account_id, weight) in recipients {
+        let account_id: AccountId = account_id.into();
+        assert!(weight > 0, "ERR_ZERO_WEIGHT");
+        assert!(
+            validated.iter().all(|(other, _)| *other != account_id),
+            "ERR_DUPLICATE_RECIPIENT"
+        );
+        validated.push((account_id, weight));
+    }
+    validated
+}
+
+/// Shares of `amount` for each recipient, in order.
+fn split_amount(recipients: &[(AccountId, u32)], amount: Balance) -> Vec<Balance> {
+    let total_weight: u128 = recipients.iter().map(|(_, weight)| *weight as u128).sum();
+    let mut shares: Vec<Balance> = recipients
+        .iter()
+        .map(|(_, weight)| mul_div(amount, *weight as u128, total_weight, false))
+        .collect();
+    shares[0] += amount - shares.iter().sum::<Balance>();
+    shares
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Creates a splitter group owned by the predecessor. The attached deposit covers its
+    /// storage; the rest is refunded.
+    #[payable]
+    pub fn create_splitter(&mut self, recipients: Vec<(ValidAccountId, u32)>) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let splitter_id = self.next_splitter_id;
+        self.next_splitter_id += 1;
+        self.splitters.insert(
+            &splitter_id,
+            &Splitter {
+                owner_id: env::predecessor_account_id(),
+                recipients: validate_recipients(recipients),
+            },
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        splitter_id.into()
+    }
+
+    /// Replaces the recipients of a splitter group owned by the predecessor. The attached
+    /// deposit covers any storage the new recipients add.
+    #[payable]
+    pub fn update_splitter(&mut self, splitter_id: U64, recipients: Vec<(ValidAccountId, u32)>) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let mut splitter = self.splitters.get(&splitter_id.0).expect("ERR_NO_SPLITTER");
+        assert_eq!(
+            env::predecessor_account_id(),
+            splitter.owner_id,
+            "ERR_NOT_ALLOWED"
+        );
+        splitter.recipients = validate_recipients(recipients);
+        self.splitters.insert(&splitter_id.0, &splitter);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Transfers `amount` of the predecessor's tokens to the recipients of a splitter group in
+    /// proportion to their weights.
+    #[payable]
+    pub fn split_transfer(&mut self, splitter_id: U64, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        self.assert_not_paused(PauseSurface::Transfers);
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        let splitter = self.splitters.get(&splitter_id.0).expect("ERR_NO_SPLITTER");
+        let sender_id = env::predecessor_account_id();
+        let shares = split_amount(&splitter.recipients, amount.0);
+        for ((receiver_id, _), share) in splitter.recipients.into_iter().zip(shares) {
+            if share > 0 {
+                self.internal_holder_transfer(
+                    sender_id.clone(),
+                    receiver_id,
+                    share.into(),
+                    memo.clone(),
+                    None,
+                );
+            }
+        }
+    }
+
+    pub fn get_splitter(&self, splitter_id: U64) -> Option<SplitterView> {
+        self.splitters
+            .get(&splitter_id.0)
+            .map(|splitter| SplitterView {
+                owner_id: splitter.owner_id,
+                recipients: splitter.recipients,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_split_amount() {
+        let recipients = vec![
+            ("a".to_string(), 1),
+            ("b".to_string(), 1),
+            ("c".to_string(), 1),
+        ];
+        assert_eq!(split_amount(&recipients, 100), vec![34, 33, 33]);
+        assert_eq!(split_amount(&recipients, 1), vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn test_split_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(
+            1_000,
+            &[(accounts(1), 500), (accounts(2), 0), (accounts(3), 0)],
+        );
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        let splitter_id = contract.create_splitter(vec![(accounts(2), 3), (accounts(3), 1)]);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.split_transfer(splitter_id, 400.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 300);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_DUPLICATE_RECIPIENT")]
+    fn test_duplicate_recipient() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.create_splitter(vec![(accounts(2), 1), (accounts(2), 1)]);
+    }
+}
@@ -0,0 +1,715 @@
+//! Staking: holders stake tokens and earn rewards emitted at a rate per second, shared pro rata
+//! between all stakers.
+//!
+//! The rate is a history of segments, each effective from its start until the next one starts.
+//! New rates can only take effect from now on, so rewards accrued so far never change.
+//!
+//! Staked tokens are escrowed on the contract account. Rewards are tracked with a cumulative
+//! reward per staked token and minted on claim, within `max_supply`. A staker may have rewards
+//! paid out to a different account than the staking one, e.g. a cold wallet, or have them
+//! restaked on claim with auto-compounding.
+//!
+//! Stakes can be locked into one of the owner-configured lock tiers. Unstaking before the lock
+//! expires forfeits the tier's penalty, either to the reward reserve or burned. The reserve
+//! holds forfeited tokens on the contract account and pays out claims before any new rewards
+//! are minted.
+
+use crate::interest::BPS_DENOMINATOR;
+use crate::pause::PauseSurface;
+use crate::utils::{mul_div, refund_deposit};
+use crate::*;
+use near_sdk::collections::Vector;
+use near_sdk::json_types::U64;
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json::json;
+
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+/// Scale of `reward_per_token`.
+const REWARD_PRECISION: u128 = 1_000_000_000_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RewardRateSegment {
+    pub start_at: U64,
+    pub tokens_per_second: U128,
+}
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum PenaltyDestination {
+    RewardReserve,
+    Burn,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakingLockTier {
+    pub duration: U64,
+    /// Share of the unstaked amount forfeited when unstaking before the lock expires.
+    pub penalty_bps: u32,
+    pub penalty_destination: PenaltyDestination,
+}
+
+/// Lock of a stake, with the penalty terms of its tier at the time it was locked.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakeLock {
+    pub unlock_at: U64,
+    pub penalty_bps: u32,
+    pub penalty_destination: PenaltyDestination,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StakingPool {
+    pub total_staked: Balance,
+    /// Reward rates ordered by start, the first one starting at zero.
+    pub rate_segments: Vector<RewardRateSegment>,
+    /// Rewards per staked token emitted up to `updated_at`, scaled by `REWARD_PRECISION`.
+    pub reward_per_token: u128,
+    pub updated_at: u64,
+    pub lock_tiers: Vec<StakingLockTier>,
+    /// Forfeited stake escrowed on the contract account, paid out before minting rewards.
+    pub reward_reserve: Balance,
+}
+
+impl StakingPool {
+    pub fn new() -> Self {
+        let mut rate_segments = Vector::new(StorageKey::RewardRateSegments);
+        rate_segments.push(&RewardRateSegment {
+            start_at: 0.into(),
+            tokens_per_second: 0.into(),
+        });
+        Self {
+            total_staked: 0,
+            rate_segments,
+            reward_per_token: 0,
+            updated_at: env::block_timestamp(),
+            lock_tiers: vec![],
+            reward_reserve: 0,
+        }
+    }
+
+    fn reward_rate_at(&self, now: u64) -> Balance {
+        self.rate_segments
+            .iter()
+            .take_while(|segment| segment.start_at.0 <= now)
+            .last()
+            .map_or(0, |segment| segment.tokens_per_second.0)
+    }
+
+    /// Rewards emitted between `from` and `to`, walking the rate segments back from the latest.
+    fn rewards_between(&self, from: u64, to: u64) -> Balance {
+        let mut rewards = 0;
+        let mut end = to;
+        for index in (0..self.rate_segments.len()).rev() {
+            let segment = self.rate_segments.get(index).unwrap();
+            if segment.start_at.0 >= end {
+                continue;
+            }
+            let start = std::cmp::max(segment.start_at.0, from);
+            rewards += mul_div(
+                segment.tokens_per_second.0,
+                (end - start) as u128,
+                NANOSECONDS_PER_SECOND as u128,
+                false,
+            );
+            if start == from {
+                break;
+            }
+            end = start;
+        }
+        rewards
+    }
+
+    fn reward_per_token_at(&self, now: u64) -> u128 {
+        if self.total_staked == 0 || now <= self.updated_at {
+            return self.reward_per_token;
+        }
+        self.reward_per_token
+            + mul_div(
+                self.rewards_between(self.updated_at, now),
+                REWARD_PRECISION,
+                self.total_staked,
+                false,
+            )
+    }
+
+    fn update(&mut self, now: u64) {
+        self.reward_per_token = self.reward_per_token_at(now);
+        self.updated_at = std::cmp::max(self.updated_at, now);
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct Stake {
+    pub amount: Balance,
+    /// `reward_per_token` the rewards were settled at.
+    pub reward_per_token_paid: u128,
+    /// Rewards settled and not claimed yet.
+    pub pending_rewards: Balance,
+    pub reward_destination: Option<AccountId>,
+    /// Restake rewards on claim instead of paying them out.
+    pub auto_compound: bool,
+    pub lock: Option<StakeLock>,
+}
+
+impl Stake {
+    fn rewards_at(&self, reward_per_token: u128) -> Balance {
+        self.pending_rewards
+            + mul_div(
+                self.amount,
+                reward_per_token - self.reward_per_token_paid,
+                REWARD_PRECISION,
+                false,
+            )
+    }
+
+    fn settle(&mut self, reward_per_token: u128) {
+        self.pending_rewards = self.rewards_at(reward_per_token);
+        self.reward_per_token_paid = reward_per_token;
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakeView {
+    pub amount: U128,
+    pub pending_rewards: U128,
+    /// Where rewards are paid out, the staking account itself if `None`.
+    pub reward_destination: Option<AccountId>,
+    pub auto_compound: bool,
+    pub lock: Option<StakeLock>,
+}
+
+impl Contract {
+    /// Settles the pool and the account's rewards up to now.
+    fn internal_settled_stake(&mut self, account_id: &AccountId) -> Stake {
+        self.staking.update(env::block_timestamp());
+        let mut stake = self.stakes.get(account_id).unwrap_or_default();
+        stake.settle(self.staking.reward_per_token);
+        stake
+    }
+
+    /// Pays `amount` of rewards to `receiver_id`, out of the reserve first and minting the rest.
+    fn internal_pay_staking_rewards(&mut self, receiver_id: &AccountId, amount: Balance) {
+        let from_reserve = std::cmp::min(amount, self.staking.reward_reserve);
+        self.staking.reward_reserve -= from_reserve;
+        if from_reserve > 0 && receiver_id != &env::current_account_id() {
+            self.internal_escrow_release(receiver_id, from_reserve);
+        }
+        if amount > from_reserve {
+            self.assert_not_paused(PauseSurface::Mint);
+            self.internal_mint(receiver_id, amount - from_reserve, Some("staking_rewards"));
+        }
+    }
+
+    /// Escrows `amount` of `account_id`'s tokens as stake, locked into `lock_tier` if set.
+    pub(crate) fn internal_stake(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        lock_tier: Option<u32>,
+    ) {
+        self.assert_not_paused(PauseSurface::Staking);
+        self.assert_not_emergency();
+        self.assert_not_blacklisted(account_id);
+        assert!(amount > 0, "ERR_ZERO_AMOUNT");
+        let mut stake = self.internal_settled_stake(account_id);
+        if let Some(lock_tier) = lock_tier {
+            let tier = self
+                .staking
+                .lock_tiers
+                .get(lock_tier as usize)
+                .expect("ERR_NO_LOCK_TIER");
+            let now = env::block_timestamp();
+            let mut lock = StakeLock {
+                unlock_at: (now + tier.duration.0).into(),
+                penalty_bps: tier.penalty_bps,
+                penalty_destination: tier.penalty_destination,
+            };
+            // A running lock keeps its end and, if stricter, its penalty.
+            if let Some(previous) = stake.lock.as_ref().filter(|lock| lock.unlock_at.0 > now) {
+                lock.unlock_at = std::cmp::max(lock.unlock_at.0, previous.unlock_at.0).into();
+                if previous.penalty_bps > lock.penalty_bps {
+                    lock.penalty_bps = previous.penalty_bps;
+                    lock.penalty_destination = previous.penalty_destination;
+                }
+            }
+            stake.lock = Some(lock);
+        }
+        self.internal_escrow_deposit(account_id, amount);
+        stake.amount += amount;
+        self.staking.total_staked += amount;
+        self.stakes.insert(account_id, &stake);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the rewards emitted per second to all stakers from `effective_from` on, now by
+    /// default. `effective_from` can't be in the past nor before an already scheduled rate.
+    #[payable]
+    pub fn set_reward_rate(&mut self, tokens_per_second: U128, effective_from: Option<U64>) {
+        assert_one_yocto();
+        self.assert_owner();
+        let now = env::block_timestamp();
+        let effective_from = effective_from.map_or(now, |effective_from| effective_from.0);
+        assert!(effective_from >= now, "ERR_RETROACTIVE_RATE");
+        self.staking.update(now);
+        let segments = &mut self.staking.rate_segments;
+        let last = segments.get(segments.len() - 1).unwrap();
+        assert!(effective_from >= last.start_at.0, "ERR_RETROACTIVE_RATE");
+        let segment = RewardRateSegment {
+            start_at: effective_from.into(),
+            tokens_per_second,
+        };
+        if effective_from == last.start_at.0 {
+            segments.replace(segments.len() - 1, &segment);
+        } else {
+            segments.push(&segment);
+        }
+        events::emit_config_changed("reward_rate", segment);
+    }
+
+    /// Replaces the lock tiers stakers can choose from. Existing locks keep their terms.
+    #[payable]
+    pub fn set_staking_lock_tiers(&mut self, tiers: Vec<StakingLockTier>) {
+        assert_one_yocto();
+        self.assert_owner();
+        for tier in tiers.iter() {
+            assert!(
+                tier.penalty_bps as u128 <= BPS_DENOMINATOR,
+                "ERR_INVALID_PENALTY"
+            );
+        }
+        events::emit_config_changed("staking_lock_tiers", &tiers);
+        self.staking.lock_tiers = tiers;
+    }
+
+    pub fn get_staking_lock_tiers(&self) -> Vec<StakingLockTier> {
+        self.staking.lock_tiers.clone()
+    }
+
+    /// Stakes `amount` of the predecessor's tokens. With a `lock_tier`, the whole stake is
+    /// locked for the tier's duration, or longer if it was already locked until later, with the
+    /// higher penalty of the tier and of the running lock.
+    /// The attached deposit covers the storage of a new stake.
+    #[payable]
+    pub fn stake(&mut self, amount: U128, lock_tier: Option<u32>) {
+        let initial_storage_usage = env::storage_usage();
+        self.internal_stake(&env::predecessor_account_id(), amount.into(), lock_tier);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Returns `amount` of the predecessor's staked tokens, minus the lock's penalty if the
+    /// stake is still locked. Pending rewards stay claimable. Returns the amount paid out.
+    #[payable]
+    pub fn unstake(&mut self, amount: U128) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut stake = self.internal_settled_stake(&account_id);
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        assert!(amount.0 <= stake.amount, "ERR_NOT_ENOUGH_STAKED");
+        stake.amount -= amount.0;
+        self.staking.total_staked -= amount.0;
+        if stake
+            .lock
+            .as_ref()
+            .is_some_and(|lock| lock.unlock_at.0 <= env::block_timestamp())
+        {
+            stake.lock = None;
+        }
+        self.stakes.insert(&account_id, &stake);
+        // Exits are free during emergency mode.
+        let penalty = match stake.lock.filter(|_| !self.config.emergency_mode) {
+            Some(lock) => {
+                let penalty = mul_div(amount.0, lock.penalty_bps as u128, BPS_DENOMINATOR, false);
+                if penalty > 0 {
+                    match lock.penalty_destination {
+                        PenaltyDestination::RewardReserve => self.staking.reward_reserve += penalty,
+                        PenaltyDestination::Burn => self.internal_burn(
+                            &env::current_account_id(),
+                            penalty,
+                            Some("early_unstake".to_string()),
+                            None,
+                        ),
+                    }
+                    events::emit_event(
+                        "early_unstake_penalty",
+                        json!({
+                            "account_id": account_id,
+                            "penalty": U128(penalty),
+                            "destination": lock.penalty_destination,
+                        }),
+                    );
+                }
+                penalty
+            }
+            None => 0,
+        };
+        let payout = amount.0 - penalty;
+        if payout > 0 {
+            self.internal_escrow_release(&account_id, payout);
+        }
+        payout.into()
+    }
+
+    /// Mints the predecessor's rewards to their reward destination, or into their stake if
+    /// auto-compounding is on and emergency mode is off. Returns the claimed amount.
+    #[payable]
+    pub fn claim_staking_rewards(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let mut stake = self.internal_settled_stake(&account_id);
+        let amount = stake.pending_rewards;
+        assert!(amount > 0, "ERR_NOTHING_TO_CLAIM");
+        stake.pending_rewards = 0;
+        if stake.auto_compound && !self.config.emergency_mode {
+            self.internal_pay_staking_rewards(&env::current_account_id(), amount);
+            stake.amount += amount;
+            self.staking.total_staked += amount;
+            self.stakes.insert(&account_id, &stake);
+        } else {
+            self.stakes.insert(&account_id, &stake);
+            let receiver_id = stake.reward_destination.unwrap_or(account_id);
+            self.assert_not_blacklisted(&receiver_id);
+            self.internal_pay_staking_rewards(&receiver_id, amount);
+        }
+        amount.into()
+    }
+
+    /// Turns restaking of claimed rewards on or off for the predecessor. The attached deposit
+    /// covers the storage of the setting.
+    #[payable]
+    pub fn set_auto_compound(&mut self, enabled: bool) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let account_id = env::predecessor_account_id();
+        let mut stake = self.internal_settled_stake(&account_id);
+        stake.auto_compound = enabled;
+        self.stakes.insert(&account_id, &stake);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Pays the predecessor's future reward claims out to `account_id`, or back to the staking
+    /// account if `None`. The attached deposit covers the storage of the setting.
+    #[payable]
+    pub fn set_reward_destination(&mut self, account_id: Option<ValidAccountId>) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let staker_id = env::predecessor_account_id();
+        let reward_destination: Option<AccountId> = account_id.map(|account_id| account_id.into());
+        if let Some(reward_destination) = reward_destination.as_ref() {
+            assert!(
+                self.token.accounts.contains_key(reward_destination),
+                "ERR_NOT_REGISTERED"
+            );
+        }
+        let mut stake = self.internal_settled_stake(&staker_id);
+        stake.reward_destination = reward_destination;
+        self.stakes.insert(&staker_id, &stake);
+        events::emit_event(
+            "reward_destination_changed",
+            json!({
+                "account_id": staker_id,
+                "reward_destination": stake.reward_destination,
+            }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn get_reward_destination(&self, account_id: ValidAccountId) -> Option<AccountId> {
+        self.stakes
+            .get(account_id.as_ref())
+            .and_then(|stake| stake.reward_destination)
+    }
+
+    pub fn get_stake(&self, account_id: ValidAccountId) -> StakeView {
+        let stake = self.stakes.get(account_id.as_ref()).unwrap_or_default();
+        let reward_per_token = self.staking.reward_per_token_at(env::block_timestamp());
+        StakeView {
+            amount: stake.amount.into(),
+            pending_rewards: stake.rewards_at(reward_per_token).into(),
+            reward_destination: stake.reward_destination,
+            auto_compound: stake.auto_compound,
+            lock: stake.lock,
+        }
+    }
+
+    pub fn get_total_staked(&self) -> U128 {
+        self.staking.total_staked.into()
+    }
+
+    pub fn get_staking_reward_reserve(&self) -> U128 {
+        self.staking.reward_reserve.into()
+    }
+
+    /// Rate in effect right now.
+    pub fn get_reward_rate(&self) -> U128 {
+        self.staking.reward_rate_at(env::block_timestamp()).into()
+    }
+
+    /// Past, current and scheduled rates, oldest first.
+    pub fn get_reward_rate_segments(&self) -> Vec<RewardRateSegment> {
+        self.staking.rate_segments.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_staking_rewards() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        mint_to(&mut contract, accounts(2), 1_000);
+        contract.set_reward_rate(10.into(), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(200.into(), None);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(10 * NANOSECONDS_PER_SECOND)
+            .build());
+        contract.stake(50.into(), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .block_timestamp(30 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.get_stake(accounts(1)).pending_rewards.0, 100 + 160);
+        assert_eq!(contract.get_stake(accounts(2)).pending_rewards.0, 40);
+        assert_eq!(contract.claim_staking_rewards().0, 260);
+        contract.unstake(200.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_260);
+        assert_eq!(contract.get_total_staked().0, 50);
+    }
+
+    #[test]
+    fn test_reward_destination() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        mint_to(&mut contract, accounts(3), 0);
+        contract.set_reward_rate(10.into(), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(200.into(), None);
+        contract.set_reward_destination(Some(accounts(3)));
+        assert_eq!(
+            contract.get_reward_destination(accounts(1)),
+            Some(accounts(3).to_string())
+        );
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(10 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.claim_staking_rewards().0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 800);
+    }
+
+    #[test]
+    fn test_auto_compound() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.set_reward_rate(10.into(), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(200.into(), None);
+        contract.set_auto_compound(true);
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(10 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.claim_staking_rewards().0, 100);
+        let stake = contract.get_stake(accounts(1));
+        assert!(stake.auto_compound);
+        assert_eq!(stake.amount.0, 300);
+        assert_eq!(stake.pending_rewards.0, 0);
+        assert_eq!(contract.get_total_staked().0, 300);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 800);
+
+        testing_env!(context.block_timestamp(40 * NANOSECONDS_PER_SECOND).build());
+        assert_eq!(contract.get_stake(accounts(1)).pending_rewards.0, 300);
+    }
+
+    #[test]
+    fn test_reward_rate_segments() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        contract.set_reward_rate(10.into(), None);
+        contract.set_reward_rate(20.into(), Some((20 * NANOSECONDS_PER_SECOND).into()));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(100.into(), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .block_timestamp(30 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.get_reward_rate().0, 20);
+        contract.set_reward_rate(5.into(), None);
+        assert_eq!(contract.get_reward_rate_segments().len(), 3);
+
+        testing_env!(context.block_timestamp(40 * NANOSECONDS_PER_SECOND).build());
+        assert_eq!(
+            contract.get_stake(accounts(1)).pending_rewards.0,
+            200 + 200 + 50
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RETROACTIVE_RATE")]
+    fn test_retroactive_reward_rate() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(10 * NANOSECONDS_PER_SECOND).build());
+        let mut contract = new_contract(1_000_000);
+        contract.set_reward_rate(10.into(), Some(NANOSECONDS_PER_SECOND.into()));
+    }
+
+    fn set_lock_tiers(contract: &mut Contract) {
+        contract.set_staking_lock_tiers(vec![
+            StakingLockTier {
+                duration: (100 * NANOSECONDS_PER_SECOND).into(),
+                penalty_bps: 1_000,
+                penalty_destination: PenaltyDestination::RewardReserve,
+            },
+            StakingLockTier {
+                duration: (100 * NANOSECONDS_PER_SECOND).into(),
+                penalty_bps: 5_000,
+                penalty_destination: PenaltyDestination::Burn,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_early_unstake_penalty() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        mint_to(&mut contract, accounts(2), 1_000);
+        set_lock_tiers(&mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(500.into(), Some(0));
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.stake(500.into(), Some(1));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .block_timestamp(50 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.unstake(200.into()).0, 180);
+        assert_eq!(contract.get_staking_reward_reserve().0, 20);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert_eq!(contract.unstake(200.into()).0, 100);
+        assert_eq!(contract.ft_total_supply().0, 1_900);
+
+        testing_env!(context
+            .block_timestamp(100 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.unstake(300.into()).0, 300);
+        assert!(contract.get_stake(accounts(2)).lock.is_none());
+    }
+
+    #[test]
+    fn test_restake_keeps_stricter_penalty() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        set_lock_tiers(&mut contract);
+        let mut tiers = contract.get_staking_lock_tiers();
+        tiers.push(StakingLockTier {
+            duration: NANOSECONDS_PER_SECOND.into(),
+            penalty_bps: 0,
+            penalty_destination: PenaltyDestination::RewardReserve,
+        });
+        contract.set_staking_lock_tiers(tiers);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(500.into(), Some(1));
+        contract.stake(1.into(), Some(2));
+        let lock = contract.get_stake(accounts(1)).lock.unwrap();
+        assert_eq!(lock.unlock_at.0, 100 * NANOSECONDS_PER_SECOND);
+        assert_eq!(lock.penalty_bps, 5_000);
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(50 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.unstake(200.into()).0, 100);
+    }
+
+    #[test]
+    fn test_rewards_paid_from_reserve() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        set_lock_tiers(&mut contract);
+        contract.set_reward_rate(1.into(), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.stake(500.into(), Some(0));
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(50 * NANOSECONDS_PER_SECOND)
+            .build());
+        assert_eq!(contract.unstake(400.into()).0, 360);
+        assert_eq!(contract.claim_staking_rewards().0, 50);
+        assert_eq!(contract.get_staking_reward_reserve().0, 0);
+        assert_eq!(contract.ft_total_supply().0, 1_010);
+    }
+}
@@ -0,0 +1,78 @@
+//! Headline counters for dashboards: completed holder transfers and their volume, and the number
+//! of mints and burns since deployment.
+//!
+//! A transfer counts once the receiver got the tokens, so transfers blocked by the compliance
+//! registry or rejected by their receiver don't. Contract payouts and escrow movements aren't
+//! holder transfers and aren't counted. The counters live in the contract state, so keeping them
+//! costs no storage reads.
+
+use crate::*;
+use near_sdk::serde::Serialize;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct Stats {
+    pub transfer_count: u64,
+    pub transfer_volume: Balance,
+    pub mint_count: u64,
+    pub burn_count: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StatsView {
+    pub transfer_count: U64,
+    pub transfer_volume: U128,
+    pub mint_count: U64,
+    pub burn_count: U64,
+}
+
+impl Contract {
+    pub(crate) fn internal_record_transfer_stats(
+        &mut self,
+        sender_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.stats.transfer_count += 1;
+        self.stats.transfer_volume = self.stats.transfer_volume.saturating_add(amount);
+        self.internal_record_daily_volume(sender_id, amount);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_stats(&self) -> StatsView {
+        StatsView {
+            transfer_count: self.stats.transfer_count.into(),
+            transfer_volume: self.stats.transfer_volume.into(),
+            mint_count: self.stats.mint_count.into(),
+            burn_count: self.stats.burn_count.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_stats() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        contract.ft_transfer(accounts(2), 50.into(), None);
+        contract.self_burn(10.into(), None, None);
+        let stats = contract.get_stats();
+        assert_eq!(stats.transfer_count.0, 2);
+        assert_eq!(stats.transfer_volume.0, 150);
+        assert_eq!(stats.mint_count.0, 2);
+        assert_eq!(stats.burn_count.0, 1);
+    }
+}
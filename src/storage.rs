@@ -0,0 +1,93 @@
+//! NEP-145 storage management. Wraps the standard implementation so that every registered
+//! account is also tracked in `account_index`, which makes the holder set enumerable.
+//!
+//! `token.accounts` is a `LookupMap` and can't be iterated, so the index is a parallel
+//! `TreeMap` over the account IDs. Its entry roughly doubles the storage of a registration,
+//! which `storage_deposit` charges for. Deployments that don't need enumeration can turn it off
+//! with `InitOptions::enumerable_accounts`; features walking the index then fail with
+//! `ERR_NOT_ENUMERABLE`.
+
+use crate::*;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+
+impl Contract {
+    /// Measures the storage of an `account_index` entry and adds it to the per-account storage
+    /// usage, so that `storage_deposit` charges for it.
+    pub(crate) fn measure_account_index_storage_usage(&mut self) {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id = "a".repeat(64);
+        self.account_index.insert(&tmp_account_id, &());
+        self.token.account_storage_usage += env::storage_usage() - initial_storage_usage;
+        self.account_index.remove(&tmp_account_id);
+    }
+
+    pub(crate) fn assert_enumerable(&self) {
+        assert!(self.enumerable_accounts, "ERR_NOT_ENUMERABLE");
+    }
+
+    fn internal_index_account(&mut self, account_id: &AccountId) {
+        if self.enumerable_accounts {
+            self.account_index.insert(account_id, &());
+        }
+    }
+
+    /// Registers the account unless it's registered already. The storage is paid by the
+    /// contract, or by the caller where the calling method charges for it.
+    pub(crate) fn internal_ensure_registered(&mut self, account_id: &AccountId) {
+        if !self.token.accounts.contains_key(account_id) {
+            self.token.internal_register_account(account_id);
+            self.internal_index_account(account_id);
+        }
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<ValidAccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let registered_account_id: AccountId = account_id
+            .as_ref()
+            .map(|a| a.clone().into())
+            .unwrap_or_else(env::predecessor_account_id);
+        let storage_balance = self.token.storage_deposit(account_id, registration_only);
+        self.internal_index_account(&registered_account_id);
+        storage_balance
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        self.token.storage_withdraw(amount)
+    }
+
+    /// Accounts the transfer fee is routed to can't unregister.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert!(
+            !self.internal_is_fee_account(&env::predecessor_account_id()),
+            "ERR_FEE_ACCOUNT"
+        );
+        if let Some((account_id, balance)) = self.token.internal_storage_unregister(force) {
+            self.account_index.remove(&account_id);
+            self.internal_checkpoint_account(&account_id, balance, 0);
+            self.internal_record_twab(&account_id, balance, 0);
+            self.on_account_closed(account_id, balance);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        self.token.storage_balance_bounds()
+    }
+
+    fn storage_balance_of(&self, account_id: ValidAccountId) -> Option<StorageBalance> {
+        self.token.storage_balance_of(account_id)
+    }
+}
@@ -0,0 +1,215 @@
+//! Storage cost estimates, so wallets can attach the exact deposit for a storage-paying call
+//! instead of guessing.
+//!
+//! Costs are computed from the Borsh sizes of the records a call writes, plus the overhead NEAR
+//! charges per record, against the current state: an allowance replacing an existing one only
+//! costs the bytes it adds. Calls still refund whatever is attached on top.
+
+use crate::allowances::Allowance;
+use crate::*;
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::IntoStorageKey;
+
+/// Bytes NEAR charges per storage record on top of its key and value.
+const STORAGE_RECORD_OVERHEAD: u64 = 40;
+/// Bytes a `TreeMap` node grows by when a child is linked to it.
+const TREE_LINK_SIZE: u64 = 8;
+
+fn record_size(key_size: usize, value_size: usize) -> u64 {
+    (key_size + value_size) as u64 + STORAGE_RECORD_OVERHEAD
+}
+
+fn key_size<K: BorshSerialize>(prefix: StorageKey, key: &K) -> usize {
+    prefix.into_storage_key().len() + key.try_to_vec().unwrap().len()
+}
+
+/// Storage of a new `TreeMap` entry with a `()` value: the value record plus the tree node,
+/// which holds the key and empty child links, and the link from its parent if it has one.
+fn tree_entry_size<K: BorshSerialize>(prefix: StorageKey, key: &K, has_parent: bool) -> u64 {
+    let prefix = prefix.into_storage_key();
+    let key = key.try_to_vec().unwrap();
+    let value_record = record_size(prefix.len() + 1 + key.len(), 0);
+    // id, key, two empty links, height
+    let node_record = record_size(prefix.len() + 1 + 8, 8 + key.len() + 1 + 1 + 8);
+    value_record + node_record + if has_parent { TREE_LINK_SIZE } else { 0 }
+}
+
+fn storage_cost(bytes: u64) -> U128 {
+    (Balance::from(bytes) * env::storage_byte_cost()).into()
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Deposit `storage_deposit` needs to register `account_id`, 0 if it's registered already.
+    pub fn estimate_registration_cost(&self, account_id: ValidAccountId) -> U128 {
+        if self.token.accounts.contains_key(account_id.as_ref()) {
+            0.into()
+        } else {
+            self.storage_balance_bounds().min
+        }
+    }
+
+    /// Deposit `ft_approve` needs to set an allowance of `spender_id` on `owner_id`'s tokens
+    /// expiring at `expires_at`, taking the allowance it replaces into account.
+    pub fn estimate_allowance_cost(
+        &self,
+        owner_id: ValidAccountId,
+        spender_id: ValidAccountId,
+        expires_at: Option<U64>,
+    ) -> U128 {
+        let key: (AccountId, AccountId) = (owner_id.into(), spender_id.into());
+        let expiry_key = |expires_at: u64| (expires_at, key.0.clone(), key.1.clone());
+        let size = |allowance: &Allowance, has_parent: bool| {
+            let key_size = key_size(StorageKey::Allowances, &key);
+            record_size(key_size, allowance.try_to_vec().unwrap().len())
+                + allowance.expires_at.map_or(0, |expires_at| {
+                    tree_entry_size(
+                        StorageKey::AllowanceExpiries,
+                        &expiry_key(expires_at),
+                        has_parent,
+                    )
+                })
+        };
+        let current = self.allowances.get(&key);
+        let other_expiries = self.allowance_expiries.len()
+            - current
+                .as_ref()
+                .map_or(0, |allowance| allowance.expires_at.is_some() as u64);
+        let new_size = size(
+            &Allowance {
+                amount: 0,
+                expires_at: expires_at.map(|expires_at| expires_at.into()),
+            },
+            other_expiries > 0,
+        );
+        let current_size = current.map_or(0, |allowance| size(&allowance, other_expiries > 0));
+        storage_cost(new_size.saturating_sub(current_size))
+    }
+
+    /// Deposit `create_vesting_grant` or `ft_transfer_vested` needs for a grant from
+    /// `grantor_id` to `beneficiary_id`, funded by escrow unless `funding` says otherwise. The
+    /// first escrow also registers the contract's own account, which is charged like any
+    /// registration.
+    #[cfg(feature = "vesting")]
+    pub fn estimate_vesting_grant_cost(
+        &self,
+        grantor_id: ValidAccountId,
+        beneficiary_id: ValidAccountId,
+        funding: Option<crate::vesting::VestingFunding>,
+    ) -> U128 {
+        use crate::vesting::{RevokeDestination, VestingFunding, VestingGrant};
+
+        let funding = funding.unwrap_or(VestingFunding::Escrow);
+        let contract_id = env::current_account_id();
+        let escrow_registration = if funding == VestingFunding::Escrow
+            && *grantor_id.as_ref() != contract_id
+            && !self.token.accounts.contains_key(&contract_id)
+        {
+            self.storage_balance_bounds().min.0
+        } else {
+            0
+        };
+        let grant = VestingGrant {
+            grantor_id: grantor_id.into(),
+            beneficiary_id: beneficiary_id.into(),
+            total: 0,
+            claimed: 0,
+            start_at: 0,
+            cliff_at: 0,
+            end_at: 0,
+            revocable: false,
+            revoke_destination: RevokeDestination::Owner,
+            revoked: false,
+            funding,
+        };
+        let grant_size = record_size(
+            key_size(StorageKey::VestingGrants, &self.next_vesting_grant_id),
+            grant.try_to_vec().unwrap().len(),
+        );
+        let grant_id_size = self.next_vesting_grant_id.try_to_vec().unwrap().len();
+        let index_size = if self
+            .vesting_by_beneficiary
+            .contains_key(&grant.beneficiary_id)
+        {
+            grant_id_size as u64
+        } else {
+            let grant_ids = vec![self.next_vesting_grant_id];
+            record_size(
+                key_size(StorageKey::BeneficiaryGrants, &grant.beneficiary_id),
+                grant_ids.try_to_vec().unwrap().len(),
+            )
+        };
+        (storage_cost(grant_size + index_size).0 + escrow_registration).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_estimates_match_storage() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(1), 500);
+
+        let cost = contract.estimate_registration_cost(accounts(2)).0;
+        let initial_storage_usage = env::storage_usage();
+        contract.storage_deposit(Some(accounts(2)), None);
+        let used = Balance::from(env::storage_usage() - initial_storage_usage);
+        assert!(used * env::storage_byte_cost() <= cost);
+        assert_eq!(contract.estimate_registration_cost(accounts(2)).0, 0);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        for (spender, expires_at) in [
+            (accounts(2), None),
+            (accounts(3), Some(1_000)),
+            (accounts(2), Some(2_000)),
+            (accounts(4), Some(500)),
+            (accounts(3), Some(3_000)),
+        ] {
+            let expires_at = expires_at.map(U64);
+            let cost = contract.estimate_allowance_cost(accounts(1), spender.clone(), expires_at);
+            let initial_storage_usage = env::storage_usage();
+            contract.ft_approve(spender, 10.into(), expires_at);
+            let used = Balance::from(env::storage_usage() - initial_storage_usage);
+            assert_eq!(cost.0, used * env::storage_byte_cost());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "vesting")]
+    fn test_vesting_grant_estimate_matches_storage() {
+        use crate::vesting::VestingSchedule;
+
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(0), 500);
+        mint_to(&mut contract, accounts(1), 0);
+        mint_to(&mut contract, contract_account(), 0);
+        for _ in 0..2 {
+            let cost = contract.estimate_vesting_grant_cost(accounts(0), accounts(1), None);
+            let initial_storage_usage = env::storage_usage();
+            contract.create_vesting_grant(
+                accounts(1),
+                10.into(),
+                VestingSchedule {
+                    start_at: 0.into(),
+                    cliff_at: 0.into(),
+                    end_at: 100.into(),
+                },
+                false,
+                None,
+                None,
+            );
+            let used = Balance::from(env::storage_usage() - initial_storage_usage);
+            assert_eq!(cost.0, used * env::storage_byte_cost());
+        }
+    }
+}
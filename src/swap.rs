@@ -0,0 +1,187 @@
+//! Migration from a legacy token: holders send the legacy token to this contract with
+//! `ft_transfer_call` and get this token minted at a fixed ratio until the migration deadline.
+//!
+//! Received legacy tokens stay locked on this contract's account in the legacy token, since
+//! NEP-141 has no standard burn; nothing can transfer them out. Swaps count as mints, so they
+//! respect mint pauses, `max_supply`, the blacklist and the KYC threshold. Holders must be
+//! registered here before swapping; a failed swap is refunded by the legacy token.
+
+use crate::pause::PauseSurface;
+use crate::utils::mul_div;
+use crate::*;
+use near_sdk::serde_json::json;
+use near_sdk::PromiseOrValue;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Swap {
+    pub legacy_token_id: AccountId,
+    /// Units minted per `rate_denominator` legacy units.
+    pub rate_numerator: Balance,
+    pub rate_denominator: Balance,
+    /// Block timestamp from which swaps are rejected.
+    pub deadline: u64,
+    /// Legacy units received and locked.
+    pub legacy_received: Balance,
+    pub minted: Balance,
+    pub swap_count: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapView {
+    pub legacy_token_id: AccountId,
+    pub rate_numerator: U128,
+    pub rate_denominator: U128,
+    pub deadline: U64,
+    pub legacy_received: U128,
+    pub minted: U128,
+    pub swap_count: U64,
+    pub active: bool,
+}
+
+impl Contract {
+    pub(crate) fn internal_is_legacy_token(&self, token_id: &AccountId) -> bool {
+        self.swap
+            .as_ref()
+            .is_some_and(|swap| swap.legacy_token_id == *token_id)
+    }
+
+    /// Mints this token for `amount` of the legacy token received from `sender_id`.
+    pub(crate) fn internal_swap_legacy(
+        &mut self,
+        sender_id: AccountId,
+        amount: Balance,
+    ) -> PromiseOrValue<U128> {
+        self.assert_not_paused(PauseSurface::Mint);
+        let mut swap = self.swap.take().expect("ERR_NO_SWAP");
+        assert!(env::block_timestamp() < swap.deadline, "ERR_SWAP_ENDED");
+        assert!(
+            self.token.accounts.contains_key(&sender_id),
+            "ERR_NOT_REGISTERED"
+        );
+        let minted = mul_div(amount, swap.rate_numerator, swap.rate_denominator, false);
+        assert!(minted > 0, "ERR_AMOUNT_TOO_SMALL");
+        self.assert_not_blacklisted(&sender_id);
+        self.assert_kyc_for_receipt(&sender_id, minted);
+        self.internal_mint_registered(&sender_id, minted, Some("legacy swap"));
+        swap.legacy_received += amount;
+        swap.minted += minted;
+        swap.swap_count += 1;
+        self.swap = Some(swap);
+        events::emit_event(
+            "legacy_swapped",
+            json!({
+                "account_id": sender_id,
+                "legacy_amount": U128(amount),
+                "amount": U128(minted),
+            }),
+        );
+        PromiseOrValue::Value(0.into())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Opens the migration from `legacy_token_id`, minting `rate_numerator` units per
+    /// `rate_denominator` legacy units until `deadline`.
+    #[payable]
+    pub fn start_swap(
+        &mut self,
+        legacy_token_id: ValidAccountId,
+        rate_numerator: U128,
+        rate_denominator: U128,
+        deadline: U64,
+    ) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(self.swap.is_none(), "ERR_SWAP_EXISTS");
+        assert!(
+            rate_numerator.0 > 0 && rate_denominator.0 > 0,
+            "ERR_INVALID_RATE"
+        );
+        assert!(deadline.0 > env::block_timestamp(), "ERR_DEADLINE_PASSED");
+        self.swap = Some(Swap {
+            legacy_token_id: legacy_token_id.into(),
+            rate_numerator: rate_numerator.into(),
+            rate_denominator: rate_denominator.into(),
+            deadline: deadline.into(),
+            legacy_received: 0,
+            minted: 0,
+            swap_count: 0,
+        });
+        events::emit_config_changed("swap", self.get_swap());
+    }
+
+    /// Moves the deadline of the migration. It can only be pushed back.
+    #[payable]
+    pub fn extend_swap_deadline(&mut self, deadline: U64) {
+        assert_one_yocto();
+        self.assert_owner();
+        let swap = self.swap.as_mut().expect("ERR_NO_SWAP");
+        assert!(deadline.0 > swap.deadline, "ERR_DEADLINE_DECREASE");
+        swap.deadline = deadline.into();
+        events::emit_config_changed("swap_deadline", deadline);
+    }
+
+    /// The migration with its totals so far, `None` if there is none.
+    pub fn get_swap(&self) -> Option<SwapView> {
+        self.swap.as_ref().map(|swap| SwapView {
+            legacy_token_id: swap.legacy_token_id.clone(),
+            rate_numerator: swap.rate_numerator.into(),
+            rate_denominator: swap.rate_denominator.into(),
+            deadline: swap.deadline.into(),
+            legacy_received: swap.legacy_received.into(),
+            minted: swap.minted.into(),
+            swap_count: swap.swap_count.into(),
+            active: env::block_timestamp() < swap.deadline,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    fn value(result: near_sdk::PromiseOrValue<near_sdk::json_types::U128>) -> u128 {
+        match result {
+            near_sdk::PromiseOrValue::Value(value) => value.0,
+            near_sdk::PromiseOrValue::Promise(_) => panic!("expected a value"),
+        }
+    }
+
+    #[test]
+    fn test_swap() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 0)]);
+        contract.start_swap(accounts(3), 3.into(), 2.into(), 100.into());
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let unused = value(contract.ft_on_transfer(accounts(1), 200.into(), String::new()));
+        assert_eq!(unused, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 300);
+        let swap = contract.get_swap().unwrap();
+        assert_eq!(swap.legacy_received.0, 200);
+        assert_eq!(swap.minted.0, 300);
+        assert_eq!(swap.swap_count.0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SWAP_ENDED")]
+    fn test_swap_after_deadline() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(1), 0)]);
+        contract.start_swap(accounts(3), 1.into(), 1.into(), 100.into());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(100)
+            .build());
+        contract.ft_on_transfer(accounts(1), 200.into(), String::new());
+    }
+}
@@ -0,0 +1,216 @@
+//! Compliance tags: labels such as `exchange`, `sanctioned-review` or `team` that the owner or
+//! the compliance officer attaches to accounts. Tags belong to the account and can't be moved
+//! or removed by it.
+//!
+//! Tag rules make tags conditions of holder transfers: each rule applies to transfers sent or
+//! received by accounts with its tag, and either blocks them or caps their amount. Rules are
+//! checked with the other transfer restrictions, and tags are only looked up while rules exist.
+
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+
+const MAX_TAG_LEN: usize = 32;
+const MAX_TAGS_PER_ACCOUNT: usize = 10;
+const MAX_TAG_RULES: usize = 20;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum TagSide {
+    Sender,
+    Receiver,
+    Either,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TagRule {
+    pub tag: String,
+    pub side: TagSide,
+    /// Largest transfer allowed, none if `None`.
+    pub max_amount: Option<U128>,
+}
+
+fn assert_valid_tag(tag: &str) {
+    assert!(
+        !tag.is_empty()
+            && tag.len() <= MAX_TAG_LEN
+            && tag
+                .bytes()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-' || c == b'_'),
+        "ERR_INVALID_TAG"
+    );
+}
+
+impl Contract {
+    pub(crate) fn assert_compliance_officer(&self) {
+        let predecessor_id = env::predecessor_account_id();
+        assert!(
+            predecessor_id == self.owner_id
+                || self.compliance_officer.as_ref() == Some(&predecessor_id),
+            "ERR_NOT_ALLOWED"
+        );
+    }
+
+    /// Checks a holder transfer against the tag rules.
+    pub(crate) fn check_tag_rules(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Result<(), &'static str> {
+        if self.tag_rules.is_empty() {
+            return Ok(());
+        }
+        let sender_tags = self.account_tags.get(sender_id).unwrap_or_default();
+        let receiver_tags = self.account_tags.get(receiver_id).unwrap_or_default();
+        for rule in &self.tag_rules {
+            let applies = match rule.side {
+                TagSide::Sender => sender_tags.contains(&rule.tag),
+                TagSide::Receiver => receiver_tags.contains(&rule.tag),
+                TagSide::Either => {
+                    sender_tags.contains(&rule.tag) || receiver_tags.contains(&rule.tag)
+                }
+            };
+            if applies && rule.max_amount.is_none_or(|max| amount > max.0) {
+                return Err("ERR_TAG_RULE");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account that may tag accounts in addition to the owner.
+    #[payable]
+    pub fn set_compliance_officer(&mut self, officer_id: Option<ValidAccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.compliance_officer = officer_id.map(|a| a.into());
+        events::emit_role_changed("compliance_officer", self.compliance_officer.as_ref());
+    }
+
+    pub fn get_compliance_officer(&self) -> Option<AccountId> {
+        self.compliance_officer.clone()
+    }
+
+    /// Tags `account_id`. The attached deposit covers the storage; the rest is refunded.
+    #[payable]
+    pub fn add_account_tag(&mut self, account_id: ValidAccountId, tag: String) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_compliance_officer();
+        assert_valid_tag(&tag);
+        let mut tags = self
+            .account_tags
+            .get(account_id.as_ref())
+            .unwrap_or_default();
+        assert!(!tags.contains(&tag), "ERR_TAG_EXISTS");
+        assert!(tags.len() < MAX_TAGS_PER_ACCOUNT, "ERR_TOO_MANY_TAGS");
+        tags.push(tag.clone());
+        self.account_tags.insert(account_id.as_ref(), &tags);
+        events::emit_event(
+            "account_tagged",
+            json!({ "account_id": account_id, "tag": tag }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    #[payable]
+    pub fn remove_account_tag(&mut self, account_id: ValidAccountId, tag: String) {
+        assert_one_yocto();
+        self.assert_compliance_officer();
+        let mut tags = self
+            .account_tags
+            .get(account_id.as_ref())
+            .unwrap_or_default();
+        let index = tags.iter().position(|t| *t == tag).expect("ERR_NO_TAG");
+        tags.remove(index);
+        if tags.is_empty() {
+            self.account_tags.remove(account_id.as_ref());
+        } else {
+            self.account_tags.insert(account_id.as_ref(), &tags);
+        }
+        events::emit_event(
+            "account_untagged",
+            json!({ "account_id": account_id, "tag": tag }),
+        );
+    }
+
+    pub fn get_account_tags(&self, account_id: ValidAccountId) -> Vec<String> {
+        self.account_tags
+            .get(account_id.as_ref())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the tag rules applied to holder transfers.
+    #[payable]
+    pub fn set_tag_rules(&mut self, rules: Vec<TagRule>) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(rules.len() <= MAX_TAG_RULES, "ERR_TOO_MANY_RULES");
+        for rule in &rules {
+            assert_valid_tag(&rule.tag);
+        }
+        self.tag_rules = rules;
+        events::emit_config_changed("tag_rules", &self.tag_rules);
+    }
+
+    pub fn get_tag_rules(&self) -> Vec<TagRule> {
+        self.tag_rules.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract =
+            new_contract_with_balances(1_000, &[(accounts(1), 500), (accounts(2), 0)]);
+        contract.set_compliance_officer(Some(accounts(3)));
+        contract.set_tag_rules(vec![TagRule {
+            tag: "exchange".to_string(),
+            side: TagSide::Receiver,
+            max_amount: Some(100.into()),
+        }]);
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(10u128.pow(24))
+            .build());
+        contract.add_account_tag(accounts(2), "exchange".to_string());
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_tag_rule_caps_transfers() {
+        let (_, mut contract) = setup();
+        assert_eq!(contract.get_account_tags(accounts(2)), vec!["exchange"]);
+        contract.ft_transfer(accounts(2), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TAG_RULE")]
+    fn test_tag_rule_blocks_larger_transfers() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(2), 101.into(), None);
+    }
+}
@@ -0,0 +1,79 @@
+//! Shared setup for unit tests. Also available to other crates with the `test-utils` feature, so
+//! contracts composing with this token can test against it without copying the setup:
+//!
+//! ```ignore
+//! testing_env!(get_context(accounts(0)).build());
+//! let mut token = new_contract_with_balances(1_000, &[(accounts(1), 100)]);
+//! ```
+
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::json_types::{ValidAccountId, U64};
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::{env, Balance};
+use std::convert::TryFrom;
+
+pub use crate::{Contract, InitOptions};
+
+/// Account the contract is deployed on in tests.
+pub fn contract_account() -> ValidAccountId {
+    accounts(5)
+}
+
+/// Context for a call from `predecessor_id` with one yoctoNEAR attached.
+pub fn get_context(predecessor_id: ValidAccountId) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id(contract_account())
+        .predecessor_account_id(predecessor_id)
+        .attached_deposit(1);
+    builder
+}
+
+/// Contract owned by `accounts(0)`. Expects `testing_env!` to be set up already.
+pub fn new_contract(max_supply: Balance) -> Contract {
+    new_contract_with_options(max_supply, InitOptions::default())
+}
+
+/// Like `new_contract`, with non-default deployment options.
+pub fn new_contract_with_options(max_supply: Balance, options: InitOptions) -> Contract {
+    Contract::new_with_options(
+        accounts(0).to_string(),
+        FungibleTokenMetadata {
+            spec: "ft-1.0.0".to_string(),
+            name: "ZEUS".to_string(),
+            symbol: "zeus".to_string(),
+            decimals: 8,
+            icon: None,
+            reference: None,
+            reference_hash: None,
+        },
+        max_supply,
+        options,
+    )
+}
+
+/// Registers `account_id` without a storage deposit and mints `amount` to it.
+pub fn mint_to(contract: &mut Contract, account_id: ValidAccountId, amount: Balance) {
+    contract.internal_ensure_registered(account_id.as_ref());
+    let nonce = next_mint_nonce(contract);
+    contract.mint(account_id, amount.into(), None, nonce);
+}
+
+/// Nonce accepted by the next `mint` of the predecessor.
+pub fn next_mint_nonce(contract: &Contract) -> U64 {
+    let minter_id = ValidAccountId::try_from(env::predecessor_account_id()).unwrap();
+    (contract.get_mint_nonce(minter_id).0 + 1).into()
+}
+
+/// Contract owned by `accounts(0)` with `balances` minted to registered accounts. Expects
+/// `testing_env!` to be set up already with `accounts(0)` as predecessor.
+pub fn new_contract_with_balances(
+    max_supply: Balance,
+    balances: &[(ValidAccountId, Balance)],
+) -> Contract {
+    let mut contract = new_contract(max_supply);
+    for (account_id, amount) in balances {
+        mint_to(&mut contract, account_id.clone(), *amount);
+    }
+    contract
+}
@@ -0,0 +1,188 @@
+//! Time-weighted average balances, so reward programs can pay on average holdings over a period
+//! instead of a snapshot that can be gamed by moving tokens around it.
+//!
+//! Every balance change of an account appends an observation of its shares and the shares ×
+//! nanoseconds it accumulated since TWAB tracking was enabled, at most one per block timestamp.
+//! The first change of an account also records the shares it held when tracking was enabled.
+//! The average over a period is then the difference of two accumulators, found by binary search.
+//! Like checkpoints, tracking is opt-in because the observations are state the contract pays
+//! for. Averages are computed in shares and valued at the current share index.
+
+use crate::utils::{full_mul, wide_add, wide_div, wide_sub};
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct TwabObservation {
+    pub timestamp: u64,
+    /// Shares held from `timestamp` on.
+    pub shares: Balance,
+    /// Shares × nanoseconds accumulated up to `timestamp`, as 256-bit `(high, low)` halves.
+    pub cumulative: (u128, u128),
+}
+
+impl Contract {
+    /// Records the `shares` of the account after a change. `previous_shares` are the shares
+    /// before the change, needed to seed the history of accounts that had no observation yet.
+    pub(crate) fn internal_record_twab(
+        &mut self,
+        account_id: &AccountId,
+        previous_shares: Balance,
+        shares: Balance,
+    ) {
+        let enabled_at = match self.twab_enabled_at {
+            Some(enabled_at) => enabled_at,
+            None => return,
+        };
+        let now = env::block_timestamp();
+        let mut count = self.twab_counts.get(account_id).unwrap_or(0);
+        if count == 0 && enabled_at < now {
+            self.twab_observations.insert(
+                &(account_id.clone(), 0),
+                &TwabObservation {
+                    timestamp: enabled_at,
+                    shares: previous_shares,
+                    cumulative: (0, 0),
+                },
+            );
+            count = 1;
+        }
+        let last = match count {
+            0 => None,
+            _ => self.twab_observations.get(&(account_id.clone(), count - 1)),
+        };
+        let (position, observation) = match last {
+            Some(last) if last.timestamp == now => (count - 1, TwabObservation { shares, ..last }),
+            Some(last) => (
+                count,
+                TwabObservation {
+                    timestamp: now,
+                    shares,
+                    cumulative: wide_add(
+                        last.cumulative,
+                        full_mul(last.shares, (now - last.timestamp) as u128),
+                    ),
+                },
+            ),
+            None => (
+                0,
+                TwabObservation {
+                    timestamp: now,
+                    shares,
+                    cumulative: (0, 0),
+                },
+            ),
+        };
+        self.twab_observations
+            .insert(&(account_id.clone(), position), &observation);
+        self.twab_counts.insert(account_id, &(position + 1));
+    }
+
+    /// Shares × nanoseconds `account_id` accumulated from enabling until `timestamp`.
+    fn internal_twab_cumulative_at(&self, account_id: &AccountId, timestamp: u64) -> (u128, u128) {
+        let enabled_at = self.twab_enabled_at.unwrap();
+        let count = self.twab_counts.get(account_id).unwrap_or(0);
+        if count == 0 {
+            let shares = self.token.accounts.get(account_id).unwrap_or(0);
+            return full_mul(shares, (timestamp - enabled_at) as u128);
+        }
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = (low + high) / 2;
+            let observation = self
+                .twab_observations
+                .get(&(account_id.clone(), mid))
+                .unwrap();
+            if observation.timestamp <= timestamp {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        // `low` is positive since the first observation is at `enabled_at`.
+        let observation = self
+            .twab_observations
+            .get(&(account_id.clone(), low - 1))
+            .unwrap();
+        wide_add(
+            observation.cumulative,
+            full_mul(
+                observation.shares,
+                (timestamp - observation.timestamp) as u128,
+            ),
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Starts tracking average balances. Can't be undone, as a gap would make averages
+    /// unreliable.
+    #[payable]
+    pub fn enable_twab(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(self.twab_enabled_at.is_none(), "ERR_ALREADY_ENABLED");
+        self.twab_enabled_at = Some(env::block_timestamp());
+        events::emit_config_changed("twab_enabled", true);
+    }
+
+    pub fn get_twab_enabled_at(&self) -> Option<U64> {
+        self.twab_enabled_at.map(|timestamp| timestamp.into())
+    }
+
+    /// Average balance of `account_id` from `from_ts` to `to_ts`, block timestamps in
+    /// nanoseconds.
+    pub fn twab(&self, account_id: ValidAccountId, from_ts: U64, to_ts: U64) -> U128 {
+        let enabled_at = self.twab_enabled_at.expect("ERR_TWAB_DISABLED");
+        assert!(from_ts.0 >= enabled_at, "ERR_BEFORE_TWAB");
+        assert!(to_ts.0 <= env::block_timestamp(), "ERR_FUTURE_TIMESTAMP");
+        assert!(from_ts.0 < to_ts.0, "ERR_INVALID_RANGE");
+        let accumulated = wide_sub(
+            self.internal_twab_cumulative_at(account_id.as_ref(), to_ts.0),
+            self.internal_twab_cumulative_at(account_id.as_ref(), from_ts.0),
+        );
+        let shares = wide_div(accumulated, (to_ts.0 - from_ts.0) as u128, false);
+        self.shares_to_amount(shares).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_twab() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(100).build());
+        let mut contract =
+            new_contract_with_balances(10_000, &[(accounts(1), 1_000), (accounts(2), 0)]);
+        contract.enable_twab();
+
+        testing_env!(context
+            .block_timestamp(200)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.ft_transfer(accounts(2), 600.into(), None);
+        testing_env!(context.block_timestamp(400).build());
+
+        assert_eq!(contract.twab(accounts(1), 150.into(), 200.into()).0, 1_000);
+        assert_eq!(contract.twab(accounts(1), 100.into(), 300.into()).0, 700);
+        assert_eq!(contract.twab(accounts(1), 100.into(), 400.into()).0, 600);
+        assert_eq!(contract.twab(accounts(2), 100.into(), 400.into()).0, 400);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BEFORE_TWAB")]
+    fn test_twab_before_enabled() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(100).build());
+        let mut contract = new_contract_with_balances(10_000, &[(accounts(1), 1_000)]);
+        contract.enable_twab();
+        testing_env!(context.block_timestamp(200).build());
+        contract.twab(accounts(1), 50.into(), 150.into());
+    }
+}
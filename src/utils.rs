@@ -0,0 +1,123 @@
+//! Arithmetic and storage helpers shared across modules.
+
+use near_sdk::{env, AccountId, Balance, Promise, StorageUsage};
+
+/// Charges the predecessor for `storage_used` bytes out of the attached deposit and refunds the
+/// rest to the predecessor, which is the delegating account rather than the relayer in a NEP-366
+/// delegate action.
+pub(crate) fn refund_deposit(storage_used: StorageUsage) {
+    refund_deposit_with_fee(storage_used, 0)
+}
+
+/// Like `refund_deposit`, but also keeps `fee` on top of the storage cost.
+pub(crate) fn refund_deposit_with_fee(storage_used: StorageUsage, fee: Balance) {
+    let required_cost = env::storage_byte_cost() * Balance::from(storage_used) + fee;
+    let attached_deposit = env::attached_deposit();
+    assert!(
+        required_cost <= attached_deposit,
+        "Must attach {} yoctoNEAR to cover storage",
+        required_cost
+    );
+    let refund = attached_deposit - required_cost;
+    if refund > 1 {
+        Promise::new(env::predecessor_account_id()).transfer(refund);
+    }
+}
+
+/// Returns the cost of `storage_released` bytes to `account_id`, which paid for them.
+pub(crate) fn refund_released_storage(account_id: &AccountId, storage_released: StorageUsage) {
+    let refund = env::storage_byte_cost() * Balance::from(storage_released);
+    if refund > 0 {
+        Promise::new(account_id.clone()).transfer(refund);
+    }
+}
+
+/// Computes `a * b / c` with a 256-bit intermediate product, so that share conversions don't
+/// overflow for large balances. Rounds the result up when `round_up` is set.
+/// Panics if `c` is zero or the result doesn't fit into `u128`.
+pub(crate) fn mul_div(a: u128, b: u128, c: u128, round_up: bool) -> u128 {
+    wide_div(full_mul(a, b), c, round_up)
+}
+
+/// Divides the 256-bit `(high, low)` value by `c`. Rounds the result up when `round_up` is set.
+/// Panics if `c` is zero or the result doesn't fit into `u128`.
+pub(crate) fn wide_div((hi, lo): (u128, u128), c: u128, round_up: bool) -> u128 {
+    assert!(c > 0, "ERR_DIVISION_BY_ZERO");
+    assert!(hi < c, "ERR_MUL_DIV_OVERFLOW");
+    let (mut quotient, mut remainder) = (0u128, hi);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    if round_up && remainder > 0 {
+        quotient.checked_add(1).expect("ERR_MUL_DIV_OVERFLOW")
+    } else {
+        quotient
+    }
+}
+
+/// Returns the 256-bit product of `a` and `b` as `(high, low)` halves.
+pub(crate) fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & MASK);
+    let (b_hi, b_lo) = (b >> 64, b & MASK);
+    let lo_lo = a_lo * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_lo = a_hi * b_lo;
+    let hi_hi = a_hi * b_hi;
+    let mid = (lo_lo >> 64) + (lo_hi & MASK) + (hi_lo & MASK);
+    let lo = (lo_lo & MASK) | (mid << 64);
+    let hi = hi_hi + (lo_hi >> 64) + (hi_lo >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+/// Sum of two 256-bit `(high, low)` values. Panics on overflow.
+pub(crate) fn wide_add(a: (u128, u128), b: (u128, u128)) -> (u128, u128) {
+    let (lo, carry) = a.1.overflowing_add(b.1);
+    let hi =
+        a.0.checked_add(b.0)
+            .and_then(|hi| hi.checked_add(carry as u128))
+            .expect("ERR_WIDE_OVERFLOW");
+    (hi, lo)
+}
+
+/// Difference of two 256-bit `(high, low)` values. Panics if `b` is larger than `a`.
+pub(crate) fn wide_sub(a: (u128, u128), b: (u128, u128)) -> (u128, u128) {
+    let (lo, borrow) = a.1.overflowing_sub(b.1);
+    let hi =
+        a.0.checked_sub(b.0)
+            .and_then(|hi| hi.checked_sub(borrow as u128))
+            .expect("ERR_WIDE_UNDERFLOW");
+    (hi, lo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul_div(10, 20, 7, false), 28);
+        assert_eq!(mul_div(10, 20, 7, true), 29);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX, false), u128::MAX);
+        let big = 10u128.pow(33);
+        assert_eq!(
+            mul_div(big, 3 * 10u128.pow(18), 10u128.pow(18), false),
+            3 * big
+        );
+    }
+
+    #[test]
+    fn test_wide_add_sub() {
+        let a = full_mul(u128::MAX, 3);
+        let b = full_mul(u128::MAX, 2);
+        assert_eq!(wide_add(b, full_mul(u128::MAX, 1)), a);
+        assert_eq!(wide_sub(a, b), (0, u128::MAX));
+        assert_eq!(wide_div(wide_sub(a, b), 1, false), u128::MAX);
+    }
+}
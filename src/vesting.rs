@@ -0,0 +1,747 @@
+//! Vesting: tokens escrowed on the contract account that unlock linearly between the start and
+//! the end of a schedule, with nothing claimable before the cliff.
+//!
+//! The owner creates grants with `create_vesting_grant` and may revoke them if they were created
+//! as revocable. Any holder can deliver vesting tokens with `ft_transfer_vested`; those grants
+//! can't be revoked. Each revocable grant records where its unvested tokens go on revocation:
+//! back to the grantor, to the treasury, or burned.
+//!
+//! Grants are funded either from the grantor's balance, escrowed upfront, or by minting on claim.
+//! Mint-on-claim grants don't reserve supply, so each claim has to fit under `max_supply`.
+
+use crate::pause::PauseSurface;
+use crate::utils::{mul_div, refund_deposit};
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+
+const MAX_GRANTS_LIMIT: u64 = 100;
+
+pub type GrantId = u64;
+
+/// Timestamps of a linear vesting schedule, in nanoseconds.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingSchedule {
+    pub start_at: U64,
+    pub cliff_at: U64,
+    pub end_at: U64,
+}
+
+impl VestingSchedule {
+    fn assert_valid(&self) {
+        assert!(
+            self.start_at.0 <= self.cliff_at.0 && self.cliff_at.0 <= self.end_at.0,
+            "ERR_INVALID_SCHEDULE"
+        );
+        assert!(self.start_at.0 < self.end_at.0, "ERR_INVALID_SCHEDULE");
+    }
+}
+
+/// Where the unvested part of a revoked grant goes.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum RevokeDestination {
+    /// The grantor, i.e. the owner who created the grant.
+    Owner,
+    Treasury,
+    Burn,
+}
+
+/// How a grant is funded.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum VestingFunding {
+    /// Escrowed from the grantor's balance when the grant is created.
+    Escrow,
+    /// Minted when claimed, within `max_supply`.
+    MintOnClaim,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct VestingGrant {
+    pub grantor_id: AccountId,
+    pub beneficiary_id: AccountId,
+    /// Total amount of the grant. Revoking cuts it down to what had vested.
+    pub total: Balance,
+    pub claimed: Balance,
+    pub start_at: u64,
+    pub cliff_at: u64,
+    pub end_at: u64,
+    pub revocable: bool,
+    pub revoke_destination: RevokeDestination,
+    pub revoked: bool,
+    pub funding: VestingFunding,
+}
+
+impl VestingGrant {
+    /// Amount vested at `now`, claimed or not.
+    fn vested_at(&self, now: u64) -> Balance {
+        if self.revoked || now >= self.end_at {
+            self.total
+        } else if now < self.cliff_at {
+            0
+        } else {
+            let elapsed = (now - self.start_at) as u128;
+            mul_div(
+                self.total,
+                elapsed,
+                (self.end_at - self.start_at) as u128,
+                false,
+            )
+        }
+    }
+
+    fn schedule(&self) -> VestingSchedule {
+        VestingSchedule {
+            start_at: self.start_at.into(),
+            cliff_at: self.cliff_at.into(),
+            end_at: self.end_at.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingGrantView {
+    pub grant_id: U64,
+    pub grantor_id: AccountId,
+    pub beneficiary_id: AccountId,
+    pub schedule: VestingSchedule,
+    pub total: U128,
+    pub claimed: U128,
+    /// Not claimed yet, whether vested or not.
+    pub remaining: U128,
+    pub claimable: U128,
+    pub revocable: bool,
+    pub revoke_destination: RevokeDestination,
+    pub revoked: bool,
+    pub funding: VestingFunding,
+}
+
+impl Contract {
+    fn internal_get_vesting_grant(&self, grant_id: GrantId) -> VestingGrant {
+        self.vesting_grants.get(&grant_id).expect("ERR_NO_GRANT")
+    }
+
+    fn internal_vesting_grant_view(&self, grant_id: GrantId) -> VestingGrantView {
+        let grant = self.internal_get_vesting_grant(grant_id);
+        VestingGrantView {
+            grant_id: grant_id.into(),
+            schedule: grant.schedule(),
+            total: grant.total.into(),
+            claimed: grant.claimed.into(),
+            remaining: (grant.total - grant.claimed).into(),
+            claimable: (grant.vested_at(env::block_timestamp()) - grant.claimed).into(),
+            revocable: grant.revocable,
+            revoke_destination: grant.revoke_destination,
+            revoked: grant.revoked,
+            funding: grant.funding,
+            grantor_id: grant.grantor_id,
+            beneficiary_id: grant.beneficiary_id,
+        }
+    }
+
+    /// Vests `amount` to `beneficiary_id` on `schedule`, escrowing it from `grantor_id` unless
+    /// the grant mints on claim or the grantor is the contract itself, whose tokens are escrowed
+    /// already. The grant is revocable if `revoke_destination` is set.
+    pub(crate) fn internal_create_vesting_grant(
+        &mut self,
+        grantor_id: &AccountId,
+        beneficiary_id: &AccountId,
+        amount: Balance,
+        schedule: VestingSchedule,
+        revoke_destination: Option<RevokeDestination>,
+        funding: VestingFunding,
+    ) -> GrantId {
+        assert!(amount > 0, "ERR_ZERO_AMOUNT");
+        schedule.assert_valid();
+        if revoke_destination == Some(RevokeDestination::Treasury) {
+            assert!(self.treasury_id.is_some(), "ERR_NO_TREASURY");
+        }
+        assert!(
+            self.token.accounts.contains_key(beneficiary_id),
+            "ERR_NOT_REGISTERED"
+        );
+        if funding == VestingFunding::Escrow && grantor_id != &env::current_account_id() {
+            self.internal_escrow_deposit(grantor_id, amount);
+        }
+        let revocable = revoke_destination.is_some();
+        let revoke_destination = revoke_destination.unwrap_or(RevokeDestination::Owner);
+        let grant_id = self.next_vesting_grant_id;
+        self.next_vesting_grant_id += 1;
+        self.vesting_grants.insert(
+            &grant_id,
+            &VestingGrant {
+                grantor_id: grantor_id.clone(),
+                beneficiary_id: beneficiary_id.clone(),
+                total: amount,
+                claimed: 0,
+                start_at: schedule.start_at.into(),
+                cliff_at: schedule.cliff_at.into(),
+                end_at: schedule.end_at.into(),
+                revocable,
+                revoke_destination,
+                revoked: false,
+                funding,
+            },
+        );
+        let mut grants = self
+            .vesting_by_beneficiary
+            .get(beneficiary_id)
+            .unwrap_or_default();
+        grants.push(grant_id);
+        self.vesting_by_beneficiary.insert(beneficiary_id, &grants);
+        events::emit_event(
+            "vesting_grant_created",
+            json!({
+                "grant_id": U64(grant_id),
+                "grantor_id": grantor_id,
+                "beneficiary_id": beneficiary_id,
+                "amount": U128(amount),
+                "schedule": schedule,
+                "revocable": revocable,
+                "revoke_destination": revoke_destination,
+                "funding": funding,
+            }),
+        );
+        grant_id
+    }
+
+    /// Stops a grant without paying anything out, including what has vested. Returns the
+    /// unclaimed amount, which the caller has to dispose of.
+    #[cfg(feature = "sale")]
+    pub(crate) fn internal_cancel_vesting_grant(&mut self, grant_id: GrantId) -> Balance {
+        let mut grant = self.internal_get_vesting_grant(grant_id);
+        let unclaimed = grant.total - grant.claimed;
+        grant.total = grant.claimed;
+        grant.revoked = true;
+        self.vesting_grants.insert(&grant_id, &grant);
+        events::emit_event(
+            "vesting_grant_cancelled",
+            json!({ "grant_id": U64(grant_id), "unclaimed": U128(unclaimed) }),
+        );
+        unclaimed
+    }
+
+    /// Pays `amount` of the grant out to `account_id`, from escrow or by minting.
+    fn internal_vesting_payout(
+        &mut self,
+        grant: &VestingGrant,
+        account_id: &AccountId,
+        amount: Balance,
+    ) {
+        match grant.funding {
+            VestingFunding::Escrow => self.internal_escrow_release(account_id, amount),
+            VestingFunding::MintOnClaim => {
+                self.assert_not_paused(PauseSurface::Mint);
+                self.internal_mint(account_id, amount, None)
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Vests `amount` to `beneficiary_id`, from the owner's balance or minted on claim depending
+    /// on `funding` (escrow by default). If the grant is revocable, its unvested tokens go to
+    /// `revoke_destination` on revocation, back to the owner by default.
+    /// The attached deposit covers the storage of the grant.
+    #[payable]
+    pub fn create_vesting_grant(
+        &mut self,
+        beneficiary_id: ValidAccountId,
+        amount: U128,
+        schedule: VestingSchedule,
+        revocable: bool,
+        revoke_destination: Option<RevokeDestination>,
+        funding: Option<VestingFunding>,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_owner();
+        let grant_id = self.internal_create_vesting_grant(
+            &env::predecessor_account_id(),
+            beneficiary_id.as_ref(),
+            amount.into(),
+            schedule,
+            if revocable {
+                Some(revoke_destination.unwrap_or(RevokeDestination::Owner))
+            } else {
+                None
+            },
+            funding.unwrap_or(VestingFunding::Escrow),
+        );
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+        grant_id.into()
+    }
+
+    /// Transfers `amount` to `receiver_id` as a non-revocable grant that unlocks on `schedule`.
+    /// The transfer restrictions and the transfer fee apply as for `ft_transfer`; transfers that
+    /// need a co-signature or a compliance check can't be vested. The attached deposit covers
+    /// the storage of the grant.
+    #[payable]
+    pub fn ft_transfer_vested(
+        &mut self,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        schedule: VestingSchedule,
+    ) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+        self.assert_not_paused(PauseSurface::Transfers);
+        self.assert_not_emergency();
+        let sender_id = env::predecessor_account_id();
+        assert_ne!(
+            &sender_id,
+            receiver_id.as_ref(),
+            "Sender and receiver should be different"
+        );
+        self.internal_apply_holder_transfer_checks(
+            &sender_id,
+            receiver_id.as_ref(),
+            amount.into(),
+            None,
+        );
+        assert!(
+            !self.internal_requires_cosignature(&sender_id, amount.into()),
+            "ERR_COSIGNATURE_REQUIRED"
+        );
+        assert!(
+            self.config.compliance_registry.is_none(),
+            "ERR_COMPLIANCE_CHECK_REQUIRED"
+        );
+        let grant_id = self.internal_create_vesting_grant(
+            &sender_id,
+            receiver_id.as_ref(),
+            amount.into(),
+            schedule,
+            None,
+            VestingFunding::Escrow,
+        );
+        let fee = self.internal_transfer_fee(amount.into());
+        self.internal_charge_transfer_fee(&sender_id, fee);
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+        grant_id.into()
+    }
+
+    /// Pays out `amount` of the vested and unclaimed tokens, or all of them if `amount` is
+    /// omitted. Returns the claimed amount.
+    #[payable]
+    pub fn claim_vested(&mut self, grant_id: U64, amount: Option<U128>) -> U128 {
+        assert_one_yocto();
+        let grant_id: GrantId = grant_id.into();
+        let mut grant = self.internal_get_vesting_grant(grant_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            grant.beneficiary_id,
+            "ERR_NOT_ALLOWED"
+        );
+        self.assert_not_blacklisted(&grant.beneficiary_id);
+        if grant.grantor_id != env::current_account_id() {
+            self.assert_not_blacklisted(&grant.grantor_id);
+        }
+        let claimable = grant.vested_at(env::block_timestamp()) - grant.claimed;
+        assert!(claimable > 0, "ERR_NOTHING_TO_CLAIM");
+        let amount = amount.map_or(claimable, |amount| amount.0);
+        assert!(amount > 0, "ERR_ZERO_AMOUNT");
+        assert!(amount <= claimable, "ERR_NOT_ENOUGH_VESTED");
+        grant.claimed += amount;
+        self.vesting_grants.insert(&grant_id, &grant);
+        self.internal_vesting_payout(&grant, &grant.beneficiary_id, amount);
+        amount.into()
+    }
+
+    /// Stops a revocable grant. What has vested stays claimable; the rest of an escrowed grant
+    /// goes to the grant's revoke destination, that of a mint-on-claim grant is never minted.
+    /// Returns the unvested amount.
+    #[payable]
+    pub fn revoke_vesting_grant(&mut self, grant_id: U64) -> U128 {
+        assert_one_yocto();
+        let grant_id: GrantId = grant_id.into();
+        let mut grant = self.internal_get_vesting_grant(grant_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            grant.grantor_id,
+            "ERR_NOT_ALLOWED"
+        );
+        assert!(grant.revocable, "ERR_NOT_REVOCABLE");
+        assert!(!grant.revoked, "ERR_REVOKED");
+        let vested = grant.vested_at(env::block_timestamp());
+        let unvested = grant.total - vested;
+        grant.total = vested;
+        grant.revoked = true;
+        self.vesting_grants.insert(&grant_id, &grant);
+        // Mint-on-claim grants have nothing to hand over: their unvested part is never minted,
+        // which leaves its room under `max_supply` free.
+        if unvested > 0 && grant.funding == VestingFunding::Escrow {
+            match grant.revoke_destination {
+                RevokeDestination::Owner => {
+                    self.internal_escrow_release(&grant.grantor_id, unvested)
+                }
+                RevokeDestination::Treasury => {
+                    let treasury_id = self.treasury_id.clone().expect("ERR_NO_TREASURY");
+                    self.internal_escrow_release(&treasury_id, unvested);
+                }
+                RevokeDestination::Burn => {
+                    self.internal_burn(&env::current_account_id(), unvested, None, None)
+                }
+            }
+        }
+        events::emit_event(
+            "vesting_grant_revoked",
+            json!({
+                "grant_id": U64(grant_id),
+                "unvested": U128(unvested),
+                "destination": grant.revoke_destination,
+            }),
+        );
+        unvested.into()
+    }
+
+    pub fn get_vesting_grant(&self, grant_id: U64) -> VestingGrantView {
+        self.internal_vesting_grant_view(grant_id.into())
+    }
+
+    pub fn get_vesting_grants(&self, account_id: ValidAccountId) -> Vec<VestingGrantView> {
+        self.vesting_by_beneficiary
+            .get(account_id.as_ref())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|grant_id| self.internal_vesting_grant_view(grant_id))
+            .collect()
+    }
+
+    /// Returns up to `limit` grants in creation order, starting with grant ID `from`.
+    pub fn get_all_grants(&self, from: U64, limit: U64) -> Vec<VestingGrantView> {
+        let limit = std::cmp::min(limit.0, MAX_GRANTS_LIMIT);
+        let end = std::cmp::min(from.0.saturating_add(limit), self.next_vesting_grant_id);
+        (from.0..end)
+            .map(|grant_id| self.internal_vesting_grant_view(grant_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    fn schedule(start: u64, cliff: u64, end: u64) -> VestingSchedule {
+        VestingSchedule {
+            start_at: (start * SECOND).into(),
+            cliff_at: (cliff * SECOND).into(),
+            end_at: (end * SECOND).into(),
+        }
+    }
+
+    #[test]
+    fn test_transfer_vested() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        mint_to(&mut contract, accounts(2), 0);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        let grant_id = contract.ft_transfer_vested(accounts(2), 1_000.into(), schedule(0, 10, 100));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .block_timestamp(40 * SECOND)
+            .build());
+        assert_eq!(contract.claim_vested(grant_id, None).0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 400);
+
+        testing_env!(context.block_timestamp(200 * SECOND).build());
+        let grant = contract.get_vesting_grant(grant_id);
+        assert_eq!(grant.claimable.0, 600);
+        assert_eq!(grant.remaining.0, 600);
+        assert_eq!(contract.claim_vested(grant_id, Some(100.into())).0, 100);
+        assert_eq!(contract.get_vesting_grant(grant_id).claimable.0, 500);
+        assert_eq!(contract.claim_vested(grant_id, None).0, 500);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PAUSED")]
+    fn test_transfer_vested_while_paused() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        mint_to(&mut contract, accounts(2), 0);
+        contract.pause(Some(vec![PauseSurface::Transfers]));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        contract.ft_transfer_vested(accounts(2), 1_000.into(), schedule(0, 10, 100));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOTHING_TO_CLAIM")]
+    fn test_claim_before_cliff() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(1), 1_000);
+        mint_to(&mut contract, accounts(2), 0);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        let grant_id = contract.ft_transfer_vested(accounts(2), 1_000.into(), schedule(0, 10, 100));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .block_timestamp(5 * SECOND)
+            .build());
+        contract.claim_vested(grant_id, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_VESTED")]
+    fn test_claim_more_than_vested() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(0), 1_000);
+        mint_to(&mut contract, accounts(2), 0);
+
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        let grant_id = contract.create_vesting_grant(
+            accounts(2),
+            1_000.into(),
+            schedule(0, 0, 100),
+            false,
+            None,
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .block_timestamp(10 * SECOND)
+            .build());
+        contract.claim_vested(grant_id, Some(101.into()));
+    }
+
+    #[test]
+    fn test_revoke_grant() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(0), 1_000);
+        mint_to(&mut contract, accounts(2), 0);
+
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        let grant_id = contract.create_vesting_grant(
+            accounts(2),
+            1_000.into(),
+            schedule(0, 0, 100),
+            true,
+            None,
+            None,
+        );
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(25 * SECOND)
+            .build());
+        assert_eq!(contract.revoke_vesting_grant(grant_id).0, 750);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 750);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(50 * SECOND)
+            .build());
+        assert_eq!(contract.claim_vested(grant_id, None).0, 250);
+    }
+
+    #[test]
+    fn test_revoke_to_treasury_and_burn() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(0), 1_000);
+        mint_to(&mut contract, accounts(2), 0);
+        mint_to(&mut contract, accounts(3), 0);
+        contract.set_treasury(accounts(3));
+
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 2_000)
+            .build());
+        let to_treasury = contract.create_vesting_grant(
+            accounts(2),
+            500.into(),
+            schedule(0, 0, 100),
+            true,
+            Some(RevokeDestination::Treasury),
+            None,
+        );
+        let to_burn = contract.create_vesting_grant(
+            accounts(2),
+            500.into(),
+            schedule(0, 0, 100),
+            true,
+            Some(RevokeDestination::Burn),
+            None,
+        );
+        assert_eq!(
+            contract.get_vesting_grant(to_burn).revoke_destination,
+            RevokeDestination::Burn
+        );
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(40 * SECOND)
+            .build());
+        assert_eq!(contract.revoke_vesting_grant(to_treasury).0, 300);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 300);
+        assert_eq!(contract.revoke_vesting_grant(to_burn).0, 300);
+        assert_eq!(contract.ft_total_supply().0, 700);
+    }
+
+    #[test]
+    fn test_grant_views() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000_000);
+        mint_to(&mut contract, accounts(0), 1_000);
+        mint_to(&mut contract, accounts(1), 0);
+        mint_to(&mut contract, accounts(2), 0);
+
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        for account_id in &[accounts(1), accounts(2), accounts(1)] {
+            contract.create_vesting_grant(
+                account_id.clone(),
+                100.into(),
+                schedule(0, 0, 100),
+                false,
+                None,
+                None,
+            );
+        }
+
+        let grants = contract.get_vesting_grants(accounts(1));
+        assert_eq!(grants.len(), 2);
+        assert_eq!(grants[1].grant_id.0, 2);
+        let page = contract.get_all_grants(1.into(), 5.into());
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].beneficiary_id, accounts(2).to_string());
+        assert!(contract.get_all_grants(3.into(), 5.into()).is_empty());
+    }
+
+    #[test]
+    fn test_mint_on_claim() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(2), 0);
+
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        let grant_id = contract.create_vesting_grant(
+            accounts(2),
+            800.into(),
+            schedule(0, 0, 100),
+            false,
+            None,
+            Some(VestingFunding::MintOnClaim),
+        );
+        assert_eq!(contract.ft_total_supply().0, 0);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .block_timestamp(50 * SECOND)
+            .build());
+        assert_eq!(contract.claim_vested(grant_id, None).0, 400);
+        assert_eq!(contract.ft_total_supply().0, 400);
+    }
+
+    #[test]
+    fn test_revoke_mint_on_claim_mints_nothing() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(2), 0);
+
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        let grant_id = contract.create_vesting_grant(
+            accounts(2),
+            800.into(),
+            schedule(0, 0, 100),
+            true,
+            Some(RevokeDestination::Owner),
+            Some(VestingFunding::MintOnClaim),
+        );
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(25 * SECOND)
+            .build());
+        assert_eq!(contract.ft_total_supply().0, 0);
+        assert_eq!(contract.revoke_vesting_grant(grant_id).0, 600);
+        assert_eq!(contract.ft_total_supply().0, 0);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert_eq!(contract.claim_vested(grant_id, None).0, 200);
+        assert_eq!(contract.ft_total_supply().0, 200);
+    }
+
+    #[test]
+    #[should_panic(expected = "Overflow")]
+    fn test_mint_on_claim_over_max_supply() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = new_contract(1_000);
+        mint_to(&mut contract, accounts(2), 0);
+
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1_000)
+            .build());
+        let grant_id = contract.create_vesting_grant(
+            accounts(2),
+            800.into(),
+            schedule(0, 0, 100),
+            false,
+            None,
+            Some(VestingFunding::MintOnClaim),
+        );
+        testing_env!(context.attached_deposit(1).build());
+        mint_to(&mut contract, accounts(0), 900);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(50 * SECOND)
+            .build());
+        contract.claim_vested(grant_id, None);
+    }
+}
@@ -0,0 +1,206 @@
+//! Rolling 24-hour limit on the volume each account sends.
+//!
+//! Outgoing transfers are counted in hourly buckets, so the window rolls forward by the hour.
+//! Tokens escrowed for receivers not known yet, linkdrops and payroll pools, count when they are
+//! escrowed.
+//! `Config::daily_volume_cap` applies to every account the owner hasn't exempted, and the sender's
+//! tier may set a lower ceiling of its own. Volume is only tracked for senders subject to one of
+//! the limits.
+
+use crate::account_tiers::TierLimits;
+use crate::config::ConfigUpdate;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::serde_json::json;
+
+const HOUR: u64 = 60 * 60 * 1_000_000_000;
+pub(crate) const DAY: u64 = 24 * HOUR;
+
+/// Amounts sent per hour within the last 24 hours.
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct OutgoingVolume {
+    /// Hours since the Unix epoch and the amount sent in them, oldest first.
+    buckets: Vec<(u64, Balance)>,
+}
+
+impl OutgoingVolume {
+    fn prune(&mut self, hour: u64) {
+        self.buckets
+            .retain(|(bucket_hour, _)| bucket_hour + DAY / HOUR > hour);
+    }
+
+    fn total(&self) -> Balance {
+        self.buckets.iter().map(|(_, amount)| amount).sum()
+    }
+
+    fn add(&mut self, hour: u64, amount: Balance) {
+        match self.buckets.last_mut() {
+            Some((bucket_hour, bucket_amount)) if *bucket_hour == hour => *bucket_amount += amount,
+            _ => self.buckets.push((hour, amount)),
+        }
+    }
+}
+
+impl Contract {
+    /// Outgoing volume of the sender including a transfer of `amount`, `None` if the sender isn't
+    /// subject to a volume limit. Fails if the transfer takes the sender over the volume cap or
+    /// its tier's ceiling within the last 24 hours.
+    pub(crate) fn internal_outgoing_volume_after(
+        &self,
+        sender_id: &AccountId,
+        tier_limits: &TierLimits,
+        amount: Balance,
+    ) -> Result<Option<OutgoingVolume>, &'static str> {
+        let tier_limit = tier_limits.daily_volume.map(|limit| limit.0);
+        let cap = self
+            .config
+            .daily_volume_cap
+            .filter(|_| !self.volume_cap_exemptions.contains(sender_id));
+        if tier_limit.is_none() && cap.is_none() {
+            return Ok(None);
+        }
+        let hour = env::block_timestamp() / HOUR;
+        let mut volume = self.outgoing_volumes.get(sender_id).unwrap_or_default();
+        volume.prune(hour);
+        let sent = volume.total().checked_add(amount).unwrap();
+        if tier_limit.is_some_and(|tier_limit| sent > tier_limit) {
+            return Err("ERR_TIER_DAILY_VOLUME");
+        }
+        if cap.is_some_and(|cap| sent > cap) {
+            return Err("ERR_DAILY_VOLUME_CAP");
+        }
+        volume.add(hour, amount);
+        Ok(Some(volume))
+    }
+
+    pub(crate) fn internal_set_volume_cap_exempt(&mut self, account_id: &AccountId, exempt: bool) {
+        if exempt {
+            self.volume_cap_exemptions.insert(account_id);
+        } else {
+            self.volume_cap_exemptions.remove(account_id);
+        }
+        events::emit_event(
+            "volume_cap_exemption_changed",
+            json!({ "account_id": account_id, "exempt": exempt }),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the most an account may send within 24 hours, or lifts the cap with `None`.
+    #[payable]
+    pub fn set_daily_volume_cap(&mut self, cap: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.internal_update_config(ConfigUpdate {
+            daily_volume_cap: Some(cap),
+            ..Default::default()
+        });
+    }
+
+    pub fn get_daily_volume_cap(&self) -> Option<U128> {
+        self.config.daily_volume_cap.map(|cap| cap.into())
+    }
+
+    /// Exempts `account_id` from the volume cap, e.g. an exchange hot wallet. Tier ceilings still
+    /// apply. The owner pays for the storage out of the attached deposit.
+    #[payable]
+    pub fn set_volume_cap_exempt(&mut self, account_id: ValidAccountId, exempt: bool) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        self.internal_set_volume_cap_exempt(account_id.as_ref(), exempt);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    pub fn is_volume_cap_exempt(&self, account_id: ValidAccountId) -> bool {
+        self.volume_cap_exemptions.contains(account_id.as_ref())
+    }
+
+    /// Amount `account_id` sent within the last 24 hours, as far as it's tracked.
+    pub fn get_outgoing_volume(&self, account_id: ValidAccountId) -> U128 {
+        let mut volume = self
+            .outgoing_volumes
+            .get(account_id.as_ref())
+            .unwrap_or_default();
+        volume.prune(env::block_timestamp() / HOUR);
+        volume.total().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+    use std::convert::TryInto;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(10_000);
+        mint_to(&mut contract, accounts(1), 5_000);
+        mint_to(&mut contract, accounts(2), 5_000);
+        contract.set_volume_cap_exempt(accounts(2), true);
+        context.attached_deposit(1);
+        testing_env!(context.build());
+        contract.set_daily_volume_cap(Some(100.into()));
+        (context, contract)
+    }
+
+    #[test]
+    fn test_window_rolls() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 60.into(), None);
+        testing_env!(context.block_timestamp(12 * HOUR).build());
+        contract.ft_transfer(accounts(2), 40.into(), None);
+        assert_eq!(contract.get_outgoing_volume(accounts(1)).0, 100);
+
+        testing_env!(context.block_timestamp(DAY).build());
+        assert_eq!(contract.get_outgoing_volume(accounts(1)).0, 40);
+        contract.ft_transfer(accounts(2), 60.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_DAILY_VOLUME_CAP")]
+    fn test_cap_exceeded() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer(accounts(2), 60.into(), None);
+        testing_env!(context.block_timestamp(DAY - 1).build());
+        contract.ft_transfer(accounts(2), 41.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_DAILY_VOLUME_CAP")]
+    fn test_cap_includes_escrowed_outflows() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.payroll_fund(40.into());
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        contract.create_drop(
+            "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+                .try_into()
+                .unwrap(),
+            40.into(),
+        );
+        assert_eq!(contract.get_outgoing_volume(accounts(1)).0, 80);
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(2), 21.into(), None);
+    }
+
+    #[test]
+    fn test_exempt_account() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer(accounts(1), 1_000.into(), None);
+        assert_eq!(contract.get_outgoing_volume(accounts(2)).0, 0);
+    }
+}
@@ -0,0 +1,221 @@
+//! Voucher codes for offline promotions: the owner registers the SHA-256 of a secret code and
+//! whoever presents the code before it expires gets the voucher's tokens.
+//!
+//! A voucher either mints its amount on redemption, within `max_supply` at that time, or pays it
+//! out of tokens the owner escrowed when creating it. Redeeming reveals the code on chain, so a
+//! voucher can only be redeemed once. Expired vouchers can be removed by anyone, which returns
+//! escrowed tokens to their creator.
+
+use crate::pause::PauseSurface;
+use crate::utils::refund_deposit;
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::json;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VoucherFunding {
+    /// Minted on redemption.
+    Mint,
+    /// Paid out of tokens escrowed at creation.
+    Escrow,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Voucher {
+    pub creator_id: AccountId,
+    pub amount: Balance,
+    pub expires_at: u64,
+    pub funding: VoucherFunding,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VoucherView {
+    pub amount: U128,
+    pub expires_at: U64,
+    pub funding: VoucherFunding,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Creates a voucher of `amount` redeemable until `expires_at` with the code whose SHA-256
+    /// is `code_hash`. Escrowed vouchers take the tokens from the owner's balance. The attached
+    /// deposit covers the voucher's storage; the rest is refunded.
+    #[payable]
+    pub fn create_voucher(
+        &mut self,
+        code_hash: Base64VecU8,
+        amount: U128,
+        expires_at: U64,
+        funding: VoucherFunding,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        self.assert_owner();
+        let code_hash: Vec<u8> = code_hash.into();
+        assert_eq!(code_hash.len(), 32, "ERR_INVALID_CODE_HASH");
+        assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        assert!(expires_at.0 > env::block_timestamp(), "ERR_EXPIRED");
+        assert!(
+            !self.vouchers.contains_key(&code_hash),
+            "ERR_VOUCHER_EXISTS"
+        );
+        let creator_id = env::predecessor_account_id();
+        if funding == VoucherFunding::Escrow {
+            self.internal_escrow_deposit(&creator_id, amount.into());
+        }
+        self.vouchers.insert(
+            &code_hash,
+            &Voucher {
+                creator_id,
+                amount: amount.into(),
+                expires_at: expires_at.into(),
+                funding,
+            },
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Redeems the voucher of `code` to the predecessor. An unregistered account must attach
+    /// enough deposit to cover its registration.
+    #[payable]
+    pub fn redeem_voucher(&mut self, code: String) -> U128 {
+        let initial_storage_usage = env::storage_usage();
+        let code_hash = env::sha256(code.as_bytes());
+        let voucher = self.vouchers.remove(&code_hash).expect("ERR_NO_VOUCHER");
+        assert!(voucher.expires_at > env::block_timestamp(), "ERR_EXPIRED");
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blacklisted(&account_id);
+        self.assert_kyc_for_receipt(&account_id, voucher.amount);
+        match voucher.funding {
+            VoucherFunding::Mint => {
+                self.assert_not_paused(PauseSurface::Mint);
+                self.internal_mint(&account_id, voucher.amount, Some("voucher"));
+            }
+            VoucherFunding::Escrow => {
+                self.assert_not_paused(PauseSurface::Transfers);
+                self.internal_ensure_registered(&account_id);
+                self.internal_escrow_release(&account_id, voucher.amount);
+            }
+        }
+        events::emit_event(
+            "voucher_redeemed",
+            json!({
+                "code_hash": Base64VecU8(code_hash),
+                "account_id": account_id,
+                "amount": U128(voucher.amount),
+            }),
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        voucher.amount.into()
+    }
+
+    /// Drops an expired voucher, returning escrowed tokens to its creator.
+    pub fn remove_expired_voucher(&mut self, code_hash: Base64VecU8) {
+        let code_hash: Vec<u8> = code_hash.into();
+        let voucher = self.vouchers.get(&code_hash).expect("ERR_NO_VOUCHER");
+        assert!(
+            voucher.expires_at <= env::block_timestamp(),
+            "ERR_NOT_EXPIRED"
+        );
+        self.vouchers.remove(&code_hash);
+        if voucher.funding == VoucherFunding::Escrow {
+            self.internal_escrow_release(&voucher.creator_id, voucher.amount);
+        }
+    }
+
+    pub fn get_voucher(&self, code_hash: Base64VecU8) -> Option<VoucherView> {
+        self.vouchers
+            .get(&code_hash.into())
+            .map(|voucher| VoucherView {
+                amount: voucher.amount.into(),
+                expires_at: voucher.expires_at.into(),
+                funding: voucher.funding,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn code_hash(code: &str) -> Base64VecU8 {
+        env::sha256(code.as_bytes()).into()
+    }
+
+    #[test]
+    fn test_redeem_vouchers() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(0), 500)]);
+        contract.create_voucher(
+            code_hash("mint"),
+            100.into(),
+            50.into(),
+            VoucherFunding::Mint,
+        );
+        contract.create_voucher(
+            code_hash("escrow"),
+            200.into(),
+            50.into(),
+            VoucherFunding::Escrow,
+        );
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 300);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        assert_eq!(contract.redeem_voucher("mint".to_string()).0, 100);
+        assert_eq!(contract.redeem_voucher("escrow".to_string()).0, 200);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 300);
+        assert_eq!(contract.ft_total_supply().0, 600);
+        assert!(contract.get_voucher(code_hash("mint")).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_VOUCHER")]
+    fn test_redeem_voucher_twice() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract(1_000);
+        contract.create_voucher(
+            code_hash("code"),
+            100.into(),
+            50.into(),
+            VoucherFunding::Mint,
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.redeem_voucher("code".to_string());
+        contract.redeem_voucher("code".to_string());
+    }
+
+    #[test]
+    fn test_remove_expired_voucher() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = new_contract_with_balances(1_000, &[(accounts(0), 500)]);
+        contract.create_voucher(
+            code_hash("code"),
+            200.into(),
+            50.into(),
+            VoucherFunding::Escrow,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(50)
+            .attached_deposit(0)
+            .build());
+        contract.remove_expired_voucher(code_hash("code"));
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 500);
+    }
+}